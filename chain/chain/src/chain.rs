@@ -2,7 +2,8 @@ use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 use std::time::{Duration as TimeDuration, Instant};
 
-use borsh::BorshSerialize;
+use borsh::{BorshDeserialize, BorshSerialize};
+use cached::{Cached, SizedCache};
 use chrono::Duration;
 use chrono::Utc;
 use rand::rngs::StdRng;
@@ -43,7 +44,7 @@ use near_primitives::sharding::{
 use near_primitives::syncing::{
     get_num_state_parts, ReceiptProofResponse, ReceiptResponse, RootProof,
     ShardStateSyncResponseHeader, ShardStateSyncResponseHeaderV1, ShardStateSyncResponseHeaderV2,
-    StateHeaderKey, StatePartKey,
+    StateHeaderKey, StatePartChunk, StatePartKey, StatePartSyncProgress,
 };
 use near_primitives::transaction::ExecutionOutcomeWithIdAndProof;
 use near_primitives::types::{
@@ -56,7 +57,10 @@ use near_primitives::views::{
     FinalExecutionOutcomeWithReceiptView, FinalExecutionStatus, LightClientBlockView,
     SignedTransactionView,
 };
-use near_store::{ColState, ColStateHeaders, ColStateParts, ShardTries, StoreUpdate};
+use near_store::{
+    ColState, ColStateDlInfos, ColStateHeaders, ColStateParts, FlatStateHeadUpdate, ShardTries,
+    StoreUpdate,
+};
 
 #[cfg(feature = "delay_detector")]
 use delay_detector::DelayDetector;
@@ -186,6 +190,9 @@ impl OrphanBlockPool {
     }
 }
 
+/// Number of state parts kept split into chunks for serving chunk requests.
+const STATE_PART_CHUNKS_CACHE_SIZE: usize = 16;
+
 /// Facade to the blockchain block processing and storage.
 /// Provides current view on the state according to the chain state.
 pub struct Chain {
@@ -199,6 +206,8 @@ pub struct Chain {
     /// Block economics, relevant to changes when new block must be produced.
     pub block_economics_config: BlockEconomicsConfig,
     pub doomslug_threshold_mode: DoomslugThresholdMode,
+    /// Recently served state parts split into chunks, keyed by (sync_hash, shard_id, part_id).
+    state_part_chunks: SizedCache<(CryptoHash, ShardId, u64), Arc<Vec<Vec<u8>>>>,
 }
 
 impl Chain {
@@ -235,6 +244,7 @@ impl Chain {
             epoch_length: chain_genesis.epoch_length,
             block_economics_config: BlockEconomicsConfig::from(chain_genesis),
             doomslug_threshold_mode,
+            state_part_chunks: SizedCache::with_size(STATE_PART_CHUNKS_CACHE_SIZE),
         })
     }
 
@@ -330,6 +340,16 @@ impl Chain {
                     store_update.save_head(&head)?;
                     store_update.save_final_head(&head)?;
 
+                    // Flat state of a new node starts at genesis, existing ones are migrated.
+                    let tries = runtime_adapter.get_tries();
+                    if tries.is_flat_state_enabled() {
+                        for (shard_id, state_root) in state_roots.iter().enumerate() {
+                            tries
+                                .build_flat_state(shard_id as ShardId, genesis.hash(), state_root)
+                                .map_err(|e| Error::from(ErrorKind::StorageError(e)))?;
+                        }
+                    }
+
                     info!(target: "chain", "Init: saved genesis: {:?} / {:?}", genesis.hash(), state_roots);
                 }
                 e => return Err(e.into()),
@@ -349,6 +369,7 @@ impl Chain {
             epoch_length: chain_genesis.epoch_length,
             block_economics_config: BlockEconomicsConfig::from(chain_genesis),
             doomslug_threshold_mode,
+            state_part_chunks: SizedCache::with_size(STATE_PART_CHUNKS_CACHE_SIZE),
         })
     }
 
@@ -924,12 +945,17 @@ impl Chain {
         chain_store_update.commit()?;
 
         // clear all trie data
+        // Parts of this sync applied before a restart are the only state left after the reset
+        // done when the sync started, so they are kept to resume the sync.
 
-        let tries = self.runtime_adapter.get_tries();
+        let has_applied_state_parts = self.has_applied_state_parts(sync_hash);
         let mut chain_store_update = self.mut_store().store_update();
-        let mut store_update = StoreUpdate::new_with_tries(tries);
-        store_update.delete_all(ColState);
-        chain_store_update.merge(store_update);
+        if !has_applied_state_parts {
+            let tries = self.runtime_adapter.get_tries();
+            let mut store_update = StoreUpdate::new_with_tries(tries);
+            store_update.delete_all(ColState);
+            chain_store_update.merge(store_update);
+        }
 
         // The reason to reset tail here is not to allow Tail be greater than Head
         chain_store_update.reset_tail();
@@ -1468,6 +1494,28 @@ impl Chain {
         Ok(shard_state_header)
     }
 
+    /// Whether the state part is in the parts cache and can be served without computing it.
+    pub fn has_state_response_part(
+        &self,
+        shard_id: ShardId,
+        part_id: u64,
+        sync_hash: CryptoHash,
+    ) -> Result<bool, Error> {
+        let key = StatePartKey(sync_hash, shard_id, part_id).try_to_vec()?;
+        Ok(self.store.owned_store().exists(ColStateParts, &key)?)
+    }
+
+    /// Whether the state part is already split into chunks, so that they can be served
+    /// without computing them.
+    pub fn has_state_response_part_chunks(
+        &mut self,
+        shard_id: ShardId,
+        part_id: u64,
+        sync_hash: CryptoHash,
+    ) -> bool {
+        self.state_part_chunks.cache_get(&(sync_hash, shard_id, part_id)).is_some()
+    }
+
     pub fn get_state_response_part(
         &mut self,
         shard_id: ShardId,
@@ -1524,6 +1572,36 @@ impl Chain {
         Ok(state_part)
     }
 
+    pub fn get_state_response_part_chunk(
+        &mut self,
+        shard_id: ShardId,
+        part_id: u64,
+        chunk_id: u64,
+        sync_hash: CryptoHash,
+    ) -> Result<StatePartChunk, Error> {
+        let cache_key = (sync_hash, shard_id, part_id);
+        let chunks = match self.state_part_chunks.cache_get(&cache_key) {
+            Some(chunks) => chunks.clone(),
+            None => {
+                let state_part = self.get_state_response_part(shard_id, part_id, sync_hash)?;
+                let shard_state_header = self.get_state_response_header(shard_id, sync_hash)?;
+                let state_root = shard_state_header.chunk_prev_state_root();
+                let chunks = Arc::new(
+                    self.runtime_adapter
+                        .split_state_part(&state_root, &state_part)
+                        .log_storage_error("split_state_part fail")?,
+                );
+                self.state_part_chunks.cache_set(cache_key, chunks.clone());
+                chunks
+            }
+        };
+        let num_chunks = chunks.len() as u64;
+        match chunks.get(chunk_id as usize) {
+            Some(data) => Ok(StatePartChunk { part_id, chunk_id, num_chunks, data: data.clone() }),
+            None => Err(ErrorKind::InvalidStateRequest("chunk_id out of bound".to_string()).into()),
+        }
+    }
+
     pub fn set_state_header(
         &mut self,
         shard_id: ShardId,
@@ -1725,12 +1803,99 @@ impl Chain {
             .into());
         }
 
-        // Saving the part data.
-        let mut store_update = self.store.owned_store().store_update();
+        // The part is applied right away instead of being kept until all parts are downloaded,
+        // the same way as a part received in chunks, so that its nodes can be released as well.
+        match self.get_state_part_progress(shard_id, sync_hash, part_id)? {
+            Some(progress) if progress.is_complete() => return Ok(()),
+            Some(_) => self.release_state_part(shard_id, sync_hash, part_id)?,
+            None => {}
+        }
+        let chunks = self.runtime_adapter.split_state_part(&state_root, data)?;
+        let num_chunks = chunks.len() as u64;
+        for (chunk_id, data) in chunks.into_iter().enumerate() {
+            let chunk = StatePartChunk { part_id, chunk_id: chunk_id as u64, num_chunks, data };
+            if let Err(err) = self.set_state_part_chunk(shard_id, sync_hash, num_parts, &chunk) {
+                self.release_state_part(shard_id, sync_hash, part_id)?;
+                return Err(err);
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns how much of the state part is already applied, if its download has started.
+    pub fn get_state_part_progress(
+        &self,
+        shard_id: ShardId,
+        sync_hash: CryptoHash,
+        part_id: u64,
+    ) -> Result<Option<StatePartSyncProgress>, Error> {
         let key = StatePartKey(sync_hash, shard_id, part_id).try_to_vec()?;
-        store_update.set(ColStateParts, &key, data);
+        Ok(self.store.owned_store().get_ser(ColStateDlInfos, &key)?)
+    }
+
+    /// Verifies and applies the next chunk of a state part, saving the progress so that the
+    /// download can be resumed after a restart. Chunks must come in order.
+    /// Returns true if the part is completely applied.
+    pub fn set_state_part_chunk(
+        &mut self,
+        shard_id: ShardId,
+        sync_hash: CryptoHash,
+        num_parts: u64,
+        chunk: &StatePartChunk,
+    ) -> Result<bool, Error> {
+        let StatePartChunk { part_id, chunk_id, num_chunks, ref data } = *chunk;
+        if part_id >= num_parts || chunk_id >= num_chunks {
+            return Err(ErrorKind::Other(format!(
+                "set_state_part_chunk failed: invalid chunk {}/{} of part {}/{}",
+                chunk_id, num_chunks, part_id, num_parts
+            ))
+            .into());
+        }
+        let shard_state_header = self.get_state_header(shard_id, sync_hash)?;
+        let state_root = shard_state_header.chunk_prev_state_root();
+        let mut progress = match self.get_state_part_progress(shard_id, sync_hash, part_id)? {
+            Some(progress) => progress,
+            None => StatePartSyncProgress::new(part_id, num_chunks, &state_root),
+        };
+        if progress.is_complete() {
+            return Ok(true);
+        }
+        if chunk_id != progress.next_chunk_id || num_chunks != progress.num_chunks {
+            return Err(ErrorKind::Other(format!(
+                "set_state_part_chunk failed: expected chunk {}/{}, got {}/{}",
+                progress.next_chunk_id, progress.num_chunks, chunk_id, num_chunks
+            ))
+            .into());
+        }
+
+        let mut store_update = self.runtime_adapter.apply_state_part_chunk(
+            shard_id,
+            &state_root,
+            &mut progress,
+            data,
+        )?;
+        let key = StatePartKey(sync_hash, shard_id, part_id).try_to_vec()?;
+        store_update.set_ser(ColStateDlInfos, &key, &progress)?;
         store_update.commit()?;
-        Ok(())
+
+        if !progress.is_complete() {
+            return Ok(false);
+        }
+        if !self.runtime_adapter.validate_applied_state_part(
+            shard_id,
+            &state_root,
+            part_id,
+            num_parts,
+        ) {
+            // The part has to be downloaded again from scratch.
+            byzantine_assert!(false);
+            self.release_state_part(shard_id, sync_hash, part_id)?;
+            return Err(ErrorKind::Other(
+                "set_state_part_chunk failed: validate_applied_state_part failed".into(),
+            )
+            .into());
+        }
+        Ok(true)
     }
 
     pub fn set_state_finalize(
@@ -1741,17 +1906,18 @@ impl Chain {
     ) -> Result<(), Error> {
         let shard_state_header = self.get_state_header(shard_id, sync_hash)?;
         let mut height = shard_state_header.chunk_height_included();
-        let state_root = shard_state_header.chunk_prev_state_root();
+        // Parts are applied as they arrive, just make sure none of them is missing.
         for part_id in 0..num_parts {
-            let key = StatePartKey(sync_hash, shard_id, part_id).try_to_vec()?;
-            let part = self.store.owned_store().get(ColStateParts, &key)?.unwrap();
-            self.runtime_adapter.apply_state_part(
-                shard_id,
-                &state_root,
-                part_id,
-                num_parts,
-                &part,
-            )?;
+            match self.get_state_part_progress(shard_id, sync_hash, part_id)? {
+                Some(progress) if progress.is_complete() => {}
+                _ => {
+                    return Err(ErrorKind::Other(format!(
+                        "set_state_finalize failed: state part {} is not applied",
+                        part_id
+                    ))
+                    .into())
+                }
+            }
         }
 
         // Applying the chunk starts here
@@ -1786,6 +1952,62 @@ impl Chain {
         Ok(chain_store_update.commit()?)
     }
 
+    /// Same as `clear_downloaded_parts`, but also releases the nodes applied from the parts,
+    /// for the case when the state sync of the shard has to start over.
+    pub fn abandon_downloaded_parts(
+        &mut self,
+        shard_id: ShardId,
+        sync_hash: CryptoHash,
+        num_parts: u64,
+    ) -> Result<(), Error> {
+        for part_id in 0..num_parts {
+            self.release_state_part(shard_id, sync_hash, part_id)?;
+        }
+        self.clear_downloaded_parts(shard_id, sync_hash, num_parts)
+    }
+
+    /// Releases the nodes applied from the parts of all state syncs but the one to `sync_hash`.
+    /// Such syncs were abandoned, e.g. because the node restarted and the sync hash moved on.
+    pub fn release_abandoned_state_parts(&mut self, sync_hash: CryptoHash) -> Result<(), Error> {
+        let store = self.store.owned_store();
+        let part_keys = store
+            .iter(ColStateDlInfos)
+            .filter_map(|(key, _)| StatePartKey::try_from_slice(&key).ok())
+            .filter(|key| key.0 != sync_hash)
+            .collect::<Vec<_>>();
+        for StatePartKey(part_sync_hash, shard_id, part_id) in part_keys {
+            self.release_state_part(shard_id, part_sync_hash, part_id)?;
+        }
+        Ok(())
+    }
+
+    /// Whether some state parts of the state sync to `sync_hash` were already applied.
+    fn has_applied_state_parts(&self, sync_hash: CryptoHash) -> bool {
+        let store = self.store.owned_store();
+        let mut keys = store.iter_prefix(ColStateDlInfos, sync_hash.as_ref());
+        keys.any(|(key, _)| StatePartKey::try_from_slice(&key).is_ok())
+    }
+
+    /// Releases the nodes applied so far for the state part and forgets its progress,
+    /// so that the part is downloaded again from scratch.
+    fn release_state_part(
+        &mut self,
+        shard_id: ShardId,
+        sync_hash: CryptoHash,
+        part_id: u64,
+    ) -> Result<(), Error> {
+        let progress = match self.get_state_part_progress(shard_id, sync_hash, part_id)? {
+            Some(progress) => progress,
+            None => return Ok(()),
+        };
+        let mut store_update =
+            self.runtime_adapter.release_applied_state_part(shard_id, &progress)?;
+        let key = StatePartKey(sync_hash, shard_id, part_id).try_to_vec()?;
+        store_update.delete(ColStateDlInfos, &key);
+        store_update.commit()?;
+        Ok(())
+    }
+
     /// Apply transactions in chunks for the next epoch in blocks that were blocked on the state sync
     pub fn catchup_blocks<F, F2, F3>(
         &mut self,
@@ -2875,6 +3097,8 @@ impl<'a> ChainUpdate<'a> {
                         apply_result.outcomes,
                         outcome_paths,
                     );
+                    self.chain_store_update
+                        .save_receipt_gas_profiles(&block.hash(), apply_result.gas_profiles);
                 } else {
                     let mut new_extra = self
                         .chain_store_update
@@ -3382,12 +3606,44 @@ impl<'a> ChainUpdate<'a> {
         if last_final_block_header.height() > final_head.height {
             let tip = Tip::from_header(last_final_block_header);
             self.chain_store_update.save_final_head(&tip)?;
+            self.update_flat_state_head(&tip)?;
             Ok(Some(tip))
         } else {
             Ok(None)
         }
     }
 
+    /// Moves flat state of the shards applied in the new final block to it. Flat state which is
+    /// missing or can't be moved is built from the trie by the store migration on restart, until
+    /// then the shard is read through the trie.
+    fn update_flat_state_head(&mut self, final_head: &Tip) -> Result<(), Error> {
+        let tries = self.runtime_adapter.get_tries();
+        if !tries.is_flat_state_enabled() {
+            return Ok(());
+        }
+        let block_hash = final_head.last_block_hash;
+        for shard_id in 0..self.runtime_adapter.num_shards() {
+            if let Err(e) = self.chain_store_update.get_chunk_extra(&block_hash, shard_id) {
+                match e.kind() {
+                    ErrorKind::DBNotFoundErr(_) => continue,
+                    _ => return Err(e),
+                }
+            }
+            let mut store_update = self.chain_store_update.store().store_update();
+            let head_update = tries
+                .update_flat_state_head(shard_id, &block_hash, &mut store_update)
+                .map_err(|e| Error::from(ErrorKind::StorageError(e)))?;
+            self.chain_store_update.merge(store_update);
+            match head_update {
+                FlatStateHeadUpdate::Moved | FlatStateHeadUpdate::Missing => {}
+                FlatStateHeadUpdate::Dropped => {
+                    warn!(target: "chain", "Dropped flat state of shard {}, it can't be moved to {}, it will be built again on restart", shard_id, block_hash);
+                }
+            }
+        }
+        Ok(())
+    }
+
     /// Directly updates the head if we've just appended a new block to it or handle
     /// the situation where the block has higher height to have a fork
     fn update_head(&mut self, header: &BlockHeader) -> Result<Option<Tip>, Error> {
@@ -3607,6 +3863,8 @@ impl<'a> ChainUpdate<'a> {
             apply_result.outcomes,
             outcome_proofs,
         );
+        self.chain_store_update
+            .save_receipt_gas_profiles(block_header.hash(), apply_result.gas_profiles);
         // Saving all incoming receipts.
         for receipt_proof_response in incoming_receipts_proofs {
             self.chain_store_update.save_incoming_receipt(
@@ -13,6 +13,7 @@ use near_primitives::block::{Approval, Tip};
 use near_primitives::errors::InvalidTxError;
 use near_primitives::hash::CryptoHash;
 use near_primitives::merkle::{MerklePath, PartialMerkleTree};
+use near_primitives::profile::ActionGasProfile;
 use near_primitives::receipt::Receipt;
 use near_primitives::sharding::{
     ChunkHash, EncodedShardChunk, PartialEncodedChunk, ReceiptProof, ShardChunk, ShardChunkHeader,
@@ -31,20 +32,22 @@ use near_primitives::types::{
     StateChanges, StateChangesExt, StateChangesKinds, StateChangesKindsExt, StateChangesRequest,
 };
 use near_primitives::utils::{get_block_shard_id, index_to_bytes, to_timestamp};
-use near_primitives::views::LightClientBlockView;
+use near_primitives::views::{
+    AccountHistoryItemKind, AccountHistoryItemView, LightClientBlockView,
+};
 use near_store::{
-    read_with_cache, ColBlock, ColBlockExtra, ColBlockHeader, ColBlockHeight, ColBlockInfo,
-    ColBlockMerkleTree, ColBlockMisc, ColBlockOrdinal, ColBlockPerHeight, ColBlockRefCount,
-    ColBlocksToCatchup, ColChallengedBlocks, ColChunkExtra, ColChunkHashesByHeight,
-    ColChunkPerHeightShard, ColChunks, ColEpochLightClientBlocks, ColGCCount,
-    ColHeaderHashesByHeight, ColIncomingReceipts, ColInvalidChunks, ColLastBlockWithNewChunk,
-    ColNextBlockHashes, ColNextBlockWithNewChunk, ColOutcomeIds, ColOutgoingReceipts,
-    ColPartialChunks, ColProcessedBlockHeights, ColReceiptIdToShardId, ColReceipts, ColState,
-    ColStateChanges, ColStateDlInfos, ColStateHeaders, ColStateParts, ColTransactionResult,
-    ColTransactions, ColTrieChanges, DBCol, KeyForStateChanges, ShardTries, Store, StoreUpdate,
-    TrieChanges, WrappedTrieChanges, CHUNK_TAIL_KEY, FINAL_HEAD_KEY, FORK_TAIL_KEY,
-    HEADER_HEAD_KEY, HEAD_KEY, LARGEST_TARGET_HEIGHT_KEY, LATEST_KNOWN_KEY, SHOULD_COL_GC,
-    TAIL_KEY,
+    read_with_cache, ColAccountHistory, ColBlock, ColBlockExtra, ColBlockHeader, ColBlockHeight,
+    ColBlockInfo, ColBlockMerkleTree, ColBlockMisc, ColBlockOrdinal, ColBlockPerHeight,
+    ColBlockRefCount, ColBlocksToCatchup, ColChallengedBlocks, ColChunkExtra,
+    ColChunkHashesByHeight, ColChunkPerHeightShard, ColChunks, ColEpochLightClientBlocks,
+    ColFlatStateDeltas, ColGCCount, ColHeaderHashesByHeight, ColIncomingReceipts, ColInvalidChunks,
+    ColLastBlockWithNewChunk, ColNextBlockHashes, ColNextBlockWithNewChunk, ColOutcomeIds,
+    ColOutgoingReceipts, ColPartialChunks, ColProcessedBlockHeights, ColReceiptGasProfiles,
+    ColReceiptIdToShardId, ColReceipts, ColState, ColStateChanges, ColStateDlInfos,
+    ColStateHeaders, ColStateParts, ColTransactionResult, ColTransactions, ColTrieChanges, DBCol,
+    KeyForStateChanges, ShardTries, Store, StoreUpdate, TrieChanges, WrappedTrieChanges,
+    CHUNK_TAIL_KEY, FINAL_HEAD_KEY, FORK_TAIL_KEY, HEADER_HEAD_KEY, HEAD_KEY,
+    LARGEST_TARGET_HEIGHT_KEY, LATEST_KNOWN_KEY, SHOULD_COL_GC, TAIL_KEY,
 };
 
 use crate::types::{Block, BlockHeader, LatestKnown};
@@ -68,6 +71,36 @@ pub enum GCMode {
     StateSync { clear_block_info: bool },
 }
 
+/// Separates account id from the rest of the key in `ColAccountHistory`. It is not allowed in
+/// account ids, so the prefix of one account never matches keys of another account.
+const ACCOUNT_HISTORY_SEPARATOR: u8 = b',';
+
+fn get_account_history_prefix(account_id: &AccountId) -> Vec<u8> {
+    let mut res = Vec::with_capacity(account_id.len() + 1);
+    res.extend_from_slice(account_id.as_bytes());
+    res.push(ACCOUNT_HISTORY_SEPARATOR);
+    res
+}
+
+/// Height is stored in big endian, so the history of an account is iterated in height order.
+fn get_account_history_key(
+    account_id: &AccountId,
+    height: BlockHeight,
+    id: &CryptoHash,
+) -> Vec<u8> {
+    let mut res = get_account_history_prefix(account_id);
+    res.extend_from_slice(&height.to_be_bytes());
+    res.extend_from_slice(id.as_ref());
+    res
+}
+
+fn get_receipt_gas_profile_key(block_hash: &CryptoHash, receipt_id: &CryptoHash) -> Vec<u8> {
+    let mut res = Vec::with_capacity(64);
+    res.extend_from_slice(block_hash.as_ref());
+    res.extend_from_slice(receipt_id.as_ref());
+    res
+}
+
 fn get_height_shard_id(height: BlockHeight, shard_id: ShardId) -> Vec<u8> {
     let mut res = Vec::with_capacity(40);
     res.extend_from_slice(&height.to_le_bytes());
@@ -395,6 +428,8 @@ pub struct ChainStore {
     block_ordinal_to_hash: SizedCache<Vec<u8>, CryptoHash>,
     /// Processed block heights.
     processed_block_heights: SizedCache<Vec<u8>, ()>,
+    /// Whether to index transactions and receipts of saved chunks by account.
+    save_account_history: bool,
 }
 
 pub fn option_to_not_found<T>(res: io::Result<Option<T>>, field_name: &str) -> Result<T, Error> {
@@ -438,9 +473,65 @@ impl ChainStore {
             block_merkle_tree: SizedCache::with_size(CACHE_SIZE),
             block_ordinal_to_hash: SizedCache::with_size(CACHE_SIZE),
             processed_block_heights: SizedCache::with_size(CACHE_SIZE),
+            save_account_history: false,
         }
     }
 
+    /// Enables maintaining of `ColAccountHistory` for blocks which become final from now on.
+    /// The index is only complete on archival nodes, which never garbage collect chunks.
+    pub fn set_save_account_history(&mut self, save_account_history: bool) {
+        self.save_account_history = save_account_history;
+    }
+
+    /// Returns up to `limit` transactions and receipts touching `account_id` in height order,
+    /// starting right after the `after` item if it is given.
+    pub fn get_account_history(
+        &self,
+        account_id: &AccountId,
+        after: Option<(BlockHeight, CryptoHash)>,
+        limit: usize,
+    ) -> Result<Vec<AccountHistoryItemView>, Error> {
+        let prefix = get_account_history_prefix(account_id);
+        let after_key = after.map(|(height, id)| get_account_history_key(account_id, height, &id));
+        let from = after_key.as_ref().unwrap_or(&prefix);
+        self.store
+            .iter_prefix_from(ColAccountHistory, &prefix, from)
+            // Only the `after` item itself may precede the rest of the page.
+            .skip_while(|(key, _)| Some(key.as_ref()) == after_key.as_deref())
+            .take(limit)
+            .map(|(key, value)| {
+                let rest = &key[prefix.len()..];
+                if rest.len() != 8 + 32 {
+                    return Err(ErrorKind::Other(format!(
+                        "Invalid account history key for {}",
+                        account_id
+                    ))
+                    .into());
+                }
+                let mut height = [0u8; 8];
+                height.copy_from_slice(&rest[..8]);
+                Ok(AccountHistoryItemView {
+                    block_height: BlockHeight::from_be_bytes(height),
+                    id: CryptoHash::try_from(&rest[8..])
+                        .map_err(|err| ErrorKind::Other(err.to_string()))?,
+                    kind: AccountHistoryItemKind::try_from_slice(&value)?,
+                })
+            })
+            .collect()
+    }
+
+    /// Returns the gas profiles of the actions of the receipt executed in the given block.
+    /// They are only recorded if the node records gas profiles and is built with `costs_counting`.
+    pub fn get_receipt_gas_profile(
+        &self,
+        block_hash: &CryptoHash,
+        receipt_id: &CryptoHash,
+    ) -> Result<Option<Vec<ActionGasProfile>>, Error> {
+        Ok(self
+            .store
+            .get_ser(ColReceiptGasProfiles, &get_receipt_gas_profile_key(block_hash, receipt_id))?)
+    }
+
     pub fn owned_store(&self) -> Arc<Store> {
         self.store.clone()
     }
@@ -452,11 +543,11 @@ impl ChainStore {
     pub fn iterate_state_sync_infos(&self) -> Vec<(CryptoHash, StateSyncInfo)> {
         self.store
             .iter(ColStateDlInfos)
-            .map(|(k, v)| {
-                (
-                    CryptoHash::try_from(k.as_ref()).unwrap(),
-                    StateSyncInfo::try_from_slice(v.as_ref()).unwrap(),
-                )
+            // State parts download progress is keyed by `StatePartKey`, skip it
+            .filter_map(|(k, v)| {
+                CryptoHash::try_from(k.as_ref())
+                    .ok()
+                    .map(|hash| (hash, StateSyncInfo::try_from_slice(v.as_ref()).unwrap()))
             })
             .collect()
     }
@@ -1148,6 +1239,7 @@ struct ChainStoreCacheUpdate {
     incoming_receipts: HashMap<(CryptoHash, ShardId), Vec<ReceiptProof>>,
     outcomes: HashMap<CryptoHash, Vec<ExecutionOutcomeWithIdAndProof>>,
     outcome_ids: HashMap<(CryptoHash, ShardId), Vec<CryptoHash>>,
+    receipt_gas_profiles: HashMap<(CryptoHash, CryptoHash), Vec<ActionGasProfile>>,
     invalid_chunks: HashMap<ChunkHash, EncodedShardChunk>,
     receipt_id_to_shard_id: HashMap<CryptoHash, ShardId>,
     next_block_with_new_chunk: HashMap<(CryptoHash, ShardId), CryptoHash>,
@@ -1939,6 +2031,18 @@ impl<'a> ChainStoreUpdate<'a> {
         self.chain_store_cache_update.outcome_ids.insert((*block_hash, shard_id), outcome_ids);
     }
 
+    pub fn save_receipt_gas_profiles(
+        &mut self,
+        block_hash: &CryptoHash,
+        gas_profiles: Vec<(CryptoHash, Vec<ActionGasProfile>)>,
+    ) {
+        for (receipt_id, gas_profile) in gas_profiles {
+            self.chain_store_cache_update
+                .receipt_gas_profiles
+                .insert((*block_hash, receipt_id), gas_profile);
+        }
+    }
+
     pub fn save_trie_changes(&mut self, trie_changes: WrappedTrieChanges) {
         self.trie_changes.push(trie_changes);
     }
@@ -2163,6 +2267,7 @@ impl<'a> ChainStoreUpdate<'a> {
             self.gc_col(ColChunkPerHeightShard, &block_shard_id);
             self.gc_col(ColNextBlockWithNewChunk, &block_shard_id);
             self.gc_col(ColChunkExtra, &block_shard_id);
+            self.gc_col(ColFlatStateDeltas, &block_shard_id);
 
             // For incoming State Parts it's done in chain.clear_downloaded_parts()
             // The following code is mostly for outgoing State Parts.
@@ -2285,6 +2390,7 @@ impl<'a> ChainStoreUpdate<'a> {
         for part_id in 0..num_parts {
             let key = StatePartKey(sync_hash, shard_id, part_id).try_to_vec()?;
             self.gc_col(ColStateParts, &key);
+            self.gc_col(ColStateDlInfos, &key);
         }
         Ok(())
     }
@@ -2333,6 +2439,10 @@ impl<'a> ChainStoreUpdate<'a> {
             for outcome_id in outcome_ids {
                 let mut outcomes_with_id = self.get_outcomes_by_id(&outcome_id)?;
                 outcomes_with_id.retain(|outcome| &outcome.block_hash != block_hash);
+                self.gc_col(
+                    ColReceiptGasProfiles,
+                    &get_receipt_gas_profile_key(block_hash, &outcome_id),
+                );
                 if outcomes_with_id.is_empty() {
                     self.gc_col(ColTransactionResult, &outcome_id.as_ref().into());
                 } else {
@@ -2441,6 +2551,12 @@ impl<'a> ChainStoreUpdate<'a> {
             DBCol::ColTrieChanges => {
                 store_update.delete(col, key);
             }
+            DBCol::ColFlatStateDeltas => {
+                store_update.delete(col, key);
+            }
+            DBCol::ColReceiptGasProfiles => {
+                store_update.delete(col, key);
+            }
             DBCol::ColBlockPerHeight => {
                 panic!("Must use gc_col_glock_per_height method to gc ColBlockPerHeight");
             }
@@ -2483,7 +2599,9 @@ impl<'a> ChainStoreUpdate<'a> {
             | DBCol::ColEpochValidatorInfo
             | DBCol::ColBlockOrdinal
             | DBCol::_ColTransactionRefCount
-            | DBCol::ColCachedContractCode => {
+            | DBCol::ColCachedContractCode
+            | DBCol::ColAccountHistory
+            | DBCol::ColFlatState => {
                 unreachable!();
             }
         }
@@ -2491,6 +2609,95 @@ impl<'a> ChainStoreUpdate<'a> {
         self.merge(store_update);
     }
 
+    /// Indexes transactions and receipts of the blocks which became final with `final_head`.
+    /// Blocks are only indexed once they are final, so chunks of abandoned forks never get into
+    /// the index.
+    fn save_account_history(
+        &mut self,
+        store_update: &mut StoreUpdate,
+        final_head: &Tip,
+    ) -> Result<(), Error> {
+        let prev_final_height = match self.chain_store.final_head() {
+            Ok(prev_final_head) => prev_final_head.height,
+            Err(_) => self.chain_store.genesis_height,
+        };
+        let mut block_hash = final_head.last_block_hash;
+        loop {
+            // Blocks before the state sync point are not available, there is nothing to index.
+            let block = match self.get_block(&block_hash) {
+                Ok(block) => block.clone(),
+                Err(_) => break,
+            };
+            if block.header().height() <= prev_final_height {
+                break;
+            }
+            self.save_block_account_history(store_update, &block)?;
+            block_hash = *block.header().prev_hash();
+        }
+        Ok(())
+    }
+
+    /// Indexes transactions and receipts of the chunks included in `block` at its height.
+    fn save_block_account_history(
+        &mut self,
+        store_update: &mut StoreUpdate,
+        block: &Block,
+    ) -> Result<(), Error> {
+        let height = block.header().height();
+        for chunk_header in block.chunks().iter() {
+            if chunk_header.height_included() != height {
+                continue;
+            }
+            // Chunks of shards the node doesn't track are not stored.
+            let chunk = match self.get_chunk(&chunk_header.chunk_hash()) {
+                Ok(chunk) => chunk.clone(),
+                Err(_) => continue,
+            };
+            for tx in chunk.transactions().iter() {
+                let tx_hash = tx.get_hash();
+                for account_id in [&tx.transaction.signer_id, &tx.transaction.receiver_id].iter() {
+                    store_update.set_ser(
+                        ColAccountHistory,
+                        &get_account_history_key(account_id, height, &tx_hash),
+                        &AccountHistoryItemKind::Transaction,
+                    )?;
+                }
+                // Transactions to the signer itself are converted into local receipts, which are
+                // executed right away and never appear among the receipts of a chunk.
+                if tx.transaction.signer_id == tx.transaction.receiver_id {
+                    let local_receipt_id = self
+                        .get_outcomes_by_id(&tx_hash)?
+                        .into_iter()
+                        .find(|outcome| &outcome.block_hash == block.hash())
+                        .and_then(|outcome| {
+                            outcome.outcome_with_id.outcome.receipt_ids.first().cloned()
+                        });
+                    if let Some(receipt_id) = local_receipt_id {
+                        store_update.set_ser(
+                            ColAccountHistory,
+                            &get_account_history_key(
+                                &tx.transaction.signer_id,
+                                height,
+                                &receipt_id,
+                            ),
+                            &AccountHistoryItemKind::Receipt,
+                        )?;
+                    }
+                }
+            }
+            for receipt in chunk.receipts().iter() {
+                for account_id in [&receipt.predecessor_id, &receipt.receiver_id].iter() {
+                    store_update.set_ser(
+                        ColAccountHistory,
+                        &get_account_history_key(account_id, height, &receipt.receipt_id),
+                        &AccountHistoryItemKind::Receipt,
+                    )?;
+                }
+            }
+        }
+        Ok(())
+    }
+
     /// Merge another StoreUpdate into this one
     pub fn merge(&mut self, store_update: StoreUpdate) {
         self.store_updates.push(store_update);
@@ -2514,6 +2721,11 @@ impl<'a> ChainStoreUpdate<'a> {
         Self::write_col_misc(&mut store_update, CHUNK_TAIL_KEY, &mut self.chunk_tail)?;
         Self::write_col_misc(&mut store_update, FORK_TAIL_KEY, &mut self.fork_tail)?;
         Self::write_col_misc(&mut store_update, HEADER_HEAD_KEY, &mut self.header_head)?;
+        if self.chain_store.save_account_history {
+            if let Some(final_head) = self.final_head.clone() {
+                self.save_account_history(&mut store_update, &final_head)?;
+            }
+        }
         Self::write_col_misc(&mut store_update, FINAL_HEAD_KEY, &mut self.final_head)?;
         Self::write_col_misc(
             &mut store_update,
@@ -2675,6 +2887,15 @@ impl<'a> ChainStoreUpdate<'a> {
                 &ids,
             )?;
         }
+        for ((block_hash, receipt_id), gas_profile) in
+            self.chain_store_cache_update.receipt_gas_profiles.iter()
+        {
+            store_update.set_ser(
+                ColReceiptGasProfiles,
+                &get_receipt_gas_profile_key(block_hash, receipt_id),
+                gas_profile,
+            )?;
+        }
         for (receipt_id, shard_id) in self.chain_store_cache_update.receipt_id_to_shard_id.iter() {
             let data = shard_id.try_to_vec()?;
             store_update.update_refcount(ColReceiptIdToShardId, receipt_id.as_ref(), &data, 1);
@@ -2956,15 +3177,16 @@ mod tests {
     use near_primitives::epoch_manager::BlockInfo;
     use near_primitives::errors::InvalidTxError;
     use near_primitives::hash::hash;
-    use near_primitives::types::{BlockHeight, EpochId, GCCount, NumBlocks};
+    use near_primitives::types::{AccountId, BlockHeight, EpochId, GCCount, NumBlocks};
     use near_primitives::utils::index_to_bytes;
     use near_primitives::validator_signer::InMemoryValidatorSigner;
+    use near_primitives::views::AccountHistoryItemKind;
     use near_store::test_utils::create_test_store;
-    use near_store::DBCol;
+    use near_store::{ColAccountHistory, DBCol};
     #[cfg(feature = "expensive_tests")]
     use {crate::store_validator::StoreValidator, near_chain_configs::GenesisConfig};
 
-    use crate::store::{ChainStoreAccess, GCMode};
+    use crate::store::{get_account_history_key, ChainStoreAccess, GCMode};
     use crate::test_utils::KeyValueRuntime;
     use crate::{Chain, ChainGenesis, DoomslugThresholdMode};
 
@@ -3182,6 +3404,48 @@ mod tests {
         assert_ne!(epoch_id_to_hash, epoch_id_to_hash1);
     }
 
+    #[test]
+    fn test_account_history_pagination() {
+        let chain = get_chain();
+        let store = chain.store().owned_store();
+        let alice: AccountId = "alice".to_string();
+        let alice_near: AccountId = "alice.near".to_string();
+        let mut store_update = store.store_update();
+        for (account_id, height, id, kind) in [
+            (&alice, 2, hash(&[2]), AccountHistoryItemKind::Receipt),
+            (&alice_near, 1, hash(&[3]), AccountHistoryItemKind::Transaction),
+            (&alice, 1, hash(&[1]), AccountHistoryItemKind::Transaction),
+            (&alice, 300, hash(&[4]), AccountHistoryItemKind::Transaction),
+        ]
+        .iter()
+        {
+            store_update
+                .set_ser(ColAccountHistory, &get_account_history_key(account_id, *height, id), kind)
+                .unwrap();
+        }
+        store_update.commit().unwrap();
+
+        let history = chain.store().get_account_history(&alice, None, 10).unwrap();
+        assert_eq!(
+            history.iter().map(|item| (item.block_height, item.id)).collect::<Vec<_>>(),
+            vec![(1, hash(&[1])), (2, hash(&[2])), (300, hash(&[4]))]
+        );
+        assert_eq!(history[1].kind, AccountHistoryItemKind::Receipt);
+
+        let first_page = chain.store().get_account_history(&alice, None, 2).unwrap();
+        let last = first_page.last().unwrap();
+        let second_page = chain
+            .store()
+            .get_account_history(&alice, Some((last.block_height, last.id)), 2)
+            .unwrap();
+        assert_eq!(first_page.len(), 2);
+        assert_eq!(second_page, vec![history[2].clone()]);
+
+        let history = chain.store().get_account_history(&alice_near, None, 10).unwrap();
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].id, hash(&[3]));
+    }
+
     /// Test that garbage collection works properly. The blocks behind gc head should be garbage
     /// collected while the blocks that are ahead of it should not.
     #[test]
@@ -13,12 +13,15 @@ use near_primitives::borsh;
 use near_primitives::epoch_manager::{BlockInfo, EpochInfo, AGGREGATOR_KEY};
 use near_primitives::hash::CryptoHash;
 use near_primitives::sharding::{ChunkHash, ShardChunk, StateSyncInfo};
-use near_primitives::syncing::{ShardStateSyncResponseHeader, StateHeaderKey, StatePartKey};
+use near_primitives::syncing::{
+    ShardStateSyncResponseHeader, StateHeaderKey, StatePartKey, StatePartSyncProgress,
+};
 use near_primitives::transaction::ExecutionOutcomeWithIdAndProof;
 use near_primitives::types::{AccountId, BlockHeight, ChunkExtra, EpochId, GCCount, ShardId};
 use near_primitives::utils::get_block_shard_id_rev;
 use near_store::{
-    decode_value_with_rc, DBCol, Store, TrieChanges, NUM_COLS, SHOULD_COL_GC, SKIP_COL_GC,
+    decode_value_with_rc, DBCol, FlatStateDelta, Store, TrieChanges, NUM_COLS, SHOULD_COL_GC,
+    SKIP_COL_GC,
 };
 use validate::StoreValidatorError;
 
@@ -211,6 +214,17 @@ impl StoreValidator {
                         col,
                     );
                 }
+                DBCol::ColFlatStateDeltas => {
+                    let (block_hash, shard_id) = get_block_shard_id_rev(key_ref)?;
+                    let delta = FlatStateDelta::try_from_slice(value_ref)?;
+                    // Block of the Flat State Delta should exist
+                    self.check(
+                        &validate::flat_state_delta_block_exists,
+                        &(block_hash, shard_id),
+                        &delta,
+                        col,
+                    );
+                }
                 DBCol::ColChunkHashesByHeight => {
                     let height = BlockHeight::try_from_slice(key_ref)?;
                     let chunk_hashes = HashSet::<ChunkHash>::try_from_slice(value_ref)?;
@@ -253,7 +267,7 @@ impl StoreValidator {
                         col,
                     );
                 }
-                DBCol::ColStateDlInfos => {
+                DBCol::ColStateDlInfos if key_ref.len() == std::mem::size_of::<CryptoHash>() => {
                     let block_hash = CryptoHash::try_from(key_ref)?;
                     let state_sync_info = StateSyncInfo::try_from_slice(value_ref)?;
                     // StateSyncInfo is valid
@@ -271,6 +285,12 @@ impl StoreValidator {
                         col,
                     );
                 }
+                DBCol::ColStateDlInfos => {
+                    let key = StatePartKey::try_from_slice(key_ref)?;
+                    let progress = StatePartSyncProgress::try_from_slice(value_ref)?;
+                    // State Header for the part being downloaded exists
+                    self.check(&validate::state_part_progress_header_exists, &key, &progress, col);
+                }
                 DBCol::ColBlockInfo => {
                     let block_hash = CryptoHash::try_from(key_ref)?;
                     let block_info = BlockInfo::try_from_slice(value_ref)?;
@@ -370,6 +390,10 @@ impl StoreValidator {
         if let Err(e) = validate::block_height_cmp_tail_final(self) {
             self.process_error(e, "TAIL", DBCol::ColBlockMisc)
         }
+        // Flat State should match the Trie at the Flat State Head
+        if let Err(e) = validate::flat_state_matches_trie_final(self) {
+            self.process_error(e, "FLAT_STATE", DBCol::ColFlatState)
+        }
         // Check GC counters
         if let Err(_) = validate::gc_col_count_final(self) {
             // TODO #2861
@@ -9,14 +9,16 @@ use near_primitives::hash::CryptoHash;
 use near_primitives::sharding::{ChunkHash, ShardChunk, StateSyncInfo};
 use near_primitives::syncing::{
     get_num_state_parts, ShardStateSyncResponseHeader, StateHeaderKey, StatePartKey,
+    StatePartSyncProgress,
 };
 use near_primitives::transaction::{ExecutionOutcomeWithIdAndProof, SignedTransaction};
 use near_primitives::types::{BlockHeight, ChunkExtra, EpochId, ShardId};
 use near_primitives::utils::{get_block_shard_id, index_to_bytes};
 use near_store::{
-    ColBlock, ColBlockHeader, ColBlockHeight, ColBlockInfo, ColBlockMisc, ColBlockPerHeight,
-    ColChunkExtra, ColChunkHashesByHeight, ColChunks, ColHeaderHashesByHeight, ColOutcomeIds,
-    ColStateHeaders, ColTransactionResult, DBCol, TrieChanges, TrieIterator, CHUNK_TAIL_KEY,
+    get_flat_state_head, get_flat_state_key, ColBlock, ColBlockHeader, ColBlockHeight,
+    ColBlockInfo, ColBlockMisc, ColBlockPerHeight, ColChunkExtra, ColChunkHashesByHeight,
+    ColChunks, ColFlatState, ColHeaderHashesByHeight, ColOutcomeIds, ColStateHeaders,
+    ColTransactionResult, DBCol, FlatStateDelta, TrieChanges, TrieIterator, CHUNK_TAIL_KEY,
     FORK_TAIL_KEY, HEADER_HEAD_KEY, HEAD_KEY, NUM_COLS, SHOULD_COL_GC, TAIL_KEY,
 };
 
@@ -590,6 +592,25 @@ pub(crate) fn trie_changes_chunk_extra_exists(
     err!("ShardChunk is not included into Block {:?}", block)
 }
 
+pub(crate) fn flat_state_delta_block_exists(
+    sv: &mut StoreValidator,
+    (block_hash, _shard_id): &(CryptoHash, ShardId),
+    delta: &FlatStateDelta,
+) -> Result<(), StoreValidatorError> {
+    // 1. Block Header with `block_hash` should be available
+    let header = unwrap_or_err_db!(
+        sv.store.get_ser::<BlockHeader>(ColBlockHeader, block_hash.as_ref()),
+        "Can't get Block Header from DB"
+    );
+    // 2. Delta should point to the previous Block
+    check_discrepancy!(
+        *header.prev_hash(),
+        delta.prev_block_hash,
+        "Flat State Delta prev block hash discrepancy"
+    );
+    Ok(())
+}
+
 pub(crate) fn chunk_of_height_exists(
     sv: &mut StoreValidator,
     height: &BlockHeight,
@@ -896,6 +917,28 @@ pub(crate) fn state_part_header_exists(
     Ok(())
 }
 
+pub(crate) fn state_part_progress_header_exists(
+    sv: &mut StoreValidator,
+    key: &StatePartKey,
+    progress: &StatePartSyncProgress,
+) -> Result<(), StoreValidatorError> {
+    let StatePartKey(block_hash, shard_id, part_id) = *key;
+    check_discrepancy!(progress.part_id, part_id, "Invalid part_id in StatePartSyncProgress");
+    let state_header_key = unwrap_or_err!(
+        StateHeaderKey(shard_id, block_hash).try_to_vec(),
+        "Can't serialize StateHeaderKey"
+    );
+    let header = unwrap_or_err_db!(
+        sv.store.get_ser::<ShardStateSyncResponseHeader>(ColStateHeaders, &state_header_key),
+        "Can't get StateHeaderKey from DB"
+    );
+    let num_parts = get_num_state_parts(header.state_root_node().memory_usage);
+    if part_id >= num_parts {
+        err!("Invalid part_id {:?}, num_parts {:?}", part_id, num_parts)
+    }
+    Ok(())
+}
+
 // Final checks
 
 pub(crate) fn block_height_cmp_tail_final(
@@ -934,6 +977,66 @@ pub(crate) fn gc_col_count_final(sv: &mut StoreValidator) -> Result<(), StoreVal
     err!("Suspicious, look into GC values manually")
 }
 
+pub(crate) fn flat_state_matches_trie_final(
+    sv: &mut StoreValidator,
+) -> Result<(), StoreValidatorError> {
+    for shard_id in 0..sv.runtime_adapter.num_shards() {
+        let flat_head = unwrap_or_err!(
+            get_flat_state_head(&sv.store, shard_id),
+            "Can't get Flat State Head of shard {:?}",
+            shard_id
+        );
+        let flat_head = match flat_head {
+            Some(flat_head) => flat_head,
+            None => continue,
+        };
+        // 1. Chunk Extra of the Flat State Head should be available
+        let chunk_extra = unwrap_or_err_db!(
+            sv.store
+                .get_ser::<ChunkExtra>(ColChunkExtra, &get_block_shard_id(&flat_head, shard_id)),
+            "Can't get Chunk Extra from storage with key {:?} {:?}",
+            flat_head,
+            shard_id
+        );
+        let trie = sv.runtime_adapter.get_trie_for_shard(shard_id);
+        let trie_iterator = unwrap_or_err!(
+            TrieIterator::new(&trie, &chunk_extra.state_root),
+            "Trie Node Missing for State Root of Flat State Head {:?}",
+            flat_head
+        );
+        // 2. Flat State should have exactly the same keys and values as the Trie
+        let prefix = get_flat_state_key(shard_id, &[]);
+        let mut flat_state_iterator = sv.store.iter_prefix(ColFlatState, &prefix);
+        for item in trie_iterator {
+            let (key, value) = unwrap_or_err!(item, "Can't iterate Trie of shard {:?}", shard_id);
+            match flat_state_iterator.next() {
+                Some((flat_key, flat_value)) => {
+                    check_discrepancy!(
+                        key.as_slice(),
+                        &flat_key[prefix.len()..],
+                        "Flat State key discrepancy in shard {:?}",
+                        shard_id
+                    );
+                    check_discrepancy!(
+                        value.as_slice(),
+                        &flat_value[..],
+                        "Flat State value discrepancy for key {:?}",
+                        key
+                    );
+                }
+                None => {
+                    err!("Key {:?} of shard {:?} is missing in Flat State", key, shard_id);
+                }
+            }
+        }
+        if let Some((flat_key, _)) = flat_state_iterator.next() {
+            let key = flat_key[prefix.len()..].to_vec();
+            err!("Key {:?} of shard {:?} is missing in Trie", key, shard_id);
+        }
+    }
+    Ok(())
+}
+
 pub(crate) fn tx_refcount_final(sv: &mut StoreValidator) -> Result<(), StoreValidatorError> {
     let len = sv.inner.tx_refcount.len();
     if len > 0 {
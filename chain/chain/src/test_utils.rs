@@ -20,6 +20,7 @@ use near_primitives::hash::{hash, CryptoHash};
 use near_primitives::receipt::{ActionReceipt, Receipt, ReceiptEnum};
 use near_primitives::serialize::to_base;
 use near_primitives::sharding::ChunkHash;
+use near_primitives::syncing::StatePartSyncProgress;
 use near_primitives::transaction::{
     Action, ExecutionOutcome, ExecutionOutcomeWithId, ExecutionStatus, SignedTransaction,
     TransferAction,
@@ -32,7 +33,7 @@ use near_primitives::validator_signer::InMemoryValidatorSigner;
 use near_primitives::version::{ProtocolVersion, PROTOCOL_VERSION};
 use near_primitives::views::{
     AccessKeyInfoView, AccessKeyList, CallResult, ContractCodeView, EpochValidatorInfo,
-    QueryRequest, QueryResponse, QueryResponseKind, ViewStateResult,
+    QueryRequest, QueryResponse, QueryResponseKind, ViewStatePageResult, ViewStateResult,
 };
 use near_store::test_utils::create_test_store;
 use near_store::{
@@ -533,6 +534,25 @@ impl RuntimeAdapter for KeyValueRuntime {
         false
     }
 
+    fn track_shards(
+        &self,
+        _account_ids: &[AccountId],
+        _shard_ids: &[ShardId],
+    ) -> Result<(), Error> {
+        Err(ErrorKind::Other("KeyValueRuntime tracks shards of its validators only".to_string())
+            .into())
+    }
+
+    fn untrack_shards(
+        &self,
+        _block_hash: &CryptoHash,
+        _account_ids: Vec<AccountId>,
+        _shard_ids: Vec<ShardId>,
+    ) -> Result<(), Error> {
+        Err(ErrorKind::Other("KeyValueRuntime tracks shards of its validators only".to_string())
+            .into())
+    }
+
     fn validate_tx(
         &self,
         _gas_price: Balance,
@@ -590,7 +610,7 @@ impl RuntimeAdapter for KeyValueRuntime {
         state_root: &StateRoot,
         _height: BlockHeight,
         _block_timestamp: u64,
-        _prev_block_hash: &CryptoHash,
+        prev_block_hash: &CryptoHash,
         block_hash: &CryptoHash,
         receipts: &[Receipt],
         transactions: &[SignedTransaction],
@@ -750,6 +770,7 @@ impl RuntimeAdapter for KeyValueRuntime {
                 shard_id,
                 TrieChanges::empty(state_root),
                 Default::default(),
+                *prev_block_hash,
                 block_hash.clone(),
             ),
             new_root: state_root,
@@ -759,6 +780,7 @@ impl RuntimeAdapter for KeyValueRuntime {
             total_gas_burnt: 0,
             total_balance_burnt: 0,
             proof: None,
+            gas_profiles: vec![],
         })
     }
 
@@ -841,6 +863,15 @@ impl RuntimeAdapter for KeyValueRuntime {
                 block_height,
                 block_hash: *block_hash,
             }),
+            QueryRequest::ViewStatePage { .. } => Ok(QueryResponse {
+                kind: QueryResponseKind::ViewStatePage(ViewStatePageResult {
+                    values: Default::default(),
+                    next_key: None,
+                    proof_nodes: vec![],
+                }),
+                block_height,
+                block_hash: *block_hash,
+            }),
             QueryRequest::CallFunction { .. } => Ok(QueryResponse {
                 kind: QueryResponseKind::CallResult(CallResult {
                     result: Default::default(),
@@ -899,6 +930,46 @@ impl RuntimeAdapter for KeyValueRuntime {
         Ok(())
     }
 
+    fn split_state_part(
+        &self,
+        _state_root: &StateRoot,
+        part: &[u8],
+    ) -> Result<Vec<Vec<u8>>, Error> {
+        Ok(vec![part.to_vec()])
+    }
+
+    fn apply_state_part_chunk(
+        &self,
+        shard_id: ShardId,
+        state_root: &StateRoot,
+        progress: &mut StatePartSyncProgress,
+        chunk: &[u8],
+    ) -> Result<StoreUpdate, Error> {
+        self.apply_state_part(shard_id, state_root, progress.part_id, 1, chunk)?;
+        progress.next_chunk_id += 1;
+        Ok(self.store.store_update())
+    }
+
+    fn release_applied_state_part(
+        &self,
+        _shard_id: ShardId,
+        _progress: &StatePartSyncProgress,
+    ) -> Result<StoreUpdate, Error> {
+        Ok(self.store.store_update())
+    }
+
+    fn validate_applied_state_part(
+        &self,
+        _shard_id: ShardId,
+        _state_root: &StateRoot,
+        part_id: u64,
+        num_parts: u64,
+    ) -> bool {
+        assert!(part_id < num_parts);
+        // We do not care about deeper validation in test_utils
+        true
+    }
+
     fn get_state_root_node(
         &self,
         _shard_id: ShardId,
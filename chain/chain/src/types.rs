@@ -17,8 +17,10 @@ use near_primitives::epoch_manager::{BlockInfo, EpochInfo};
 use near_primitives::errors::InvalidTxError;
 use near_primitives::hash::{hash, CryptoHash};
 use near_primitives::merkle::{merklize, MerklePath};
+use near_primitives::profile::ActionGasProfile;
 use near_primitives::receipt::Receipt;
 use near_primitives::sharding::{ChunkHash, ReceiptList, ShardChunkHeader};
+use near_primitives::syncing::StatePartSyncProgress;
 use near_primitives::transaction::{ExecutionOutcomeWithId, SignedTransaction};
 use near_primitives::types::{
     AccountId, ApprovalStake, Balance, BlockHeight, BlockHeightDelta, EpochId, Gas, MerkleHash,
@@ -86,6 +88,8 @@ pub struct ApplyTransactionResult {
     pub total_gas_burnt: Gas,
     pub total_balance_burnt: Balance,
     pub proof: Option<PartialStorage>,
+    /// Gas profiles of the actions of the executed receipts, empty unless costs are counted
+    pub gas_profiles: Vec<(CryptoHash, Vec<ActionGasProfile>)>,
 }
 
 impl ApplyTransactionResult {
@@ -433,6 +437,18 @@ pub trait RuntimeAdapter: Send + Sync {
         is_me: bool,
     ) -> bool;
 
+    /// Starts tracking given accounts and shards right away, in addition to the tracked ones.
+    fn track_shards(&self, account_ids: &[AccountId], shard_ids: &[ShardId]) -> Result<(), Error>;
+
+    /// Stops tracking given accounts and shards, starting from the epoch after the one
+    /// `block_hash` belongs to.
+    fn untrack_shards(
+        &self,
+        block_hash: &CryptoHash,
+        account_ids: Vec<AccountId>,
+        shard_ids: Vec<ShardId>,
+    ) -> Result<(), Error>;
+
     /// Returns true, if given hash is last block in it's epoch.
     fn is_next_block_epoch_start(&self, parent_hash: &CryptoHash) -> Result<bool, Error>;
 
@@ -615,6 +631,37 @@ pub trait RuntimeAdapter: Send + Sync {
         part: &[u8],
     ) -> Result<(), Error>;
 
+    /// Splits a state part into chunks which can be sent and applied one by one.
+    fn split_state_part(&self, state_root: &StateRoot, part: &[u8]) -> Result<Vec<Vec<u8>>, Error>;
+
+    /// Verifies the next chunk of a state part against the chunks applied before and returns
+    /// the store update writing it to the state. `progress` is advanced to the next chunk.
+    fn apply_state_part_chunk(
+        &self,
+        shard_id: ShardId,
+        state_root: &StateRoot,
+        progress: &mut StatePartSyncProgress,
+        chunk: &[u8],
+    ) -> Result<StoreUpdate, Error>;
+
+    /// Returns the store update releasing the nodes applied so far for a state part,
+    /// used when its download is abandoned.
+    fn release_applied_state_part(
+        &self,
+        shard_id: ShardId,
+        progress: &StatePartSyncProgress,
+    ) -> Result<StoreUpdate, Error>;
+
+    /// Checks that the state part applied by chunks is complete.
+    /// Returns false if some of its nodes are missing.
+    fn validate_applied_state_part(
+        &self,
+        shard_id: ShardId,
+        state_root: &StateRoot,
+        part_id: u64,
+        num_parts: u64,
+    ) -> bool;
+
     /// Returns StateRootNode of a state.
     /// Panics if requested hash is not in storage.
     /// Never returns Error
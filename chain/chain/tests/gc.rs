@@ -88,6 +88,7 @@ mod tests {
                     shard_id,
                     trie_changes,
                     Default::default(),
+                    *prev_block.hash(),
                     *block.hash(),
                 );
                 store_update.save_trie_changes(wrapped_trie_changes);
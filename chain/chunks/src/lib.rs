@@ -20,7 +20,7 @@ use near_network::types::{
     PartialEncodedChunkResponseMsg,
 };
 use near_network::NetworkRequests;
-use near_pool::{PoolIteratorWrapper, TransactionPool};
+use near_pool::{InsertTransactionResult, PoolConfig, PoolIteratorWrapper, TransactionPool};
 use near_primitives::block::{BlockHeader, Tip};
 use near_primitives::hash::{hash, CryptoHash};
 use near_primitives::merkle::{merklize, verify_path, MerklePath};
@@ -378,6 +378,7 @@ pub struct ShardsManager {
     me: Option<AccountId>,
 
     tx_pools: HashMap<ShardId, TransactionPool>,
+    pool_config: PoolConfig,
 
     runtime_adapter: Arc<dyn RuntimeAdapter>,
     network_adapter: Arc<dyn NetworkAdapter>,
@@ -395,10 +396,12 @@ impl ShardsManager {
         me: Option<AccountId>,
         runtime_adapter: Arc<dyn RuntimeAdapter>,
         network_adapter: Arc<dyn NetworkAdapter>,
+        pool_config: PoolConfig,
     ) -> Self {
         Self {
             me: me.clone(),
             tx_pools: HashMap::new(),
+            pool_config,
             runtime_adapter: runtime_adapter.clone(),
             network_adapter,
             encoded_chunks: EncodedChunksCache::new(),
@@ -770,9 +773,23 @@ impl ShardsManager {
         self.encoded_chunks.get_chunk_headers_for_block(&prev_block_hash)
     }
 
-    /// Returns true if transaction is not in the pool before call
-    pub fn insert_transaction(&mut self, shard_id: ShardId, tx: SignedTransaction) -> bool {
-        self.tx_pools.entry(shard_id).or_insert_with(TransactionPool::new).insert_transaction(tx)
+    pub fn insert_transaction(
+        &mut self,
+        shard_id: ShardId,
+        tx: SignedTransaction,
+    ) -> InsertTransactionResult {
+        let pool_config = &self.pool_config;
+        self.tx_pools
+            .entry(shard_id)
+            .or_insert_with(|| TransactionPool::new(pool_config.clone()))
+            .insert_transaction(tx)
+    }
+
+    /// Transactions in the pools of all shards, in no particular order.
+    pub fn pending_transactions(&self) -> impl Iterator<Item = (ShardId, &SignedTransaction)> {
+        self.tx_pools.iter().flat_map(|(shard_id, pool)| {
+            pool.transactions.values().flatten().map(move |tx| (*shard_id, tx))
+        })
     }
 
     pub fn remove_transactions(
@@ -790,9 +807,10 @@ impl ShardsManager {
         shard_id: ShardId,
         transactions: &Vec<SignedTransaction>,
     ) {
+        let pool_config = &self.pool_config;
         self.tx_pools
             .entry(shard_id)
-            .or_insert_with(TransactionPool::new)
+            .or_insert_with(|| TransactionPool::new(pool_config.clone()))
             .reintroduce_transactions(transactions.clone());
     }
 
@@ -1739,8 +1757,12 @@ mod test {
     fn test_request_partial_encoded_chunk_from_self() {
         let runtime_adapter = Arc::new(KeyValueRuntime::new(create_test_store()));
         let network_adapter = Arc::new(MockNetworkAdapter::default());
-        let mut shards_manager =
-            ShardsManager::new(Some("test".to_string()), runtime_adapter, network_adapter.clone());
+        let mut shards_manager = ShardsManager::new(
+            Some("test".to_string()),
+            runtime_adapter,
+            network_adapter.clone(),
+            PoolConfig::default(),
+        );
         shards_manager.requested_partial_encoded_chunks.insert(
             ChunkHash(hash(&[1])),
             ChunkRequestInfo {
@@ -1794,6 +1816,7 @@ mod test {
             Some("test".to_string()),
             runtime_adapter.clone(),
             network_adapter.clone(),
+            PoolConfig::default(),
         );
         let signer = InMemoryValidatorSigner::from_seed("test", KeyType::ED25519, "test");
         let mut rs = ReedSolomonWrapper::new(4, 10);
@@ -1959,6 +1982,7 @@ mod test {
             Some(fixture.mock_chunk_part_owner.clone()),
             fixture.mock_runtime.clone(),
             fixture.mock_network.clone(),
+            PoolConfig::default(),
         );
         let partial_encoded_chunk = fixture.make_partial_encoded_chunk(&fixture.mock_part_ords);
         let result = shards_manager
@@ -2015,6 +2039,7 @@ mod test {
             Some(fixture.mock_shard_tracker.clone()),
             fixture.mock_runtime.clone(),
             fixture.mock_network.clone(),
+            PoolConfig::default(),
         );
         let (most_parts, other_parts) = {
             let mut most_parts = fixture.mock_chunk_parts.clone();
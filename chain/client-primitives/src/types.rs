@@ -1,11 +1,13 @@
 #[cfg(feature = "metric_recorder")]
 use near_network::recorder::MetricRecorder;
 use std::collections::HashMap;
+use std::path::PathBuf;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 
 use actix::Message;
 use chrono::{DateTime, Utc};
+use futures::channel::oneshot;
 use serde::{Deserialize, Serialize};
 
 use near_chain_configs::ProtocolConfigView;
@@ -16,15 +18,16 @@ use near_primitives::hash::CryptoHash;
 use near_primitives::merkle::{MerklePath, PartialMerkleTree};
 use near_primitives::sharding::ChunkHash;
 use near_primitives::types::{
-    AccountId, BlockHeight, BlockReference, EpochReference, MaybeBlockId, ShardId,
-    TransactionOrReceiptId,
+    AccountId, BlockHeight, BlockReference, EpochReference, MaybeBlockId, Nonce, ShardId,
+    StateRoot, TransactionOrReceiptId,
 };
 use near_primitives::utils::generate_random_string;
 use near_primitives::views::{
-    BlockView, ChunkView, EpochValidatorInfo, ExecutionOutcomeWithIdView,
-    FinalExecutionOutcomeViewEnum, GasPriceView, LightClientBlockLiteView, LightClientBlockView,
-    QueryRequest, QueryResponse, ReceiptView, StateChangesKindsView, StateChangesRequestView,
-    StateChangesView, ValidatorStakeView,
+    AccountHistoryItemView, ActionGasProfileView, BlockView, ChunkView, EpochValidatorInfo,
+    ExecutionOutcomeWithIdView, FinalExecutionOutcomeViewEnum, GasPriceView,
+    LightClientBlockLiteView, LightClientBlockView, PendingTransactionView, QueryRequest,
+    QueryResponse, ReceiptView, StateChangesKindsView, StateChangesRequestView, StateChangesView,
+    StateDiffItemView, ValidatorStakeView,
 };
 pub use near_primitives::views::{StatusResponse, StatusSyncInfo};
 
@@ -579,3 +582,91 @@ impl From<near_chain_primitives::Error> for GetProtocolConfigError {
         }
     }
 }
+
+/// Creates a consistent snapshot of the node's store in a new directory.
+/// The snapshot is written on a separate thread, the returned receiver resolves once it's done.
+pub struct CreateStoreSnapshot {
+    pub path: PathBuf,
+}
+
+impl Message for CreateStoreSnapshot {
+    type Result = Result<oneshot::Receiver<Result<(), String>>, String>;
+}
+
+/// Runs garbage collection right away instead of waiting for the next head update.
+pub struct TriggerGc {}
+
+impl Message for TriggerGc {
+    type Result = Result<(), String>;
+}
+
+/// Changes the accounts and shards tracked by the node. Tracking starts immediately,
+/// while untracking takes effect from the next epoch.
+pub struct UpdateTrackedShards {
+    pub track: bool,
+    pub account_ids: Vec<AccountId>,
+    pub shard_ids: Vec<ShardId>,
+}
+
+impl Message for UpdateTrackedShards {
+    type Result = Result<(), String>;
+}
+
+/// Transactions and receipts touching an account, in the order of block heights.
+pub struct GetAccountHistory {
+    pub account_id: AccountId,
+    /// Height and id of the last item of the previous page.
+    pub after: Option<(BlockHeight, CryptoHash)>,
+    pub limit: usize,
+}
+
+impl Message for GetAccountHistory {
+    type Result = Result<Vec<AccountHistoryItemView>, String>;
+}
+
+/// Gas profiles of the actions of a receipt executed in the given block. `None` if they were not
+/// recorded, i.e. the node doesn't record gas profiles or is built without `costs_counting`.
+pub struct GetReceiptGasProfile {
+    pub block_hash: CryptoHash,
+    pub receipt_id: CryptoHash,
+}
+
+impl Message for GetReceiptGasProfile {
+    type Result = Result<Option<Vec<ActionGasProfileView>>, String>;
+}
+
+/// Records of a shard which differ between two state roots, in the order of trie keys.
+pub struct GetStateDiff {
+    pub shard_id: ShardId,
+    pub old_state_root: StateRoot,
+    pub new_state_root: StateRoot,
+    /// Raw trie key after which the records are returned.
+    pub after_key: Option<Vec<u8>>,
+    pub limit: usize,
+}
+
+pub struct GetStateDiffResponse {
+    pub changes: Vec<StateDiffItemView>,
+    /// Raw trie key of the last returned record if there are more records after it.
+    pub next_after_key: Option<Vec<u8>>,
+}
+
+impl Message for GetStateDiff {
+    type Result = Result<GetStateDiffResponse, String>;
+}
+
+/// Transactions waiting in the pools of the shards tracked by the node, ordered by shard, signer
+/// and nonce. Served from the snapshot of the pools periodically taken by the client.
+pub struct GetPendingTransactions {
+    /// Only return the transactions signed by this account.
+    pub signer_id: Option<AccountId>,
+    /// Only return the transaction with this hash.
+    pub transaction_hash: Option<CryptoHash>,
+    /// Shard, signer, nonce and hash of the last transaction of the previous page.
+    pub after: Option<(ShardId, AccountId, Nonce, CryptoHash)>,
+    pub limit: usize,
+}
+
+impl Message for GetPendingTransactions {
+    type Result = Result<Vec<PendingTransactionView>, String>;
+}
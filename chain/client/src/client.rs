@@ -24,6 +24,7 @@ use near_network::{
     FullPeerInfo, NetworkAdapter, NetworkClientResponses, NetworkRequests,
     EPOCH_SYNC_PEER_TIMEOUT_MS, EPOCH_SYNC_REQUEST_TIMEOUT_MS,
 };
+use near_pool::{InsertTransactionResult, PoolConfig};
 use near_primitives::block::{Approval, ApprovalInner, ApprovalMessage, Block, BlockHeader, Tip};
 use near_primitives::challenge::{Challenge, ChallengeBody};
 use near_primitives::hash::CryptoHash;
@@ -109,11 +110,23 @@ impl Client {
         } else {
             DoomslugThresholdMode::NoApprovals
         };
-        let chain = Chain::new(runtime_adapter.clone(), &chain_genesis, doomslug_threshold_mode)?;
+        let mut chain =
+            Chain::new(runtime_adapter.clone(), &chain_genesis, doomslug_threshold_mode)?;
+        if config.account_history_index {
+            if config.archive {
+                chain.mut_store().set_save_account_history(true);
+            } else {
+                warn!(target: "client", "Account history index requires an archival node");
+            }
+        }
         let shards_mgr = ShardsManager::new(
             validator_signer.as_ref().map(|x| x.validator_id().clone()),
             runtime_adapter.clone(),
             network_adapter.clone(),
+            PoolConfig {
+                max_size_bytes: config.transaction_pool_size_limit,
+                max_transactions_per_signer: config.transaction_pool_signer_limit,
+            },
         );
         let sync_status = SyncStatus::AwaitingPeers;
         let genesis_block = chain.genesis_block();
@@ -1502,7 +1515,8 @@ impl Client {
                     shard_id,
                     is_forwarded
                 );
-                self.shards_mgr.insert_transaction(shard_id, tx.clone());
+                let pool_full = self.shards_mgr.insert_transaction(shard_id, tx.clone())
+                    == InsertTransactionResult::NoSpaceLeft;
 
                 // Active validator:
                 //   possibly forward to next epoch validators
@@ -1510,6 +1524,10 @@ impl Client {
                 //   forward to current epoch validators,
                 //   possibly forward to next epoch validators
                 if active_validator {
+                    if pool_full {
+                        debug!(target: "client", "Transaction pool of shard {} is full, rejecting {}", shard_id, tx.get_hash());
+                        return Ok(NetworkClientResponses::TxPoolFull);
+                    }
                     if !is_forwarded {
                         self.possibly_forward_tx_to_next_epoch(tx)?;
                     }
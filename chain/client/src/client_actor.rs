@@ -1,6 +1,7 @@
 //! Client actor orchestrates Client and facilitates network connection.
 
 use std::collections::HashMap;
+use std::sync::atomic::Ordering;
 use std::sync::{Arc, RwLock};
 use std::thread;
 use std::time::{Duration, Instant};
@@ -29,7 +30,7 @@ use near_crypto::Signature;
 use near_network::recorder::MetricRecorder;
 #[cfg(feature = "adversarial")]
 use near_network::types::NetworkAdversarialMessage;
-use near_network::types::{NetworkInfo, ReasonForBan};
+use near_network::types::{NetworkInfo, PeerScoreEvent, ReasonForBan};
 use near_network::{
     NetworkAdapter, NetworkClientMessages, NetworkClientResponses, NetworkRequests,
 };
@@ -49,13 +50,15 @@ use near_telemetry::TelemetryActor;
 
 use crate::client::Client;
 use crate::info::{InfoHelper, ValidatorInfoHelper};
+use crate::metrics;
+use crate::pending_transactions::PendingTransactionsSnapshot;
 use crate::sync::{highest_height_peer, StateSync, StateSyncResult};
 #[cfg(feature = "adversarial")]
 use crate::AdversarialControls;
 use crate::StatusResponse;
 use near_client_primitives::types::{
     Error, GetNetworkInfo, NetworkInfoResponse, ShardSyncDownload, ShardSyncStatus, Status,
-    StatusSyncInfo, SyncStatus,
+    StatusSyncInfo, SyncStatus, TriggerGc, UpdateTrackedShards,
 };
 use near_primitives::block_header::ApprovalType;
 
@@ -66,6 +69,8 @@ const BLOCK_HORIZON: u64 = 500;
 /// `max_block_production_time` times this multiplier is how long we wait before rebroadcasting
 /// the current `head`
 const HEAD_STALL_MULTIPLIER: u32 = 4;
+/// How often the snapshot of the transaction pools served by the view client is refreshed.
+const PENDING_TRANSACTIONS_SNAPSHOT_PERIOD: Duration = Duration::from_secs(1);
 
 pub struct ClientActor {
     /// Adversarial controls
@@ -82,6 +87,8 @@ pub struct ClientActor {
     last_validator_announce_time: Option<Instant>,
     /// Info helper.
     info_helper: InfoHelper,
+    /// Snapshot of the transaction pools shared with the view client.
+    pending_transactions: Arc<RwLock<PendingTransactionsSnapshot>>,
 
     /// Last time handle_block_production method was called
     block_production_next_attempt: DateTime<Utc>,
@@ -121,6 +128,7 @@ impl ClientActor {
         validator_signer: Option<Arc<dyn ValidatorSigner>>,
         telemetry_actor: Addr<TelemetryActor>,
         enable_doomslug: bool,
+        pending_transactions: Arc<RwLock<PendingTransactionsSnapshot>>,
         #[cfg(feature = "adversarial")] adv: Arc<RwLock<AdversarialControls>>,
     ) -> Result<Self, Error> {
         wait_until_genesis(&chain_genesis.time);
@@ -158,6 +166,7 @@ impl ClientActor {
             },
             last_validator_announce_time: None,
             info_helper,
+            pending_transactions,
             block_production_next_attempt: now,
             block_production_started: false,
             doomslug_timer_next_attempt: now,
@@ -187,6 +196,9 @@ impl Actor for ClientActor {
 
         // Start periodic logging of current state of the client.
         self.log_summary(ctx);
+
+        // Start periodic refresh of the transaction pools snapshot.
+        self.update_pending_transactions(ctx);
     }
 }
 
@@ -434,8 +446,36 @@ impl Handler<NetworkClientMessages> for ClientActor {
                             }
                         }
                         ShardSyncStatus::StateDownloadParts => {
-                            if let Some(part) = state_response.take_part() {
-                                let num_parts = shard_sync_download.downloads.len() as u64;
+                            let num_parts = shard_sync_download.downloads.len() as u64;
+                            if let Some(part_chunk) = state_response.part_chunk() {
+                                let part_id = part_chunk.part_id;
+                                if part_id >= num_parts {
+                                    error!(target: "sync", "State sync received incorrect part_id # {:?} for hash {:?}, potential malicious peer", part_id, hash);
+                                    return NetworkClientResponses::NoResponse;
+                                }
+                                let part_download =
+                                    &mut shard_sync_download.downloads[part_id as usize];
+                                if !part_download.done {
+                                    match self
+                                        .client
+                                        .chain
+                                        .set_state_part_chunk(shard_id, hash, num_parts, part_chunk)
+                                    {
+                                        Ok(true) => {
+                                            part_download.done = true;
+                                        }
+                                        Ok(false) => {
+                                            // Request the next chunk of the part right away.
+                                            part_download.run_me.store(true, Ordering::SeqCst);
+                                            part_download.prev_update_time = Utc::now();
+                                        }
+                                        Err(err) => {
+                                            error!(target: "sync", "State sync set_state_part_chunk error, shard = {}, part = {}, chunk = {}, hash = {}: {:?}", shard_id, part_id, part_chunk.chunk_id, hash, err);
+                                            part_download.error = true;
+                                        }
+                                    }
+                                }
+                            } else if let Some(part) = state_response.take_part() {
                                 let (part_id, data) = part;
                                 if part_id >= num_parts {
                                     error!(target: "sync", "State sync received incorrect part_id # {:?} for hash {:?}, potential malicious peer", part_id, hash);
@@ -632,6 +672,44 @@ impl Handler<GetNetworkInfo> for ClientActor {
     }
 }
 
+impl Handler<TriggerGc> for ClientActor {
+    type Result = Result<(), String>;
+
+    #[perf]
+    fn handle(&mut self, _msg: TriggerGc, _ctx: &mut Context<Self>) -> Self::Result {
+        if self.client.config.archive {
+            return Err("Archival nodes don't garbage collect".to_string());
+        }
+        let timer = near_metrics::start_timer(&metrics::GC_TIME);
+        let result = self
+            .client
+            .chain
+            .clear_data(self.client.runtime_adapter.get_tries(), self.client.config.gc_blocks_limit)
+            .map_err(|err| err.to_string());
+        near_metrics::stop_timer(timer);
+        result
+    }
+}
+
+impl Handler<UpdateTrackedShards> for ClientActor {
+    type Result = Result<(), String>;
+
+    #[perf]
+    fn handle(&mut self, msg: UpdateTrackedShards, _ctx: &mut Context<Self>) -> Self::Result {
+        let UpdateTrackedShards { track, account_ids, shard_ids } = msg;
+        let runtime_adapter = &self.client.runtime_adapter;
+        let result = if track {
+            info!(target: "client", "Start tracking accounts {:?} and shards {:?}", account_ids, shard_ids);
+            runtime_adapter.track_shards(&account_ids, &shard_ids)
+        } else {
+            info!(target: "client", "Stop tracking accounts {:?} and shards {:?} from the next epoch", account_ids, shard_ids);
+            let head = self.client.chain.head().map_err(|err| err.to_string())?;
+            runtime_adapter.untrack_shards(&head.last_block_hash, account_ids, shard_ids)
+        };
+        result.map_err(|err| err.to_string())
+    }
+}
+
 impl ClientActor {
     fn sign_announce_account(&self, epoch_id: &EpochId) -> Result<Signature, ()> {
         if let Some(validator_signer) = self.client.validator_signer.as_ref() {
@@ -1035,11 +1113,27 @@ impl ClientActor {
 
     fn receive_headers(&mut self, headers: Vec<BlockHeader>, peer_id: PeerId) -> bool {
         info!(target: "client", "Received {} block headers from {}", headers.len(), peer_id);
+        // Only the response to the header sync request changes the score of the peer.
+        let requested = self.client.header_sync.take_headers_response(&peer_id);
         if headers.len() == 0 {
+            if requested {
+                self.network_adapter.do_send(NetworkRequests::UpdatePeerScore {
+                    peer_id,
+                    event: PeerScoreEvent::UselessResponse,
+                });
+            }
             return true;
         }
         match self.client.sync_block_headers(headers) {
-            Ok(_) => true,
+            Ok(_) => {
+                if requested {
+                    self.network_adapter.do_send(NetworkRequests::UpdatePeerScore {
+                        peer_id,
+                        event: PeerScoreEvent::HeadersDelivered,
+                    });
+                }
+                true
+            }
             Err(err) => {
                 if err.is_bad_data() {
                     error!(target: "client", "Error processing sync blocks: {}", err);
@@ -1311,6 +1405,12 @@ impl ClientActor {
                     })
                     .collect();
 
+                if just_enter_state_sync {
+                    unwrap_or_run_later!(self
+                        .client
+                        .chain
+                        .release_abandoned_state_parts(sync_hash));
+                }
                 if !self.client.config.archive && just_enter_state_sync {
                     unwrap_or_run_later!(self.client.chain.reset_data_pre_state_sync(sync_hash));
                 }
@@ -1400,6 +1500,23 @@ impl ClientActor {
         );
     }
 
+    /// Periodically replace the snapshot of the transaction pools served by the view client.
+    fn update_pending_transactions(&self, ctx: &mut Context<Self>) {
+        let snapshot =
+            PendingTransactionsSnapshot::new(self.client.shards_mgr.pending_transactions());
+        *self.pending_transactions.write().unwrap() = snapshot;
+
+        near_performance_metrics::actix::run_later(
+            ctx,
+            file!(),
+            line!(),
+            PENDING_TRANSACTIONS_SNAPSHOT_PERIOD,
+            move |act, ctx| {
+                act.update_pending_transactions(ctx);
+            },
+        );
+    }
+
     /// Periodically log summary.
     fn log_summary(&self, ctx: &mut Context<Self>) {
         near_performance_metrics::actix::run_later(
@@ -1447,6 +1564,9 @@ impl ClientActor {
                     &act.network_info,
                     validator_info,
                 );
+                if let Some(rocksdb) = act.client.chain.store().store().get_rocksdb() {
+                    rocksdb.update_column_metrics();
+                }
 
                 act.log_summary(ctx);
             },
@@ -1463,6 +1583,7 @@ pub fn start_client(
     network_adapter: Arc<dyn NetworkAdapter>,
     validator_signer: Option<Arc<dyn ValidatorSigner>>,
     telemetry_actor: Addr<TelemetryActor>,
+    pending_transactions: Arc<RwLock<PendingTransactionsSnapshot>>,
     #[cfg(feature = "adversarial")] adv: Arc<RwLock<AdversarialControls>>,
 ) -> (Addr<ClientActor>, ArbiterHandle) {
     let client_arbiter_handle = Arbiter::current();
@@ -1476,6 +1597,7 @@ pub fn start_client(
             validator_signer,
             telemetry_actor,
             true,
+            pending_transactions,
             #[cfg(feature = "adversarial")]
             adv,
         )
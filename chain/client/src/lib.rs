@@ -2,15 +2,18 @@
 extern crate lazy_static;
 
 pub use near_client_primitives::types::{
-    Error, GetBlock, GetBlockProof, GetBlockProofResponse, GetBlockWithMerkleTree, GetChunk,
-    GetExecutionOutcome, GetExecutionOutcomeResponse, GetExecutionOutcomesForBlock, GetGasPrice,
-    GetNetworkInfo, GetNextLightClientBlock, GetProtocolConfig, GetReceipt, GetStateChanges,
-    GetStateChangesInBlock, GetStateChangesWithCauseInBlock, GetValidatorInfo, GetValidatorOrdered,
-    Query, QueryError, Status, StatusResponse, SyncStatus, TxStatus, TxStatusError,
+    CreateStoreSnapshot, Error, GetAccountHistory, GetBlock, GetBlockProof, GetBlockProofResponse,
+    GetBlockWithMerkleTree, GetChunk, GetExecutionOutcome, GetExecutionOutcomeResponse,
+    GetExecutionOutcomesForBlock, GetGasPrice, GetNetworkInfo, GetNextLightClientBlock,
+    GetPendingTransactions, GetProtocolConfig, GetReceipt, GetReceiptGasProfile, GetStateChanges,
+    GetStateChangesInBlock, GetStateChangesWithCauseInBlock, GetStateDiff, GetStateDiffResponse,
+    GetValidatorInfo, GetValidatorOrdered, Query, QueryError, Status, StatusResponse, SyncStatus,
+    TriggerGc, TxStatus, TxStatusError, UpdateTrackedShards,
 };
 
 pub use crate::client::Client;
 pub use crate::client_actor::{start_client, ClientActor};
+pub use crate::pending_transactions::PendingTransactionsSnapshot;
 #[cfg(feature = "adversarial")]
 pub use crate::view_client::AdversarialControls;
 pub use crate::view_client::{start_view_client, ViewClientActor};
@@ -19,6 +22,7 @@ mod client;
 mod client_actor;
 mod info;
 mod metrics;
+mod pending_transactions;
 pub mod sync;
 pub mod test_utils;
 mod view_client;
@@ -30,4 +30,29 @@ lazy_static! {
         try_create_int_gauge("near_memory_usage_bytes", "Amount of RAM memory usage");
     pub static ref GC_TIME: near_metrics::Result<Histogram> =
         try_create_histogram("near_gc_time", "Time taken to do garbage collection");
+    pub static ref STATE_PART_CACHE_HIT_TOTAL: near_metrics::Result<IntCounter> =
+        try_create_int_counter(
+            "near_state_part_cache_hit_total",
+            "Number of state part requests served from the state parts cache"
+        );
+    pub static ref STATE_PART_CACHE_MISS_TOTAL: near_metrics::Result<IntCounter> =
+        try_create_int_counter(
+            "near_state_part_cache_miss_total",
+            "Number of state part requests which required computing the part"
+        );
+    pub static ref STATE_PART_SERVED_BYTES_TOTAL: near_metrics::Result<IntCounter> =
+        try_create_int_counter(
+            "near_state_part_served_bytes_total",
+            "Total size of state parts and chunks sent to syncing peers"
+        );
+    pub static ref STATE_REQUESTS_THROTTLED_TOTAL: near_metrics::Result<IntCounter> =
+        try_create_int_counter(
+            "near_state_requests_throttled_total",
+            "Number of state requests dropped because of the view client throttling"
+        );
+    pub static ref STATE_REQUESTS_PEER_QUOTA_EXCEEDED_TOTAL: near_metrics::Result<IntCounter> =
+        try_create_int_counter(
+            "near_state_requests_peer_quota_exceeded_total",
+            "Number of state requests dropped because the peer exceeded its quota"
+        );
 }
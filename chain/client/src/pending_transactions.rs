@@ -0,0 +1,147 @@
+//! Snapshot of the transaction pools shared by the client and the view clients.
+//!
+//! The client periodically replaces the snapshot with the current content of the pools, so the
+//! queries of pending transactions are served by the view clients without blocking the client.
+
+use std::collections::{BTreeMap, HashMap};
+use std::ops::Bound;
+
+use near_primitives::hash::CryptoHash;
+use near_primitives::transaction::SignedTransaction;
+use near_primitives::types::{AccountId, Nonce, ShardId};
+use near_primitives::views::PendingTransactionView;
+
+/// Position of a transaction in the snapshot, which is ordered by shard, signer and nonce.
+pub type PendingTransactionKey = (ShardId, AccountId, Nonce, CryptoHash);
+
+#[derive(Default)]
+pub struct PendingTransactionsSnapshot {
+    transactions: BTreeMap<PendingTransactionKey, SignedTransaction>,
+    by_hash: HashMap<CryptoHash, PendingTransactionKey>,
+}
+
+impl PendingTransactionsSnapshot {
+    pub fn new<'a>(transactions: impl Iterator<Item = (ShardId, &'a SignedTransaction)>) -> Self {
+        let mut snapshot = Self::default();
+        for (shard_id, tx) in transactions {
+            let tx_hash = tx.get_hash();
+            let key = (shard_id, tx.transaction.signer_id.clone(), tx.transaction.nonce, tx_hash);
+            snapshot.by_hash.insert(tx_hash, key.clone());
+            snapshot.transactions.insert(key, tx.clone());
+        }
+        snapshot
+    }
+
+    pub fn len(&self) -> usize {
+        self.transactions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.transactions.is_empty()
+    }
+
+    /// Returns up to `limit` transactions following `after`, optionally only those of the signer.
+    pub fn get(
+        &self,
+        signer_id: Option<&AccountId>,
+        after: Option<&PendingTransactionKey>,
+        limit: usize,
+    ) -> Vec<PendingTransactionView> {
+        let start = after.map_or(Bound::Unbounded, Bound::Excluded);
+        self.transactions
+            .range((start, Bound::Unbounded))
+            .filter(|((_, tx_signer_id, _, _), _)| {
+                signer_id.map_or(true, |signer_id| tx_signer_id == signer_id)
+            })
+            .take(limit)
+            .map(|((shard_id, _, _, _), tx)| PendingTransactionView {
+                shard_id: *shard_id,
+                transaction: tx.clone().into(),
+            })
+            .collect()
+    }
+
+    pub fn get_by_hash(&self, tx_hash: &CryptoHash) -> Option<PendingTransactionView> {
+        let key = self.by_hash.get(tx_hash)?;
+        Some(PendingTransactionView {
+            shard_id: key.0,
+            transaction: self.transactions[key].clone().into(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use near_crypto::{InMemorySigner, KeyType};
+
+    use super::*;
+
+    fn generate_transactions(signer_id: &str, nonces: &[Nonce]) -> Vec<SignedTransaction> {
+        let signer = Arc::new(InMemorySigner::from_seed(signer_id, KeyType::ED25519, signer_id));
+        nonces
+            .iter()
+            .map(|nonce| {
+                SignedTransaction::send_money(
+                    *nonce,
+                    signer_id.to_string(),
+                    "bob.near".to_string(),
+                    &*signer,
+                    1,
+                    CryptoHash::default(),
+                )
+            })
+            .collect()
+    }
+
+    fn nonces(transactions: &[PendingTransactionView]) -> Vec<(ShardId, Nonce)> {
+        transactions.iter().map(|tx| (tx.shard_id, tx.transaction.nonce)).collect()
+    }
+
+    fn key(transaction: &PendingTransactionView) -> PendingTransactionKey {
+        (
+            transaction.shard_id,
+            transaction.transaction.signer_id.clone(),
+            transaction.transaction.nonce,
+            transaction.transaction.hash,
+        )
+    }
+
+    #[test]
+    fn test_pending_transactions_pages() {
+        let alice_txs = generate_transactions("alice.near", &[3, 1, 2]);
+        let carol_txs = generate_transactions("carol.near", &[12, 11]);
+        let snapshot = PendingTransactionsSnapshot::new(
+            alice_txs.iter().map(|tx| (1, tx)).chain(carol_txs.iter().map(|tx| (0, tx))),
+        );
+        assert_eq!(snapshot.len(), 5);
+
+        let page = snapshot.get(None, None, 3);
+        assert_eq!(nonces(&page), vec![(0, 11), (0, 12), (1, 1)]);
+        let page = snapshot.get(None, Some(&key(page.last().unwrap())), 3);
+        assert_eq!(nonces(&page), vec![(1, 2), (1, 3)]);
+        assert!(snapshot.get(None, Some(&key(page.last().unwrap())), 3).is_empty());
+
+        let alice = "alice.near".to_string();
+        let page = snapshot.get(Some(&alice), None, 2);
+        assert_eq!(nonces(&page), vec![(1, 1), (1, 2)]);
+        let page = snapshot.get(Some(&alice), Some(&key(&page[1])), 2);
+        assert_eq!(nonces(&page), vec![(1, 3)]);
+
+        // The cursor doesn't have to be in the snapshot anymore.
+        let gone = (0, "carol.near".to_string(), 11, CryptoHash::default());
+        assert_eq!(nonces(&snapshot.get(None, Some(&gone), 1)), vec![(0, 12)]);
+    }
+
+    #[test]
+    fn test_pending_transaction_by_hash() {
+        let transactions = generate_transactions("alice.near", &[1, 2]);
+        let snapshot = PendingTransactionsSnapshot::new(transactions.iter().map(|tx| (2, tx)));
+        let found = snapshot.get_by_hash(&transactions[1].get_hash()).unwrap();
+        assert_eq!(found.shard_id, 2);
+        assert_eq!(found.transaction.nonce, 2);
+        assert!(snapshot.get_by_hash(&CryptoHash::default()).is_none());
+        assert!(PendingTransactionsSnapshot::default().is_empty());
+    }
+}
@@ -13,9 +13,12 @@ use rand::seq::{IteratorRandom, SliceRandom};
 use rand::{thread_rng, Rng};
 
 use near_chain::{Chain, RuntimeAdapter};
-use near_network::types::{AccountOrPeerIdOrHash, NetworkResponses, ReasonForBan};
+use near_network::types::{
+    peer_score_weight, AccountOrPeerIdOrHash, NetworkResponses, PeerScoreEvent, ReasonForBan,
+};
 use near_network::{FullPeerInfo, NetworkAdapter, NetworkRequests};
 use near_primitives::block::Tip;
+use near_primitives::checked_feature;
 use near_primitives::hash::CryptoHash;
 use near_primitives::network::PeerId;
 use near_primitives::syncing::get_num_state_parts;
@@ -45,16 +48,28 @@ pub const MAX_PENDING_PART: u64 = MAX_STATE_PART_REQUEST * 10000;
 
 pub const NS_PER_SECOND: u128 = 1_000_000_000;
 
-/// Get random peer from the hightest height peers.
+/// Get random peer from the hightest height peers, preferring peers with higher reputation score.
 pub fn highest_height_peer(highest_height_peers: &Vec<FullPeerInfo>) -> Option<FullPeerInfo> {
-    if highest_height_peers.len() == 0 {
-        return None;
-    }
+    highest_height_peers
+        .choose_weighted(&mut thread_rng(), |peer| peer_score_weight(peer.score))
+        .ok()
+        .cloned()
+}
 
-    match highest_height_peers.iter().choose(&mut thread_rng()) {
-        None => highest_height_peers.choose(&mut thread_rng()).cloned(),
-        Some(peer) => Some(peer.clone()),
-    }
+/// Weight of the state sync target. Validators are picked by account, so they get the weight
+/// of a peer with neutral score.
+fn state_sync_target_weight(
+    target: &AccountOrPeerIdOrHash,
+    highest_height_peers: &[FullPeerInfo],
+) -> u64 {
+    let score = match target {
+        AccountOrPeerIdOrHash::PeerId(peer_id) => highest_height_peers
+            .iter()
+            .find(|peer| &peer.peer_info.id == peer_id)
+            .map_or(0, |peer| peer.score),
+        _ => 0,
+    };
+    peer_score_weight(score)
 }
 
 /// Helper to keep track of the Epoch Sync
@@ -136,6 +151,8 @@ pub struct HeaderSync {
     history_locator: Vec<(BlockHeight, CryptoHash)>,
     prev_header_sync: (DateTime<Utc>, BlockHeight, BlockHeight, BlockHeight),
     syncing_peer: Option<FullPeerInfo>,
+    /// Peer we've requested headers from and haven't got the response yet.
+    awaiting_headers_from: Option<PeerId>,
     stalling_ts: Option<DateTime<Utc>>,
 
     initial_timeout: Duration,
@@ -157,6 +174,7 @@ impl HeaderSync {
             history_locator: vec![],
             prev_header_sync: (Utc::now(), 0, 0, 0),
             syncing_peer: None,
+            awaiting_headers_from: None,
             stalling_ts: None,
             initial_timeout: Duration::from_std(initial_timeout).unwrap(),
             progress_timeout: Duration::from_std(progress_timeout).unwrap(),
@@ -279,6 +297,12 @@ impl HeaderSync {
                             }
                             _ => (),
                         }
+                        if self.awaiting_headers_from.as_ref() == Some(&peer.peer_info.id) {
+                            self.network_adapter.do_send(NetworkRequests::UpdatePeerScore {
+                                peer_id: peer.peer_info.id.clone(),
+                                event: PeerScoreEvent::Timeout,
+                            });
+                        }
                     }
                 }
             }
@@ -305,6 +329,17 @@ impl HeaderSync {
         }
     }
 
+    /// Whether the headers received from the peer are the response to our last request.
+    /// Each request is answered only once, so repeated or unsolicited headers return false.
+    pub fn take_headers_response(&mut self, peer_id: &PeerId) -> bool {
+        if self.awaiting_headers_from.as_ref() == Some(peer_id) {
+            self.awaiting_headers_from = None;
+            true
+        } else {
+            false
+        }
+    }
+
     /// Request headers from a given peer to advance the chain.
     fn request_headers(&mut self, chain: &mut Chain, peer: FullPeerInfo) -> Option<FullPeerInfo> {
         if let Ok(locator) = self.get_locator(chain) {
@@ -313,6 +348,7 @@ impl HeaderSync {
                 hashes: locator,
                 peer_id: peer.peer_info.id.clone(),
             });
+            self.awaiting_headers_from = Some(peer.peer_info.id.clone());
             return Some(peer);
         }
         None
@@ -689,19 +725,33 @@ impl StateSync {
                         let shard_state_header = chain.get_state_header(shard_id, sync_hash)?;
                         let state_num_parts =
                             get_num_state_parts(shard_state_header.state_root_node().memory_usage);
+                        let mut downloads = vec![
+                            DownloadStatus {
+                                start_time: now,
+                                prev_update_time: now,
+                                run_me: Arc::new(AtomicBool::new(true)),
+                                error: false,
+                                done: false,
+                                state_requests_count: 0,
+                                last_target: None,
+                            };
+                            state_num_parts as usize
+                        ];
+                        // Parts applied before a restart don't have to be downloaded again.
+                        for (part_id, download) in downloads.iter_mut().enumerate() {
+                            if let Some(progress) = chain.get_state_part_progress(
+                                shard_id,
+                                sync_hash,
+                                part_id as u64,
+                            )? {
+                                if progress.is_complete() {
+                                    download.run_me.store(false, Ordering::SeqCst);
+                                    download.done = true;
+                                }
+                            }
+                        }
                         *shard_sync_download = ShardSyncDownload {
-                            downloads: vec![
-                                DownloadStatus {
-                                    start_time: now,
-                                    prev_update_time: now,
-                                    run_me: Arc::new(AtomicBool::new(true)),
-                                    error: false,
-                                    done: false,
-                                    state_requests_count: 0,
-                                    last_target: None,
-                                };
-                                state_num_parts as usize
-                            ],
+                            downloads,
                             status: ShardSyncStatus::StateDownloadParts,
                         };
                         need_shard = true;
@@ -764,7 +814,7 @@ impl StateSync {
                             error!(target: "sync", "State sync finalizing error, shard = {}, hash = {}: {:?}", shard_id, sync_hash, e);
                             update_sync_status = true;
                             *shard_sync_download = init_sync_download.clone();
-                            chain.clear_downloaded_parts(shard_id, sync_hash, state_num_parts)?;
+                            chain.abandon_downloaded_parts(shard_id, sync_hash, state_num_parts)?;
                         }
                     }
                 }
@@ -964,7 +1014,12 @@ impl StateSync {
 
         match shard_sync_download.status {
             ShardSyncStatus::StateDownloadHeader => {
-                let target = possible_targets.choose(&mut thread_rng()).cloned().unwrap();
+                let target = possible_targets
+                    .choose_weighted(&mut thread_rng(), |target| {
+                        state_sync_target_weight(target, highest_height_peers)
+                    })
+                    .cloned()
+                    .unwrap();
                 assert!(new_shard_sync_download.downloads[0].run_me.load(Ordering::SeqCst));
                 new_shard_sync_download.downloads[0].run_me.store(false, Ordering::SeqCst);
                 new_shard_sync_download.downloads[0].state_requests_count += 1;
@@ -986,13 +1041,19 @@ impl StateSync {
                 );
             }
             ShardSyncStatus::StateDownloadParts => {
+                let weights = possible_targets
+                    .iter()
+                    .map(|target| state_sync_target_weight(target, highest_height_peers))
+                    .collect();
                 let possible_targets_sampler =
-                    SamplerLimited::new(possible_targets, MAX_STATE_PART_REQUEST);
+                    SamplerLimited::new(possible_targets, weights, MAX_STATE_PART_REQUEST);
+                let epoch_id = chain.get_block_header(&sync_hash)?.epoch_id().clone();
+                let protocol_version = runtime_adapter.get_epoch_protocol_version(&epoch_id)?;
 
                 // Iterate over all parts that needs to be requested (i.e. download.run_me is true).
                 // Parts are ordered such that its index match its part_id.
-                // Finally, for every part that needs to be requested it is selected one peer (target) randomly
-                // to request the part from
+                // Finally, for every part that needs to be requested it is selected one peer (target) randomly,
+                // weighted by its score, to request the part from
                 for ((part_id, download), target) in new_shard_sync_download
                     .downloads
                     .iter_mut()
@@ -1000,6 +1061,33 @@ impl StateSync {
                     .filter(|(_, download)| download.run_me.load(Ordering::SeqCst))
                     .zip(possible_targets_sampler)
                 {
+                    let request = checked_feature!(
+                        "protocol_feature_state_part_chunks",
+                        StatePartChunks,
+                        protocol_version,
+                        {
+                            // Parts are downloaded chunk by chunk, continue from the first chunk
+                            // which isn't applied yet.
+                            let chunk_id = chain
+                                .get_state_part_progress(shard_id, sync_hash, part_id as u64)?
+                                .map_or(0, |progress| progress.next_chunk_id);
+                            NetworkRequests::StateRequestPartChunk {
+                                shard_id,
+                                sync_hash,
+                                part_id: part_id as u64,
+                                chunk_id,
+                                target: target.clone(),
+                            }
+                        },
+                        {
+                            NetworkRequests::StateRequestPart {
+                                shard_id,
+                                sync_hash,
+                                part_id: part_id as u64,
+                                target: target.clone(),
+                            }
+                        }
+                    );
                     self.sent_request_part(target.clone(), part_id as u64, shard_id, sync_hash);
                     download.run_me.store(false, Ordering::SeqCst);
                     download.state_requests_count += 1;
@@ -1010,20 +1098,13 @@ impl StateSync {
                         std::any::type_name::<Self>(),
                         file!(),
                         line!(),
-                        self.network_adapter
-                            .send(NetworkRequests::StateRequestPart {
-                                shard_id,
-                                sync_hash,
-                                part_id: part_id as u64,
-                                target: target.clone(),
-                            })
-                            .then(move |result| {
-                                if let Ok(NetworkResponses::RouteNotFound) = result {
-                                    // Send a StateRequestPart on the next iteration
-                                    run_me.store(true, Ordering::SeqCst);
-                                }
-                                future::ready(())
-                            }),
+                        self.network_adapter.send(request).then(move |result| {
+                            if let Ok(NetworkResponses::RouteNotFound) = result {
+                                // Send a StateRequestPart on the next iteration
+                                run_me.store(true, Ordering::SeqCst);
+                            }
+                            future::ready(())
+                        }),
                     );
                 }
             }
@@ -1085,10 +1166,11 @@ impl StateSync {
 
 /// Create an abstract collection of elements to be shuffled.
 /// Each element will appear in the shuffled output exactly `limit` times.
+/// Elements with higher weight are more likely to appear earlier.
 /// Use it as an iterator to access the shuffled collection.
 ///
 /// ```rust,ignore
-/// let sampler = SamplerLimited::new(vec![1, 2, 3], 2);
+/// let sampler = SamplerLimited::new(vec![1, 2, 3], vec![1, 1, 1], 2);
 ///
 /// let res = sampler.collect::<Vec<_>>();
 ///
@@ -1105,18 +1187,36 @@ impl StateSync {
 /// ```
 struct SamplerLimited<T> {
     data: Vec<T>,
+    weights: Vec<u64>,
     limit: Vec<u64>,
 }
 
 impl<T> SamplerLimited<T> {
-    fn new(data: Vec<T>, limit: u64) -> Self {
+    fn new(data: Vec<T>, weights: Vec<u64>, limit: u64) -> Self {
+        assert_eq!(data.len(), weights.len());
         if limit == 0 {
-            Self { data: vec![], limit: vec![] }
+            Self { data: vec![], weights: vec![], limit: vec![] }
         } else {
             let len = data.len();
-            Self { data, limit: vec![limit; len] }
+            Self { data, weights, limit: vec![limit; len] }
         }
     }
+
+    /// Index of a random element, picked with probability proportional to its weight.
+    fn sample_index(&self) -> usize {
+        let total_weight: u64 = self.weights.iter().sum();
+        if total_weight == 0 {
+            return thread_rng().gen_range(0, self.weights.len());
+        }
+        let mut point = thread_rng().gen_range(0, total_weight);
+        for (ix, weight) in self.weights.iter().enumerate() {
+            if point < *weight {
+                return ix;
+            }
+            point -= weight;
+        }
+        unreachable!()
+    }
 }
 
 impl<T: Clone> Iterator for SamplerLimited<T> {
@@ -1127,16 +1227,18 @@ impl<T: Clone> Iterator for SamplerLimited<T> {
             None
         } else {
             let len = self.limit.len();
-            let ix = thread_rng().gen_range(0, len);
+            let ix = self.sample_index();
             self.limit[ix] -= 1;
 
             if self.limit[ix] == 0 {
                 if ix + 1 != len {
                     self.limit[ix] = self.limit[len - 1];
+                    self.weights.swap(ix, len - 1);
                     self.data.swap(ix, len - 1);
                 }
 
                 self.limit.pop();
+                self.weights.pop();
                 self.data.pop()
             } else {
                 Some(self.data[ix].clone())
@@ -1155,7 +1257,7 @@ mod test {
     use near_crypto::{KeyType, PublicKey};
     use near_network::routing::EdgeInfo;
     use near_network::test_utils::MockNetworkAdapter;
-    use near_network::types::PeerChainInfoV2;
+    use near_network::types::{PeerChainInfoV2, MAX_PEER_SCORE, MIN_PEER_SCORE};
     use near_network::PeerInfo;
     use near_primitives::block::{Approval, Block, GenesisId};
     use near_primitives::network::PeerId;
@@ -1228,6 +1330,7 @@ mod test {
                 archival: false,
             },
             edge_info: EdgeInfo::default(),
+            score: 0,
         };
         let head = chain.head().unwrap();
         assert!(header_sync
@@ -1242,9 +1345,13 @@ mod test {
                     .iter()
                     .map(|i| *chain.get_block_by_height(*i).unwrap().hash())
                     .collect(),
-                peer_id: peer1.peer_info.id
+                peer_id: peer1.peer_info.id.clone()
             }
         );
+        // Only the first response of the requested peer is counted.
+        assert!(!header_sync.take_headers_response(&PeerInfo::random().id));
+        assert!(header_sync.take_headers_response(&peer1.peer_info.id));
+        assert!(!header_sync.take_headers_response(&peer1.peer_info.id));
     }
 
     /// Sets up `HeaderSync` with particular tolerance for slowness, and makes sure that a peer that
@@ -1273,9 +1380,11 @@ mod test {
                     id: PeerId::new(PublicKey::empty(KeyType::ED25519)),
                     addr: None,
                     account_id: None,
+                    extra_addrs: vec![],
                 },
                 chain_info: Default::default(),
                 edge_info: Default::default(),
+                score: 0,
             });
             header_sync.syncing_peer.as_mut().unwrap().chain_info.height = highest_height;
         };
@@ -1424,13 +1533,43 @@ mod test {
                     id: PeerId::new(PublicKey::empty(KeyType::ED25519)),
                     addr: None,
                     account_id: None,
+                    extra_addrs: vec![],
                 },
                 chain_info: Default::default(),
                 edge_info: Default::default(),
+                score: 0,
             })
             .collect()
     }
 
+    #[test]
+    fn test_highest_height_peer_score() {
+        assert!(highest_height_peer(&vec![]).is_none());
+        let mut peers = create_peer_infos(2);
+        peers[0].score = MAX_PEER_SCORE;
+        peers[1].score = MIN_PEER_SCORE;
+        let num_good = (0..1000)
+            .filter(|_| highest_height_peer(&peers).unwrap().score == MAX_PEER_SCORE)
+            .count();
+        assert!(num_good > 900);
+    }
+
+    #[test]
+    fn test_sampler_limited_weights() {
+        let mut num_heavy_first = 0;
+        for _ in 0..100 {
+            let sampled =
+                SamplerLimited::new(vec![0, 1, 2], vec![1000, 1, 0], 2).collect::<Vec<_>>();
+            let mut sorted = sampled.clone();
+            sorted.sort();
+            assert_eq!(sorted, vec![0, 0, 1, 1, 2, 2]);
+            if sampled[0] == 0 {
+                num_heavy_first += 1;
+            }
+        }
+        assert!(num_heavy_first > 90);
+    }
+
     #[test]
     fn test_block_sync() {
         let network_adapter = Arc::new(MockNetworkAdapter::default());
@@ -43,7 +43,10 @@ use near_telemetry::TelemetryActor;
 
 #[cfg(feature = "adversarial")]
 use crate::AdversarialControls;
-use crate::{start_view_client, Client, ClientActor, SyncStatus, ViewClientActor};
+use crate::{
+    start_view_client, Client, ClientActor, PendingTransactionsSnapshot, SyncStatus,
+    ViewClientActor,
+};
 use near_network::test_utils::MockNetworkAdapter;
 use near_primitives::merkle::{merklize, MerklePath};
 use near_primitives::receipt::Receipt;
@@ -115,6 +118,7 @@ pub fn setup(
 
     #[cfg(feature = "adversarial")]
     let adv = Arc::new(RwLock::new(AdversarialControls::default()));
+    let pending_transactions = Arc::new(RwLock::new(PendingTransactionsSnapshot::default()));
 
     let view_client_addr = start_view_client(
         Some(signer.validator_id().clone()),
@@ -122,6 +126,7 @@ pub fn setup(
         runtime.clone(),
         network_adapter.clone(),
         config.clone(),
+        pending_transactions.clone(),
         #[cfg(feature = "adversarial")]
         adv.clone(),
     );
@@ -135,6 +140,7 @@ pub fn setup(
         Some(signer),
         telemetry,
         enable_doomslug,
+        pending_transactions,
         #[cfg(feature = "adversarial")]
         adv,
     )
@@ -469,6 +475,7 @@ pub fn setup_mock_all_validators(
                                     archival: true,
                                 },
                                 edge_info: EdgeInfo::default(),
+                                score: 0,
                             })
                             .collect();
                         let peers2 = peers.clone();
@@ -711,6 +718,7 @@ pub fn setup_mock_all_validators(
                                             .send(NetworkViewClientMessages::StateRequestHeader {
                                                 shard_id: *shard_id,
                                                 sync_hash: *sync_hash,
+                                                peer_id: my_key_pair.id.clone(),
                                             })
                                             .then(move |response| {
                                                 let response = response.unwrap();
@@ -755,6 +763,7 @@ pub fn setup_mock_all_validators(
                                                 shard_id: *shard_id,
                                                 sync_hash: *sync_hash,
                                                 part_id: *part_id,
+                                                peer_id: my_key_pair.id.clone(),
                                             })
                                             .then(move |response| {
                                                 let response = response.unwrap();
@@ -779,6 +788,56 @@ pub fn setup_mock_all_validators(
                                 }
                             }
                         }
+                        #[cfg(feature = "protocol_feature_state_part_chunks")]
+                        NetworkRequests::StateRequestPartChunk {
+                            shard_id,
+                            sync_hash,
+                            part_id,
+                            chunk_id,
+                            target: target_account_id,
+                        } => {
+                            let target_account_id = match target_account_id {
+                                AccountOrPeerIdOrHash::AccountId(x) => x,
+                                _ => panic!(),
+                            };
+                            for (i, name) in validators_clone2.iter().flatten().enumerate() {
+                                if name == target_account_id {
+                                    let connectors2 = connectors1.clone();
+                                    actix::spawn(
+                                        connectors1.read().unwrap()[i]
+                                            .1
+                                            .send(
+                                                NetworkViewClientMessages::StateRequestPartChunk {
+                                                    shard_id: *shard_id,
+                                                    sync_hash: *sync_hash,
+                                                    part_id: *part_id,
+                                                    chunk_id: *chunk_id,
+                                                    peer_id: my_key_pair.id.clone(),
+                                                },
+                                            )
+                                            .then(move |response| {
+                                                let response = response.unwrap();
+                                                match response {
+                                                    NetworkViewClientResponses::StateResponse(
+                                                        response,
+                                                    ) => {
+                                                        connectors2.read().unwrap()[my_ord]
+                                                            .0
+                                                            .do_send(
+                                                            NetworkClientMessages::StateResponse(
+                                                                *response,
+                                                            ),
+                                                        );
+                                                    }
+                                                    NetworkViewClientResponses::NoResponse => {}
+                                                    _ => assert!(false),
+                                                }
+                                                future::ready(())
+                                            }),
+                                    );
+                                }
+                            }
+                        }
                         NetworkRequests::StateResponse { route_back, response } => {
                             for (i, address) in addresses.iter().enumerate() {
                                 if route_back == address {
@@ -873,6 +932,10 @@ pub fn setup_mock_all_validators(
                         | NetworkRequests::PingTo(_, _)
                         | NetworkRequests::FetchPingPongInfo
                         | NetworkRequests::BanPeer { .. }
+                        | NetworkRequests::UnbanPeer { .. }
+                        | NetworkRequests::UpdatePeerScore { .. }
+                        | NetworkRequests::AddBootNode(_)
+                        | NetworkRequests::RemoveBootNode(_)
                         | NetworkRequests::TxStatus(_, _, _)
                         | NetworkRequests::Query { .. }
                         | NetworkRequests::Challenge(_)
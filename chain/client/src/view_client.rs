@@ -9,7 +9,9 @@ use std::sync::{Arc, Mutex, RwLock};
 use std::time::{Duration, Instant};
 
 use actix::{Actor, Addr, Handler, SyncArbiter, SyncContext};
+use borsh::BorshSerialize;
 use cached::{Cached, SizedCache};
+use futures::channel::oneshot;
 use log::{debug, error, info, trace, warn};
 
 use near_chain::types::ValidatorInfoIdentifier;
@@ -19,11 +21,12 @@ use near_chain::{
 };
 use near_chain_configs::{ClientConfig, ProtocolConfigView};
 use near_client_primitives::types::{
-    Error, GetBlock, GetBlockError, GetBlockProof, GetBlockProofResponse, GetBlockWithMerkleTree,
-    GetChunkError, GetExecutionOutcome, GetExecutionOutcomesForBlock, GetGasPrice,
-    GetProtocolConfig, GetProtocolConfigError, GetReceipt, GetReceiptError,
-    GetStateChangesWithCauseInBlock, GetValidatorInfoError, Query, QueryError, TxStatus,
-    TxStatusError,
+    CreateStoreSnapshot, Error, GetAccountHistory, GetBlock, GetBlockError, GetBlockProof,
+    GetBlockProofResponse, GetBlockWithMerkleTree, GetChunkError, GetExecutionOutcome,
+    GetExecutionOutcomesForBlock, GetGasPrice, GetPendingTransactions, GetProtocolConfig,
+    GetProtocolConfigError, GetReceipt, GetReceiptError, GetReceiptGasProfile,
+    GetStateChangesWithCauseInBlock, GetStateDiff, GetStateDiffResponse, GetValidatorInfoError,
+    Query, QueryError, TxStatus, TxStatusError,
 };
 #[cfg(feature = "adversarial")]
 use near_network::types::NetworkAdversarialMessage;
@@ -37,8 +40,10 @@ use near_performance_metrics_macros::perf_with_debug;
 use near_primitives::block::{Block, BlockHeader, GenesisId, Tip};
 use near_primitives::hash::CryptoHash;
 use near_primitives::merkle::{merklize, PartialMerkleTree};
-use near_primitives::network::AnnounceAccount;
+use near_primitives::network::{AnnounceAccount, PeerId};
 use near_primitives::sharding::ShardChunk;
+#[cfg(feature = "protocol_feature_state_part_chunks")]
+use near_primitives::syncing::ShardStateSyncResponseV3;
 use near_primitives::syncing::{
     ShardStateSyncResponse, ShardStateSyncResponseHeader, ShardStateSyncResponseV1,
     ShardStateSyncResponseV2,
@@ -48,14 +53,16 @@ use near_primitives::types::{
     ShardId, TransactionOrReceiptId,
 };
 use near_primitives::views::{
-    BlockView, ChunkView, EpochValidatorInfo, ExecutionOutcomeWithIdView,
-    FinalExecutionOutcomeView, FinalExecutionOutcomeViewEnum, FinalExecutionStatus, GasPriceView,
-    LightClientBlockView, QueryRequest, QueryResponse, ReceiptView, StateChangesKindsView,
-    StateChangesView, ValidatorStakeView,
+    AccountHistoryItemView, ActionGasProfileView, BlockView, ChunkView, EpochValidatorInfo,
+    ExecutionOutcomeWithIdView, FinalExecutionOutcomeView, FinalExecutionOutcomeViewEnum,
+    FinalExecutionStatus, GasPriceView, LightClientBlockView, PendingTransactionView, QueryRequest,
+    QueryResponse, ReceiptView, StateChangesKindsView, StateChangesView, StateDiffItemView,
+    ValidatorStakeView,
 };
 
+use crate::pending_transactions::PendingTransactionsSnapshot;
 use crate::{
-    sync, GetChunk, GetExecutionOutcomeResponse, GetNextLightClientBlock, GetStateChanges,
+    metrics, sync, GetChunk, GetExecutionOutcomeResponse, GetNextLightClientBlock, GetStateChanges,
     GetStateChangesInBlock, GetValidatorInfo, GetValidatorOrdered,
 };
 
@@ -63,6 +70,8 @@ use crate::{
 const QUERY_REQUEST_LIMIT: usize = 500;
 /// Waiting time between requests, in ms
 const REQUEST_WAIT_TIME: u64 = 1000;
+/// Maximum number of pending transactions returned in one response.
+const PENDING_TRANSACTIONS_LIMIT: usize = 1000;
 
 const POISONED_LOCK_ERR: &str = "The lock was poisoned.";
 
@@ -101,6 +110,10 @@ pub struct ViewClientActor {
     pub config: ClientConfig,
     request_manager: Arc<RwLock<ViewClientRequestManager>>,
     state_request_cache: Arc<Mutex<VecDeque<Instant>>>,
+    /// Times and sizes of the state responses recently sent to each peer.
+    state_request_peer_cache: Arc<Mutex<SizedCache<PeerId, VecDeque<(Instant, u64)>>>>,
+    /// Snapshot of the transaction pools taken by the client.
+    pending_transactions: Arc<RwLock<PendingTransactionsSnapshot>>,
 }
 
 impl ViewClientRequestManager {
@@ -118,6 +131,8 @@ impl ViewClientRequestManager {
 impl ViewClientActor {
     /// Maximum number of state requests allowed per `view_client_throttle_period`.
     const MAX_NUM_STATE_REQUESTS: usize = 30;
+    /// Maximum number of peers whose state requests are tracked for the peer quota.
+    const MAX_NUM_STATE_REQUEST_PEERS: usize = 1000;
 
     pub fn new(
         validator_account_id: Option<AccountId>,
@@ -126,6 +141,7 @@ impl ViewClientActor {
         network_adapter: Arc<dyn NetworkAdapter>,
         config: ClientConfig,
         request_manager: Arc<RwLock<ViewClientRequestManager>>,
+        pending_transactions: Arc<RwLock<PendingTransactionsSnapshot>>,
         #[cfg(feature = "adversarial")] adv: Arc<RwLock<AdversarialControls>>,
     ) -> Result<Self, Error> {
         // TODO: should we create shared ChainStore that is passed to both Client and ViewClient?
@@ -144,6 +160,10 @@ impl ViewClientActor {
             config,
             request_manager,
             state_request_cache: Arc::new(Mutex::new(VecDeque::default())),
+            state_request_peer_cache: Arc::new(Mutex::new(SizedCache::with_size(
+                Self::MAX_NUM_STATE_REQUEST_PEERS,
+            ))),
+            pending_transactions,
         })
     }
 
@@ -232,6 +252,7 @@ impl ViewClientActor {
             QueryRequest::ViewAccessKeyList { account_id, .. } => account_id,
             QueryRequest::CallFunction { account_id, .. } => account_id,
             QueryRequest::ViewCode { account_id, .. } => account_id,
+            QueryRequest::ViewStatePage { account_id, .. } => account_id,
         };
         let shard_id = self.runtime_adapter.account_id_to_shard_id(account_id);
 
@@ -460,11 +481,64 @@ impl ViewClientActor {
             cache.pop_front();
         }
         if cache.len() >= Self::MAX_NUM_STATE_REQUESTS {
+            near_metrics::inc_counter(&metrics::STATE_REQUESTS_THROTTLED_TOTAL);
             return false;
         }
         cache.push_back(now);
         true
     }
+
+    /// Returns false if the peer has used up its `state_request_peer_quota_bytes`
+    /// for the current `view_client_throttle_period`.
+    fn check_state_sync_peer_quota(&self, peer_id: &PeerId) -> bool {
+        let mut cache = self.state_request_peer_cache.lock().expect(POISONED_LOCK_ERR);
+        let cutoff = Instant::now() - self.config.view_client_throttle_period;
+        let responses = match cache.cache_get_mut(peer_id) {
+            Some(responses) => responses,
+            None => return true,
+        };
+        while !responses.is_empty() && responses.front().unwrap().0 < cutoff {
+            responses.pop_front();
+        }
+        let served_bytes: u64 = responses.iter().map(|(_, size)| size).sum();
+        if served_bytes >= self.config.state_request_peer_quota_bytes {
+            near_metrics::inc_counter(&metrics::STATE_REQUESTS_PEER_QUOTA_EXCEEDED_TOTAL);
+            return false;
+        }
+        true
+    }
+
+    /// Charges the size of the state response to the quota of the peer which requested it.
+    fn state_response_to_peer(
+        &self,
+        peer_id: PeerId,
+        info: StateResponseInfo,
+    ) -> NetworkViewClientResponses {
+        let size = info.try_to_vec().map_or(0, |data| data.len() as u64);
+        let mut cache = self.state_request_peer_cache.lock().expect(POISONED_LOCK_ERR);
+        if cache.cache_get(&peer_id).is_none() {
+            cache.cache_set(peer_id.clone(), VecDeque::default());
+        }
+        cache.cache_get_mut(&peer_id).unwrap().push_back((Instant::now(), size));
+        NetworkViewClientResponses::StateResponse(Box::new(info))
+    }
+
+    /// Parts which are ready to be served are cheap, so requests for them are only limited
+    /// by the peer quota. Parts which have to be computed are also throttled.
+    fn check_state_part_request(&self, peer_id: &PeerId, is_part_ready: bool) -> bool {
+        if !self.check_state_sync_peer_quota(peer_id) {
+            return false;
+        }
+        if is_part_ready {
+            near_metrics::inc_counter(&metrics::STATE_PART_CACHE_HIT_TOTAL);
+            return true;
+        }
+        if !self.check_state_sync_request() {
+            return false;
+        }
+        near_metrics::inc_counter(&metrics::STATE_PART_CACHE_MISS_TOTAL);
+        true
+    }
 }
 
 impl Actor for ViewClientActor {
@@ -888,6 +962,123 @@ impl Handler<GetProtocolConfig> for ViewClientActor {
     }
 }
 
+impl Handler<CreateStoreSnapshot> for ViewClientActor {
+    type Result = Result<oneshot::Receiver<Result<(), String>>, String>;
+
+    #[perf]
+    fn handle(&mut self, msg: CreateStoreSnapshot, _: &mut Self::Context) -> Self::Result {
+        if msg.path.exists() {
+            return Err(format!("Snapshot path {} already exists", msg.path.display()));
+        }
+        let (sender, receiver) = oneshot::channel();
+        let store = self.chain.store().owned_store();
+        // Copying the whole store may take a long time, don't hold the view client thread.
+        std::thread::spawn(move || {
+            info!(target: "client", "Creating store snapshot at {}", msg.path.display());
+            let result = store.checkpoint(&msg.path).map_err(|e| e.to_string());
+            match &result {
+                Ok(()) => {
+                    info!(target: "client", "Store snapshot at {} is created", msg.path.display())
+                }
+                Err(err) => error!(
+                    target: "client",
+                    "Failed to create store snapshot at {}: {}",
+                    msg.path.display(),
+                    err
+                ),
+            }
+            let _ = sender.send(result);
+        });
+        Ok(receiver)
+    }
+}
+
+impl Handler<GetAccountHistory> for ViewClientActor {
+    type Result = Result<Vec<AccountHistoryItemView>, String>;
+
+    #[perf]
+    fn handle(&mut self, msg: GetAccountHistory, _: &mut Self::Context) -> Self::Result {
+        if !self.config.archive || !self.config.account_history_index {
+            return Err("Account history index is not enabled on this node".to_string());
+        }
+        self.chain
+            .store()
+            .get_account_history(&msg.account_id, msg.after, msg.limit)
+            .map_err(|e| e.to_string())
+    }
+}
+
+impl Handler<GetReceiptGasProfile> for ViewClientActor {
+    type Result = Result<Option<Vec<ActionGasProfileView>>, String>;
+
+    #[perf]
+    fn handle(&mut self, msg: GetReceiptGasProfile, _: &mut Self::Context) -> Self::Result {
+        Ok(self
+            .chain
+            .store()
+            .get_receipt_gas_profile(&msg.block_hash, &msg.receipt_id)
+            .map_err(|e| e.to_string())?
+            .map(|profiles| profiles.into_iter().map(Into::into).collect()))
+    }
+}
+
+impl Handler<GetStateDiff> for ViewClientActor {
+    type Result = Result<GetStateDiffResponse, String>;
+
+    #[perf]
+    fn handle(&mut self, msg: GetStateDiff, _: &mut Self::Context) -> Self::Result {
+        if msg.shard_id >= self.runtime_adapter.num_shards() {
+            return Err(format!("Shard {} does not exist", msg.shard_id));
+        }
+        let trie = self.runtime_adapter.get_view_trie_for_shard(msg.shard_id);
+        // One more record is read to tell whether the page is the last one.
+        let mut diff = trie
+            .diff(
+                &msg.old_state_root,
+                &msg.new_state_root,
+                msg.after_key.as_deref(),
+                Some(msg.limit + 1),
+            )
+            .map_err(|e| e.to_string())?;
+        let next_after_key = if diff.len() > msg.limit {
+            diff.truncate(msg.limit);
+            diff.last().map(|item| item.key.clone())
+        } else {
+            None
+        };
+        let changes = diff
+            .into_iter()
+            .map(|item| StateDiffItemView::new(&item.key, item.old_value, item.new_value))
+            .collect();
+        Ok(GetStateDiffResponse { changes, next_after_key })
+    }
+}
+
+impl Handler<GetPendingTransactions> for ViewClientActor {
+    type Result = Result<Vec<PendingTransactionView>, String>;
+
+    #[perf]
+    fn handle(&mut self, msg: GetPendingTransactions, _: &mut Self::Context) -> Self::Result {
+        let snapshot = self.pending_transactions.read().expect(POISONED_LOCK_ERR);
+        if let Some(tx_hash) = msg.transaction_hash {
+            return Ok(snapshot
+                .get_by_hash(&tx_hash)
+                .into_iter()
+                .filter(|tx| {
+                    msg.signer_id
+                        .as_ref()
+                        .map_or(true, |signer_id| &tx.transaction.signer_id == signer_id)
+                })
+                .collect());
+        }
+        Ok(snapshot.get(
+            msg.signer_id.as_ref(),
+            msg.after.as_ref(),
+            msg.limit.min(PENDING_TRANSACTIONS_LIMIT),
+        ))
+    }
+}
+
 impl Handler<NetworkViewClientMessages> for ViewClientActor {
     type Result = NetworkViewClientResponses;
 
@@ -1035,8 +1226,8 @@ impl Handler<NetworkViewClientMessages> for ViewClientActor {
                     NetworkViewClientResponses::NoResponse
                 }
             },
-            NetworkViewClientMessages::StateRequestHeader { shard_id, sync_hash } => {
-                if !self.check_state_sync_request() {
+            NetworkViewClientMessages::StateRequestHeader { shard_id, sync_hash, peer_id } => {
+                if !self.check_state_sync_peer_quota(&peer_id) || !self.check_state_sync_request() {
                     return NetworkViewClientResponses::NoResponse;
                 }
 
@@ -1099,20 +1290,29 @@ impl Handler<NetworkViewClientMessages> for ViewClientActor {
                             sync_hash,
                             state_response,
                         });
-                        NetworkViewClientResponses::StateResponse(Box::new(info))
+                        self.state_response_to_peer(peer_id, info)
                     }
-                    state_response @ ShardStateSyncResponse::V2(_) => {
+                    state_response => {
                         let info = StateResponseInfo::V2(StateResponseInfoV2 {
                             shard_id,
                             sync_hash,
                             state_response,
                         });
-                        NetworkViewClientResponses::StateResponse(Box::new(info))
+                        self.state_response_to_peer(peer_id, info)
                     }
                 }
             }
-            NetworkViewClientMessages::StateRequestPart { shard_id, sync_hash, part_id } => {
-                if !self.check_state_sync_request() {
+            NetworkViewClientMessages::StateRequestPart {
+                shard_id,
+                sync_hash,
+                part_id,
+                peer_id,
+            } => {
+                let is_part_ready = self
+                    .chain
+                    .has_state_response_part(shard_id, part_id, sync_hash)
+                    .unwrap_or(false);
+                if !self.check_state_part_request(&peer_id, is_part_ready) {
                     return NetworkViewClientResponses::NoResponse;
                 }
                 trace!(target: "sync", "Computing state request part {} {} {}", shard_id, sync_hash, part_id);
@@ -1122,7 +1322,13 @@ impl Handler<NetworkViewClientMessages> for ViewClientActor {
                             .chain
                             .get_state_response_part(shard_id, part_id, sync_hash)
                         {
-                            Ok(part) => Some((part_id, part)),
+                            Ok(part) => {
+                                near_metrics::inc_counter_by(
+                                    &metrics::STATE_PART_SERVED_BYTES_TOTAL,
+                                    part.len() as u64,
+                                );
+                                Some((part_id, part))
+                            }
                             Err(e) => {
                                 error!(target: "sync", "Cannot build sync part #{:?} (get_state_response_part): {}", part_id, e);
                                 None
@@ -1154,7 +1360,66 @@ impl Handler<NetworkViewClientMessages> for ViewClientActor {
                     sync_hash,
                     state_response,
                 });
-                NetworkViewClientResponses::StateResponse(Box::new(info))
+                self.state_response_to_peer(peer_id, info)
+            }
+            #[cfg(feature = "protocol_feature_state_part_chunks")]
+            NetworkViewClientMessages::StateRequestPartChunk {
+                shard_id,
+                sync_hash,
+                part_id,
+                chunk_id,
+                peer_id,
+            } => {
+                // Chunks are cheap to serve only once the part is split.
+                let is_part_ready =
+                    self.chain.has_state_response_part_chunks(shard_id, part_id, sync_hash);
+                if !self.check_state_part_request(&peer_id, is_part_ready) {
+                    return NetworkViewClientResponses::NoResponse;
+                }
+                trace!(target: "sync", "Computing state request part {} chunk {} {} {}", part_id, chunk_id, shard_id, sync_hash);
+                let part_chunk = match self.chain.check_sync_hash_validity(&sync_hash) {
+                    Ok(true) => match self
+                        .chain
+                        .get_state_response_part_chunk(shard_id, part_id, chunk_id, sync_hash)
+                    {
+                        Ok(part_chunk) => {
+                            near_metrics::inc_counter_by(
+                                &metrics::STATE_PART_SERVED_BYTES_TOTAL,
+                                part_chunk.data.len() as u64,
+                            );
+                            Some(part_chunk)
+                        }
+                        Err(e) => {
+                            error!(target: "sync", "Cannot build sync part #{:?} chunk #{:?} (get_state_response_part_chunk): {}", part_id, chunk_id, e);
+                            None
+                        }
+                    },
+                    Ok(false) => {
+                        warn!(target: "sync", "sync_hash {:?} didn't pass validation, possible malicious behavior", sync_hash);
+                        return NetworkViewClientResponses::NoResponse;
+                    }
+                    Err(e) => match e.kind() {
+                        ErrorKind::DBNotFoundErr(_) => {
+                            // This case may appear in case of latency in epoch switching.
+                            // Request sender is ready to sync but we still didn't get the block.
+                            info!(target: "sync", "Can't get sync_hash block {:?} for state request part chunk", sync_hash);
+                            None
+                        }
+                        _ => {
+                            error!(target: "sync", "Failed to verify sync_hash {:?} validity, {:?}", sync_hash, e);
+                            None
+                        }
+                    },
+                };
+                let info = StateResponseInfo::V2(StateResponseInfoV2 {
+                    shard_id,
+                    sync_hash,
+                    state_response: ShardStateSyncResponse::V3(ShardStateSyncResponseV3 {
+                        header: None,
+                        part_chunk,
+                    }),
+                });
+                self.state_response_to_peer(peer_id, info)
             }
             NetworkViewClientMessages::AnnounceAccount(announce_accounts) => {
                 let mut filtered_announce_accounts = Vec::new();
@@ -1221,6 +1486,7 @@ pub fn start_view_client(
     runtime_adapter: Arc<dyn RuntimeAdapter>,
     network_adapter: Arc<dyn NetworkAdapter>,
     config: ClientConfig,
+    pending_transactions: Arc<RwLock<PendingTransactionsSnapshot>>,
     #[cfg(feature = "adversarial")] adv: Arc<RwLock<AdversarialControls>>,
 ) -> Addr<ViewClientActor> {
     let request_manager = Arc::new(RwLock::new(ViewClientRequestManager::new()));
@@ -1238,6 +1504,7 @@ pub fn start_view_client(
             network_adapter1,
             config1,
             request_manager1,
+            pending_transactions.clone(),
             #[cfg(feature = "adversarial")]
             adv.clone(),
         )
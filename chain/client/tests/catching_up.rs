@@ -263,13 +263,24 @@ mod tests {
                                         }
                                     }
                                 }
-                                if let NetworkRequests::StateRequestPart {
-                                    shard_id,
-                                    sync_hash,
-                                    part_id,
-                                    target,
-                                } = msg
-                                {
+                                let part_request = match msg {
+                                    NetworkRequests::StateRequestPart {
+                                        shard_id,
+                                        sync_hash,
+                                        part_id,
+                                        target,
+                                    } => Some((shard_id, sync_hash, part_id, target)),
+                                    #[cfg(feature = "protocol_feature_state_part_chunks")]
+                                    NetworkRequests::StateRequestPartChunk {
+                                        shard_id,
+                                        sync_hash,
+                                        part_id,
+                                        target,
+                                        ..
+                                    } => Some((shard_id, sync_hash, part_id, target)),
+                                    _ => None,
+                                };
+                                if let Some((shard_id, sync_hash, part_id, target)) = part_request {
                                     if sync_hold {
                                         let srs = StateRequestStruct {
                                             shard_id: *shard_id,
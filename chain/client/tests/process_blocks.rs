@@ -725,6 +725,7 @@ fn client_sync_headers() {
                     archival: false,
                 },
                 edge_info: EdgeInfo::default(),
+                score: 0,
             }],
             num_active_peers: 1,
             peer_max_count: 1,
@@ -737,6 +738,7 @@ fn client_sync_headers() {
                     archival: false,
                 },
                 edge_info: EdgeInfo::default(),
+                score: 0,
             }],
             sent_bytes_per_sec: 0,
             received_bytes_per_sec: 0,
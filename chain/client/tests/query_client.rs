@@ -1,11 +1,18 @@
 use actix::System;
+use borsh::BorshSerialize;
 use futures::{future, FutureExt};
 
 use chrono::Utc;
 use near_actix_test_utils::run_actix_until_stop;
+use near_chain::test_utils::KeyValueRuntime;
+use near_chain::{Chain, ChainGenesis, DoomslugThresholdMode};
+use near_chain_configs::ClientConfig;
 use near_client::test_utils::{setup, setup_no_network};
+#[cfg(feature = "adversarial")]
+use near_client::AdversarialControls;
 use near_client::{
-    GetBlock, GetBlockWithMerkleTree, GetExecutionOutcomesForBlock, Query, Status, TxStatus,
+    start_view_client, GetBlock, GetBlockWithMerkleTree, GetExecutionOutcomesForBlock,
+    GetPendingTransactions, PendingTransactionsSnapshot, Query, Status, TxStatus,
 };
 use near_crypto::{InMemorySigner, KeyType};
 use near_logger_utils::init_test_logger;
@@ -13,12 +20,15 @@ use near_network::test_utils::MockNetworkAdapter;
 use near_network::types::{NetworkViewClientMessages, NetworkViewClientResponses};
 use near_network::{NetworkClientMessages, NetworkClientResponses, PeerInfo};
 use near_primitives::block::{Block, BlockHeader};
+use near_primitives::hash::CryptoHash;
+use near_primitives::network::PeerId;
 use near_primitives::transaction::SignedTransaction;
 use near_primitives::types::{BlockId, BlockReference, EpochId};
 use near_primitives::utils::to_timestamp;
 use near_primitives::validator_signer::InMemoryValidatorSigner;
 use near_primitives::version::PROTOCOL_VERSION;
 use near_primitives::views::{FinalExecutionOutcomeViewEnum, QueryRequest, QueryResponseKind};
+use near_store::test_utils::create_test_store;
 use num_rational::Rational;
 use std::sync::Arc;
 use std::time::Duration;
@@ -202,6 +212,7 @@ fn test_state_request() {
                     .send(NetworkViewClientMessages::StateRequestHeader {
                         shard_id: 0,
                         sync_hash: block_hash,
+                        peer_id: PeerId::random(),
                     })
                     .await
                     .unwrap();
@@ -213,6 +224,7 @@ fn test_state_request() {
                 .send(NetworkViewClientMessages::StateRequestHeader {
                     shard_id: 0,
                     sync_hash: block_hash,
+                    peer_id: PeerId::random(),
                 })
                 .await
                 .unwrap();
@@ -224,6 +236,7 @@ fn test_state_request() {
                     .send(NetworkViewClientMessages::StateRequestHeader {
                         shard_id: 0,
                         sync_hash: block_hash,
+                        peer_id: PeerId::random(),
                     })
                     .await
                     .unwrap();
@@ -234,3 +247,169 @@ fn test_state_request() {
         near_network::test_utils::wait_or_panic(50000);
     });
 }
+
+/// Every peer gets its own quota of state response bytes on top of the global throttling.
+#[test]
+fn test_state_request_peer_quota() {
+    run_actix_until_stop(async {
+        let store = create_test_store();
+        let runtime = Arc::new(KeyValueRuntime::new(store));
+        let chain_genesis = ChainGenesis::test();
+        Chain::new(runtime.clone(), &chain_genesis, DoomslugThresholdMode::NoApprovals).unwrap();
+        let mut config = ClientConfig::test(true, 200, 400, 1, false, false);
+        config.state_request_peer_quota_bytes = 500;
+        let view_client = start_view_client(
+            None,
+            chain_genesis,
+            runtime,
+            Arc::new(MockNetworkAdapter::default()),
+            config,
+            Arc::new(std::sync::RwLock::new(PendingTransactionsSnapshot::default())),
+            #[cfg(feature = "adversarial")]
+            Arc::new(std::sync::RwLock::new(AdversarialControls::default())),
+        );
+        actix::spawn(async move {
+            let block_hash = view_client
+                .send(GetBlock(BlockReference::BlockId(BlockId::Height(0))))
+                .await
+                .unwrap()
+                .unwrap()
+                .header
+                .hash;
+            let peer_id = PeerId::random();
+            let mut served_bytes = 0;
+            let mut last_response_bytes = 0;
+            loop {
+                let res = view_client
+                    .send(NetworkViewClientMessages::StateRequestHeader {
+                        shard_id: 0,
+                        sync_hash: block_hash,
+                        peer_id: peer_id.clone(),
+                    })
+                    .await
+                    .unwrap();
+                match res {
+                    NetworkViewClientResponses::StateResponse(info) => {
+                        last_response_bytes = info.try_to_vec().unwrap().len();
+                        served_bytes += last_response_bytes;
+                    }
+                    NetworkViewClientResponses::NoResponse => break,
+                    _ => panic!("unexpected response"),
+                }
+            }
+            // the peer is served until it has used up its quota
+            assert!(served_bytes >= 500);
+            assert!(served_bytes - last_response_bytes < 500);
+
+            // other peers are still served
+            let res = view_client
+                .send(NetworkViewClientMessages::StateRequestHeader {
+                    shard_id: 0,
+                    sync_hash: block_hash,
+                    peer_id: PeerId::random(),
+                })
+                .await
+                .unwrap();
+            assert!(matches!(res, NetworkViewClientResponses::StateResponse(_)));
+            System::current().stop();
+        });
+        near_network::test_utils::wait_or_panic(50000);
+    });
+}
+
+/// Pending transactions are served by the view client from the snapshot of the pools, in pages
+/// of a limited size.
+#[test]
+fn test_pending_transactions() {
+    run_actix_until_stop(async {
+        let store = create_test_store();
+        let runtime = Arc::new(KeyValueRuntime::new(store));
+        let chain_genesis = ChainGenesis::test();
+        Chain::new(runtime.clone(), &chain_genesis, DoomslugThresholdMode::NoApprovals).unwrap();
+        let signer = InMemorySigner::from_seed("test", KeyType::ED25519, "test");
+        let transactions: Vec<_> = (1..=1001)
+            .map(|nonce| {
+                SignedTransaction::send_money(
+                    nonce,
+                    if nonce == 1001 { "other" } else { "test" }.to_string(),
+                    "near".to_string(),
+                    &signer,
+                    1,
+                    CryptoHash::default(),
+                )
+            })
+            .collect();
+        let snapshot = PendingTransactionsSnapshot::new(transactions.iter().map(|tx| (0, tx)));
+        let view_client = start_view_client(
+            None,
+            chain_genesis,
+            runtime,
+            Arc::new(MockNetworkAdapter::default()),
+            ClientConfig::test(true, 200, 400, 1, false, false),
+            Arc::new(std::sync::RwLock::new(snapshot)),
+            #[cfg(feature = "adversarial")]
+            Arc::new(std::sync::RwLock::new(AdversarialControls::default())),
+        );
+        actix::spawn(async move {
+            let page = view_client
+                .send(GetPendingTransactions {
+                    signer_id: None,
+                    transaction_hash: None,
+                    after: None,
+                    limit: usize::MAX,
+                })
+                .await
+                .unwrap()
+                .unwrap();
+            // the response is capped, "other" comes first
+            assert_eq!(page.len(), 1000);
+            assert_eq!(page[0].transaction.signer_id, "other");
+            assert_eq!(page[1].transaction.nonce, 1);
+            let last = page.last().unwrap();
+            let page = view_client
+                .send(GetPendingTransactions {
+                    signer_id: None,
+                    transaction_hash: None,
+                    after: Some((
+                        last.shard_id,
+                        last.transaction.signer_id.clone(),
+                        last.transaction.nonce,
+                        last.transaction.hash,
+                    )),
+                    limit: usize::MAX,
+                })
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(page.iter().map(|tx| tx.transaction.nonce).collect::<Vec<_>>(), vec![1000]);
+
+            let page = view_client
+                .send(GetPendingTransactions {
+                    signer_id: Some("other".to_string()),
+                    transaction_hash: None,
+                    after: None,
+                    limit: 10,
+                })
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(page.len(), 1);
+            assert_eq!(page[0].transaction.nonce, 1001);
+
+            let page = view_client
+                .send(GetPendingTransactions {
+                    signer_id: None,
+                    transaction_hash: Some(transactions[41].get_hash()),
+                    after: None,
+                    limit: 1,
+                })
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(page.len(), 1);
+            assert_eq!(page[0].transaction.nonce, 42);
+            System::current().stop();
+        });
+        near_network::test_utils::wait_or_panic(50000);
+    });
+}
@@ -0,0 +1,161 @@
+//! Filter applied by the streamer before it fetches the data of the block, so the data
+//! the indexer is not interested in is never requested from the node.
+use near_primitives::types::{AccountId, ShardId};
+use near_primitives::views;
+
+/// Kind of the action in transactions and receipts, to filter them by
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ActionKind {
+    CreateAccount,
+    DeployContract,
+    FunctionCall,
+    Transfer,
+    Stake,
+    AddKey,
+    DeleteKey,
+    DeleteAccount,
+}
+
+impl From<&views::ActionView> for ActionKind {
+    fn from(action: &views::ActionView) -> Self {
+        match action {
+            views::ActionView::CreateAccount => ActionKind::CreateAccount,
+            views::ActionView::DeployContract { .. } => ActionKind::DeployContract,
+            views::ActionView::FunctionCall { .. } => ActionKind::FunctionCall,
+            views::ActionView::Transfer { .. } => ActionKind::Transfer,
+            views::ActionView::Stake { .. } => ActionKind::Stake,
+            views::ActionView::AddKey { .. } => ActionKind::AddKey,
+            views::ActionView::DeleteKey { .. } => ActionKind::DeleteKey,
+            views::ActionView::DeleteAccount { .. } => ActionKind::DeleteAccount,
+        }
+    }
+}
+
+/// Defines which part of the blocks ends up in `StreamerMessage`. Blocks themselves are always
+/// streamed, the filter only applies to their chunks, transactions, receipts, execution outcomes
+/// and state changes. The default filter lets everything through.
+#[derive(Debug, Clone)]
+pub struct IndexerFilter {
+    /// Accounts to stream the data of, `None` for all the accounts. `*` in the pattern matches
+    /// any sequence of characters, e.g. `*.poolv1.near`.
+    /// Transactions are matched by their signer or receiver, receipts and their execution outcomes
+    /// by the receiver, state changes by the account they change.
+    pub accounts: Option<Vec<String>>,
+    /// Shards to stream the chunks of, `None` for all the shards
+    pub shard_ids: Option<Vec<ShardId>>,
+    /// Actions to stream the transactions and receipts with, `None` for all the actions.
+    /// A transaction or receipt matches if any of its actions does, data receipts never match.
+    /// Execution outcomes whose receipt couldn't be fetched are streamed regardless.
+    pub action_kinds: Option<Vec<ActionKind>>,
+    /// Whether to fetch and stream state changes
+    pub include_state_changes: bool,
+}
+
+impl Default for IndexerFilter {
+    fn default() -> Self {
+        Self { accounts: None, shard_ids: None, action_kinds: None, include_state_changes: true }
+    }
+}
+
+/// Whether `account_id` matches the `pattern`, where `*` matches any sequence of characters.
+fn matches_pattern(pattern: &str, account_id: &str) -> bool {
+    let mut parts = pattern.split('*');
+    // `split` always yields at least one part.
+    let first = parts.next().unwrap();
+    let mut rest = match account_id.strip_prefix(first) {
+        Some(rest) => rest,
+        None => return false,
+    };
+    let mut parts = parts.peekable();
+    if parts.peek().is_none() {
+        return rest.is_empty();
+    }
+    while let Some(part) = parts.next() {
+        if parts.peek().is_none() {
+            return rest.ends_with(part);
+        }
+        match rest.find(part) {
+            Some(index) => rest = &rest[index + part.len()..],
+            None => return false,
+        }
+    }
+    unreachable!()
+}
+
+impl IndexerFilter {
+    pub(crate) fn matches_shard(&self, shard_id: ShardId) -> bool {
+        self.shard_ids.as_ref().map_or(true, |shard_ids| shard_ids.contains(&shard_id))
+    }
+
+    pub(crate) fn matches_account(&self, account_id: &AccountId) -> bool {
+        self.accounts.as_ref().map_or(true, |patterns| {
+            patterns.iter().any(|pattern| matches_pattern(pattern, account_id))
+        })
+    }
+
+    fn matches_actions(&self, actions: &[views::ActionView]) -> bool {
+        self.action_kinds.as_ref().map_or(true, |action_kinds| {
+            actions.iter().any(|action| action_kinds.contains(&ActionKind::from(action)))
+        })
+    }
+
+    pub(crate) fn matches_transaction(&self, transaction: &views::SignedTransactionView) -> bool {
+        (self.matches_account(&transaction.signer_id)
+            || self.matches_account(&transaction.receiver_id))
+            && self.matches_actions(&transaction.actions)
+    }
+
+    pub(crate) fn matches_receipt(&self, receipt: &views::ReceiptView) -> bool {
+        self.matches_account(&receipt.receiver_id)
+            && match &receipt.receipt {
+                views::ReceiptEnumView::Action { actions, .. } => self.matches_actions(actions),
+                views::ReceiptEnumView::Data { .. } => self.action_kinds.is_none(),
+            }
+    }
+
+    /// Whether the execution outcome of a receipt matches. `receipt` is `None` if the receipt
+    /// is not known (yet), such outcomes are matched by the executor only.
+    pub(crate) fn matches_receipt_outcome(
+        &self,
+        outcome: &views::ExecutionOutcomeWithIdView,
+        receipt: Option<&views::ReceiptView>,
+    ) -> bool {
+        self.matches_account(&outcome.outcome.executor_id)
+            && receipt.map_or(true, |receipt| self.matches_receipt(receipt))
+    }
+
+    pub(crate) fn matches_state_change(
+        &self,
+        state_change: &views::StateChangeWithCauseView,
+    ) -> bool {
+        let account_id = match &state_change.value {
+            views::StateChangeValueView::AccountUpdate { account_id, .. }
+            | views::StateChangeValueView::AccountDeletion { account_id }
+            | views::StateChangeValueView::AccessKeyUpdate { account_id, .. }
+            | views::StateChangeValueView::AccessKeyDeletion { account_id, .. }
+            | views::StateChangeValueView::DataUpdate { account_id, .. }
+            | views::StateChangeValueView::DataDeletion { account_id, .. }
+            | views::StateChangeValueView::ContractCodeUpdate { account_id, .. }
+            | views::StateChangeValueView::ContractCodeDeletion { account_id } => account_id,
+        };
+        self.matches_account(account_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matches_pattern() {
+        assert!(matches_pattern("alice.near", "alice.near"));
+        assert!(!matches_pattern("alice.near", "alice.near2"));
+        assert!(matches_pattern("*.poolv1.near", "figment.poolv1.near"));
+        assert!(!matches_pattern("*.poolv1.near", "poolv1.near"));
+        assert!(matches_pattern("app.*", "app.near"));
+        assert!(matches_pattern("a*b*c", "abbc"));
+        assert!(!matches_pattern("a*b*c", "acb"));
+        assert!(matches_pattern("*", "anything"));
+        assert!(!matches_pattern("aa*aa", "aaa"));
+    }
+}
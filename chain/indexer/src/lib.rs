@@ -10,11 +10,14 @@
 use tokio::sync::mpsc;
 
 pub use neard::{get_default_home, init_configs, NearConfig};
+mod filter;
+pub mod sinks;
 mod streamer;
 
+pub use self::filter::{ActionKind, IndexerFilter};
 pub use self::streamer::{
-    IndexerChunkView, IndexerExecutionOutcomeWithReceipt, IndexerTransactionWithOutcome,
-    StreamerMessage,
+    BlockAcknowledger, IndexerChunkView, IndexerExecutionOutcomeWithReceipt,
+    IndexerTransactionWithOutcome, StreamerEvent, StreamerMessage,
 };
 pub use near_primitives;
 
@@ -69,6 +72,13 @@ pub struct IndexerConfig {
     pub sync_mode: SyncModeEnum,
     /// Whether await for node to be synced or not
     pub await_for_node_synced: AwaitForNodeSyncedEnum,
+    /// Which accounts, shards and actions to stream the data of
+    pub filter: IndexerFilter,
+    /// Maximum number of streamed blocks the consumer hasn't acknowledged yet with `Indexer::acknowledger()`,
+    /// the streamer waits for acknowledgements once it's reached. With the window set `SyncModeEnum::FromInterruption`
+    /// resumes from the block after the last acknowledged one, instead of the last streamed one.
+    /// `None` turns acknowledgements off, the window can't be `Some(0)`.
+    pub in_flight_window: Option<usize>,
 }
 
 /// This is the core component, which handles `nearcore` and internal `streamer`.
@@ -77,12 +87,13 @@ pub struct Indexer {
     near_config: neard::NearConfig,
     view_client: actix::Addr<near_client::ViewClientActor>,
     client: actix::Addr<near_client::ClientActor>,
+    checkpoints: streamer::Checkpoints,
 }
 
 impl Indexer {
     /// Initialize Indexer by configuring `nearcore`
     pub fn new(indexer_config: IndexerConfig) -> Self {
-        let near_config = neard::load_config(&indexer_config.home_dir);
+        let mut near_config = neard::load_config(&indexer_config.home_dir);
         neard::genesis_validate::validate_genesis(&near_config.genesis);
         assert!(
             !&near_config.client_config.tracked_shards.is_empty(),
@@ -91,9 +102,16 @@ impl Indexer {
             ",
             indexer_config.home_dir.join("config.json").display()
         );
+        assert_ne!(
+            indexer_config.in_flight_window,
+            Some(0),
+            "Indexer's in_flight_window should allow at least one block in flight, use None to turn acknowledgements off"
+        );
+        near_config.record_gas_profiles = cfg!(feature = "gas_profile");
         let (client, view_client, _) =
             neard::start_with_config(&indexer_config.home_dir, near_config.clone());
-        Self { view_client, client, near_config, indexer_config }
+        let checkpoints = streamer::Checkpoints::open(&indexer_config.home_dir);
+        Self { view_client, client, near_config, indexer_config, checkpoints }
     }
 
     /// Boots up `near_indexer::streamer`, so it monitors the new blocks with chunks, transactions, receipts, and execution outcomes inside. The returned stream handler should be drained and handled on the user side.
@@ -104,11 +122,31 @@ impl Indexer {
             self.client.clone(),
             self.near_config.clone(),
             self.indexer_config.clone(),
+            self.checkpoints.clone(),
             sender,
         ));
         receiver
     }
 
+    /// Boots up optimistic `near_indexer::streamer`, which streams the blocks as soon as they are accepted by the node, before they are final. Whenever the node switches to another fork `StreamerEvent::Rollback` is streamed, and the consumer should revert the blocks it got after the one it rolls back to. The returned stream handler should be drained and handled on the user side.
+    pub fn optimistic_streamer(&self) -> mpsc::Receiver<streamer::StreamerEvent> {
+        let (sender, receiver) = mpsc::channel(16);
+        actix::spawn(streamer::optimistic::start(
+            self.view_client.clone(),
+            self.client.clone(),
+            self.near_config.clone(),
+            self.indexer_config.clone(),
+            self.checkpoints.clone(),
+            sender,
+        ));
+        receiver
+    }
+
+    /// Handle to acknowledge the blocks processed by the consumer of the stream, see `IndexerConfig::in_flight_window`
+    pub fn acknowledger(&self) -> BlockAcknowledger {
+        BlockAcknowledger::new(self.checkpoints.clone())
+    }
+
     /// Expose neard config
     pub fn near_config(&self) -> &neard::NearConfig {
         &self.near_config
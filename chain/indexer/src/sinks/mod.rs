@@ -0,0 +1,185 @@
+//! Sinks write the stream of blocks to a storage, so indexers which just need the data
+//! stored somewhere don't have to implement it themselves.
+//!
+//! ```ignore
+//! let indexer = near_indexer::Indexer::new(indexer_config);
+//! let sink = near_indexer::sinks::NdjsonSink::open(ndjson_config)?;
+//! actix::spawn(near_indexer::sinks::run_sink(indexer.streamer(), sink, Some(indexer.acknowledger())));
+//! ```
+use std::fmt;
+
+use tokio::sync::mpsc;
+use tokio::task;
+use tracing::error;
+
+use crate::{BlockAcknowledger, StreamerMessage};
+
+pub use self::ndjson::{NdjsonSink, NdjsonSinkConfig};
+#[cfg(feature = "sqlite")]
+pub use self::sqlite::SqliteSink;
+
+mod ndjson;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+
+/// Error occurs in case the sink failed to write the block
+#[derive(Debug)]
+pub enum SinkError {
+    Io(std::io::Error),
+    Serialization(serde_json::Error),
+    #[cfg(feature = "sqlite")]
+    Sql(rusqlite::Error),
+}
+
+impl fmt::Display for SinkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SinkError::Io(err) => write!(f, "IO error: {}", err),
+            SinkError::Serialization(err) => write!(f, "Serialization error: {}", err),
+            #[cfg(feature = "sqlite")]
+            SinkError::Sql(err) => write!(f, "SQL error: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for SinkError {}
+
+impl From<std::io::Error> for SinkError {
+    fn from(err: std::io::Error) -> Self {
+        SinkError::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for SinkError {
+    fn from(err: serde_json::Error) -> Self {
+        SinkError::Serialization(err)
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl From<rusqlite::Error> for SinkError {
+    fn from(err: rusqlite::Error) -> Self {
+        SinkError::Sql(err)
+    }
+}
+
+/// Storage the streamed blocks are written to
+pub trait Sink {
+    /// Writes the block. Once it returns the block must be stored durably, as the streamer
+    /// may be told not to stream it again. The same block may be written more than once,
+    /// e.g. after the restart of the Indexer.
+    fn write(&mut self, streamer_message: &StreamerMessage) -> Result<(), SinkError>;
+}
+
+/// Writes all the blocks from the stream to the sink. Every block is acknowledged once it's
+/// written, if the `acknowledger` is given. Stops at the first block the sink fails to write.
+///
+/// The sink writes are blocking, so they are run on the blocking threads of the runtime.
+pub async fn run_sink<S: Sink + Send + 'static>(
+    mut stream: mpsc::Receiver<StreamerMessage>,
+    mut sink: S,
+    acknowledger: Option<BlockAcknowledger>,
+) -> Result<(), SinkError> {
+    while let Some(streamer_message) = stream.recv().await {
+        let block_height = streamer_message.block.header.height;
+        let (returned_sink, result) = task::spawn_blocking(move || {
+            let result = sink.write(&streamer_message);
+            (sink, result)
+        })
+        .await
+        .expect("Sink panicked while writing the block");
+        sink = returned_sink;
+        if let Err(err) = result {
+            error!(target: "indexer", "Failed to write block #{} to the sink: {}", block_height, err);
+            return Err(err);
+        }
+        if let Some(acknowledger) = &acknowledger {
+            acknowledger.ack(block_height);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use near_primitives::block_header::BlockHeader;
+    use near_primitives::hash::CryptoHash;
+    use near_primitives::types::BlockHeight;
+    use near_primitives::utils::from_timestamp;
+    use near_primitives::version::PROTOCOL_VERSION;
+    use near_primitives::views;
+
+    use super::*;
+
+    pub(crate) fn streamer_message(block_height: BlockHeight) -> StreamerMessage {
+        let header = BlockHeader::genesis(
+            PROTOCOL_VERSION,
+            block_height,
+            CryptoHash::default(),
+            CryptoHash::default(),
+            CryptoHash::default(),
+            CryptoHash::default(),
+            1,
+            CryptoHash::default(),
+            from_timestamp(0),
+            0,
+            0,
+            CryptoHash::default(),
+        );
+        StreamerMessage {
+            block: views::BlockView {
+                author: "test.near".to_string(),
+                header: header.into(),
+                chunks: vec![],
+            },
+            chunks: vec![],
+            state_changes: vec![],
+        }
+    }
+
+    /// Records the heights of the written blocks and fails to write the block of `fail_at` height
+    struct TestSink {
+        written: Arc<Mutex<Vec<BlockHeight>>>,
+        fail_at: BlockHeight,
+    }
+
+    impl Sink for TestSink {
+        fn write(&mut self, streamer_message: &StreamerMessage) -> Result<(), SinkError> {
+            let block_height = streamer_message.block.header.height;
+            if block_height == self.fail_at {
+                return Err(std::io::Error::new(std::io::ErrorKind::Other, "disk is full").into());
+            }
+            self.written.lock().unwrap().push(block_height);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_run_sink() {
+        let written = Arc::new(Mutex::new(vec![]));
+        let sink = TestSink { written: written.clone(), fail_at: 3 };
+        let (sender, receiver) = mpsc::channel(16);
+        actix::System::new().block_on(async move {
+            for block_height in 1..=4 {
+                sender.send(streamer_message(block_height)).await.unwrap();
+            }
+            // The blocks after the one the sink failed to write are not written
+            assert!(matches!(run_sink(receiver, sink, None).await, Err(SinkError::Io(_))));
+        });
+        assert_eq!(*written.lock().unwrap(), vec![1, 2]);
+
+        let written = Arc::new(Mutex::new(vec![]));
+        let sink = TestSink { written: written.clone(), fail_at: 0 };
+        let (sender, receiver) = mpsc::channel(16);
+        actix::System::new().block_on(async move {
+            for block_height in 1..=2 {
+                sender.send(streamer_message(block_height)).await.unwrap();
+            }
+            drop(sender);
+            run_sink(receiver, sink, None).await.unwrap();
+        });
+        assert_eq!(*written.lock().unwrap(), vec![1, 2]);
+    }
+}
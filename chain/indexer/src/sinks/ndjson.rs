@@ -0,0 +1,209 @@
+//! Sink writing the blocks to newline-delimited JSON files, one `StreamerMessage` per line.
+//!
+//! Blocks are appended to `<first block height>.ndjson` in the configured directory, until the file
+//! reaches its size or height span limit. The file is then rotated: compressed with zstd into
+//! `<first block height>.ndjson.zst` if configured, and the next block starts a new file.
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use tracing::info;
+
+use near_primitives::types::BlockHeight;
+
+use super::{Sink, SinkError};
+use crate::StreamerMessage;
+
+const NDJSON_EXTENSION: &str = "ndjson";
+const COMPRESSION_LEVEL: i32 = 3;
+
+#[derive(Debug, Clone)]
+pub struct NdjsonSinkConfig {
+    /// Directory the files are written to, created if it doesn't exist
+    pub dir: PathBuf,
+    /// The file is rotated once it gets larger than this many bytes
+    pub max_file_size: Option<u64>,
+    /// The file is rotated before the block which is this many heights above the first block in it
+    pub max_file_height_span: Option<BlockHeight>,
+    /// Whether to compress the rotated files with zstd
+    pub compress: bool,
+}
+
+struct NdjsonFile {
+    path: PathBuf,
+    writer: BufWriter<File>,
+    first_block_height: BlockHeight,
+    size: u64,
+}
+
+pub struct NdjsonSink {
+    config: NdjsonSinkConfig,
+    current: Option<NdjsonFile>,
+}
+
+impl NdjsonSink {
+    /// Opens the sink. The files left from the previous run which were not rotated yet
+    /// are rotated, since the streaming may resume from another block.
+    pub fn open(config: NdjsonSinkConfig) -> Result<Self, SinkError> {
+        fs::create_dir_all(&config.dir)?;
+        for entry in fs::read_dir(&config.dir)? {
+            let path = entry?.path();
+            if path.extension().map_or(false, |extension| extension == NDJSON_EXTENSION) {
+                rotate_file(&path, config.compress)?;
+            }
+        }
+        Ok(Self { config, current: None })
+    }
+
+    fn should_rotate(&self, file: &NdjsonFile, block_height: BlockHeight) -> bool {
+        self.config.max_file_size.map_or(false, |max_file_size| file.size >= max_file_size)
+            || self.config.max_file_height_span.map_or(false, |max_file_height_span| {
+                block_height >= file.first_block_height + max_file_height_span
+            })
+    }
+
+    fn rotate(&mut self) -> Result<(), SinkError> {
+        if let Some(mut file) = self.current.take() {
+            file.writer.flush()?;
+            file.writer.get_ref().sync_all()?;
+            rotate_file(&file.path, self.config.compress)?;
+        }
+        Ok(())
+    }
+}
+
+/// Compresses the finished file and removes the original one
+fn rotate_file(path: &Path, compress: bool) -> Result<(), SinkError> {
+    if !compress {
+        return Ok(());
+    }
+    info!(target: "indexer", "Compressing {}", path.display());
+    let mut compressed_path = path.as_os_str().to_owned();
+    compressed_path.push(".zst");
+    let mut compressed = File::create(&compressed_path)?;
+    zstd::stream::copy_encode(File::open(path)?, &mut compressed, COMPRESSION_LEVEL)?;
+    compressed.sync_all()?;
+    fs::remove_file(path)?;
+    Ok(())
+}
+
+impl Sink for NdjsonSink {
+    fn write(&mut self, streamer_message: &StreamerMessage) -> Result<(), SinkError> {
+        let block_height = streamer_message.block.header.height;
+        if self.current.as_ref().map_or(false, |file| self.should_rotate(file, block_height)) {
+            self.rotate()?;
+        }
+        if self.current.is_none() {
+            let path = self.config.dir.join(format!("{:012}.{}", block_height, NDJSON_EXTENSION));
+            let file = OpenOptions::new().create(true).append(true).open(&path)?;
+            let size = file.metadata()?.len();
+            self.current = Some(NdjsonFile {
+                path,
+                writer: BufWriter::new(file),
+                first_block_height: block_height,
+                size,
+            });
+        }
+        let file = self.current.as_mut().unwrap();
+        let mut line = serde_json::to_vec(streamer_message)?;
+        line.push(b'\n');
+        file.writer.write_all(&line)?;
+        file.writer.flush()?;
+        file.writer.get_ref().sync_data()?;
+        file.size += line.len() as u64;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader};
+
+    use super::super::tests::streamer_message;
+    use super::*;
+
+    fn heights(lines: impl BufRead) -> Vec<BlockHeight> {
+        lines
+            .lines()
+            .map(|line| {
+                let streamer_message: StreamerMessage =
+                    serde_json::from_str(&line.unwrap()).unwrap();
+                streamer_message.block.header.height
+            })
+            .collect()
+    }
+
+    fn file_heights(path: &Path) -> Vec<BlockHeight> {
+        heights(BufReader::new(File::open(path).unwrap()))
+    }
+
+    fn compressed_file_heights(path: &Path) -> Vec<BlockHeight> {
+        heights(&zstd::stream::decode_all(File::open(path).unwrap()).unwrap()[..])
+    }
+
+    fn file_names(dir: &Path) -> Vec<String> {
+        let mut names: Vec<_> = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn test_rotate_by_height_span() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = NdjsonSinkConfig {
+            dir: dir.path().join("blocks"),
+            max_file_size: None,
+            max_file_height_span: Some(2),
+            compress: false,
+        };
+        let mut sink = NdjsonSink::open(config.clone()).unwrap();
+        for block_height in &[1, 2, 3, 5, 6] {
+            sink.write(&streamer_message(*block_height)).unwrap();
+        }
+        assert_eq!(
+            file_names(&config.dir),
+            vec!["000000000001.ndjson", "000000000003.ndjson", "000000000005.ndjson"]
+        );
+        assert_eq!(file_heights(&config.dir.join("000000000001.ndjson")), vec![1, 2]);
+        assert_eq!(file_heights(&config.dir.join("000000000003.ndjson")), vec![3]);
+        assert_eq!(file_heights(&config.dir.join("000000000005.ndjson")), vec![5, 6]);
+
+        // After the restart the first block starts a new file, appending to the file left
+        // with the same first block
+        drop(sink);
+        let mut sink = NdjsonSink::open(config.clone()).unwrap();
+        sink.write(&streamer_message(5)).unwrap();
+        assert_eq!(file_heights(&config.dir.join("000000000005.ndjson")), vec![5, 6, 5]);
+        sink.write(&streamer_message(7)).unwrap();
+        assert_eq!(file_heights(&config.dir.join("000000000007.ndjson")), vec![7]);
+    }
+
+    #[test]
+    fn test_rotate_by_size_with_compression() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = NdjsonSinkConfig {
+            dir: dir.path().to_path_buf(),
+            max_file_size: Some(1),
+            max_file_height_span: None,
+            compress: true,
+        };
+        let mut sink = NdjsonSink::open(config.clone()).unwrap();
+        sink.write(&streamer_message(1)).unwrap();
+        sink.write(&streamer_message(2)).unwrap();
+        assert_eq!(file_names(dir.path()), vec!["000000000001.ndjson.zst", "000000000002.ndjson"]);
+        assert_eq!(compressed_file_heights(&dir.path().join("000000000001.ndjson.zst")), vec![1]);
+        assert_eq!(file_heights(&dir.path().join("000000000002.ndjson")), vec![2]);
+
+        // The file left unfinished is compressed on restart
+        drop(sink);
+        NdjsonSink::open(config).unwrap();
+        assert_eq!(
+            file_names(dir.path()),
+            vec!["000000000001.ndjson.zst", "000000000002.ndjson.zst"]
+        );
+        assert_eq!(compressed_file_heights(&dir.path().join("000000000002.ndjson.zst")), vec![2]);
+    }
+}
@@ -0,0 +1,505 @@
+//! Sink writing the blocks into relational tables of a SQLite database.
+//!
+//! The schema is created and upgraded by `MIGRATIONS` when the sink is opened, the number of
+//! the applied migrations is kept in `PRAGMA user_version`. Each block is written in a single
+//! transaction, and writing the same block again replaces its rows.
+use std::path::Path;
+
+use rusqlite::{params, Connection, Transaction};
+
+use near_primitives::views;
+
+use super::{Sink, SinkError};
+use crate::{IndexerExecutionOutcomeWithReceipt, StreamerMessage};
+
+/// Schema migrations, applied in order. Applied migrations must never be changed,
+/// the schema is changed by appending a new one.
+const MIGRATIONS: &[&str] = &[
+    "CREATE TABLE blocks (
+        hash TEXT PRIMARY KEY,
+        height INTEGER NOT NULL,
+        prev_hash TEXT NOT NULL,
+        author TEXT NOT NULL,
+        timestamp INTEGER NOT NULL,
+        gas_price TEXT NOT NULL
+    );
+    CREATE TABLE chunks (
+        hash TEXT PRIMARY KEY,
+        block_hash TEXT NOT NULL REFERENCES blocks (hash),
+        shard_id INTEGER NOT NULL,
+        author TEXT NOT NULL,
+        gas_used INTEGER NOT NULL,
+        gas_limit INTEGER NOT NULL
+    );
+    CREATE TABLE transactions (
+        hash TEXT PRIMARY KEY,
+        block_hash TEXT NOT NULL REFERENCES blocks (hash),
+        chunk_hash TEXT NOT NULL REFERENCES chunks (hash),
+        signer_id TEXT NOT NULL,
+        public_key TEXT NOT NULL,
+        nonce INTEGER NOT NULL,
+        receiver_id TEXT NOT NULL,
+        actions TEXT NOT NULL
+    );
+    CREATE TABLE receipts (
+        receipt_id TEXT PRIMARY KEY,
+        block_hash TEXT NOT NULL REFERENCES blocks (hash),
+        chunk_hash TEXT NOT NULL REFERENCES chunks (hash),
+        predecessor_id TEXT NOT NULL,
+        receiver_id TEXT NOT NULL,
+        receipt TEXT NOT NULL
+    );
+    CREATE TABLE execution_outcomes (
+        id TEXT PRIMARY KEY,
+        block_hash TEXT NOT NULL REFERENCES blocks (hash),
+        executor_id TEXT NOT NULL,
+        gas_burnt INTEGER NOT NULL,
+        tokens_burnt TEXT NOT NULL,
+        status TEXT NOT NULL,
+        logs TEXT NOT NULL,
+        receipt_ids TEXT NOT NULL
+    );
+    CREATE TABLE account_changes (
+        block_hash TEXT NOT NULL REFERENCES blocks (hash),
+        account_id TEXT NOT NULL,
+        cause TEXT NOT NULL,
+        amount TEXT,
+        locked TEXT,
+        code_hash TEXT,
+        storage_usage INTEGER,
+        deleted INTEGER NOT NULL
+    );",
+    "CREATE INDEX blocks_height_idx ON blocks (height);
+    CREATE INDEX transactions_signer_id_idx ON transactions (signer_id);
+    CREATE INDEX transactions_receiver_id_idx ON transactions (receiver_id);
+    CREATE INDEX receipts_receiver_id_idx ON receipts (receiver_id);
+    CREATE INDEX execution_outcomes_executor_id_idx ON execution_outcomes (executor_id);
+    CREATE INDEX account_changes_block_hash_idx ON account_changes (block_hash);
+    CREATE INDEX account_changes_account_id_idx ON account_changes (account_id);",
+    "ALTER TABLE execution_outcomes ADD COLUMN gas_profile TEXT;",
+];
+
+pub struct SqliteSink {
+    connection: Connection,
+}
+
+impl SqliteSink {
+    /// Opens the database, creating it if needed, and applies the missing migrations
+    pub fn open(path: &Path) -> Result<Self, SinkError> {
+        let mut connection = Connection::open(path)?;
+        migrate(&mut connection)?;
+        Ok(Self { connection })
+    }
+}
+
+fn migrate(connection: &mut Connection) -> Result<(), SinkError> {
+    let version: i64 = connection.pragma_query_value(None, "user_version", |row| row.get(0))?;
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        let transaction = connection.transaction()?;
+        transaction.execute_batch(migration)?;
+        transaction.pragma_update(None, "user_version", &(index as i64 + 1))?;
+        transaction.commit()?;
+    }
+    Ok(())
+}
+
+fn insert_outcome(
+    transaction: &Transaction,
+    indexer_outcome: &IndexerExecutionOutcomeWithReceipt,
+) -> Result<(), SinkError> {
+    let outcome = &indexer_outcome.execution_outcome;
+    let gas_profile = match &indexer_outcome.gas_profile {
+        Some(gas_profile) => Some(serde_json::to_string(gas_profile)?),
+        None => None,
+    };
+    transaction.execute(
+        "INSERT OR REPLACE INTO execution_outcomes
+            (id, block_hash, executor_id, gas_burnt, tokens_burnt, status, logs, receipt_ids,
+                gas_profile)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+        params![
+            outcome.id.to_string(),
+            outcome.block_hash.to_string(),
+            outcome.outcome.executor_id,
+            outcome.outcome.gas_burnt as i64,
+            outcome.outcome.tokens_burnt.to_string(),
+            serde_json::to_string(&outcome.outcome.status)?,
+            serde_json::to_string(&outcome.outcome.logs)?,
+            serde_json::to_string(&outcome.outcome.receipt_ids)?,
+            gas_profile,
+        ],
+    )?;
+    Ok(())
+}
+
+fn insert_account_change(
+    transaction: &Transaction,
+    block_hash: &str,
+    state_change: &views::StateChangeWithCauseView,
+) -> Result<(), SinkError> {
+    let (account_id, account) = match &state_change.value {
+        views::StateChangeValueView::AccountUpdate { account_id, account } => {
+            (account_id, Some(account))
+        }
+        views::StateChangeValueView::AccountDeletion { account_id } => (account_id, None),
+        _ => return Ok(()),
+    };
+    transaction.execute(
+        "INSERT INTO account_changes
+            (block_hash, account_id, cause, amount, locked, code_hash, storage_usage, deleted)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+        params![
+            block_hash,
+            account_id,
+            serde_json::to_string(&state_change.cause)?,
+            account.map(|account| account.amount.to_string()),
+            account.map(|account| account.locked.to_string()),
+            account.map(|account| account.code_hash.to_string()),
+            account.map(|account| account.storage_usage as i64),
+            account.is_none(),
+        ],
+    )?;
+    Ok(())
+}
+
+fn insert_receipt(
+    transaction: &Transaction,
+    block_hash: &str,
+    chunk_hash: &str,
+    receipt: &views::ReceiptView,
+) -> Result<(), SinkError> {
+    transaction.execute(
+        "INSERT OR REPLACE INTO receipts
+            (receipt_id, block_hash, chunk_hash, predecessor_id, receiver_id, receipt)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![
+            receipt.receipt_id.to_string(),
+            block_hash,
+            chunk_hash,
+            receipt.predecessor_id,
+            receipt.receiver_id,
+            serde_json::to_string(&receipt.receipt)?,
+        ],
+    )?;
+    Ok(())
+}
+
+impl Sink for SqliteSink {
+    fn write(&mut self, streamer_message: &StreamerMessage) -> Result<(), SinkError> {
+        let block = &streamer_message.block;
+        let block_hash = block.header.hash.to_string();
+        let transaction = self.connection.transaction()?;
+
+        transaction.execute(
+            "INSERT OR REPLACE INTO blocks (hash, height, prev_hash, author, timestamp, gas_price)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                block_hash,
+                block.header.height as i64,
+                block.header.prev_hash.to_string(),
+                block.author,
+                block.header.timestamp as i64,
+                block.header.gas_price.to_string(),
+            ],
+        )?;
+
+        for chunk in &streamer_message.chunks {
+            let chunk_hash = chunk.header.chunk_hash.to_string();
+            transaction.execute(
+                "INSERT OR REPLACE INTO chunks (hash, block_hash, shard_id, author, gas_used, gas_limit)
+                    VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![
+                    chunk_hash,
+                    block_hash,
+                    chunk.header.shard_id as i64,
+                    chunk.author,
+                    chunk.header.gas_used as i64,
+                    chunk.header.gas_limit as i64,
+                ],
+            )?;
+            for indexer_transaction in &chunk.transactions {
+                let signed_transaction = &indexer_transaction.transaction;
+                transaction.execute(
+                    "INSERT OR REPLACE INTO transactions
+                        (hash, block_hash, chunk_hash, signer_id, public_key, nonce, receiver_id, actions)
+                        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                    params![
+                        signed_transaction.hash.to_string(),
+                        block_hash,
+                        chunk_hash,
+                        signed_transaction.signer_id,
+                        signed_transaction.public_key.to_string(),
+                        signed_transaction.nonce as i64,
+                        signed_transaction.receiver_id,
+                        serde_json::to_string(&signed_transaction.actions)?,
+                    ],
+                )?;
+                insert_outcome(&transaction, &indexer_transaction.outcome)?;
+            }
+            for receipt in &chunk.receipts {
+                insert_receipt(&transaction, &block_hash, &chunk_hash, receipt)?;
+            }
+            for outcome in &chunk.receipt_execution_outcomes {
+                insert_outcome(&transaction, outcome)?;
+            }
+        }
+
+        // Account changes have no key of their own, so they are replaced for the whole block
+        transaction
+            .execute("DELETE FROM account_changes WHERE block_hash = ?1", params![block_hash])?;
+        for state_change in &streamer_message.state_changes {
+            insert_account_change(&transaction, &block_hash, state_change)?;
+        }
+
+        transaction.commit()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rusqlite::types::Value;
+
+    use near_crypto::{InMemorySigner, KeyType};
+    use near_primitives::hash::{hash, CryptoHash};
+    use near_primitives::receipt::Receipt;
+    use near_primitives::sharding::{ShardChunkHeader, ShardChunkHeaderV2};
+    use near_primitives::transaction::SignedTransaction;
+    use near_primitives::validator_signer::InMemoryValidatorSigner;
+
+    use super::super::tests::streamer_message;
+    use super::*;
+    use crate::{IndexerChunkView, IndexerTransactionWithOutcome};
+
+    const TABLES: &[&str] =
+        &["blocks", "chunks", "transactions", "receipts", "execution_outcomes", "account_changes"];
+
+    fn outcome(
+        id: CryptoHash,
+        block_hash: CryptoHash,
+        executor_id: &str,
+        receipt_ids: Vec<CryptoHash>,
+    ) -> IndexerExecutionOutcomeWithReceipt {
+        IndexerExecutionOutcomeWithReceipt {
+            execution_outcome: views::ExecutionOutcomeWithIdView {
+                proof: vec![],
+                block_hash,
+                id,
+                outcome: views::ExecutionOutcomeView {
+                    logs: vec!["transfer".to_string()],
+                    receipt_ids,
+                    gas_burnt: 223182562500,
+                    tokens_burnt: 22318256250000000000,
+                    executor_id: executor_id.to_string(),
+                    status: views::ExecutionStatusView::SuccessValue(String::new()),
+                },
+            },
+            receipt: None,
+            state_changes: vec![],
+            gas_profile: None,
+        }
+    }
+
+    /// Block with a transfer from `alice.near` to `bob.near` and the deletion of `carol.near`.
+    fn transfer_message() -> StreamerMessage {
+        let mut streamer_message = streamer_message(1);
+        let block_hash = streamer_message.block.header.hash;
+
+        let validator_signer =
+            InMemoryValidatorSigner::from_seed("test.near", KeyType::ED25519, "test.near");
+        let chunk_header = ShardChunkHeader::V2(ShardChunkHeaderV2::new(
+            streamer_message.block.header.prev_hash,
+            CryptoHash::default(),
+            CryptoHash::default(),
+            CryptoHash::default(),
+            0,
+            1,
+            0,
+            424555062500,
+            1000000000000000,
+            0,
+            CryptoHash::default(),
+            CryptoHash::default(),
+            vec![],
+            &validator_signer,
+        ));
+
+        let signer = InMemorySigner::from_seed("alice.near", KeyType::ED25519, "alice.near");
+        let transaction = views::SignedTransactionView::from(SignedTransaction::send_money(
+            1,
+            "alice.near".to_string(),
+            "bob.near".to_string(),
+            &signer,
+            100,
+            block_hash,
+        ));
+        let mut receipt = Receipt::new_balance_refund(&"bob.near".to_string(), 100);
+        receipt.predecessor_id = "alice.near".to_string();
+        receipt.receipt_id = hash(b"receipt");
+        let receipt = views::ReceiptView::from(receipt);
+
+        streamer_message.chunks.push(IndexerChunkView {
+            author: "test.near".to_string(),
+            header: chunk_header.into(),
+            transactions: vec![IndexerTransactionWithOutcome {
+                outcome: outcome(
+                    transaction.hash,
+                    block_hash,
+                    "alice.near",
+                    vec![receipt.receipt_id],
+                ),
+                transaction,
+            }],
+            receipt_execution_outcomes: vec![outcome(
+                receipt.receipt_id,
+                block_hash,
+                "bob.near",
+                vec![],
+            )],
+            receipts: vec![receipt],
+        });
+
+        streamer_message.state_changes = vec![
+            views::StateChangeWithCauseView {
+                cause: views::StateChangeCauseView::ReceiptProcessing {
+                    receipt_hash: hash(b"receipt"),
+                },
+                value: views::StateChangeValueView::AccountUpdate {
+                    account_id: "bob.near".to_string(),
+                    account: views::AccountView {
+                        amount: 1100,
+                        locked: 0,
+                        code_hash: CryptoHash::default(),
+                        storage_usage: 182,
+                        storage_paid_at: 0,
+                    },
+                },
+            },
+            views::StateChangeWithCauseView {
+                cause: views::StateChangeCauseView::InitialState,
+                value: views::StateChangeValueView::AccountDeletion {
+                    account_id: "carol.near".to_string(),
+                },
+            },
+        ];
+        streamer_message
+    }
+
+    fn rows(connection: &Connection, query: &str) -> Vec<Vec<Value>> {
+        let mut statement = connection.prepare(query).unwrap();
+        let column_count = statement.column_count();
+        statement
+            .query_map(params![], |row| {
+                (0..column_count)
+                    .map(|index| row.get(index))
+                    .collect::<rusqlite::Result<Vec<Value>>>()
+            })
+            .unwrap()
+            .map(Result::unwrap)
+            .collect()
+    }
+
+    fn all_rows(connection: &Connection) -> Vec<Vec<Vec<Value>>> {
+        TABLES
+            .iter()
+            .map(|table| rows(connection, &format!("SELECT * FROM {} ORDER BY 1, 2", table)))
+            .collect()
+    }
+
+    fn text(value: &str) -> Value {
+        Value::Text(value.to_string())
+    }
+
+    #[test]
+    fn test_write_block() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut sink = SqliteSink::open(&dir.path().join("blocks.sqlite")).unwrap();
+        let streamer_message = transfer_message();
+        sink.write(&streamer_message).unwrap();
+
+        let block_hash = streamer_message.block.header.hash.to_string();
+        let chunk = &streamer_message.chunks[0];
+        let chunk_hash = chunk.header.chunk_hash.to_string();
+        let transaction_hash = chunk.transactions[0].transaction.hash.to_string();
+        let receipt_id = chunk.receipts[0].receipt_id.to_string();
+        let connection = &sink.connection;
+
+        assert_eq!(
+            rows(connection, "SELECT hash, height, author FROM blocks"),
+            vec![vec![text(&block_hash), Value::Integer(1), text("test.near")]]
+        );
+        assert_eq!(
+            rows(connection, "SELECT hash, block_hash, shard_id, gas_used FROM chunks"),
+            vec![vec![
+                text(&chunk_hash),
+                text(&block_hash),
+                Value::Integer(0),
+                Value::Integer(424555062500)
+            ]]
+        );
+        assert_eq!(
+            rows(
+                connection,
+                "SELECT hash, chunk_hash, signer_id, nonce, receiver_id FROM transactions"
+            ),
+            vec![vec![
+                text(&transaction_hash),
+                text(&chunk_hash),
+                text("alice.near"),
+                Value::Integer(1),
+                text("bob.near")
+            ]]
+        );
+        assert_eq!(
+            rows(
+                connection,
+                "SELECT receipt_id, chunk_hash, predecessor_id, receiver_id FROM receipts"
+            ),
+            vec![vec![text(&receipt_id), text(&chunk_hash), text("alice.near"), text("bob.near")]]
+        );
+        assert_eq!(
+            rows(
+                connection,
+                "SELECT id, executor_id, receipt_ids, gas_profile FROM execution_outcomes
+                    ORDER BY executor_id"
+            ),
+            vec![
+                vec![
+                    text(&transaction_hash),
+                    text("alice.near"),
+                    text(&serde_json::to_string(&vec![&receipt_id]).unwrap()),
+                    Value::Null
+                ],
+                vec![text(&receipt_id), text("bob.near"), text("[]"), Value::Null],
+            ]
+        );
+        assert_eq!(
+            rows(
+                connection,
+                "SELECT account_id, amount, storage_usage, deleted FROM account_changes
+                    ORDER BY account_id"
+            ),
+            vec![
+                vec![text("bob.near"), text("1100"), Value::Integer(182), Value::Integer(0)],
+                vec![text("carol.near"), Value::Null, Value::Null, Value::Integer(1)],
+            ]
+        );
+
+        // Writing the same block again, e.g. after the restart of the Indexer, leaves the rows as
+        // they were
+        let written = all_rows(&sink.connection);
+        sink.write(&streamer_message).unwrap();
+        assert_eq!(all_rows(&sink.connection), written);
+    }
+
+    #[test]
+    fn test_migrate() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        migrate(&mut connection).unwrap();
+        // Applied migrations are skipped
+        migrate(&mut connection).unwrap();
+        let version: i64 =
+            connection.pragma_query_value(None, "user_version", |row| row.get(0)).unwrap();
+        assert_eq!(version as usize, MIGRATIONS.len());
+    }
+}
@@ -0,0 +1,232 @@
+//! Progress of the streaming, stored in the Indexer's own database next to the node's one,
+//! so the streaming can be resumed after the restart.
+use std::sync::{Arc, Mutex};
+
+use rocksdb::{Options, DB};
+use tokio::time;
+
+use near_primitives::types::BlockHeight;
+
+use super::INTERVAL;
+
+const LAST_SYNCED_BLOCK_HEIGHT_KEY: &[u8] = b"last_synced_block_height";
+const LAST_OPTIMISTIC_SYNCED_BLOCK_HEIGHT_KEY: &[u8] = b"last_optimistic_synced_block_height";
+/// Column with the heights acknowledged by the consumer of the stream
+const COL_ACKNOWLEDGEMENTS: &str = "acknowledgements";
+const LAST_ACKNOWLEDGED_BLOCK_HEIGHT_KEY: &[u8] = b"last_acknowledged_block_height";
+
+fn parse_height(value: Vec<u8>) -> BlockHeight {
+    String::from_utf8(value).unwrap().parse::<u64>().unwrap()
+}
+
+#[derive(Clone)]
+pub(crate) struct Checkpoints {
+    db: Arc<DB>,
+    /// Cached last acknowledged height, the lock also keeps the stored height from going back
+    last_acknowledged_block_height: Arc<Mutex<Option<BlockHeight>>>,
+}
+
+impl Checkpoints {
+    pub(crate) fn open(home_dir: &std::path::Path) -> Self {
+        let mut indexer_db_path = neard::get_store_path(home_dir);
+        indexer_db_path.push_str("/indexer");
+
+        let mut opts = Options::default();
+        opts.create_if_missing(true);
+        opts.create_missing_column_families(true);
+        // TODO: implement proper error handling
+        let db = DB::open_cf(&opts, indexer_db_path, &[COL_ACKNOWLEDGEMENTS]).unwrap();
+        let last_acknowledged_block_height = db
+            .get_cf(db.cf_handle(COL_ACKNOWLEDGEMENTS).unwrap(), LAST_ACKNOWLEDGED_BLOCK_HEIGHT_KEY)
+            .unwrap()
+            .map(parse_height);
+        Self {
+            db: Arc::new(db),
+            last_acknowledged_block_height: Arc::new(Mutex::new(last_acknowledged_block_height)),
+        }
+    }
+
+    /// Height of the last block sent to the consumer
+    pub(crate) fn last_synced_block_height(&self) -> Option<BlockHeight> {
+        self.db.get(LAST_SYNCED_BLOCK_HEIGHT_KEY).unwrap().map(parse_height)
+    }
+
+    pub(crate) fn set_last_synced_block_height(&self, block_height: BlockHeight) {
+        self.db.put(LAST_SYNCED_BLOCK_HEIGHT_KEY, &block_height.to_string()).unwrap();
+    }
+
+    /// Height of the last final block sent to the consumer by the optimistic streamer
+    pub(crate) fn last_optimistic_synced_block_height(&self) -> Option<BlockHeight> {
+        self.db.get(LAST_OPTIMISTIC_SYNCED_BLOCK_HEIGHT_KEY).unwrap().map(parse_height)
+    }
+
+    pub(crate) fn set_last_optimistic_synced_block_height(&self, block_height: BlockHeight) {
+        self.db.put(LAST_OPTIMISTIC_SYNCED_BLOCK_HEIGHT_KEY, &block_height.to_string()).unwrap();
+    }
+
+    /// Height of the last block the consumer confirmed to have processed
+    pub(crate) fn last_acknowledged_block_height(&self) -> Option<BlockHeight> {
+        *self.last_acknowledged_block_height.lock().unwrap()
+    }
+
+    fn acknowledge(&self, block_height: BlockHeight) {
+        let mut last_acknowledged_block_height =
+            self.last_acknowledged_block_height.lock().unwrap();
+        if last_acknowledged_block_height.map_or(false, |height| height >= block_height) {
+            return;
+        }
+        self.db
+            .put_cf(
+                self.db.cf_handle(COL_ACKNOWLEDGEMENTS).unwrap(),
+                LAST_ACKNOWLEDGED_BLOCK_HEIGHT_KEY,
+                &block_height.to_string(),
+            )
+            .unwrap();
+        *last_acknowledged_block_height = Some(block_height);
+    }
+
+    /// Lowers the last acknowledged height to the height of the block the stream is rolled back to,
+    /// so the blocks streamed after the rollback are acknowledged again.
+    pub(crate) fn roll_back_acknowledgements(&self, block_height: BlockHeight) {
+        let mut last_acknowledged_block_height =
+            self.last_acknowledged_block_height.lock().unwrap();
+        if last_acknowledged_block_height.map_or(true, |height| height <= block_height) {
+            return;
+        }
+        self.db
+            .put_cf(
+                self.db.cf_handle(COL_ACKNOWLEDGEMENTS).unwrap(),
+                LAST_ACKNOWLEDGED_BLOCK_HEIGHT_KEY,
+                &block_height.to_string(),
+            )
+            .unwrap();
+        *last_acknowledged_block_height = Some(block_height);
+    }
+}
+
+/// Handle for the consumer of the stream to confirm the blocks it has processed.
+/// See `IndexerConfig::in_flight_window` for details.
+#[derive(Clone)]
+pub struct BlockAcknowledger {
+    checkpoints: Checkpoints,
+}
+
+impl BlockAcknowledger {
+    pub(crate) fn new(checkpoints: Checkpoints) -> Self {
+        Self { checkpoints }
+    }
+
+    /// Confirms that the block of given height and all the blocks streamed before it are
+    /// processed. The acknowledgement is stored by the time this returns. `StreamerEvent::Rollback`
+    /// lowers the acknowledged height to the block it rolls back to, so the blocks streamed after
+    /// it are acknowledged again.
+    pub fn ack(&self, block_height: BlockHeight) {
+        self.checkpoints.acknowledge(block_height)
+    }
+}
+
+/// Blocks streamed to the consumer which it hasn't acknowledged yet
+pub(crate) struct InFlightBlocks {
+    checkpoints: Checkpoints,
+    window: Option<usize>,
+    heights: Vec<BlockHeight>,
+}
+
+impl InFlightBlocks {
+    pub(crate) fn new(checkpoints: Checkpoints, window: Option<usize>) -> Self {
+        Self { checkpoints, window, heights: vec![] }
+    }
+
+    /// Waits until there are less blocks in flight than the window allows
+    pub(crate) async fn wait_for_window(&mut self) {
+        let window = match self.window {
+            Some(window) => window,
+            None => return,
+        };
+        loop {
+            if let Some(acknowledged) = self.checkpoints.last_acknowledged_block_height() {
+                // Heights may go back after a rollback, so they are not sorted
+                self.heights.retain(|height| *height > acknowledged);
+            }
+            if self.heights.len() < window {
+                return;
+            }
+            time::sleep(INTERVAL).await;
+        }
+    }
+
+    pub(crate) fn push(&mut self, block_height: BlockHeight) {
+        if self.window.is_some() {
+            self.heights.push(block_height);
+        }
+    }
+
+    /// Forgets the blocks after the one the stream is rolled back to, the consumer won't
+    /// acknowledge them anymore.
+    pub(crate) fn roll_back(&mut self, block_height: BlockHeight) {
+        self.heights.retain(|height| *height <= block_height);
+        self.checkpoints.roll_back_acknowledgements(block_height);
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    pub(crate) fn open_checkpoints() -> (tempfile::TempDir, Checkpoints) {
+        let home_dir = tempfile::tempdir().unwrap();
+        let checkpoints = Checkpoints::open(home_dir.path());
+        (home_dir, checkpoints)
+    }
+
+    #[test]
+    fn test_acknowledgements() {
+        let (home_dir, checkpoints) = open_checkpoints();
+        assert_eq!(checkpoints.last_acknowledged_block_height(), None);
+        let acknowledger = BlockAcknowledger::new(checkpoints.clone());
+        acknowledger.ack(5);
+        // Acknowledgements don't go back
+        acknowledger.ack(3);
+        assert_eq!(checkpoints.last_acknowledged_block_height(), Some(5));
+
+        // Rollbacks only lower the acknowledged height
+        checkpoints.roll_back_acknowledgements(7);
+        assert_eq!(checkpoints.last_acknowledged_block_height(), Some(5));
+        checkpoints.roll_back_acknowledgements(4);
+        assert_eq!(checkpoints.last_acknowledged_block_height(), Some(4));
+        acknowledger.ack(5);
+        assert_eq!(checkpoints.last_acknowledged_block_height(), Some(5));
+        checkpoints.roll_back_acknowledgements(2);
+
+        drop((acknowledger, checkpoints));
+        let checkpoints = Checkpoints::open(home_dir.path());
+        assert_eq!(checkpoints.last_acknowledged_block_height(), Some(2));
+    }
+
+    #[test]
+    fn test_in_flight_window() {
+        let (_home_dir, checkpoints) = open_checkpoints();
+        let acknowledger = BlockAcknowledger::new(checkpoints.clone());
+        let mut in_flight_blocks = InFlightBlocks::new(checkpoints.clone(), Some(2));
+        actix::System::new().block_on(async move {
+            let timeout = INTERVAL * 3;
+            in_flight_blocks.push(1);
+            time::timeout(timeout, in_flight_blocks.wait_for_window()).await.unwrap();
+            in_flight_blocks.push(2);
+            assert!(time::timeout(timeout, in_flight_blocks.wait_for_window()).await.is_err());
+
+            acknowledger.ack(1);
+            time::timeout(timeout, in_flight_blocks.wait_for_window()).await.unwrap();
+            in_flight_blocks.push(3);
+            assert!(time::timeout(timeout, in_flight_blocks.wait_for_window()).await.is_err());
+
+            // The rolled back blocks are not in flight anymore
+            in_flight_blocks.roll_back(2);
+            time::timeout(timeout, in_flight_blocks.wait_for_window()).await.unwrap();
+            in_flight_blocks.push(3);
+            assert!(time::timeout(timeout, in_flight_blocks.wait_for_window()).await.is_err());
+            acknowledger.ack(3);
+            time::timeout(timeout, in_flight_blocks.wait_for_window()).await.unwrap();
+        });
+    }
+}
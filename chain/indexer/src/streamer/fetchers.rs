@@ -33,6 +33,16 @@ pub(crate) async fn fetch_latest_block(
         .map_err(|err| FailedToFetchData::String(err.to_string()))
 }
 
+/// Fetches the head of the chain, which is not necessarily final
+pub(crate) async fn fetch_head_block(
+    client: &Addr<near_client::ViewClientActor>,
+) -> Result<views::BlockView, FailedToFetchData> {
+    client
+        .send(near_client::GetBlock(types::BlockReference::Finality(types::Finality::None)))
+        .await?
+        .map_err(|err| FailedToFetchData::String(err.to_string()))
+}
+
 /// Fetches specific block by it's height
 pub(crate) async fn fetch_block_by_height(
     client: &Addr<near_client::ViewClientActor>,
@@ -76,45 +86,81 @@ async fn fetch_single_chunk(
 }
 
 /// Fetch all ExecutionOutcomeWithId for current block
-/// Returns a HashMap where the key is Receipt id or Transaction hash and the value is ExecutionOutcome wth id and proof
+/// Returns a HashMap where the key is shard id and the value is ExecutionOutcomes with id of the shard
 pub(crate) async fn fetch_outcomes(
     client: &Addr<near_client::ViewClientActor>,
     block_hash: CryptoHash,
 ) -> Result<
-    HashMap<near_primitives::types::ShardId, Vec<IndexerExecutionOutcomeWithReceipt>>,
+    HashMap<near_primitives::types::ShardId, Vec<views::ExecutionOutcomeWithIdView>>,
     FailedToFetchData,
 > {
-    let outcomes = client
+    client
         .send(near_client::GetExecutionOutcomesForBlock { block_hash })
         .await?
-        .map_err(FailedToFetchData::String)?;
-
-    let mut shard_execution_outcomes_with_receipts: HashMap<
-        near_primitives::types::ShardId,
-        Vec<IndexerExecutionOutcomeWithReceipt>,
-    > = HashMap::new();
-    for (shard_id, shard_outcomes) in outcomes {
-        let mut outcomes_with_receipts: Vec<IndexerExecutionOutcomeWithReceipt> = vec![];
-        for outcome in shard_outcomes {
-            let receipt = match fetch_receipt_by_id(&client, outcome.id).await {
-                Ok(res) => res,
-                Err(e) => {
-                    warn!(
-                        target: INDEXER,
-                        "Unable to fetch Receipt with id {}. Skipping it in ExecutionOutcome \n {:#?}",
-                        outcome.id,
-                        e,
-                    );
-                    None
-                }
-            };
-            outcomes_with_receipts
-                .push(IndexerExecutionOutcomeWithReceipt { execution_outcome: outcome, receipt });
+        .map_err(FailedToFetchData::String)
+}
+
+/// Fetches the receipt of the given ExecutionOutcome, if there is one and it's not `known_receipt`
+/// already, and its gas profile
+pub(crate) async fn fetch_outcome_receipt(
+    client: &Addr<near_client::ViewClientActor>,
+    outcome: views::ExecutionOutcomeWithIdView,
+    known_receipt: Option<views::ReceiptView>,
+) -> IndexerExecutionOutcomeWithReceipt {
+    let receipt = match known_receipt {
+        Some(receipt) => Ok(Some(receipt)),
+        None => fetch_receipt_by_id(&client, outcome.id).await,
+    };
+    let receipt = match receipt {
+        Ok(res) => res,
+        Err(e) => {
+            warn!(
+                target: INDEXER,
+                "Unable to fetch Receipt with id {}. Skipping it in ExecutionOutcome \n {:#?}",
+                outcome.id,
+                e,
+            );
+            None
+        }
+    };
+    #[cfg(feature = "gas_profile")]
+    let gas_profile = match fetch_receipt_gas_profile(&client, &outcome).await {
+        Ok(res) => res,
+        Err(e) => {
+            warn!(
+                target: INDEXER,
+                "Unable to fetch gas profile of Receipt with id {}. Skipping it in ExecutionOutcome \n {:#?}",
+                outcome.id,
+                e,
+            );
+            None
         }
-        shard_execution_outcomes_with_receipts.insert(shard_id, outcomes_with_receipts);
+    };
+    #[cfg(not(feature = "gas_profile"))]
+    let gas_profile = None;
+    IndexerExecutionOutcomeWithReceipt {
+        execution_outcome: outcome,
+        receipt,
+        state_changes: vec![],
+        gas_profile,
     }
+}
 
-    Ok(shard_execution_outcomes_with_receipts)
+/// Fetches the gas profile of the receipt executed in the block of the given ExecutionOutcome.
+/// Profiles are only recorded by the node if `NearConfig::record_gas_profiles` is set and it's built
+/// with `costs_counting`.
+#[cfg(feature = "gas_profile")]
+async fn fetch_receipt_gas_profile(
+    client: &Addr<near_client::ViewClientActor>,
+    outcome: &views::ExecutionOutcomeWithIdView,
+) -> Result<Option<Vec<views::ActionGasProfileView>>, FailedToFetchData> {
+    client
+        .send(near_client::GetReceiptGasProfile {
+            block_hash: outcome.block_hash,
+            receipt_id: outcome.id,
+        })
+        .await?
+        .map_err(FailedToFetchData::String)
 }
 
 async fn fetch_receipt_by_id(
@@ -1,28 +1,34 @@
+use std::collections::HashMap;
 use std::time::Duration;
 
 use actix::Addr;
-use rocksdb::DB;
 use tokio::sync::mpsc;
 use tokio::time;
 use tracing::{debug, info};
 
+use near_primitives::types::BlockHeight;
 pub use near_primitives::views;
 
-use crate::{AwaitForNodeSyncedEnum, IndexerConfig};
+use crate::{AwaitForNodeSyncedEnum, IndexerConfig, IndexerFilter};
 
+pub use self::checkpoints::BlockAcknowledger;
+pub(crate) use self::checkpoints::Checkpoints;
+use self::checkpoints::InFlightBlocks;
 use self::errors::FailedToFetchData;
 use self::fetchers::{
-    fetch_block_by_height, fetch_chunks, fetch_latest_block, fetch_outcomes, fetch_state_changes,
-    fetch_status,
+    fetch_block_by_height, fetch_chunks, fetch_latest_block, fetch_outcome_receipt, fetch_outcomes,
+    fetch_state_changes, fetch_status,
 };
 pub use self::types::{
     IndexerChunkView, IndexerExecutionOutcomeWithReceipt, IndexerTransactionWithOutcome,
-    StreamerMessage,
+    StreamerEvent, StreamerMessage,
 };
-use self::utils::convert_transactions_sir_into_local_receipts;
+use self::utils::{attach_state_changes_to_outcomes, convert_transactions_sir_into_local_receipts};
 
+mod checkpoints;
 mod errors;
 mod fetchers;
+pub(crate) mod optimistic;
 mod types;
 mod utils;
 
@@ -31,17 +37,19 @@ const INTERVAL: Duration = Duration::from_millis(500);
 
 /// This function supposed to return the entire `StreamerMessage`.
 /// It fetches the block and all related parts (chunks, outcomes, state changes etc.)
-/// and returns everything together in one struct
+/// and returns everything together in one struct.
+/// Only the parts matching the `filter` are fetched and returned.
 async fn build_streamer_message(
     client: &Addr<near_client::ViewClientActor>,
     block: views::BlockView,
     near_config: &neard::NearConfig,
+    filter: &IndexerFilter,
 ) -> Result<StreamerMessage, FailedToFetchData> {
     let chunks_to_fetch = block
         .chunks
         .iter()
         .filter_map(|c| {
-            if c.height_included == block.header.height {
+            if c.height_included == block.header.height && filter.matches_shard(c.shard_id) {
                 Some(c.chunk_hash)
             } else {
                 None
@@ -51,7 +59,11 @@ async fn build_streamer_message(
     let chunks = fetch_chunks(&client, chunks_to_fetch).await?;
 
     let mut local_receipts: Vec<views::ReceiptView> = vec![];
-    let mut shards_outcomes = fetch_outcomes(&client, block.header.hash).await?;
+    let mut shards_outcomes = if chunks.is_empty() {
+        HashMap::new()
+    } else {
+        fetch_outcomes(&client, block.header.hash).await?
+    };
     let mut indexer_chunks: Vec<IndexerChunkView> = vec![];
 
     for chunk in chunks {
@@ -60,19 +72,20 @@ async fn build_streamer_message(
         let mut outcomes = shards_outcomes
             .remove(&header.shard_id)
             .expect("Execution outcomes for given shard should be present");
-        let mut receipt_outcomes = outcomes.split_off(transactions.len());
+        let receipt_outcomes = outcomes.split_off(transactions.len());
 
-        let indexer_transactions = transactions
-            .into_iter()
-            .zip(outcomes.into_iter())
-            .map(|(transaction, outcome)| {
-                assert_eq!(
-                    outcome.execution_outcome.id, transaction.hash,
-                    "This ExecutionOutcome must have the same id as Transaction hash"
-                );
-                IndexerTransactionWithOutcome { outcome, transaction }
-            })
-            .collect::<Vec<IndexerTransactionWithOutcome>>();
+        let mut indexer_transactions: Vec<IndexerTransactionWithOutcome> = vec![];
+        for (transaction, outcome) in transactions.into_iter().zip(outcomes.into_iter()) {
+            assert_eq!(
+                outcome.id, transaction.hash,
+                "This ExecutionOutcome must have the same id as Transaction hash"
+            );
+            if !filter.matches_transaction(&transaction) {
+                continue;
+            }
+            let outcome = fetch_outcome_receipt(&client, outcome, None).await;
+            indexer_transactions.push(IndexerTransactionWithOutcome { outcome, transaction });
+        }
 
         let chunk_local_receipts = convert_transactions_sir_into_local_receipts(
             &client,
@@ -88,16 +101,23 @@ async fn build_streamer_message(
         local_receipts.extend_from_slice(&chunk_local_receipts);
 
         let mut chunk_receipts = chunk_local_receipts;
-        chunk_receipts.extend(chunk_non_local_receipts);
+        chunk_receipts.extend(
+            chunk_non_local_receipts.into_iter().filter(|receipt| filter.matches_receipt(receipt)),
+        );
 
-        // Add local receipts to corresponding outcomes
-        for receipt in &local_receipts {
-            if let Some(outcome) = receipt_outcomes
-                .iter_mut()
-                .find(|outcome| outcome.execution_outcome.id == receipt.receipt_id)
+        let mut indexer_receipt_outcomes: Vec<IndexerExecutionOutcomeWithReceipt> = vec![];
+        for (outcome, local_receipt) in
+            select_receipt_outcomes(filter, receipt_outcomes, &local_receipts)
+        {
+            let is_local = local_receipt.is_some();
+            let outcome = fetch_outcome_receipt(&client, outcome, local_receipt).await;
+            // The actions of the fetched receipts are checked only now. Outcomes whose receipt
+            // couldn't be fetched are kept.
+            if is_local
+                || filter
+                    .matches_receipt_outcome(&outcome.execution_outcome, outcome.receipt.as_ref())
             {
-                debug_assert!(outcome.receipt.is_none());
-                outcome.receipt = Some(receipt.clone());
+                indexer_receipt_outcomes.push(outcome);
             }
         }
 
@@ -106,15 +126,91 @@ async fn build_streamer_message(
             header,
             transactions: indexer_transactions,
             receipts: chunk_receipts,
-            receipt_execution_outcomes: receipt_outcomes,
+            receipt_execution_outcomes: indexer_receipt_outcomes,
         });
     }
 
-    let state_changes = fetch_state_changes(&client, block.header.hash).await?;
+    let state_changes = if filter.include_state_changes {
+        let state_changes: views::StateChangesView =
+            fetch_state_changes(&client, block.header.hash)
+                .await?
+                .into_iter()
+                .filter(|state_change| filter.matches_state_change(state_change))
+                .collect();
+        let outcomes = indexer_chunks.iter_mut().flat_map(|chunk| {
+            chunk
+                .transactions
+                .iter_mut()
+                .map(|transaction| &mut transaction.outcome)
+                .chain(chunk.receipt_execution_outcomes.iter_mut())
+        });
+        attach_state_changes_to_outcomes(outcomes, &state_changes);
+        state_changes
+    } else {
+        vec![]
+    };
 
     Ok(StreamerMessage { block, chunks: indexer_chunks, state_changes })
 }
 
+/// Receipt execution outcomes of a chunk which match the `filter` before their receipts are
+/// fetched, with the receipt if it's one of the `local_receipts`, which don't need fetching.
+fn select_receipt_outcomes(
+    filter: &IndexerFilter,
+    receipt_outcomes: Vec<views::ExecutionOutcomeWithIdView>,
+    local_receipts: &[views::ReceiptView],
+) -> Vec<(views::ExecutionOutcomeWithIdView, Option<views::ReceiptView>)> {
+    receipt_outcomes
+        .into_iter()
+        .filter_map(|outcome| {
+            let local_receipt =
+                local_receipts.iter().find(|receipt| receipt.receipt_id == outcome.id);
+            if filter.matches_receipt_outcome(&outcome, local_receipt) {
+                Some((outcome, local_receipt.cloned()))
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+/// Whether the node is synced enough to stream blocks according to `await_for_node_synced`
+async fn is_ready_to_stream(
+    client: &Addr<near_client::ClientActor>,
+    indexer_config: &IndexerConfig,
+) -> bool {
+    match indexer_config.await_for_node_synced {
+        AwaitForNodeSyncedEnum::WaitForFullSync => match fetch_status(&client).await {
+            Ok(status) => !status.sync_info.syncing,
+            Err(_) => true,
+        },
+        AwaitForNodeSyncedEnum::StreamWhileSyncing => true,
+    }
+}
+
+/// Height of the first block to stream according to `sync_mode`
+fn initial_block_height(
+    checkpoints: &Checkpoints,
+    indexer_config: &IndexerConfig,
+    latest_block_height: BlockHeight,
+) -> BlockHeight {
+    match indexer_config.sync_mode {
+        crate::SyncModeEnum::FromInterruption => {
+            let last_acknowledged_block_height = if indexer_config.in_flight_window.is_some() {
+                checkpoints.last_acknowledged_block_height()
+            } else {
+                None
+            };
+            match last_acknowledged_block_height {
+                Some(height) => height + 1,
+                None => checkpoints.last_synced_block_height().unwrap_or(latest_block_height),
+            }
+        }
+        crate::SyncModeEnum::LatestSynced => latest_block_height,
+        crate::SyncModeEnum::BlockHeight(height) => height,
+    }
+}
+
 /// Function that starts Streamer's busy loop. Every half a seconds it fetches the status
 /// compares to already fetched block height and in case it differs fetches new block of given height.
 ///
@@ -124,29 +220,19 @@ pub(crate) async fn start(
     client: Addr<near_client::ClientActor>,
     near_config: neard::NearConfig,
     indexer_config: IndexerConfig,
+    checkpoints: Checkpoints,
     blocks_sink: mpsc::Sender<StreamerMessage>,
 ) {
     info!(target: INDEXER, "Starting Streamer...");
-    let mut indexer_db_path = neard::get_store_path(&indexer_config.home_dir);
-    indexer_db_path.push_str("/indexer");
-
-    // TODO: implement proper error handling
-    let db = DB::open_default(indexer_db_path).unwrap();
+    let mut in_flight_blocks =
+        InFlightBlocks::new(checkpoints.clone(), indexer_config.in_flight_window);
     let mut last_synced_block_height: Option<near_primitives::types::BlockHeight> = None;
 
     'main: loop {
         time::sleep(INTERVAL).await;
-        match indexer_config.await_for_node_synced {
-            AwaitForNodeSyncedEnum::WaitForFullSync => {
-                let status = fetch_status(&client).await;
-                if let Ok(status) = status {
-                    if status.sync_info.syncing {
-                        continue;
-                    }
-                }
-            }
-            AwaitForNodeSyncedEnum::StreamWhileSyncing => {}
-        };
+        if !is_ready_to_stream(&client, &indexer_config).await {
+            continue;
+        }
 
         let block = if let Ok(block) = fetch_latest_block(&view_client).await {
             block
@@ -155,22 +241,12 @@ pub(crate) async fn start(
         };
 
         let latest_block_height = block.header.height;
-        let start_syncing_block_height = if let Some(last_synced_block_height) =
-            last_synced_block_height
-        {
-            last_synced_block_height + 1
-        } else {
-            match indexer_config.sync_mode {
-                crate::SyncModeEnum::FromInterruption => {
-                    match db.get(b"last_synced_block_height").unwrap() {
-                        Some(value) => String::from_utf8(value).unwrap().parse::<u64>().unwrap(),
-                        None => latest_block_height,
-                    }
-                }
-                crate::SyncModeEnum::LatestSynced => latest_block_height,
-                crate::SyncModeEnum::BlockHeight(height) => height,
-            }
-        };
+        let start_syncing_block_height =
+            if let Some(last_synced_block_height) = last_synced_block_height {
+                last_synced_block_height + 1
+            } else {
+                initial_block_height(&checkpoints, &indexer_config, latest_block_height)
+            };
 
         debug!(
             target: INDEXER,
@@ -180,11 +256,18 @@ pub(crate) async fn start(
         );
         for block_height in start_syncing_block_height..=latest_block_height {
             if let Ok(block) = fetch_block_by_height(&view_client, block_height).await {
-                let response = build_streamer_message(&view_client, block, &near_config).await;
+                let response = build_streamer_message(
+                    &view_client,
+                    block,
+                    &near_config,
+                    &indexer_config.filter,
+                )
+                .await;
 
                 match response {
                     Ok(streamer_message) => {
                         debug!(target: INDEXER, "{:#?}", &streamer_message);
+                        in_flight_blocks.wait_for_window().await;
                         if blocks_sink.send(streamer_message).await.is_err() {
                             info!(
                                 target: INDEXER,
@@ -192,6 +275,7 @@ pub(crate) async fn start(
                             );
                             break 'main;
                         }
+                        in_flight_blocks.push(block_height);
                     }
                     Err(err) => {
                         debug!(
@@ -202,8 +286,129 @@ pub(crate) async fn start(
                     }
                 }
             }
-            db.put(b"last_synced_block_height", &block_height.to_string()).unwrap();
+            checkpoints.set_last_synced_block_height(block_height);
             last_synced_block_height = Some(block_height);
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use near_crypto::{KeyType, PublicKey};
+    use near_primitives::hash::{hash, CryptoHash};
+
+    use super::checkpoints::tests::open_checkpoints;
+    use super::*;
+    use crate::{ActionKind, SyncModeEnum};
+
+    fn indexer_config(sync_mode: SyncModeEnum, in_flight_window: Option<usize>) -> IndexerConfig {
+        IndexerConfig {
+            home_dir: std::path::PathBuf::new(),
+            sync_mode,
+            await_for_node_synced: AwaitForNodeSyncedEnum::StreamWhileSyncing,
+            filter: IndexerFilter::default(),
+            in_flight_window,
+        }
+    }
+
+    #[test]
+    fn test_initial_block_height() {
+        let (_home_dir, checkpoints) = open_checkpoints();
+        let from_interruption = indexer_config(SyncModeEnum::FromInterruption, None);
+        assert_eq!(initial_block_height(&checkpoints, &from_interruption, 10), 10);
+        checkpoints.set_last_synced_block_height(7);
+        assert_eq!(initial_block_height(&checkpoints, &from_interruption, 10), 7);
+
+        // Acknowledgements are only used with the window
+        BlockAcknowledger::new(checkpoints.clone()).ack(5);
+        assert_eq!(initial_block_height(&checkpoints, &from_interruption, 10), 7);
+        let with_window = indexer_config(SyncModeEnum::FromInterruption, Some(4));
+        assert_eq!(initial_block_height(&checkpoints, &with_window, 10), 6);
+
+        let latest_synced = indexer_config(SyncModeEnum::LatestSynced, Some(4));
+        assert_eq!(initial_block_height(&checkpoints, &latest_synced, 10), 10);
+        let block_height = indexer_config(SyncModeEnum::BlockHeight(3), Some(4));
+        assert_eq!(initial_block_height(&checkpoints, &block_height, 10), 3);
+    }
+
+    fn outcome(id: CryptoHash, executor_id: &str) -> views::ExecutionOutcomeWithIdView {
+        views::ExecutionOutcomeWithIdView {
+            proof: vec![],
+            block_hash: CryptoHash::default(),
+            id,
+            outcome: views::ExecutionOutcomeView {
+                logs: vec![],
+                receipt_ids: vec![],
+                gas_burnt: 0,
+                tokens_burnt: 0,
+                executor_id: executor_id.to_string(),
+                status: views::ExecutionStatusView::Unknown,
+            },
+        }
+    }
+
+    fn receipt(
+        receipt_id: CryptoHash,
+        receiver_id: &str,
+        action: views::ActionView,
+    ) -> views::ReceiptView {
+        views::ReceiptView {
+            predecessor_id: "alice.near".to_string(),
+            receiver_id: receiver_id.to_string(),
+            receipt_id,
+            receipt: views::ReceiptEnumView::Action {
+                signer_id: "alice.near".to_string(),
+                signer_public_key: PublicKey::empty(KeyType::ED25519),
+                gas_price: 0,
+                output_data_receivers: vec![],
+                input_data_ids: vec![],
+                actions: vec![action],
+            },
+        }
+    }
+
+    /// The outcomes are filtered by the executor, and by the actions of the local receipts,
+    /// before any receipt is fetched.
+    #[test]
+    fn test_select_receipt_outcomes() {
+        let filter = IndexerFilter {
+            accounts: Some(vec!["*.app.near".to_string()]),
+            action_kinds: Some(vec![ActionKind::CreateAccount]),
+            ..IndexerFilter::default()
+        };
+        let ids: Vec<_> = (0..4u8).map(|i| hash(&[i])).collect();
+        let local_receipts = vec![
+            receipt(ids[0], "a.app.near", views::ActionView::CreateAccount),
+            receipt(ids[1], "b.app.near", views::ActionView::Transfer { deposit: 1 }),
+        ];
+        let receipt_outcomes = vec![
+            outcome(ids[0], "a.app.near"),
+            outcome(ids[1], "b.app.near"),
+            outcome(ids[2], "c.app.near"),
+            outcome(ids[3], "bob.near"),
+        ];
+
+        let selected = select_receipt_outcomes(&filter, receipt_outcomes, &local_receipts);
+        let selected: Vec<_> = selected
+            .into_iter()
+            .map(|(outcome, receipt)| (outcome.id, receipt.map(|receipt| receipt.receipt_id)))
+            .collect();
+        assert_eq!(selected, vec![(ids[0], Some(ids[0])), (ids[2], None)]);
+
+        // Once fetched, the receipt of the remaining outcome is checked, but the outcome is kept
+        // if the receipt couldn't be fetched.
+        let unknown = outcome(ids[2], "c.app.near");
+        assert!(filter.matches_receipt_outcome(&unknown, None));
+        let fetched = receipt(ids[2], "c.app.near", views::ActionView::Transfer { deposit: 1 });
+        assert!(!filter.matches_receipt_outcome(&unknown, Some(&fetched)));
+
+        // Everything passes the default filter.
+        let selected = select_receipt_outcomes(
+            &IndexerFilter::default(),
+            vec![outcome(ids[1], "b.app.near"), outcome(ids[3], "bob.near")],
+            &local_receipts,
+        );
+        assert_eq!(selected.len(), 2);
+        assert!(selected[0].1.is_some() && selected[1].1.is_none());
+    }
+}
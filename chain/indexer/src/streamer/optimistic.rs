@@ -0,0 +1,504 @@
+//! Optimistic streamer follows the head of the chain instead of the final blocks, so blocks are
+//! streamed as soon as the node accepts them. When the node switches to another fork, the streamer
+//! emits `StreamerEvent::Rollback` to the last block both forks share and then streams the blocks
+//! of the new fork. Whenever the streaming is restarted from the last streamed final block, e.g.
+//! after the restart of the Indexer, the blocks streamed after it are rolled back the same way.
+use std::future::Future;
+
+use actix::Addr;
+use tokio::sync::mpsc;
+use tokio::time;
+use tracing::{debug, info, warn};
+
+use near_primitives::hash::CryptoHash;
+use near_primitives::types::BlockHeight;
+
+use super::checkpoints::{Checkpoints, InFlightBlocks};
+use super::errors::FailedToFetchData;
+use super::fetchers::{
+    fetch_block_by_hash, fetch_block_by_height, fetch_head_block, fetch_latest_block,
+};
+use super::types::StreamerEvent;
+use super::{build_streamer_message, is_ready_to_stream, views, INDEXER, INTERVAL};
+use crate::{IndexerConfig, SyncModeEnum};
+
+/// What walking through the chain needs to know about a block
+trait ChainBlock {
+    fn height(&self) -> BlockHeight;
+    fn hash(&self) -> CryptoHash;
+    fn prev_hash(&self) -> CryptoHash;
+}
+
+impl ChainBlock for views::BlockView {
+    fn height(&self) -> BlockHeight {
+        self.header.height
+    }
+
+    fn hash(&self) -> CryptoHash {
+        self.header.hash
+    }
+
+    fn prev_hash(&self) -> CryptoHash {
+        self.header.prev_hash
+    }
+}
+
+/// Where the head of the chain is relative to the streamed blocks
+#[derive(Debug)]
+enum ForkWalk<B> {
+    /// The head descends from the streamed block with `common_block_index`. `new_blocks` are
+    /// the blocks after it up to the head, starting from the head.
+    Descends { common_block_index: usize, new_blocks: Vec<B> },
+    /// The head doesn't descend from the first streamed block
+    Diverged,
+}
+
+/// Walks back from the `head` until it reaches one of the `streamed` blocks
+async fn walk_to_streamed<B, F, Fut>(
+    streamed: &[(BlockHeight, CryptoHash)],
+    head: B,
+    mut fetch_block_by_hash: F,
+) -> Result<ForkWalk<B>, FailedToFetchData>
+where
+    B: ChainBlock,
+    F: FnMut(CryptoHash) -> Fut,
+    Fut: Future<Output = Result<B, FailedToFetchData>>,
+{
+    let mut new_blocks = vec![];
+    let mut block = head;
+    loop {
+        if let Some(common_block_index) =
+            streamed.iter().position(|(_, hash)| hash == &block.hash())
+        {
+            return Ok(ForkWalk::Descends { common_block_index, new_blocks });
+        }
+        if block.height() <= streamed[0].0 {
+            return Ok(ForkWalk::Diverged);
+        }
+        let prev_hash = block.prev_hash();
+        new_blocks.push(block);
+        block = fetch_block_by_hash(prev_hash).await?;
+    }
+}
+
+/// Walks back from the `block` to the latest block at or below the `height`
+async fn walk_to_height<B, F, Fut>(
+    block: B,
+    height: BlockHeight,
+    mut fetch_block_by_hash: F,
+) -> Result<B, FailedToFetchData>
+where
+    B: ChainBlock,
+    F: FnMut(CryptoHash) -> Fut,
+    Fut: Future<Output = Result<B, FailedToFetchData>>,
+{
+    let mut block = block;
+    while block.height() > height {
+        block = fetch_block_by_hash(block.prev_hash()).await?;
+    }
+    Ok(block)
+}
+
+/// Forgets the `streamed` blocks after the one with `common_block_index`. Returns the block to
+/// roll back to if there were any.
+fn roll_back(
+    streamed: &mut Vec<(BlockHeight, CryptoHash)>,
+    common_block_index: usize,
+) -> Option<(BlockHeight, CryptoHash)> {
+    if common_block_index + 1 < streamed.len() {
+        streamed.truncate(common_block_index + 1);
+        Some(streamed[common_block_index])
+    } else {
+        None
+    }
+}
+
+/// Height to roll back to before streaming according to `sync_mode`: the last streamed final block,
+/// or the last acknowledged block if it is lower
+fn initial_restart_height(
+    checkpoints: &Checkpoints,
+    indexer_config: &IndexerConfig,
+) -> Option<BlockHeight> {
+    match indexer_config.sync_mode {
+        SyncModeEnum::FromInterruption => {
+            let last_synced_block_height = checkpoints.last_optimistic_synced_block_height();
+            let last_acknowledged_block_height = if indexer_config.in_flight_window.is_some() {
+                checkpoints.last_acknowledged_block_height()
+            } else {
+                None
+            };
+            match (last_synced_block_height, last_acknowledged_block_height) {
+                (Some(synced), Some(acknowledged)) => Some(synced.min(acknowledged)),
+                (synced, acknowledged) => synced.or(acknowledged),
+            }
+        }
+        SyncModeEnum::LatestSynced | SyncModeEnum::BlockHeight(_) => None,
+    }
+}
+
+/// Sends the event to the listener. Returns `false` if the listener doesn't listen anymore.
+async fn send_event(events_sink: &mpsc::Sender<StreamerEvent>, event: StreamerEvent) -> bool {
+    debug!(target: INDEXER, "{:#?}", &event);
+    if events_sink.send(event).await.is_err() {
+        info!(
+            target: INDEXER,
+            "Unable to send StreamerEvent to listener, listener doesn't listen. terminating..."
+        );
+        return false;
+    }
+    true
+}
+
+/// Function that starts optimistic Streamer's busy loop. It streams the final blocks starting
+/// from the height defined by `sync_mode` first, and then follows the head of the chain.
+///
+/// The height of the last streamed final block is stored separately from the one of the final
+/// streamer. `SyncModeEnum::FromInterruption` rolls back to it, or to the last acknowledged block
+/// if it is lower, as the blocks streamed after it may not be on the canonical chain anymore, and
+/// resumes streaming from the next one.
+pub(crate) async fn start(
+    view_client: Addr<near_client::ViewClientActor>,
+    client: Addr<near_client::ClientActor>,
+    near_config: neard::NearConfig,
+    indexer_config: IndexerConfig,
+    checkpoints: Checkpoints,
+    events_sink: mpsc::Sender<StreamerEvent>,
+) {
+    info!(target: INDEXER, "Starting optimistic Streamer...");
+    let mut in_flight_blocks =
+        InFlightBlocks::new(checkpoints.clone(), indexer_config.in_flight_window);
+    // Streamed blocks which may still be rolled back, from the oldest one. The first one is
+    // the last streamed final block, all the others descend from it.
+    let mut streamed: Vec<(BlockHeight, CryptoHash)> = vec![];
+    // Set when the streaming restarts from the last streamed final block, the blocks streamed
+    // after the latest final block at or below this height are rolled back first
+    let mut restart_height = initial_restart_height(&checkpoints, &indexer_config);
+
+    'main: loop {
+        time::sleep(INTERVAL).await;
+        if !is_ready_to_stream(&client, &indexer_config).await {
+            continue;
+        }
+
+        if streamed.is_empty() {
+            let final_block = if let Ok(block) = fetch_latest_block(&view_client).await {
+                block
+            } else {
+                continue;
+            };
+            let (final_block_height, final_block_hash) =
+                (final_block.header.height, final_block.header.hash);
+            let start_syncing_block_height = match restart_height {
+                Some(height) => {
+                    let block = match walk_to_height(final_block, height, |hash| {
+                        fetch_block_by_hash(&view_client, hash)
+                    })
+                    .await
+                    {
+                        Ok(block) => block,
+                        Err(_) => continue,
+                    };
+                    let to_block_hash = block.header.hash;
+                    info!(
+                        target: INDEXER,
+                        "Rolling back to the final block #{} {} to restart streaming after it",
+                        block.header.height,
+                        to_block_hash
+                    );
+                    if !send_event(&events_sink, StreamerEvent::Rollback { to_block_hash }).await {
+                        break 'main;
+                    }
+                    in_flight_blocks.roll_back(block.header.height);
+                    restart_height = None;
+                    block.header.height + 1
+                }
+                None => match indexer_config.sync_mode {
+                    SyncModeEnum::BlockHeight(height) => height,
+                    _ => final_block_height,
+                },
+            };
+            debug!(
+                target: INDEXER,
+                "Optimistic streaming is about to start from block #{} and the latest final block is #{}",
+                start_syncing_block_height,
+                final_block_height
+            );
+            for block_height in start_syncing_block_height..=final_block_height {
+                if let Ok(block) = fetch_block_by_height(&view_client, block_height).await {
+                    match build_streamer_message(
+                        &view_client,
+                        block,
+                        &near_config,
+                        &indexer_config.filter,
+                    )
+                    .await
+                    {
+                        Ok(streamer_message) => {
+                            in_flight_blocks.wait_for_window().await;
+                            if !send_event(&events_sink, StreamerEvent::Block(streamer_message))
+                                .await
+                            {
+                                break 'main;
+                            }
+                            in_flight_blocks.push(block_height);
+                        }
+                        Err(err) => {
+                            debug!(
+                                target: INDEXER,
+                                "Missing data, skipping block #{}...", block_height
+                            );
+                            debug!(target: INDEXER, "{:#?}", err);
+                        }
+                    }
+                }
+                checkpoints.set_last_optimistic_synced_block_height(block_height);
+            }
+            streamed.push((final_block_height, final_block_hash));
+        }
+
+        let head = if let Ok(block) = fetch_head_block(&view_client).await {
+            block
+        } else {
+            continue;
+        };
+        if streamed.last().map(|(_, hash)| hash) == Some(&head.header.hash) {
+            continue;
+        }
+        let last_final_block = head.header.last_final_block;
+
+        let walk =
+            walk_to_streamed(&streamed, head, |hash| fetch_block_by_hash(&view_client, hash)).await;
+        let (common_block_index, new_blocks) = match walk {
+            Ok(ForkWalk::Descends { common_block_index, new_blocks }) => {
+                (common_block_index, new_blocks)
+            }
+            Ok(ForkWalk::Diverged) => {
+                warn!(
+                    target: INDEXER,
+                    "Head doesn't descend from the last streamed final block #{}, restarting streaming from it",
+                    streamed[0].0
+                );
+                restart_height = Some(streamed[0].0);
+                streamed.clear();
+                continue;
+            }
+            Err(_) => continue,
+        };
+
+        if let Some((height, to_block_hash)) = roll_back(&mut streamed, common_block_index) {
+            info!(
+                target: INDEXER,
+                "Chain switched to another fork, rolling back to block #{} {}",
+                height,
+                to_block_hash
+            );
+            if !send_event(&events_sink, StreamerEvent::Rollback { to_block_hash }).await {
+                break 'main;
+            }
+            in_flight_blocks.roll_back(height);
+        }
+
+        for block in new_blocks.into_iter().rev() {
+            let (height, hash) = (block.header.height, block.header.hash);
+            match build_streamer_message(&view_client, block, &near_config, &indexer_config.filter)
+                .await
+            {
+                Ok(streamer_message) => {
+                    in_flight_blocks.wait_for_window().await;
+                    if !send_event(&events_sink, StreamerEvent::Block(streamer_message)).await {
+                        break 'main;
+                    }
+                    in_flight_blocks.push(height);
+                    streamed.push((height, hash));
+                }
+                Err(err) => {
+                    // The blocks after it can't be streamed before it, so they all wait for the next attempt
+                    debug!(target: INDEXER, "Missing data of block #{}, retrying later...", height);
+                    debug!(target: INDEXER, "{:#?}", err);
+                    break;
+                }
+            }
+        }
+
+        // Blocks before the last final one can't be rolled back anymore
+        if let Some(index) = streamed.iter().position(|(_, hash)| hash == &last_final_block) {
+            streamed.drain(..index);
+            checkpoints.set_last_optimistic_synced_block_height(streamed[0].0);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use futures::executor::block_on;
+    use futures::future;
+
+    use near_primitives::hash::hash;
+
+    use super::super::checkpoints::tests::open_checkpoints;
+    use super::*;
+    use crate::{AwaitForNodeSyncedEnum, BlockAcknowledger, IndexerFilter};
+
+    #[derive(Clone, Debug)]
+    struct TestBlock {
+        height: BlockHeight,
+        hash: CryptoHash,
+        prev_hash: CryptoHash,
+    }
+
+    impl ChainBlock for TestBlock {
+        fn height(&self) -> BlockHeight {
+            self.height
+        }
+
+        fn hash(&self) -> CryptoHash {
+            self.hash
+        }
+
+        fn prev_hash(&self) -> CryptoHash {
+            self.prev_hash
+        }
+    }
+
+    /// Blocks by hash, built from `(name, height, prev name)`
+    fn chain(blocks: &[(&str, BlockHeight, &str)]) -> HashMap<CryptoHash, TestBlock> {
+        blocks
+            .iter()
+            .map(|(name, height, prev)| {
+                let block = TestBlock {
+                    height: *height,
+                    hash: hash(name.as_bytes()),
+                    prev_hash: hash(prev.as_bytes()),
+                };
+                (block.hash, block)
+            })
+            .collect()
+    }
+
+    fn fetch(
+        chain: &HashMap<CryptoHash, TestBlock>,
+    ) -> impl FnMut(CryptoHash) -> future::Ready<Result<TestBlock, FailedToFetchData>> + '_ {
+        move |hash| {
+            future::ready(
+                chain
+                    .get(&hash)
+                    .cloned()
+                    .ok_or_else(|| FailedToFetchData::String(format!("No block {}", hash))),
+            )
+        }
+    }
+
+    fn block(chain: &HashMap<CryptoHash, TestBlock>, name: &str) -> TestBlock {
+        chain[&hash(name.as_bytes())].clone()
+    }
+
+    fn streamed(blocks: &[(&str, BlockHeight)]) -> Vec<(BlockHeight, CryptoHash)> {
+        blocks.iter().map(|(name, height)| (*height, hash(name.as_bytes()))).collect()
+    }
+
+    #[test]
+    fn test_walk_to_streamed_fork() {
+        // a3 is streamed, then the head switches to the fork b3 <- b4
+        let chain = chain(&[
+            ("a1", 1, "a0"),
+            ("a2", 2, "a1"),
+            ("a3", 3, "a2"),
+            ("b3", 3, "a2"),
+            ("b4", 4, "b3"),
+        ]);
+        let mut streamed_blocks = streamed(&[("a1", 1), ("a2", 2), ("a3", 3)]);
+
+        let walk = block_on(walk_to_streamed(&streamed_blocks, block(&chain, "b4"), fetch(&chain)))
+            .unwrap();
+        match walk {
+            ForkWalk::Descends { common_block_index, new_blocks } => {
+                assert_eq!(common_block_index, 1);
+                let new_blocks: Vec<_> = new_blocks.iter().map(|block| block.height).collect();
+                assert_eq!(new_blocks, vec![4, 3]);
+                assert_eq!(
+                    roll_back(&mut streamed_blocks, common_block_index),
+                    Some((2, hash(b"a2")))
+                );
+                assert_eq!(streamed_blocks, streamed(&[("a1", 1), ("a2", 2)]));
+            }
+            ForkWalk::Diverged => panic!("b4 descends from a2"),
+        }
+
+        // Nothing is rolled back when the head extends the last streamed block
+        let walk = block_on(walk_to_streamed(&streamed_blocks, block(&chain, "b3"), fetch(&chain)))
+            .unwrap();
+        match walk {
+            ForkWalk::Descends { common_block_index, new_blocks } => {
+                assert_eq!(common_block_index, 1);
+                assert_eq!(new_blocks.len(), 1);
+                assert_eq!(roll_back(&mut streamed_blocks, common_block_index), None);
+                assert_eq!(streamed_blocks.len(), 2);
+            }
+            ForkWalk::Diverged => panic!("b3 descends from a2"),
+        }
+    }
+
+    #[test]
+    fn test_walk_to_streamed_diverged() {
+        let chain = chain(&[("a1", 1, "a0"), ("a2", 2, "a1"), ("a3", 3, "a2")]);
+        let streamed_blocks = streamed(&[("x2", 2)]);
+        let walk = block_on(walk_to_streamed(&streamed_blocks, block(&chain, "a3"), fetch(&chain)))
+            .unwrap();
+        assert!(matches!(walk, ForkWalk::Diverged));
+
+        // Missing blocks are reported
+        let streamed_blocks = streamed(&[("a0", 0)]);
+        assert!(block_on(walk_to_streamed(&streamed_blocks, block(&chain, "a3"), fetch(&chain)))
+            .is_err());
+    }
+
+    /// Restarts roll back to the latest final block at or below the restart height, even if
+    /// there is no block at that height.
+    #[test]
+    fn test_walk_to_height() {
+        let chain = chain(&[("a1", 1, "a0"), ("a2", 2, "a1"), ("a4", 4, "a2"), ("a5", 5, "a4")]);
+        let rollback_to = |height| {
+            block_on(walk_to_height(block(&chain, "a5"), height, fetch(&chain))).unwrap().hash
+        };
+        assert_eq!(rollback_to(4), hash(b"a4"));
+        assert_eq!(rollback_to(3), hash(b"a2"));
+        assert_eq!(rollback_to(5), hash(b"a5"));
+        assert_eq!(rollback_to(7), hash(b"a5"));
+    }
+
+    fn indexer_config(sync_mode: SyncModeEnum, in_flight_window: Option<usize>) -> IndexerConfig {
+        IndexerConfig {
+            home_dir: std::path::PathBuf::new(),
+            sync_mode,
+            await_for_node_synced: AwaitForNodeSyncedEnum::StreamWhileSyncing,
+            filter: IndexerFilter::default(),
+            in_flight_window,
+        }
+    }
+
+    #[test]
+    fn test_initial_restart_height() {
+        let (_home_dir, checkpoints) = open_checkpoints();
+        let from_interruption = indexer_config(SyncModeEnum::FromInterruption, None);
+        let with_window = indexer_config(SyncModeEnum::FromInterruption, Some(4));
+        assert_eq!(initial_restart_height(&checkpoints, &from_interruption), None);
+
+        // The final streamer's checkpoint is not used
+        checkpoints.set_last_synced_block_height(3);
+        assert_eq!(initial_restart_height(&checkpoints, &from_interruption), None);
+
+        BlockAcknowledger::new(checkpoints.clone()).ack(9);
+        assert_eq!(initial_restart_height(&checkpoints, &from_interruption), None);
+        assert_eq!(initial_restart_height(&checkpoints, &with_window), Some(9));
+        checkpoints.set_last_optimistic_synced_block_height(7);
+        assert_eq!(initial_restart_height(&checkpoints, &from_interruption), Some(7));
+        assert_eq!(initial_restart_height(&checkpoints, &with_window), Some(7));
+        checkpoints.roll_back_acknowledgements(5);
+        assert_eq!(initial_restart_height(&checkpoints, &with_window), Some(5));
+
+        let latest_synced = indexer_config(SyncModeEnum::LatestSynced, Some(4));
+        assert_eq!(initial_restart_height(&checkpoints, &latest_synced), None);
+    }
+}
@@ -8,9 +8,21 @@ pub use near_primitives::{types, views};
 pub struct StreamerMessage {
     pub block: views::BlockView,
     pub chunks: Vec<IndexerChunkView>,
+    /// All the state changes of the block. The changes caused by the streamed transactions and
+    /// receipts are also grouped in their `IndexerExecutionOutcomeWithReceipt::state_changes`.
     pub state_changes: views::StateChangesView,
 }
 
+/// Event streamed by the optimistic streamer
+#[derive(Debug, Serialize, Deserialize)]
+pub enum StreamerEvent {
+    /// Block accepted by the node, it may not be final yet
+    Block(StreamerMessage),
+    /// The node switched to another fork. The blocks streamed after `to_block_hash`
+    /// are no longer on the canonical chain and should be reverted by the consumer.
+    Rollback { to_block_hash: CryptoHash },
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct IndexerChunkView {
     pub author: types::AccountId,
@@ -30,4 +42,11 @@ pub struct IndexerTransactionWithOutcome {
 pub struct IndexerExecutionOutcomeWithReceipt {
     pub execution_outcome: views::ExecutionOutcomeWithIdView,
     pub receipt: Option<views::ReceiptView>,
+    /// State changes caused by the execution of the transaction or receipt, they are also
+    /// listed in `StreamerMessage::state_changes`
+    pub state_changes: views::StateChangesView,
+    /// Gas burnt by each action of the receipt, broken down by costs. Only the execution of
+    /// function calls is broken down, the other actions only burn their exec fee, so they have
+    /// `gas_burnt` but no costs. Only available if the Indexer is built with the `gas_profile` feature.
+    pub gas_profile: Option<Vec<views::ActionGasProfileView>>,
 }
@@ -1,13 +1,15 @@
+use std::collections::HashMap;
 use std::convert::TryFrom;
 
 use actix::Addr;
 
+use near_primitives::hash::CryptoHash;
 use near_primitives::views;
 use node_runtime::config::tx_cost;
 
 use super::errors::FailedToFetchData;
 use super::fetchers::fetch_block_by_hash;
-use super::IndexerTransactionWithOutcome;
+use super::{IndexerExecutionOutcomeWithReceipt, IndexerTransactionWithOutcome};
 
 pub(crate) async fn convert_transactions_sir_into_local_receipts(
     client: &Addr<near_client::ViewClientActor>,
@@ -65,3 +67,134 @@ pub(crate) async fn convert_transactions_sir_into_local_receipts(
 
     Ok(local_receipts)
 }
+
+/// Id of the transaction or receipt which caused the state change, if any
+fn state_change_cause_id(cause: &views::StateChangeCauseView) -> Option<CryptoHash> {
+    match cause {
+        views::StateChangeCauseView::TransactionProcessing { tx_hash } => Some(*tx_hash),
+        views::StateChangeCauseView::ActionReceiptProcessingStarted { receipt_hash }
+        | views::StateChangeCauseView::ActionReceiptGasReward { receipt_hash }
+        | views::StateChangeCauseView::ReceiptProcessing { receipt_hash } => Some(*receipt_hash),
+        // Postponed receipts are executed in another block, so they have no outcome in this one
+        views::StateChangeCauseView::PostponedReceipt { .. }
+        | views::StateChangeCauseView::NotWritableToDisk
+        | views::StateChangeCauseView::InitialState
+        | views::StateChangeCauseView::UpdatedDelayedReceipts
+        | views::StateChangeCauseView::ValidatorAccountsUpdate => None,
+    }
+}
+
+/// Copies the state changes to the outcomes which caused them.
+pub(crate) fn attach_state_changes_to_outcomes<'a>(
+    outcomes: impl Iterator<Item = &'a mut IndexerExecutionOutcomeWithReceipt>,
+    state_changes: &[views::StateChangeWithCauseView],
+) {
+    let mut outcomes: HashMap<CryptoHash, &mut IndexerExecutionOutcomeWithReceipt> =
+        outcomes.map(|outcome| (outcome.execution_outcome.id, outcome)).collect();
+    for state_change in state_changes {
+        if let Some(outcome) =
+            state_change_cause_id(&state_change.cause).and_then(|id| outcomes.get_mut(&id))
+        {
+            outcome.state_changes.push(state_change.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_primitives::hash::hash;
+
+    use super::*;
+
+    fn outcome(id: CryptoHash) -> IndexerExecutionOutcomeWithReceipt {
+        IndexerExecutionOutcomeWithReceipt {
+            execution_outcome: views::ExecutionOutcomeWithIdView {
+                proof: vec![],
+                block_hash: CryptoHash::default(),
+                id,
+                outcome: views::ExecutionOutcomeView {
+                    logs: vec![],
+                    receipt_ids: vec![],
+                    gas_burnt: 0,
+                    tokens_burnt: 0,
+                    executor_id: "alice.near".to_string(),
+                    status: views::ExecutionStatusView::Unknown,
+                },
+            },
+            receipt: None,
+            state_changes: vec![],
+            gas_profile: None,
+        }
+    }
+
+    /// State change of the account named after the cause, to tell the changes apart
+    fn state_change(
+        account_id: &str,
+        cause: views::StateChangeCauseView,
+    ) -> views::StateChangeWithCauseView {
+        views::StateChangeWithCauseView {
+            cause,
+            value: views::StateChangeValueView::AccountDeletion {
+                account_id: account_id.to_string(),
+            },
+        }
+    }
+
+    fn account_ids(outcome: &IndexerExecutionOutcomeWithReceipt) -> Vec<&str> {
+        outcome
+            .state_changes
+            .iter()
+            .map(|state_change| match &state_change.value {
+                views::StateChangeValueView::AccountDeletion { account_id } => account_id.as_str(),
+                _ => unreachable!(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_attach_state_changes_to_outcomes() {
+        let tx_hash = hash(b"transaction");
+        let receipt_hash = hash(b"receipt");
+        let mut transaction_outcome = outcome(tx_hash);
+        let mut receipt_outcome = outcome(receipt_hash);
+        let state_changes = vec![
+            state_change("not-writable", views::StateChangeCauseView::NotWritableToDisk),
+            state_change("initial", views::StateChangeCauseView::InitialState),
+            state_change(
+                "transaction",
+                views::StateChangeCauseView::TransactionProcessing { tx_hash },
+            ),
+            state_change(
+                "started",
+                views::StateChangeCauseView::ActionReceiptProcessingStarted { receipt_hash },
+            ),
+            state_change(
+                "gas-reward",
+                views::StateChangeCauseView::ActionReceiptGasReward { receipt_hash },
+            ),
+            state_change(
+                "receipt",
+                views::StateChangeCauseView::ReceiptProcessing { receipt_hash },
+            ),
+            // Postponed receipts are executed in another block
+            state_change(
+                "postponed",
+                views::StateChangeCauseView::PostponedReceipt { receipt_hash },
+            ),
+            state_change("delayed", views::StateChangeCauseView::UpdatedDelayedReceipts),
+            state_change("validators", views::StateChangeCauseView::ValidatorAccountsUpdate),
+            // Caused by an outcome which is not streamed
+            state_change(
+                "other-receipt",
+                views::StateChangeCauseView::ReceiptProcessing { receipt_hash: hash(b"other") },
+            ),
+        ];
+
+        attach_state_changes_to_outcomes(
+            vec![&mut transaction_outcome, &mut receipt_outcome].into_iter(),
+            &state_changes,
+        );
+        assert_eq!(account_ids(&transaction_outcome), vec!["transaction"]);
+        assert_eq!(account_ids(&receipt_outcome), vec!["started", "gas-reward", "receipt"]);
+    }
+}
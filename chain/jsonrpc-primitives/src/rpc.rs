@@ -2,15 +2,24 @@
 //!
 //! NOTE: This module should be only used in RPC server and RPC client implementations, and
 //! should not leak these types anywhere else.
+use std::collections::HashMap;
+use std::path::PathBuf;
+
 use serde::{Deserialize, Serialize};
 
 use near_primitives::hash::CryptoHash;
 use near_primitives::merkle::MerklePath;
+use near_primitives::network::PeerId;
+use near_primitives::serialize::option_base64_format;
 use near_primitives::transaction::SignedTransaction;
-use near_primitives::types::{AccountId, BlockReference, MaybeBlockId, TransactionOrReceiptId};
+use near_primitives::types::{
+    AccountId, BlockHeight, BlockReference, MaybeBlockId, Nonce, ShardId, StateRoot,
+    TransactionOrReceiptId,
+};
 use near_primitives::views::{
-    ExecutionOutcomeWithIdView, LightClientBlockLiteView, QueryRequest, StateChangeWithCauseView,
-    StateChangesKindsView, StateChangesRequestView,
+    AccountHistoryItemView, ExecutionOutcomeWithIdView, LightClientBlockLiteView,
+    PendingTransactionView, QueryRequest, StateChangeWithCauseView, StateChangesKindsView,
+    StateChangesRequestView, StateDiffItemView,
 };
 
 #[derive(Serialize, Deserialize)]
@@ -78,3 +87,125 @@ pub enum TransactionInfo {
 pub struct RpcValidatorsOrderedRequest {
     pub block_id: MaybeBlockId,
 }
+
+#[derive(Serialize, Deserialize)]
+pub struct RpcCreateStoreSnapshotRequest {
+    /// Directory on the node's host to write the snapshot to. It must not exist yet.
+    pub path: PathBuf,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct RpcPeerRequest {
+    pub peer_id: PeerId,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct RpcBootNodeRequest {
+    /// Boot node in the `peer_id@host:port` format used by the config.
+    pub boot_node: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct RpcTrackedShardsRequest {
+    #[serde(default)]
+    pub account_ids: Vec<AccountId>,
+    #[serde(default)]
+    pub shard_ids: Vec<ShardId>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct RpcSetLogFilterRequest {
+    /// Comma separated `target=level` directives, same as in `RUST_LOG`.
+    pub filter: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct RpcRoutingTableResponse {
+    pub account_peers: HashMap<AccountId, PeerId>,
+    /// Peers through which we route messages to each reachable peer.
+    pub peer_forwarding: HashMap<PeerId, Vec<PeerId>>,
+    /// Edges of the network graph known to the node.
+    pub edges: Vec<RpcRoutingTableEdge>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct RpcRoutingTableEdge {
+    pub peer0: PeerId,
+    pub peer1: PeerId,
+    pub nonce: u64,
+    /// Whether the peers have disconnected. Removed edges are kept to reject older nonces.
+    pub removed: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct RpcAccountHistoryRequest {
+    pub account_id: AccountId,
+    /// Last item of the previous page, the history is returned from the beginning if not set.
+    #[serde(default)]
+    pub after: Option<RpcAccountHistoryCursor>,
+    /// Maximum number of items to return.
+    #[serde(default)]
+    pub limit: Option<usize>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct RpcAccountHistoryCursor {
+    pub block_height: BlockHeight,
+    pub id: CryptoHash,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct RpcAccountHistoryResponse {
+    pub account_id: AccountId,
+    pub items: Vec<AccountHistoryItemView>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct RpcStateDiffRequest {
+    #[serde(default)]
+    pub shard_id: ShardId,
+    pub old_state_root: StateRoot,
+    pub new_state_root: StateRoot,
+    /// Only records with raw trie keys greater than this one are returned. Should be set to
+    /// `next_after_key_base64` of the previous response to get the next page.
+    #[serde(default, rename = "after_key_base64", with = "option_base64_format")]
+    pub after_key: Option<Vec<u8>>,
+    /// Maximum number of changed records to return.
+    #[serde(default)]
+    pub limit: Option<usize>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct RpcStateDiffResponse {
+    pub changes: Vec<StateDiffItemView>,
+    /// Set if the response was truncated by the limit, the next page starts after this key.
+    #[serde(rename = "next_after_key_base64", with = "option_base64_format")]
+    pub next_after_key: Option<Vec<u8>>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct RpcPendingTransactionsRequest {
+    /// Only return the transactions signed by this account.
+    #[serde(default)]
+    pub signer_id: Option<AccountId>,
+    /// Last transaction of the previous page, the transactions are returned from the beginning if
+    /// not set.
+    #[serde(default)]
+    pub after: Option<RpcPendingTransactionsCursor>,
+    /// Maximum number of transactions to return.
+    #[serde(default)]
+    pub limit: Option<usize>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct RpcPendingTransactionsCursor {
+    pub shard_id: ShardId,
+    pub signer_id: AccountId,
+    pub nonce: Nonce,
+    pub hash: CryptoHash,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct RpcPendingTransactionsResponse {
+    pub transactions: Vec<PendingTransactionView>,
+}
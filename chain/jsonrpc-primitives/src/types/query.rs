@@ -81,6 +81,7 @@ pub enum QueryResponseKind {
     CallResult(near_primitives::views::CallResult),
     AccessKey(near_primitives::views::AccessKeyView),
     AccessKeyList(near_primitives::views::AccessKeyList),
+    ViewStatePage(near_primitives::views::ViewStatePageResult),
 }
 
 impl RpcQueryRequest {
@@ -238,6 +239,9 @@ impl From<near_primitives::views::QueryResponseKind> for QueryResponseKind {
             near_primitives::views::QueryResponseKind::AccessKeyList(access_key_list) => {
                 Self::AccessKeyList(access_key_list)
             }
+            near_primitives::views::QueryResponseKind::ViewStatePage(view_state_page_result) => {
+                Self::ViewStatePage(view_state_page_result)
+            }
         }
     }
 }
@@ -8,7 +8,8 @@ use serde::Serialize;
 use near_jsonrpc_primitives::errors::RpcError;
 use near_jsonrpc_primitives::message::{from_slice, Message};
 use near_jsonrpc_primitives::rpc::{
-    RpcStateChangesRequest, RpcStateChangesResponse, RpcValidatorsOrderedRequest,
+    RpcAccountHistoryRequest, RpcAccountHistoryResponse, RpcStateChangesRequest,
+    RpcStateChangesResponse, RpcValidatorsOrderedRequest,
 };
 use near_primitives::hash::CryptoHash;
 use near_primitives::types::{BlockId, BlockReference, MaybeBlockId, ShardId};
@@ -241,6 +242,14 @@ impl JsonRpcClient {
         call_method(&self.client, &self.server_addr, "EXPERIMENTAL_receipt", request)
     }
 
+    #[allow(non_snake_case)]
+    pub fn EXPERIMENTAL_account_history(
+        &self,
+        request: RpcAccountHistoryRequest,
+    ) -> RpcRequest<RpcAccountHistoryResponse> {
+        call_method(&self.client, &self.server_addr, "EXPERIMENTAL_account_history", request)
+    }
+
     #[allow(non_snake_case)]
     pub fn EXPERIMENTAL_protocol_config(
         &self,
@@ -0,0 +1,356 @@
+//! `admin_*` JSON-RPC methods which let node operators manage the node at runtime.
+//!
+//! They are served on a separate address from the public RPC, which is either a unix socket
+//! only its owner can connect to or a TCP address guarded by a bearer token.
+use std::net::SocketAddr;
+#[cfg(unix)]
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+
+use actix::Addr;
+use actix_web::{
+    http, middleware, web, App, Error as HttpError, HttpRequest, HttpResponse, HttpServer,
+};
+use futures::{Future, FutureExt};
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::info;
+
+use near_client::{
+    ClientActor, CreateStoreSnapshot, TriggerGc, UpdateTrackedShards, ViewClientActor,
+};
+use near_jsonrpc_primitives::errors::RpcError;
+use near_jsonrpc_primitives::message::{Message, Request};
+use near_jsonrpc_primitives::rpc::{
+    RpcBootNodeRequest, RpcCreateStoreSnapshotRequest, RpcPeerRequest, RpcRoutingTableEdge,
+    RpcRoutingTableResponse, RpcSetLogFilterRequest, RpcTrackedShardsRequest,
+};
+use near_network::routing::EdgeType;
+use near_network::types::ReasonForBan;
+use near_network::{NetworkAdapter, NetworkRequests, NetworkResponses, PeerInfo};
+
+use crate::{jsonify, metrics, parse_params, ServerError};
+
+/// Prefix of `RpcAdminConfig::addr` which makes the admin server listen on a unix socket.
+const UNIX_SOCKET_PREFIX: &str = "unix:";
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RpcAdminConfig {
+    /// Either `ip:port` or `unix:<path>` to listen on a unix socket.
+    pub addr: String,
+    /// Token the callers must pass as `Authorization: Bearer <token>`. Required for TCP
+    /// addresses, unix sockets are protected by the file permissions instead.
+    #[serde(default)]
+    pub auth_token: Option<String>,
+}
+
+/// Where the admin server listens, parsed from `RpcAdminConfig::addr`.
+enum AdminAddr {
+    Tcp(SocketAddr),
+    #[cfg(unix)]
+    Unix(PathBuf),
+}
+
+impl RpcAdminConfig {
+    /// Checks the address and the token, the node does it when the config is loaded.
+    pub fn validate(&self) -> Result<(), String> {
+        self.listen_addr().map(drop)
+    }
+
+    fn listen_addr(&self) -> Result<AdminAddr, String> {
+        if let Some(auth_token) = &self.auth_token {
+            if auth_token.is_empty() || !auth_token.bytes().all(|byte| byte.is_ascii_graphic()) {
+                return Err("auth_token must be printable ASCII without whitespace".to_string());
+            }
+        }
+        match self.addr.strip_prefix(UNIX_SOCKET_PREFIX) {
+            #[cfg(unix)]
+            Some("") => Err(format!("addr {:?} is missing the socket path", self.addr)),
+            #[cfg(unix)]
+            Some(path) => Ok(AdminAddr::Unix(PathBuf::from(path))),
+            #[cfg(not(unix))]
+            Some(_) => Err("unix sockets are only supported on unix systems".to_string()),
+            None => {
+                let addr = self.addr.parse().map_err(|_| {
+                    format!("invalid addr {:?}, expected ip:port or unix:<path>", self.addr)
+                })?;
+                if self.auth_token.is_none() {
+                    return Err(format!("TCP address {} requires auth_token to be set", addr));
+                }
+                Ok(AdminAddr::Tcp(addr))
+            }
+        }
+    }
+}
+
+/// Changes the log filter of the running node, given `RUST_LOG` style directives.
+pub type LogFilterReloader = Box<dyn Fn(&str) -> Result<(), String> + Send + Sync>;
+
+lazy_static! {
+    static ref LOG_FILTER_RELOADER: RwLock<Option<LogFilterReloader>> = RwLock::new(None);
+}
+
+/// Lets `admin_set_log_filter` change the log levels. Should be called by the binary once
+/// it has set up logging, as the way to reload the filter depends on the subscriber.
+pub fn set_log_filter_reloader(reloader: LogFilterReloader) {
+    *LOG_FILTER_RELOADER.write().unwrap() = Some(reloader);
+}
+
+struct AdminRpcHandler {
+    client_addr: Addr<ClientActor>,
+    view_client_addr: Addr<ViewClientActor>,
+    network_adapter: Arc<dyn NetworkAdapter>,
+}
+
+impl AdminRpcHandler {
+    async fn process(&self, message: Message) -> Result<Message, HttpError> {
+        let id = message.id();
+        match message {
+            Message::Request(request) => {
+                Ok(Message::response(id, self.process_request(request).await))
+            }
+            _ => Ok(Message::error(RpcError::invalid_request())),
+        }
+    }
+
+    async fn process_request(&self, request: Request) -> Result<Value, RpcError> {
+        near_metrics::inc_counter_vec(&metrics::HTTP_RPC_REQUEST_COUNT, &[request.method.as_ref()]);
+        let _rpc_processing_time = near_metrics::start_timer_vec(
+            &metrics::RPC_PROCESSING_TIME,
+            &[request.method.as_ref()],
+        );
+
+        let response = match request.method.as_ref() {
+            // Handlers ordered alphabetically
+            "admin_add_boot_node" => self.add_boot_node(request.params).await,
+            "admin_ban_peer" => self.ban_peer(request.params).await,
+            "admin_create_store_snapshot" => self.create_store_snapshot(request.params).await,
+            "admin_remove_boot_node" => self.remove_boot_node(request.params).await,
+            "admin_routing_table" => self.routing_table().await,
+            "admin_set_log_filter" => self.set_log_filter(request.params).await,
+            "admin_track_shards" => self.update_tracked_shards(request.params, true).await,
+            "admin_trigger_gc" => jsonify(self.client_addr.send(TriggerGc {}).await),
+            "admin_unban_peer" => self.unban_peer(request.params).await,
+            "admin_untrack_shards" => self.update_tracked_shards(request.params, false).await,
+            _ => Err(RpcError::method_not_found(request.method.clone())),
+        };
+
+        if let Err(err) = &response {
+            near_metrics::inc_counter_vec(
+                &metrics::RPC_ERROR_COUNT,
+                &[request.method.as_ref(), &err.code.to_string()],
+            );
+        }
+
+        response
+    }
+
+    /// Sends a request about a single peer to the peer manager.
+    async fn peer_request(&self, request: NetworkRequests) -> Result<Value, RpcError> {
+        match self.network_adapter.send(request).await.map_err(ServerError::from)? {
+            NetworkResponses::PeerNotFound => {
+                Err(RpcError::invalid_params("Unknown peer".to_string()))
+            }
+            _ => Ok(Value::Null),
+        }
+    }
+
+    async fn ban_peer(&self, params: Option<Value>) -> Result<Value, RpcError> {
+        let RpcPeerRequest { peer_id } = parse_params(params)?;
+        self.peer_request(NetworkRequests::BanPeer { peer_id, ban_reason: ReasonForBan::Manual })
+            .await
+    }
+
+    async fn unban_peer(&self, params: Option<Value>) -> Result<Value, RpcError> {
+        let RpcPeerRequest { peer_id } = parse_params(params)?;
+        self.peer_request(NetworkRequests::UnbanPeer { peer_id }).await
+    }
+
+    async fn add_boot_node(&self, params: Option<Value>) -> Result<Value, RpcError> {
+        let RpcBootNodeRequest { boot_node } = parse_params(params)?;
+        let peer_info = PeerInfo::from_str(&boot_node)
+            .map_err(|err| RpcError::invalid_params(format!("Invalid boot node: {}", err)))?;
+        if peer_info.addr.is_none() {
+            return Err(RpcError::invalid_params("Boot node must have an address".to_string()));
+        }
+        self.peer_request(NetworkRequests::AddBootNode(peer_info)).await
+    }
+
+    async fn remove_boot_node(&self, params: Option<Value>) -> Result<Value, RpcError> {
+        let RpcPeerRequest { peer_id } = parse_params(params)?;
+        self.peer_request(NetworkRequests::RemoveBootNode(peer_id)).await
+    }
+
+    async fn routing_table(&self) -> Result<Value, RpcError> {
+        match self
+            .network_adapter
+            .send(NetworkRequests::FetchRoutingTable)
+            .await
+            .map_err(ServerError::from)?
+        {
+            NetworkResponses::RoutingTableInfo(info) => {
+                let edges = info
+                    .edges
+                    .into_iter()
+                    .map(|edge| RpcRoutingTableEdge {
+                        removed: edge.edge_type() == EdgeType::Removed,
+                        peer0: edge.peer0,
+                        peer1: edge.peer1,
+                        nonce: edge.nonce,
+                    })
+                    .collect();
+                serde_json::to_value(RpcRoutingTableResponse {
+                    account_peers: info.account_peers,
+                    peer_forwarding: info.peer_forwarding,
+                    edges,
+                })
+                .map_err(|err| RpcError::parse_error(err.to_string()))
+            }
+            _ => Err(ServerError::InternalError.into()),
+        }
+    }
+
+    async fn create_store_snapshot(&self, params: Option<Value>) -> Result<Value, RpcError> {
+        let RpcCreateStoreSnapshotRequest { path } = parse_params(params)?;
+        let receiver = self
+            .view_client_addr
+            .send(CreateStoreSnapshot { path })
+            .await
+            .map_err(|err| err.to_string())
+            .and_then(|result| result)
+            .map_err(|err| RpcError::server_error(Some(err)))?;
+        jsonify(Ok(receiver.await.unwrap_or_else(|_| Err("Snapshot was cancelled".to_string()))))
+    }
+
+    async fn update_tracked_shards(
+        &self,
+        params: Option<Value>,
+        track: bool,
+    ) -> Result<Value, RpcError> {
+        let RpcTrackedShardsRequest { account_ids, shard_ids } = parse_params(params)?;
+        jsonify(self.client_addr.send(UpdateTrackedShards { track, account_ids, shard_ids }).await)
+    }
+
+    async fn set_log_filter(&self, params: Option<Value>) -> Result<Value, RpcError> {
+        let RpcSetLogFilterRequest { filter } = parse_params(params)?;
+        match LOG_FILTER_RELOADER.read().unwrap().as_ref() {
+            Some(reloader) => reloader(&filter).map_err(RpcError::invalid_params)?,
+            None => {
+                return Err(RpcError::server_error(Some(
+                    "Log filter can't be changed in this binary".to_string(),
+                )))
+            }
+        }
+        info!(target: "jsonrpc", "Changed log filter to {}", filter);
+        Ok(Value::Null)
+    }
+}
+
+/// Compares the strings in time independent of the position of the first mismatch.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b.iter()).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+fn is_authorized(req: &HttpRequest, auth_token: &Option<String>) -> bool {
+    let auth_token = match auth_token {
+        Some(auth_token) => auth_token,
+        None => return true,
+    };
+    req.headers()
+        .get(http::header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map_or(false, |token| constant_time_eq(token.as_bytes(), auth_token.as_bytes()))
+}
+
+fn admin_rpc_handler(
+    req: HttpRequest,
+    message: web::Json<Message>,
+    handler: web::Data<AdminRpcHandler>,
+    auth_token: web::Data<Option<String>>,
+) -> impl Future<Output = Result<HttpResponse, HttpError>> {
+    let response = async move {
+        if !is_authorized(&req, &auth_token) {
+            near_metrics::inc_counter(&metrics::ADMIN_RPC_UNAUTHORIZED_COUNT);
+            return Ok(HttpResponse::Unauthorized().finish());
+        }
+        let message = handler.process(message.0).await?;
+        Ok(HttpResponse::Ok().json(&message))
+    };
+    response.boxed()
+}
+
+/// Removes the socket left behind by a node which didn't shut down cleanly, as binding fails
+/// while it exists. Other files and sockets something still listens on are left alone.
+#[cfg(unix)]
+fn remove_stale_socket(path: &Path) -> std::io::Result<()> {
+    use std::os::unix::fs::FileTypeExt;
+    use std::os::unix::net::UnixStream;
+
+    let is_socket =
+        std::fs::symlink_metadata(path).map_or(false, |metadata| metadata.file_type().is_socket());
+    if is_socket && UnixStream::connect(path).is_err() {
+        info!(target: "network", "Removing stale admin http socket {}", path.display());
+        std::fs::remove_file(path)?;
+    }
+    Ok(())
+}
+
+/// Removes a stale socket at the given path and returns the temporary path to bind the socket
+/// to. It's moved in place by `restrict_socket` once only its owner can connect to it, so there
+/// is no window where others could.
+#[cfg(unix)]
+fn prepare_socket_path(path: &Path) -> std::io::Result<PathBuf> {
+    remove_stale_socket(path)?;
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(format!(".{}.tmp", std::process::id()));
+    let tmp_path = PathBuf::from(tmp_path);
+    remove_stale_socket(&tmp_path)?;
+    Ok(tmp_path)
+}
+
+#[cfg(unix)]
+fn restrict_socket(tmp_path: &Path, path: &Path) -> std::io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    std::fs::set_permissions(tmp_path, std::fs::Permissions::from_mode(0o600))?;
+    std::fs::rename(tmp_path, path)
+}
+
+/// Starts the admin JSON-RPC server. Panics if the config is invalid, see
+/// `RpcAdminConfig::validate`, or the address can't be bound.
+pub fn start_admin_http(
+    config: RpcAdminConfig,
+    client_addr: Addr<ClientActor>,
+    view_client_addr: Addr<ViewClientActor>,
+    network_adapter: Arc<dyn NetworkAdapter>,
+) {
+    let listen_addr =
+        config.listen_addr().unwrap_or_else(|err| panic!("Invalid admin RPC config: {}", err));
+    let RpcAdminConfig { addr, auth_token } = config;
+    info!(target: "network", "Starting admin http server at {}", addr);
+    let server = HttpServer::new(move || {
+        App::new()
+            .data(AdminRpcHandler {
+                client_addr: client_addr.clone(),
+                view_client_addr: view_client_addr.clone(),
+                network_adapter: network_adapter.clone(),
+            })
+            .data(auth_token.clone())
+            .wrap(middleware::Logger::default())
+            .service(web::resource("/").route(web::post().to(admin_rpc_handler)))
+    });
+    let server = match listen_addr {
+        #[cfg(unix)]
+        AdminAddr::Unix(path) => {
+            let tmp_path = prepare_socket_path(&path).unwrap();
+            let server = server.bind_uds(&tmp_path).unwrap();
+            restrict_socket(&tmp_path, &path).unwrap();
+            server
+        }
+        AdminAddr::Tcp(addr) => server.bind(addr).unwrap(),
+    };
+    server.workers(1).shutdown_timeout(5).run();
+}
@@ -17,19 +17,20 @@ use tracing::info;
 
 use near_chain_configs::GenesisConfig;
 use near_client::{
-    ClientActor, GetBlock, GetBlockProof, GetChunk, GetExecutionOutcome, GetGasPrice,
-    GetNetworkInfo, GetNextLightClientBlock, GetProtocolConfig, GetReceipt, GetStateChanges,
-    GetStateChangesInBlock, GetValidatorInfo, GetValidatorOrdered, Query, Status, TxStatus,
-    TxStatusError, ViewClientActor,
+    ClientActor, GetAccountHistory, GetBlock, GetBlockProof, GetChunk, GetExecutionOutcome,
+    GetGasPrice, GetNetworkInfo, GetNextLightClientBlock, GetPendingTransactions,
+    GetProtocolConfig, GetReceipt, GetStateChanges, GetStateChangesInBlock, GetStateDiff,
+    GetValidatorInfo, GetValidatorOrdered, Query, Status, TxStatus, TxStatusError, ViewClientActor,
 };
 pub use near_jsonrpc_client as client;
 use near_jsonrpc_primitives::errors::RpcError;
 use near_jsonrpc_primitives::message::{Message, Request};
 use near_jsonrpc_primitives::rpc::{
-    RpcBroadcastTxSyncResponse, RpcLightClientExecutionProofRequest,
-    RpcLightClientExecutionProofResponse, RpcStateChangesInBlockRequest,
+    RpcAccountHistoryRequest, RpcAccountHistoryResponse, RpcBroadcastTxSyncResponse,
+    RpcLightClientExecutionProofRequest, RpcLightClientExecutionProofResponse,
+    RpcPendingTransactionsRequest, RpcPendingTransactionsResponse, RpcStateChangesInBlockRequest,
     RpcStateChangesInBlockResponse, RpcStateChangesRequest, RpcStateChangesResponse,
-    RpcValidatorsOrderedRequest, TransactionInfo,
+    RpcStateDiffRequest, RpcStateDiffResponse, RpcValidatorsOrderedRequest, TransactionInfo,
 };
 use near_jsonrpc_primitives::types::config::RpcProtocolConfigResponse;
 use near_metrics::{Encoder, TextEncoder};
@@ -44,8 +45,28 @@ use near_primitives::types::{AccountId, MaybeBlockId};
 use near_primitives::views::{FinalExecutionOutcomeView, FinalExecutionOutcomeViewEnum};
 use near_runtime_utils::is_valid_account_id;
 
+pub use crate::admin::{
+    set_log_filter_reloader, start_admin_http, LogFilterReloader, RpcAdminConfig,
+};
+
+mod admin;
 mod metrics;
 
+/// Number of account history items returned when the request doesn't set a limit.
+const ACCOUNT_HISTORY_DEFAULT_LIMIT: usize = 100;
+/// Maximum number of account history items returned in one response.
+const ACCOUNT_HISTORY_MAX_LIMIT: usize = 1000;
+/// Number of changed records returned by `EXPERIMENTAL_state_diff` without explicit limit.
+const STATE_DIFF_DEFAULT_LIMIT: usize = 100;
+/// Maximum number of changed records returned by `EXPERIMENTAL_state_diff`.
+const STATE_DIFF_MAX_LIMIT: usize = 1000;
+/// Number of transactions returned by `EXPERIMENTAL_pending_transactions` without explicit limit.
+const PENDING_TRANSACTIONS_DEFAULT_LIMIT: usize = 100;
+/// Maximum number of transactions returned by `EXPERIMENTAL_pending_transactions`.
+const PENDING_TRANSACTIONS_MAX_LIMIT: usize = 1000;
+/// Error returned when the transaction pool of the shard is full.
+const TX_POOL_FULL_ERR: &str = "Transaction pool is full, try again later";
+
 #[derive(Serialize, Deserialize, Clone, Copy, Debug)]
 pub struct RpcPollingConfig {
     pub polling_interval: Duration,
@@ -80,6 +101,14 @@ pub struct RpcConfig {
     pub polling_config: RpcPollingConfig,
     #[serde(default)]
     pub limits_config: RpcLimitsConfig,
+    /// Serves the `admin_*` methods, which let callers operate the node itself, on a
+    /// separate address. They are disabled if not set.
+    #[serde(default)]
+    pub admin: Option<RpcAdminConfig>,
+    /// Serves `EXPERIMENTAL_state_diff`. Disabled by default, as a diff between distant state
+    /// roots may visit a large part of the trie.
+    #[serde(default)]
+    pub enable_state_diff: bool,
 }
 
 impl Default for RpcConfig {
@@ -89,6 +118,8 @@ impl Default for RpcConfig {
             cors_allowed_origins: vec!["*".to_owned()],
             polling_config: Default::default(),
             limits_config: Default::default(),
+            admin: None,
+            enable_state_diff: false,
         }
     }
 }
@@ -222,6 +253,7 @@ struct JsonRpcHandler {
     view_client_addr: Addr<ViewClientActor>,
     polling_config: RpcPollingConfig,
     genesis_config: GenesisConfig,
+    enable_state_diff: bool,
 }
 
 impl JsonRpcHandler {
@@ -279,6 +311,7 @@ impl JsonRpcHandler {
                 let chunk = self.chunk(rpc_chunk_request).await?;
                 serde_json::to_value(chunk).map_err(|err| RpcError::parse_error(err.to_string()))
             }
+            "EXPERIMENTAL_account_history" => self.account_history(request.params).await,
             "EXPERIMENTAL_broadcast_tx_sync" => self.send_tx_sync(request.params).await,
             "EXPERIMENTAL_changes" => self.changes_in_block_by_type(request.params).await,
             "EXPERIMENTAL_changes_in_block" => self.changes_in_block(request.params).await,
@@ -287,6 +320,7 @@ impl JsonRpcHandler {
             "EXPERIMENTAL_light_client_proof" => {
                 self.light_client_execution_outcome_proof(request.params).await
             }
+            "EXPERIMENTAL_pending_transactions" => self.pending_transactions(request.params).await,
             "EXPERIMENTAL_protocol_config" => {
                 let rpc_protocol_config_request =
                     near_jsonrpc_primitives::types::config::RpcProtocolConfigRequest::parse(
@@ -303,6 +337,7 @@ impl JsonRpcHandler {
                 let receipt = self.receipt(rpc_receipt_request).await?;
                 serde_json::to_value(receipt).map_err(|err| RpcError::parse_error(err.to_string()))
             }
+            "EXPERIMENTAL_state_diff" => self.state_diff(request.params).await,
             "EXPERIMENTAL_tx_status" => self.tx_status_common(request.params, true).await,
             "EXPERIMENTAL_validators_ordered" => self.validators_ordered(request.params).await,
             "gas_price" => self.gas_price(request.params).await,
@@ -548,6 +583,9 @@ impl JsonRpcHandler {
             NetworkClientResponses::DoesNotTrackShard => {
                 Err(RpcError::server_error(Some(does_not_track_shard_err.to_string())))
             }
+            NetworkClientResponses::TxPoolFull => {
+                Err(RpcError::server_error(Some(TX_POOL_FULL_ERR.to_string())))
+            }
             _ => {
                 // this is only possible if something went wrong with the node internally.
                 Err(RpcError::server_error(Some(ServerError::InternalError)))
@@ -573,6 +611,9 @@ impl JsonRpcHandler {
             NetworkClientResponses::InvalidTx(err) => {
                 Err(RpcError::server_error(Some(ServerError::TxExecutionError(err.into()))))
             }
+            NetworkClientResponses::TxPoolFull => {
+                Err(RpcError::server_error(Some(TX_POOL_FULL_ERR.to_string())))
+            }
             NetworkClientResponses::NoResponse => {
                 Err(RpcError::server_error(Some(ServerError::Timeout)))
             }
@@ -699,6 +740,60 @@ impl JsonRpcHandler {
         }
     }
 
+    async fn account_history(&self, params: Option<Value>) -> Result<Value, RpcError> {
+        let RpcAccountHistoryRequest { account_id, after, limit } = parse_params(params)?;
+        let limit = limit.unwrap_or(ACCOUNT_HISTORY_DEFAULT_LIMIT).min(ACCOUNT_HISTORY_MAX_LIMIT);
+        jsonify(
+            self.view_client_addr
+                .send(GetAccountHistory {
+                    account_id: account_id.clone(),
+                    after: after.map(|cursor| (cursor.block_height, cursor.id)),
+                    limit,
+                })
+                .await
+                .map(|v| v.map(|items| RpcAccountHistoryResponse { account_id, items })),
+        )
+    }
+
+    async fn pending_transactions(&self, params: Option<Value>) -> Result<Value, RpcError> {
+        let RpcPendingTransactionsRequest { signer_id, after, limit } = parse_params(params)?;
+        let limit =
+            limit.unwrap_or(PENDING_TRANSACTIONS_DEFAULT_LIMIT).min(PENDING_TRANSACTIONS_MAX_LIMIT);
+        jsonify(
+            self.view_client_addr
+                .send(GetPendingTransactions {
+                    signer_id,
+                    transaction_hash: None,
+                    after: after.map(|cursor| {
+                        (cursor.shard_id, cursor.signer_id, cursor.nonce, cursor.hash)
+                    }),
+                    limit,
+                })
+                .await
+                .map(|v| v.map(|transactions| RpcPendingTransactionsResponse { transactions })),
+        )
+    }
+
+    async fn state_diff(&self, params: Option<Value>) -> Result<Value, RpcError> {
+        if !self.enable_state_diff {
+            return Err(RpcError::method_not_found("EXPERIMENTAL_state_diff".to_string()));
+        }
+        let RpcStateDiffRequest { shard_id, old_state_root, new_state_root, after_key, limit } =
+            parse_params(params)?;
+        let limit = limit.unwrap_or(STATE_DIFF_DEFAULT_LIMIT).min(STATE_DIFF_MAX_LIMIT).max(1);
+        jsonify(
+            self.view_client_addr
+                .send(GetStateDiff { shard_id, old_state_root, new_state_root, after_key, limit })
+                .await
+                .map(|v| {
+                    v.map(|response| RpcStateDiffResponse {
+                        changes: response.changes,
+                        next_after_key: response.next_after_key,
+                    })
+                }),
+        )
+    }
+
     async fn changes_in_block(&self, params: Option<Value>) -> Result<Value, RpcError> {
         let RpcStateChangesInBlockRequest { block_reference } = parse_params(params)?;
         // TODO refactor it. Changed to keep it working before refactoring
@@ -1018,7 +1113,14 @@ pub fn start_http(
     client_addr: Addr<ClientActor>,
     view_client_addr: Addr<ViewClientActor>,
 ) {
-    let RpcConfig { addr, cors_allowed_origins, polling_config, limits_config } = config;
+    let RpcConfig {
+        addr,
+        cors_allowed_origins,
+        polling_config,
+        limits_config,
+        admin: _,
+        enable_state_diff,
+    } = config;
     info!(target:"network", "Starting http server at {}", addr);
     HttpServer::new(move || {
         App::new()
@@ -1028,6 +1130,7 @@ pub fn start_http(
                 view_client_addr: view_client_addr.clone(),
                 polling_config,
                 genesis_config: genesis_config.clone(),
+                enable_state_diff,
             })
             .app_data(web::JsonConfig::default().limit(limits_config.json_payload_max_size))
             .wrap(middleware::Logger::default())
@@ -36,4 +36,9 @@ lazy_static! {
             "Total count of errors by method and message",
             &["method", "err_code"]
         );
+    pub static ref ADMIN_RPC_UNAUTHORIZED_COUNT: near_metrics::Result<IntCounter> =
+        near_metrics::try_create_int_counter(
+            "near_admin_rpc_unauthorized_total",
+            "Total count of admin RPC requests rejected for a missing or wrong token"
+        );
 }
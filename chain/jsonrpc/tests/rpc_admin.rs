@@ -0,0 +1,250 @@
+use std::sync::{Arc, Mutex};
+
+use actix::System;
+use actix_web::http::StatusCode;
+use serde_json::{json, Value};
+
+use near_actix_test_utils::run_actix_until_stop;
+use near_client::test_utils::setup_no_network;
+use near_jsonrpc::{set_log_filter_reloader, start_admin_http, RpcAdminConfig};
+use near_jsonrpc_primitives::message::{from_slice, Message};
+use near_logger_utils::init_test_logger;
+use near_network::test_utils::{open_port, MockNetworkAdapter};
+use near_network::types::ReasonForBan;
+use near_network::NetworkRequests;
+use near_primitives::network::PeerId;
+
+/// Starts the admin server on a TCP port with the `secret` token, returns its url.
+fn start_admin_with_token(network_adapter: Arc<MockNetworkAdapter>) -> String {
+    let (client_addr, view_client_addr) =
+        setup_no_network(vec!["test1", "test2"], "test1", true, false);
+    let addr = format!("127.0.0.1:{}", open_port());
+    start_admin_http(
+        RpcAdminConfig { addr: addr.clone(), auth_token: Some("secret".to_string()) },
+        client_addr,
+        view_client_addr,
+        network_adapter,
+    );
+    format!("http://{}", addr)
+}
+
+fn admin_request(method: &str, params: Value) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "method": method,
+        "id": "dontcare",
+        "params": params,
+    })
+}
+
+/// Sends the request with the given `Authorization` header, returns the status and the message.
+async fn post(url: &str, auth: Option<&str>, request: &Value) -> (StatusCode, Option<Message>) {
+    let client = actix_web::client::Client::new();
+    let mut request_builder = client.post(url);
+    if let Some(auth) = auth {
+        request_builder = request_builder.insert_header(("Authorization", auth));
+    }
+    let mut response = request_builder.send_json(request).await.unwrap();
+    let body = response.body().await.unwrap();
+    let message = if body.is_empty() { None } else { Some(from_slice(&body).unwrap()) };
+    (response.status(), message)
+}
+
+fn assert_null_result(message: Option<Message>) {
+    match message {
+        Some(Message::Response(response)) => assert_eq!(response.result.unwrap(), Value::Null),
+        message => panic!("Unexpected message {:?}", message),
+    }
+}
+
+/// Admin methods are only served to callers with the right token.
+#[test]
+fn test_admin_ban_peer_requires_token() {
+    init_test_logger();
+
+    run_actix_until_stop(async {
+        let network_adapter = Arc::new(MockNetworkAdapter::default());
+        let url = start_admin_with_token(network_adapter.clone());
+
+        actix::spawn(async move {
+            let peer_id = PeerId::random();
+            let request = admin_request("admin_ban_peer", json!({ "peer_id": peer_id }));
+
+            for auth in &[None, Some("Bearer wrong")] {
+                let (status, _) = post(&url, *auth, &request).await;
+                assert_eq!(status, StatusCode::UNAUTHORIZED);
+                assert!(network_adapter.pop().is_none());
+            }
+
+            let (status, message) = post(&url, Some("Bearer secret"), &request).await;
+            assert_eq!(status, StatusCode::OK);
+            assert_null_result(message);
+            assert_eq!(
+                network_adapter.pop(),
+                Some(NetworkRequests::BanPeer { peer_id, ban_reason: ReasonForBan::Manual })
+            );
+            System::current().stop();
+        });
+    });
+}
+
+#[test]
+fn test_admin_routing_table_requires_token() {
+    init_test_logger();
+
+    run_actix_until_stop(async {
+        let network_adapter = Arc::new(MockNetworkAdapter::default());
+        let url = start_admin_with_token(network_adapter.clone());
+
+        actix::spawn(async move {
+            let request = admin_request("admin_routing_table", Value::Null);
+
+            for auth in &[None, Some("Bearer wrong"), Some("secret")] {
+                let (status, _) = post(&url, *auth, &request).await;
+                assert_eq!(status, StatusCode::UNAUTHORIZED);
+                assert!(network_adapter.pop().is_none());
+            }
+
+            let (status, _) = post(&url, Some("Bearer secret"), &request).await;
+            assert_eq!(status, StatusCode::OK);
+            assert_eq!(network_adapter.pop(), Some(NetworkRequests::FetchRoutingTable));
+            System::current().stop();
+        });
+    });
+}
+
+#[test]
+fn test_admin_set_log_filter_requires_token() {
+    init_test_logger();
+
+    let filters = Arc::new(Mutex::new(vec![]));
+    let reloader_filters = filters.clone();
+    set_log_filter_reloader(Box::new(move |filter| {
+        reloader_filters.lock().unwrap().push(filter.to_string());
+        Ok(())
+    }));
+
+    run_actix_until_stop(async move {
+        let network_adapter = Arc::new(MockNetworkAdapter::default());
+        let url = start_admin_with_token(network_adapter);
+
+        actix::spawn(async move {
+            let request = admin_request("admin_set_log_filter", json!({ "filter": "near=debug" }));
+
+            for auth in &[None, Some("Bearer wrong")] {
+                let (status, _) = post(&url, *auth, &request).await;
+                assert_eq!(status, StatusCode::UNAUTHORIZED);
+                assert!(filters.lock().unwrap().is_empty());
+            }
+
+            let (status, message) = post(&url, Some("Bearer secret"), &request).await;
+            assert_eq!(status, StatusCode::OK);
+            assert_null_result(message);
+            assert_eq!(*filters.lock().unwrap(), vec!["near=debug".to_string()]);
+            System::current().stop();
+        });
+    });
+}
+
+#[test]
+fn test_admin_track_shards_requires_token() {
+    init_test_logger();
+
+    run_actix_until_stop(async {
+        let network_adapter = Arc::new(MockNetworkAdapter::default());
+        let url = start_admin_with_token(network_adapter);
+
+        actix::spawn(async move {
+            let request = admin_request("admin_track_shards", json!({ "shard_ids": [0] }));
+
+            for auth in &[None, Some("Bearer wrong")] {
+                let (status, message) = post(&url, *auth, &request).await;
+                assert_eq!(status, StatusCode::UNAUTHORIZED);
+                assert!(message.is_none());
+            }
+
+            let (status, message) = post(&url, Some("Bearer secret"), &request).await;
+            assert_eq!(status, StatusCode::OK);
+            assert!(matches!(message, Some(Message::Response(_))));
+            System::current().stop();
+        });
+    });
+}
+
+/// Sends the request over the unix socket, returns the raw HTTP response.
+#[cfg(unix)]
+fn post_unix(path: &std::path::Path, request: &Value) -> String {
+    use std::io::{Read, Write};
+
+    let body = request.to_string();
+    let mut stream = std::os::unix::net::UnixStream::connect(path).unwrap();
+    write!(
+        stream,
+        "POST / HTTP/1.1\r\nHost: localhost\r\nContent-Type: application/json\r\n\
+         Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+        body.len(),
+        body
+    )
+    .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    response
+}
+
+/// The unix socket replaces a stale one, only its owner can connect and no token is needed.
+#[cfg(unix)]
+#[test]
+fn test_admin_unix_socket() {
+    use std::os::unix::fs::PermissionsExt;
+
+    init_test_logger();
+
+    let dir = tempfile::Builder::new().prefix("admin_rpc").tempdir().unwrap();
+    let path = dir.path().join("admin.sock");
+    // Left behind as if the previous node didn't shut down cleanly
+    drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+    assert!(path.exists());
+
+    run_actix_until_stop(async move {
+        let (client_addr, view_client_addr) =
+            setup_no_network(vec!["test1", "test2"], "test1", true, false);
+        let network_adapter = Arc::new(MockNetworkAdapter::default());
+        start_admin_http(
+            RpcAdminConfig { addr: format!("unix:{}", path.display()), auth_token: None },
+            client_addr,
+            view_client_addr,
+            network_adapter.clone(),
+        );
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+
+        actix::spawn(async move {
+            let request = admin_request("admin_routing_table", Value::Null);
+            let response =
+                tokio::task::spawn_blocking(move || post_unix(&path, &request)).await.unwrap();
+            assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+            assert_eq!(network_adapter.pop(), Some(NetworkRequests::FetchRoutingTable));
+            drop(dir);
+            System::current().stop();
+        });
+    });
+}
+
+#[test]
+fn test_admin_config_validation() {
+    let config = |addr: &str, auth_token: Option<&str>| RpcAdminConfig {
+        addr: addr.to_string(),
+        auth_token: auth_token.map(str::to_string),
+    };
+    assert!(config("127.0.0.1:3040", Some("secret")).validate().is_ok());
+    assert!(config("127.0.0.1:3040", None).validate().is_err());
+    assert!(config("localhost", Some("secret")).validate().is_err());
+    assert!(config("127.0.0.1:3040", Some("")).validate().is_err());
+    assert!(config("127.0.0.1:3040", Some("two words")).validate().is_err());
+    assert!(config("unix:", None).validate().is_err());
+    #[cfg(unix)]
+    assert!(config("unix:/tmp/admin.sock", None).validate().is_ok());
+    #[cfg(unix)]
+    assert!(config("unix:/tmp/admin.sock", Some("secret")).validate().is_ok());
+}
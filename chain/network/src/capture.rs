@@ -0,0 +1,270 @@
+//! Capture of all messages exchanged with peers, and their replay.
+//!
+//! Nodes built with the `traffic_capture` feature and `traffic_capture_path` set in the network
+//! config write every message sent to or received from a peer to that file, until it reaches
+//! `traffic_capture_max_size` bytes. The capture can then
+//! be replayed into a `ClientActor`/`PeerManagerActor` pair to reproduce what the node went
+//! through, e.g. `near_network::capture::replay(CaptureReader::open(path)?, ...)` in a test.
+//!
+//! The file starts with `CAPTURE_MAGIC`, followed by the `CaptureHeader` and `CapturedMessage`s,
+//! each of them Borsh-encoded and prefixed with its length as 4 bytes little endian.
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::time::Duration;
+
+use actix::{Addr, Message, Recipient};
+use borsh::{BorshDeserialize, BorshSerialize};
+use chrono::Utc;
+use tracing::{error, warn};
+
+use near_primitives::network::PeerId;
+use near_primitives::utils::to_timestamp;
+
+use crate::codec::bytes_to_peer_message;
+use crate::peer::{client_message, view_client_message};
+use crate::types::{
+    NetworkClientMessages, NetworkRequests, NetworkViewClientMessages, PeerIdOrHash, PeerMessage,
+    PeersResponse, RoutedMessageBody,
+};
+use crate::PeerManagerActor;
+
+const CAPTURE_MAGIC: &[u8; 8] = b"NEARCAP1";
+/// Size of the length prefix of every frame.
+const FRAME_LEN_SIZE: usize = 4;
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    Inbound,
+    Outbound,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq, Eq)]
+pub struct CaptureHeader {
+    /// Node which captured the traffic.
+    pub peer_id: PeerId,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Message, Clone, Debug, PartialEq, Eq)]
+#[rtype(result = "()")]
+pub struct CapturedMessage {
+    /// Time the message was sent or received, in nanoseconds since the unix epoch.
+    pub timestamp: u64,
+    /// Peer the message was exchanged with.
+    pub peer_id: PeerId,
+    pub direction: Direction,
+    /// Borsh-encoded `PeerMessage`, as sent over the wire before encryption.
+    pub message: Vec<u8>,
+}
+
+impl CapturedMessage {
+    pub fn new(peer_id: PeerId, direction: Direction, message: Vec<u8>) -> Self {
+        Self { timestamp: to_timestamp(Utc::now()), peer_id, direction, message }
+    }
+
+    pub fn peer_message(&self) -> io::Result<PeerMessage> {
+        bytes_to_peer_message(&self.message)
+    }
+}
+
+fn write_frame(writer: &mut impl Write, bytes: &[u8]) -> io::Result<()> {
+    writer.write_all(&(bytes.len() as u32).to_le_bytes())?;
+    writer.write_all(bytes)
+}
+
+/// Reads the next frame, or `None` at the end of the file.
+fn read_frame<T: BorshDeserialize>(reader: &mut impl Read) -> io::Result<Option<T>> {
+    let mut len_bytes = [0u8; 4];
+    match reader.read_exact(&mut len_bytes) {
+        Ok(()) => {}
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(err) => return Err(err),
+    }
+    let mut bytes = vec![0u8; u32::from_le_bytes(len_bytes) as usize];
+    reader.read_exact(&mut bytes)?;
+    T::try_from_slice(&bytes).map(Some)
+}
+
+/// Writes the captured messages to a file.
+pub struct TrafficCapture {
+    writer: BufWriter<File>,
+    /// Number of bytes written to the file so far.
+    size: u64,
+    /// Messages which would make the file larger than this are dropped.
+    max_size: u64,
+}
+
+impl TrafficCapture {
+    /// Creates the capture file, overwriting the existing one. Once the file reaches `max_size`
+    /// bytes, the rest of the traffic is not captured.
+    pub fn create(path: &Path, peer_id: PeerId, max_size: u64) -> io::Result<Self> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writer.write_all(CAPTURE_MAGIC)?;
+        let header = CaptureHeader { peer_id }.try_to_vec()?;
+        write_frame(&mut writer, &header)?;
+        let size = (CAPTURE_MAGIC.len() + FRAME_LEN_SIZE + header.len()) as u64;
+        Ok(Self { writer, size, max_size })
+    }
+
+    pub fn write(&mut self, message: &CapturedMessage) {
+        if self.size >= self.max_size {
+            return;
+        }
+        let bytes = message.try_to_vec().expect("Failed to serialize");
+        let frame_size = (FRAME_LEN_SIZE + bytes.len()) as u64;
+        if self.size + frame_size > self.max_size {
+            warn!(target: "network", "Traffic capture reached its size limit of {} bytes, the rest of the traffic is not captured", self.max_size);
+            self.size = self.max_size;
+            return;
+        }
+        match write_frame(&mut self.writer, &bytes) {
+            Ok(()) => self.size += frame_size,
+            Err(err) => error!(target: "network", "Failed to write captured message: {}", err),
+        }
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Reads the captured messages in the order they were captured.
+pub struct CaptureReader<R: Read> {
+    reader: R,
+    header: CaptureHeader,
+}
+
+impl CaptureReader<BufReader<File>> {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        Self::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: Read> CaptureReader<R> {
+    pub fn new(mut reader: R) -> io::Result<Self> {
+        let mut magic = [0u8; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != CAPTURE_MAGIC {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Not a traffic capture"));
+        }
+        let header = read_frame(&mut reader)?.ok_or_else(|| {
+            io::Error::new(io::ErrorKind::UnexpectedEof, "Missing traffic capture header")
+        })?;
+        Ok(Self { reader, header })
+    }
+
+    pub fn header(&self) -> &CaptureHeader {
+        &self.header
+    }
+}
+
+impl<R: Read> Iterator for CaptureReader<R> {
+    type Item = io::Result<CapturedMessage>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        read_frame(&mut self.reader).transpose()
+    }
+}
+
+/// Replays the messages the capturing node received into the actors, as if they came from the
+/// captured peers. Every message is delivered only after the previous one was processed, so the
+/// replay is deterministic. With `realtime` the original delays between messages are kept.
+///
+/// The routing table updates go to the peer manager. Routed messages are delivered only if they
+/// were addressed to the capturing node, the ones addressed by hash are assumed to be.
+pub async fn replay<R: Read>(
+    capture: CaptureReader<R>,
+    peer_manager_addr: Addr<PeerManagerActor>,
+    client_addr: Recipient<NetworkClientMessages>,
+    view_client_addr: Recipient<NetworkViewClientMessages>,
+    realtime: bool,
+) -> io::Result<()> {
+    let me = capture.header().peer_id.clone();
+    let mut last_timestamp = None;
+    for captured in capture {
+        let captured = captured?;
+        if captured.direction != Direction::Inbound {
+            continue;
+        }
+        if let Some(last_timestamp) = last_timestamp.filter(|_| realtime) {
+            let delay = captured.timestamp.saturating_sub(last_timestamp);
+            tokio::time::sleep(Duration::from_nanos(delay)).await;
+        }
+        last_timestamp = Some(captured.timestamp);
+
+        let peer_id = captured.peer_id.clone();
+        let msg = match captured.peer_message() {
+            Ok(msg) => msg,
+            Err(err) => {
+                warn!(target: "network", "Skipping captured message which can't be decoded: {}", err);
+                continue;
+            }
+        };
+        let result = match msg {
+            PeerMessage::RoutingTableSync(sync_data) => peer_manager_addr
+                .send(NetworkRequests::Sync { peer_id, sync_data })
+                .await
+                .map(|_| ()),
+            PeerMessage::PeersResponse(peers) => {
+                peer_manager_addr.send(PeersResponse { peers }).await
+            }
+            PeerMessage::PeersResponseV2(peers) => {
+                let peers = peers.into_iter().map(Into::into).collect();
+                peer_manager_addr.send(PeersResponse { peers }).await
+            }
+            PeerMessage::Routed(routed_message) => {
+                let for_me = match &routed_message.target {
+                    PeerIdOrHash::PeerId(target) => target == &me,
+                    PeerIdOrHash::Hash(_) => true,
+                };
+                // Pings and pongs are handled by the peer manager of the capturing node.
+                if !for_me
+                    || matches!(
+                        routed_message.body,
+                        RoutedMessageBody::Ping(_) | RoutedMessageBody::Pong(_)
+                    )
+                {
+                    continue;
+                }
+                let msg = PeerMessage::Routed(routed_message);
+                if msg.is_view_client_message() {
+                    send_view_client_message(&view_client_addr, msg).await
+                } else {
+                    send_client_message(&client_addr, msg, peer_id).await
+                }
+            }
+            msg if msg.is_view_client_message() => {
+                send_view_client_message(&view_client_addr, msg).await
+            }
+            msg if msg.is_client_message() => send_client_message(&client_addr, msg, peer_id).await,
+            _ => continue,
+        };
+        if let Err(err) = result {
+            return Err(io::Error::new(io::ErrorKind::Other, err.to_string()));
+        }
+    }
+    Ok(())
+}
+
+async fn send_client_message(
+    client_addr: &Recipient<NetworkClientMessages>,
+    msg: PeerMessage,
+    peer_id: PeerId,
+) -> Result<(), actix::MailboxError> {
+    // Whether the block was requested is not captured, but it only matters for the
+    // blocks which are too far from the head.
+    match client_message(msg, peer_id, true) {
+        Some(msg) => client_addr.send(msg).await.map(|_| ()),
+        None => Ok(()),
+    }
+}
+
+async fn send_view_client_message(
+    view_client_addr: &Recipient<NetworkViewClientMessages>,
+    msg: PeerMessage,
+) -> Result<(), actix::MailboxError> {
+    match view_client_message(msg) {
+        Some(msg) => view_client_addr.send(msg).await.map(|_| ()),
+        None => Ok(()),
+    }
+}
@@ -6,6 +6,8 @@ use bytesize::{GIB, MIB};
 use tokio_util::codec::{Decoder, Encoder};
 use tracing::error;
 
+use crate::compression::{self, SharedCompression};
+use crate::encryption::{self, SharedTransportState};
 use crate::metrics;
 use crate::types::{PeerMessage, ReasonForBan};
 use near_performance_metrics::framed_write::EncoderCallBack;
@@ -18,12 +20,45 @@ const MAX_CAPACITY: u64 = GIB;
 
 pub struct Codec {
     max_length: u32,
+    /// Once set, frames are encrypted with it.
+    transport: SharedTransportState,
+    /// Large frames are compressed once it is enabled.
+    compression: SharedCompression,
 }
 
 #[allow(clippy::new_without_default)]
 impl Codec {
     pub fn new() -> Self {
-        Codec { max_length: NETWORK_MESSAGE_MAX_SIZE as u32 }
+        Self::with_transport(Default::default())
+    }
+
+    /// Codec which starts encrypting frames once the peer sets the shared transport state.
+    pub fn with_transport(transport: SharedTransportState) -> Self {
+        Codec {
+            max_length: NETWORK_MESSAGE_MAX_SIZE as u32,
+            transport,
+            compression: Default::default(),
+        }
+    }
+
+    /// Codec which compresses large frames once the peer enables the shared compression.
+    pub fn with_compression(mut self, compression: SharedCompression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Compresses the frame if it's large enough and compression makes it smaller.
+    fn maybe_compress(&self, item: Vec<u8>) -> Result<Vec<u8>, Error> {
+        if !self.compression.should_compress(item.len()) {
+            return Ok(item);
+        }
+        let compressed = compression::compress(&item)?;
+        if compressed.len() >= item.len() {
+            return Ok(item);
+        }
+        near_metrics::inc_counter_by(&metrics::COMPRESSED_MESSAGES_RAW_BYTES, item.len() as u64);
+        near_metrics::inc_counter_by(&metrics::COMPRESSED_MESSAGES_BYTES, compressed.len() as u64);
+        Ok(compressed)
     }
 }
 
@@ -45,6 +80,11 @@ impl Encoder<Vec<u8>> for Codec {
         if item.len() > self.max_length as usize {
             Err(Error::new(ErrorKind::InvalidInput, "Input is too long"))
         } else {
+            let item = self.maybe_compress(item)?;
+            let item = match self.transport.lock().unwrap().as_mut() {
+                Some(transport) => encryption::encrypt(transport, &item)?,
+                None => item,
+            };
             #[cfg(feature = "performance_stats")]
             {
                 let stat = get_thread_stats_logger();
@@ -85,7 +125,13 @@ impl Decoder for Codec {
         len_bytes.copy_from_slice(&buf[0..4]);
         let len = u32::from_le_bytes(len_bytes);
 
-        if len > self.max_length {
+        let mut transport = self.transport.lock().unwrap();
+        let max_length = match transport.as_ref() {
+            Some(_) => encryption::encrypted_len(self.max_length as usize) as u32,
+            None => self.max_length,
+        };
+
+        if len > max_length {
             // If this point is reached, abusive peer is banned.
             return Ok(Some(Err(ReasonForBan::Abusive)));
         }
@@ -94,9 +140,15 @@ impl Decoder for Codec {
             // not enough bytes, keep waiting
             Ok(None)
         } else {
-            let res = Some(Ok(buf[4..4 + len as usize].to_vec()));
+            let frame = &buf[4..4 + len as usize];
+            // Frames which fail authentication drop the connection, as they can be injected
+            // by anyone on the path to the peer.
+            let res = match transport.as_mut() {
+                Some(transport) => encryption::decrypt(transport, frame)?,
+                None => frame.to_vec(),
+            };
             buf.advance(4 + len as usize);
-            Ok(res)
+            Ok(Some(Ok(res)))
         }
     }
 }
@@ -172,6 +224,7 @@ mod test {
         version::{OLDEST_BACKWARD_COMPATIBLE_PROTOCOL_VERSION, PROTOCOL_VERSION},
     };
 
+    use crate::encryption::NoiseHandshake;
     use crate::routing::EdgeInfo;
     use crate::types::{
         Handshake, HandshakeFailureReason, HandshakeV2, PeerChainInfo, PeerChainInfoV2,
@@ -281,11 +334,49 @@ mod test {
                 archival: false,
             },
             edge_info: EdgeInfo::default(),
+            compression: false,
         };
         let msg = PeerMessage::Handshake(fake_handshake);
         test_codec(msg);
     }
 
+    /// The compression flag is only sent by the versions which know it.
+    #[test]
+    fn test_peer_message_handshake_compression() {
+        let peer_info = PeerInfo::random();
+        for &version in &[39, PROTOCOL_VERSION] {
+            let handshake = Handshake::new(
+                version,
+                peer_info.id.clone(),
+                peer_info.id.clone(),
+                None,
+                PeerChainInfoV2 {
+                    genesis_id: Default::default(),
+                    height: 0,
+                    tracked_shards: vec![],
+                    archival: false,
+                },
+                EdgeInfo::default(),
+                true,
+            );
+            let supported = compression::is_compression_supported(version);
+            assert_eq!(handshake.compression, supported);
+            // Layout of the handshake before the flag was added.
+            let mut legacy_bytes = vec![];
+            handshake.version.serialize(&mut legacy_bytes).unwrap();
+            handshake.oldest_supported_version.serialize(&mut legacy_bytes).unwrap();
+            handshake.peer_id.serialize(&mut legacy_bytes).unwrap();
+            handshake.target_peer_id.serialize(&mut legacy_bytes).unwrap();
+            handshake.listen_port.serialize(&mut legacy_bytes).unwrap();
+            handshake.chain_info.serialize(&mut legacy_bytes).unwrap();
+            handshake.edge_info.serialize(&mut legacy_bytes).unwrap();
+            let bytes = handshake.try_to_vec().unwrap();
+            assert_eq!(&bytes[..legacy_bytes.len()], &legacy_bytes[..]);
+            assert_eq!(bytes.len(), legacy_bytes.len() + supported as usize);
+            test_codec(PeerMessage::Handshake(handshake));
+        }
+    }
+
     #[test]
     fn test_peer_message_handshake_v2() {
         let peer_info = PeerInfo::random();
@@ -396,6 +487,68 @@ mod test {
         assert_eq!(account_id, dec_account_id);
     }
 
+    #[test]
+    fn test_encrypted_codec() {
+        let initiator_key = SecretKey::from_random(KeyType::ED25519);
+        let responder_key = SecretKey::from_random(KeyType::ED25519);
+        let mut initiator =
+            NoiseHandshake::initiator(&initiator_key, &PeerId::new(responder_key.public_key()))
+                .unwrap();
+        let mut responder =
+            NoiseHandshake::responder(&responder_key, &PeerId::new(initiator_key.public_key()))
+                .unwrap();
+        responder.read_message(&initiator.write_message().unwrap()).unwrap();
+        initiator.read_message(&responder.write_message().unwrap()).unwrap();
+
+        let mut encoder = Codec::new();
+        let mut decoder = Codec::new();
+        *encoder.transport.lock().unwrap() = Some(initiator.into_transport_state().unwrap());
+        *decoder.transport.lock().unwrap() = Some(responder.into_transport_state().unwrap());
+
+        let msg = PeerMessage::PeersResponse(vec![PeerInfo::random()]);
+        let bytes = peer_message_to_bytes(&msg).unwrap();
+        let mut buffer = BytesMut::new();
+        encoder.encode(bytes.clone(), &mut buffer).unwrap();
+        assert!(!buffer.windows(bytes.len()).any(|window| window == &bytes[..]));
+        let decoded = decoder.decode(&mut buffer).unwrap().unwrap().unwrap();
+        assert_eq!(bytes_to_peer_message(&decoded).unwrap(), msg);
+
+        encoder.encode(bytes, &mut buffer).unwrap();
+        let last = buffer.len() - 1;
+        buffer[last] ^= 1;
+        assert!(decoder.decode(&mut buffer).is_err());
+    }
+
+    #[test]
+    fn test_compressed_codec() {
+        let mut encoder = Codec::new().with_compression(SharedCompression::new(Some(100)));
+        let mut decoder = Codec::new();
+
+        let small_msg = PeerMessage::PeersResponse(vec![PeerInfo::random()]);
+        let large_msg = PeerMessage::PeersResponse(vec![PeerInfo::random(); 100]);
+        let large_bytes = peer_message_to_bytes(&large_msg).unwrap();
+        let mut buffer = BytesMut::new();
+        // Nothing is compressed until the peer enables the compression.
+        encoder.encode(large_bytes.clone(), &mut buffer).unwrap();
+        assert_eq!(buffer.len(), large_bytes.len() + 4);
+        assert_eq!(decoder.decode(&mut buffer).unwrap().unwrap().unwrap(), large_bytes);
+
+        encoder.compression.enable();
+        encoder.encode(peer_message_to_bytes(&small_msg).unwrap(), &mut buffer).unwrap();
+        let decoded = decoder.decode(&mut buffer).unwrap().unwrap().unwrap();
+        assert_eq!(bytes_to_peer_message(&decoded).unwrap(), small_msg);
+
+        // Compressed frames are passed on as they are, `Peer` decompresses them.
+        encoder.encode(large_bytes.clone(), &mut buffer).unwrap();
+        assert!(buffer.len() < large_bytes.len());
+        let decoded = decoder.decode(&mut buffer).unwrap().unwrap().unwrap();
+        assert!(compression::is_compressed(&decoded));
+        assert_eq!(
+            compression::decompress(&decoded, compression::MAX_DECOMPRESSED_LEN).unwrap(),
+            large_bytes
+        );
+    }
+
     #[test]
     fn test_abusive() {
         let mut codec = Codec::new();
@@ -0,0 +1,133 @@
+//! Compression of large messages exchanged with peers.
+//!
+//! Peers advertise `Handshake::compression` if they have `NetworkConfig::compression_threshold`
+//! set. Once both did, they send the messages larger than their threshold as
+//! `PeerMessage::Compressed`, which carries the zstd compressed Borsh encoding of the original
+//! message. `Codec` compresses frames before encrypting them. `Peer` counts the compressed size of
+//! received messages against the rate limits before decompressing them, and decompresses at most
+//! `MAX_DECOMPRESSED_LEN` bytes, so a peer can't make this node inflate more than it's allowed to
+//! send. Peers sending compressed frames without having negotiated compression are banned.
+use std::io::{self, Read};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use borsh::{BorshDeserialize, BorshSerialize};
+
+use near_primitives::checked_feature;
+use near_primitives::version::ProtocolVersion;
+
+use crate::types::PeerMessage;
+
+/// Borsh variant of `PeerMessage::Compressed`.
+const COMPRESSED_VARIANT: u8 = 22;
+const COMPRESSION_LEVEL: i32 = 3;
+/// Name of `PeerMessage::Compressed` in the rate limits config.
+pub const COMPRESSED_MSG_VARIANT: &str = "Compressed";
+/// Maximum size of a decompressed message. Larger messages are sent uncompressed.
+pub const MAX_DECOMPRESSED_LEN: usize = 16 * 1024 * 1024;
+
+/// Compression settings of a connection, shared between the peer actor which enables them once
+/// both peers advertised compression in their handshakes and the codec which compresses the
+/// frames.
+#[derive(Clone, Default)]
+pub struct SharedCompression {
+    /// Messages larger than this are compressed. Compression is disabled if not set.
+    threshold: Option<usize>,
+    enabled: Arc<AtomicBool>,
+}
+
+impl SharedCompression {
+    pub fn new(threshold: Option<usize>) -> Self {
+        Self { threshold, enabled: Default::default() }
+    }
+
+    /// Whether this node advertises compression to its peers.
+    pub fn is_configured(&self) -> bool {
+        self.threshold.is_some()
+    }
+
+    pub fn enable(&self) {
+        self.enabled.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether both peers advertised compression, so compressed frames are accepted.
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    /// Whether the message of given size should be compressed.
+    pub fn should_compress(&self, len: usize) -> bool {
+        self.is_enabled()
+            && self.threshold.map_or(false, |threshold| len > threshold)
+            && len <= MAX_DECOMPRESSED_LEN
+    }
+}
+
+/// Whether the handshake of the protocol version carries `Handshake::compression`.
+pub fn is_compression_supported(protocol_version: ProtocolVersion) -> bool {
+    checked_feature!(
+        "protocol_feature_peer_message_compression",
+        PeerMessageCompression,
+        protocol_version
+    )
+}
+
+/// Wraps the Borsh encoded message into `PeerMessage::Compressed`.
+pub fn compress(bytes: &[u8]) -> io::Result<Vec<u8>> {
+    let compressed = zstd::stream::encode_all(bytes, COMPRESSION_LEVEL)?;
+    PeerMessage::Compressed(compressed).try_to_vec()
+}
+
+pub fn is_compressed(bytes: &[u8]) -> bool {
+    bytes.first() == Some(&COMPRESSED_VARIANT)
+}
+
+/// Unwraps the message compressed by `compress`. Fails if it decompresses to more than
+/// `max_len` bytes, or to another compressed message.
+pub fn decompress(bytes: &[u8], max_len: usize) -> io::Result<Vec<u8>> {
+    let compressed = Vec::<u8>::try_from_slice(&bytes[1..])?;
+    let mut decompressed = vec![];
+    zstd::stream::read::Decoder::new(&compressed[..])?
+        .take(max_len as u64 + 1)
+        .read_to_end(&mut decompressed)?;
+    if decompressed.len() > max_len {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "Decompressed message is too long"));
+    }
+    if is_compressed(&decompressed) {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "Nested compressed message"));
+    }
+    Ok(decompressed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compressed_variant() {
+        let bytes = PeerMessage::Compressed(vec![1, 2, 3]).try_to_vec().unwrap();
+        assert!(is_compressed(&bytes));
+        assert!(!is_compressed(&PeerMessage::Disconnect.try_to_vec().unwrap()));
+    }
+
+    #[test]
+    fn test_decompress() {
+        let bytes = PeerMessage::PeersResponse(vec![]).try_to_vec().unwrap().repeat(1000);
+        let compressed = compress(&bytes).unwrap();
+        assert!(compressed.len() < bytes.len());
+        assert_eq!(decompress(&compressed, bytes.len()).unwrap(), bytes);
+        assert!(decompress(&compressed, bytes.len() - 1).is_err());
+        assert!(decompress(&compress(&compressed).unwrap(), bytes.len()).is_err());
+    }
+
+    #[test]
+    fn test_should_compress() {
+        let compression = SharedCompression::new(Some(100));
+        assert!(!compression.should_compress(1000));
+        compression.enable();
+        assert!(!compression.should_compress(100));
+        assert!(compression.should_compress(1000));
+        // The peer would refuse to decompress it.
+        assert!(!compression.should_compress(MAX_DECOMPRESSED_LEN + 1));
+    }
+}
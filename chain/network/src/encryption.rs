@@ -0,0 +1,289 @@
+//! Encryption of the connections between peers.
+//!
+//! After the plaintext `Handshake` is accepted, peers which agreed on a protocol version with
+//! `EncryptedPeerConnections` run a Noise IK handshake keyed by their `PeerId`s: the outbound
+//! peer already knows whom it connects to, and the inbound peer checks that the static key of
+//! the initiator matches the `PeerId` from its `Handshake`. Both `Handshake`s are part of the
+//! Noise prologue, so the session can't be paired with the handshakes of another connection.
+//! Every frame sent afterwards is encrypted by `Codec` with the resulting transport state.
+use std::io;
+use std::sync::{Arc, Mutex};
+
+use snow::{Builder, HandshakeState, TransportState};
+
+use near_crypto::key_conversion::{convert_public_key_to_x25519, convert_secret_key_to_x25519};
+use near_crypto::{PublicKey, SecretKey};
+use near_primitives::checked_feature;
+use near_primitives::network::PeerId;
+use near_primitives::version::ProtocolVersion;
+
+const NOISE_PATTERN: &str = "Noise_IK_25519_ChaChaPoly_BLAKE2s";
+/// Binds the handshake to this protocol, so it can't be replayed in another one.
+const NOISE_PROLOGUE: &[u8] = b"near-network-noise-ik";
+/// Maximum size of a single Noise message.
+const NOISE_MAX_MESSAGE_LEN: usize = 65535;
+/// Size of the authentication tag appended to every encrypted Noise message.
+const NOISE_TAG_LEN: usize = 16;
+/// Maximum size of the plaintext which fits into a single Noise message.
+const NOISE_MAX_PLAINTEXT_LEN: usize = NOISE_MAX_MESSAGE_LEN - NOISE_TAG_LEN;
+
+/// Transport state of an established encrypted connection, shared between the peer actor which
+/// sets it when the handshake completes and the codecs which use it.
+pub type SharedTransportState = Arc<Mutex<Option<TransportState>>>;
+
+/// Whether the connection between the two peers is encrypted. Both peers must derive the same
+/// answer, so it only depends on the negotiated protocol version and the `PeerId`s.
+pub fn is_encryption_enabled(
+    protocol_version: ProtocolVersion,
+    node_id: &PeerId,
+    peer_id: &PeerId,
+) -> bool {
+    checked_feature!(
+        "protocol_feature_encrypted_peer_connections",
+        EncryptedPeerConnections,
+        protocol_version
+    ) && x25519_public_key(node_id).is_some()
+        && x25519_public_key(peer_id).is_some()
+}
+
+fn x25519_public_key(peer_id: &PeerId) -> Option<[u8; 32]> {
+    match peer_id.public_key() {
+        PublicKey::ED25519(public_key) => convert_public_key_to_x25519(&public_key),
+        PublicKey::SECP256K1(_) => None,
+    }
+}
+
+/// Prologue of the Noise handshake following the given serialized `Handshake`s, both peers must
+/// pass them in the same order.
+pub fn noise_prologue(outbound_handshake: &[u8], inbound_handshake: &[u8]) -> Vec<u8> {
+    let mut prologue = NOISE_PROLOGUE.to_vec();
+    for handshake in &[outbound_handshake, inbound_handshake] {
+        prologue.extend_from_slice(&(handshake.len() as u32).to_le_bytes());
+        prologue.extend_from_slice(handshake);
+    }
+    prologue
+}
+
+fn noise_builder<'a>(local_private_key: &'a [u8], prologue: &'a [u8]) -> Builder<'a> {
+    Builder::new(NOISE_PATTERN.parse().unwrap())
+        .local_private_key(local_private_key)
+        .prologue(prologue)
+}
+
+fn noise_error(err: snow::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err.to_string())
+}
+
+/// Noise handshake in progress.
+pub struct NoiseHandshake {
+    state: HandshakeState,
+    /// Static key the remote peer must authenticate with.
+    expected_remote_key: [u8; 32],
+}
+
+impl NoiseHandshake {
+    fn new(
+        secret_key: &SecretKey,
+        peer_id: &PeerId,
+        prologue: &[u8],
+        initiator: bool,
+    ) -> io::Result<Self> {
+        let local_private_key = match secret_key {
+            SecretKey::ED25519(secret_key) => convert_secret_key_to_x25519(secret_key),
+            SecretKey::SECP256K1(_) => {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, "Not an ED25519 key"))
+            }
+        };
+        let expected_remote_key = x25519_public_key(peer_id).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "Peer id is not an ED25519 key")
+        })?;
+        let builder = noise_builder(&local_private_key, prologue);
+        let state = if initiator {
+            builder.remote_public_key(&expected_remote_key).build_initiator()
+        } else {
+            builder.build_responder()
+        }
+        .map_err(noise_error)?;
+        Ok(Self { state, expected_remote_key })
+    }
+
+    /// Starts the handshake as the outbound peer connecting to `peer_id`, the prologue comes
+    /// from `noise_prologue`.
+    pub fn initiator(
+        secret_key: &SecretKey,
+        peer_id: &PeerId,
+        prologue: &[u8],
+    ) -> io::Result<Self> {
+        Self::new(secret_key, peer_id, prologue, true)
+    }
+
+    /// Starts the handshake as the inbound peer, which expects the initiator to be `peer_id`.
+    pub fn responder(
+        secret_key: &SecretKey,
+        peer_id: &PeerId,
+        prologue: &[u8],
+    ) -> io::Result<Self> {
+        Self::new(secret_key, peer_id, prologue, false)
+    }
+
+    /// Next handshake message to send to the remote peer.
+    pub fn write_message(&mut self) -> io::Result<Vec<u8>> {
+        let mut message = vec![0u8; NOISE_MAX_MESSAGE_LEN];
+        let len = self.state.write_message(&[], &mut message).map_err(noise_error)?;
+        message.truncate(len);
+        Ok(message)
+    }
+
+    /// Processes handshake message from the remote peer and checks its static key.
+    pub fn read_message(&mut self, message: &[u8]) -> io::Result<()> {
+        let mut payload = vec![0u8; NOISE_MAX_MESSAGE_LEN];
+        self.state.read_message(message, &mut payload).map_err(noise_error)?;
+        match self.state.get_remote_static() {
+            Some(remote_key) if remote_key == &self.expected_remote_key[..] => Ok(()),
+            _ => Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "Remote static key doesn't match the peer id",
+            )),
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.state.is_handshake_finished()
+    }
+
+    pub fn into_transport_state(self) -> io::Result<TransportState> {
+        self.state.into_transport_mode().map_err(noise_error)
+    }
+}
+
+/// Size of the encrypted frame carrying `len` bytes of plaintext.
+pub fn encrypted_len(len: usize) -> usize {
+    let chunks = std::cmp::max(1, (len + NOISE_MAX_PLAINTEXT_LEN - 1) / NOISE_MAX_PLAINTEXT_LEN);
+    len + chunks * NOISE_TAG_LEN
+}
+
+/// Encrypts the frame as a sequence of Noise messages. All of them but the last one have the
+/// maximum size, so the receiver can split the frame without any additional framing.
+pub fn encrypt(transport: &mut TransportState, plaintext: &[u8]) -> io::Result<Vec<u8>> {
+    let mut ciphertext = vec![0u8; encrypted_len(plaintext.len())];
+    let mut offset = 0;
+    let mut chunks = plaintext.chunks(NOISE_MAX_PLAINTEXT_LEN).peekable();
+    if chunks.peek().is_none() {
+        offset += transport.write_message(&[], &mut ciphertext).map_err(noise_error)?;
+    }
+    for chunk in chunks {
+        offset += transport.write_message(chunk, &mut ciphertext[offset..]).map_err(noise_error)?;
+    }
+    debug_assert_eq!(offset, ciphertext.len());
+    Ok(ciphertext)
+}
+
+/// Decrypts the frame produced by `encrypt`. Fails if it was tampered with, reordered or
+/// replayed.
+pub fn decrypt(transport: &mut TransportState, ciphertext: &[u8]) -> io::Result<Vec<u8>> {
+    if ciphertext.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "Empty encrypted frame"));
+    }
+    let mut plaintext = vec![0u8; ciphertext.len()];
+    let mut offset = 0;
+    for chunk in ciphertext.chunks(NOISE_MAX_MESSAGE_LEN) {
+        offset += transport.read_message(chunk, &mut plaintext[offset..]).map_err(noise_error)?;
+    }
+    plaintext.truncate(offset);
+    Ok(plaintext)
+}
+
+#[cfg(test)]
+mod tests {
+    use near_crypto::KeyType;
+
+    use super::*;
+
+    fn handshake_with_prologues(
+        initiator_key: &SecretKey,
+        responder_key: &SecretKey,
+        expected_initiator: &PeerId,
+        initiator_prologue: &[u8],
+        responder_prologue: &[u8],
+    ) -> io::Result<(TransportState, TransportState)> {
+        let mut initiator = NoiseHandshake::initiator(
+            initiator_key,
+            &PeerId::new(responder_key.public_key()),
+            initiator_prologue,
+        )?;
+        let mut responder =
+            NoiseHandshake::responder(responder_key, expected_initiator, responder_prologue)?;
+        responder.read_message(&initiator.write_message()?)?;
+        initiator.read_message(&responder.write_message()?)?;
+        assert!(initiator.is_finished() && responder.is_finished());
+        Ok((initiator.into_transport_state()?, responder.into_transport_state()?))
+    }
+
+    fn handshake(
+        initiator_key: &SecretKey,
+        responder_key: &SecretKey,
+        expected_initiator: &PeerId,
+    ) -> io::Result<(TransportState, TransportState)> {
+        let prologue = noise_prologue(b"outbound", b"inbound");
+        handshake_with_prologues(
+            initiator_key,
+            responder_key,
+            expected_initiator,
+            &prologue,
+            &prologue,
+        )
+    }
+
+    #[test]
+    fn test_encrypted_frames() {
+        let initiator_key = SecretKey::from_random(KeyType::ED25519);
+        let responder_key = SecretKey::from_random(KeyType::ED25519);
+        let (mut initiator, mut responder) =
+            handshake(&initiator_key, &responder_key, &PeerId::new(initiator_key.public_key()))
+                .unwrap();
+
+        for len in &[0, 1, NOISE_MAX_PLAINTEXT_LEN, NOISE_MAX_PLAINTEXT_LEN + 1, 200_000] {
+            let plaintext: Vec<u8> = (0..*len).map(|i| i as u8).collect();
+            let ciphertext = encrypt(&mut initiator, &plaintext).unwrap();
+            assert_eq!(ciphertext.len(), encrypted_len(*len));
+            assert_eq!(decrypt(&mut responder, &ciphertext).unwrap(), plaintext);
+        }
+
+        let mut ciphertext = encrypt(&mut responder, b"message").unwrap();
+        ciphertext[0] ^= 1;
+        assert!(decrypt(&mut initiator, &ciphertext).is_err());
+    }
+
+    #[test]
+    fn test_handshake_with_unexpected_peer() {
+        let initiator_key = SecretKey::from_random(KeyType::ED25519);
+        let responder_key = SecretKey::from_random(KeyType::ED25519);
+        let other_peer_id = PeerId::new(SecretKey::from_random(KeyType::ED25519).public_key());
+        assert_eq!(
+            handshake(&initiator_key, &responder_key, &other_peer_id).err().unwrap().kind(),
+            io::ErrorKind::PermissionDenied
+        );
+    }
+
+    /// A session only completes between peers which exchanged the same plaintext handshakes.
+    #[test]
+    fn test_handshake_with_other_handshakes() {
+        let initiator_key = SecretKey::from_random(KeyType::ED25519);
+        let responder_key = SecretKey::from_random(KeyType::ED25519);
+        let initiator_peer_id = PeerId::new(initiator_key.public_key());
+        for (initiator_prologue, responder_prologue) in &[
+            (noise_prologue(b"outbound", b"inbound"), noise_prologue(b"outbound", b"other")),
+            (noise_prologue(b"outbound", b"inbound"), noise_prologue(b"inbound", b"outbound")),
+            (noise_prologue(b"out", b"boundinbound"), noise_prologue(b"outbound", b"inbound")),
+        ] {
+            assert!(handshake_with_prologues(
+                &initiator_key,
+                &responder_key,
+                &initiator_peer_id,
+                initiator_prologue,
+                responder_prologue,
+            )
+            .is_err());
+        }
+    }
+}
@@ -9,12 +9,16 @@ pub use types::{
 };
 
 mod cache;
+pub mod capture;
 mod codec;
+mod compression;
+mod encryption;
 pub mod metrics;
 mod peer;
 mod peer_manager;
 pub mod peer_store;
 mod rate_counter;
+pub mod rate_limiter;
 #[cfg(feature = "metric_recorder")]
 pub mod recorder;
 pub mod routing;
@@ -72,6 +72,26 @@ lazy_static! {
             "near_dropped_messages_count",
             "Total count of messages which were dropped, because write buffer was full"
         );
+    pub static ref COMPRESSED_MESSAGES_RAW_BYTES: near_metrics::Result<IntCounter> =
+        try_create_int_counter(
+            "near_compressed_messages_raw_bytes",
+            "Total size of the messages sent compressed, before the compression"
+        );
+    pub static ref COMPRESSED_MESSAGES_BYTES: near_metrics::Result<IntCounter> =
+        try_create_int_counter(
+            "near_compressed_messages_bytes",
+            "Total size of the messages sent compressed, after the compression"
+        );
+    pub static ref DECOMPRESSED_MESSAGES_BYTES: near_metrics::Result<IntCounter> =
+        try_create_int_counter(
+            "near_decompressed_messages_bytes",
+            "Total size of the compressed messages received, before the decompression"
+        );
+    pub static ref DECOMPRESSED_MESSAGES_RAW_BYTES: near_metrics::Result<IntCounter> =
+        try_create_int_counter(
+            "near_decompressed_messages_raw_bytes",
+            "Total size of the compressed messages received, after the decompression"
+        );
 }
 
 #[derive(Clone)]
@@ -106,6 +126,12 @@ impl NetworkMetrics {
                 counter_name.clone(),
                 try_create_int_counter(counter_name.as_ref(), counter_name.as_ref()).ok(),
             );
+
+            let counter_name = NetworkMetrics::peer_message_rate_limited(name.as_ref());
+            peer_messages.insert(
+                counter_name.clone(),
+                try_create_int_counter(counter_name.as_ref(), counter_name.as_ref()).ok(),
+            );
         }
 
         Self { peer_messages }
@@ -123,6 +149,10 @@ impl NetworkMetrics {
         format!("near_{}_dropped", message_name.to_lowercase())
     }
 
+    pub fn peer_message_rate_limited(message_name: &str) -> String {
+        format!("near_{}_rate_limited", message_name.to_lowercase())
+    }
+
     pub fn inc(&self, message_name: &str) {
         if let Some(counter) = self.peer_messages.get(message_name) {
             inc_counter_opt(counter.as_ref());
@@ -11,12 +11,16 @@ use actix::{
     Actor, ActorContext, ActorFuture, Addr, Arbiter, AsyncContext, Context, ContextFutureSpawner,
     Handler, Recipient, Running, StreamHandler, WrapFuture,
 };
+use borsh::BorshSerialize;
 use near_performance_metrics::framed_write::{FramedWrite, WriteHandler};
+use snow::TransportState;
 use tracing::{debug, error, info, trace, warn};
 
+use near_crypto::SecretKey;
 use near_metrics;
 use near_performance_metrics;
 use near_primitives::block::GenesisId;
+use near_primitives::checked_feature;
 use near_primitives::hash::CryptoHash;
 use near_primitives::network::PeerId;
 use near_primitives::unwrap_option_or_return;
@@ -25,8 +29,13 @@ use near_primitives::version::{
     ProtocolVersion, OLDEST_BACKWARD_COMPATIBLE_PROTOCOL_VERSION, PROTOCOL_VERSION,
 };
 
+#[cfg(feature = "traffic_capture")]
+use crate::capture::{CapturedMessage, Direction};
 use crate::codec::{self, bytes_to_peer_message, peer_message_to_bytes, Codec};
+use crate::compression::{self, SharedCompression};
+use crate::encryption::{self, NoiseHandshake, SharedTransportState};
 use crate::rate_counter::RateCounter;
+use crate::rate_limiter::RateLimiter;
 #[cfg(feature = "metric_recorder")]
 use crate::recorder::{PeerMessageMetadata, Status};
 use crate::routing::{Edge, EdgeInfo};
@@ -34,8 +43,8 @@ use crate::types::{
     Ban, Consolidate, ConsolidateResponse, Handshake, HandshakeFailureReason, HandshakeV2,
     NetworkClientMessages, NetworkClientResponses, NetworkRequests, NetworkViewClientMessages,
     NetworkViewClientResponses, PeerChainInfo, PeerChainInfoV2, PeerInfo, PeerManagerRequest,
-    PeerMessage, PeerRequest, PeerResponse, PeerStatsResult, PeerStatus, PeerType, PeersRequest,
-    PeersResponse, QueryPeerStats, ReasonForBan, RoutedMessage, RoutedMessageBody,
+    PeerMessage, PeerRequest, PeerResponse, PeerScoreEvent, PeerStatsResult, PeerStatus, PeerType,
+    PeersRequest, PeersResponse, QueryPeerStats, ReasonForBan, RoutedMessage, RoutedMessageBody,
     RoutedMessageFrom, SendMessage, StateResponseInfo, Unregister,
     UPDATE_INTERVAL_LAST_TIME_RECEIVED_MESSAGE,
 };
@@ -192,6 +201,24 @@ pub struct Peer {
     peer_counter: Arc<AtomicUsize>,
     /// The last time a Epoch Sync request was received from this peer
     last_time_received_epoch_sync_request: Instant,
+    /// This node's secret key, which authenticates it in the Noise handshake.
+    secret_key: SecretKey,
+    /// Transport state shared with the codecs, set once the connection is encrypted.
+    transport: SharedTransportState,
+    /// Serialized `Handshake`s sent to and received from the peer, the Noise handshake is
+    /// bound to them.
+    sent_handshake: Option<Vec<u8>>,
+    received_handshake: Option<Vec<u8>>,
+    /// Whether the connection is being encrypted. Until it is, outgoing messages are kept in
+    /// `pending_messages` so that none of them is sent unencrypted.
+    awaiting_encryption: bool,
+    /// Noise handshake in progress, started once both `Handshake`s were exchanged.
+    noise_handshake: Option<NoiseHandshake>,
+    pending_messages: Vec<PeerMessage>,
+    /// Limits on the messages received from this peer.
+    rate_limiter: RateLimiter,
+    /// Compression shared with the codec, enabled once the peer is known to support it.
+    compression: SharedCompression,
 }
 
 impl Peer {
@@ -209,6 +236,10 @@ impl Peer {
         network_metrics: NetworkMetrics,
         txns_since_last_block: Arc<AtomicUsize>,
         peer_counter: Arc<AtomicUsize>,
+        secret_key: SecretKey,
+        transport: SharedTransportState,
+        rate_limiter: RateLimiter,
+        compression: SharedCompression,
     ) -> Self {
         Peer {
             node_info,
@@ -232,6 +263,15 @@ impl Peer {
             peer_counter,
             last_time_received_epoch_sync_request: Instant::now()
                 - Duration::from_millis(EPOCH_SYNC_PEER_TIMEOUT_MS),
+            secret_key,
+            transport,
+            sent_handshake: None,
+            received_handshake: None,
+            awaiting_encryption: false,
+            noise_handshake: None,
+            pending_messages: vec![],
+            rate_limiter,
+            compression,
         }
     }
 
@@ -247,6 +287,18 @@ impl Peer {
     fn send_message(&mut self, msg: &PeerMessage) {
         // Skip sending block and headers if we received it or header from this peer.
         // Record block requests in tracker.
+        // Until the connection is encrypted only the handshake messages are sent.
+        if self.awaiting_encryption {
+            match msg {
+                PeerMessage::Handshake(_)
+                | PeerMessage::HandshakeV2(_)
+                | PeerMessage::NoiseHandshake(_) => (),
+                _ => {
+                    self.pending_messages.push(msg.clone());
+                    return;
+                }
+            }
+        }
         match msg {
             PeerMessage::Block(b) if self.tracker.has_received(b.hash()) => return,
             PeerMessage::BlockRequest(h) => self.tracker.push_request(*h),
@@ -266,6 +318,8 @@ impl Peer {
             Ok(bytes) => {
                 #[cfg(feature = "metric_recorder")]
                 self.peer_manager_addr.do_send(metadata.set_size(bytes.len()));
+                #[cfg(feature = "traffic_capture")]
+                self.capture(Direction::Outbound, &bytes);
                 self.tracker.increment_sent(bytes.len() as u64);
                 let bytes_len = bytes.len();
                 if !self.framed.write(bytes) {
@@ -309,7 +363,7 @@ impl Peer {
         self.view_client_addr
             .send(NetworkViewClientMessages::GetChainInfo)
             .into_actor(self)
-            .then(move |res, act, _ctx| match res {
+            .then(move |res, act, ctx| match res {
                 Ok(NetworkViewClientResponses::ChainInfo {
                     genesis_id,
                     height,
@@ -317,14 +371,19 @@ impl Peer {
                     archival,
                 }) => {
                     let handshake = match act.protocol_version {
-                        39..=PROTOCOL_VERSION => PeerMessage::Handshake(Handshake::new(
-                            act.protocol_version,
-                            act.node_id(),
-                            act.peer_id().unwrap(),
-                            act.node_info.addr_port(),
-                            PeerChainInfoV2 { genesis_id, height, tracked_shards, archival },
-                            act.edge_info.as_ref().unwrap().clone(),
-                        )),
+                        39..=PROTOCOL_VERSION => {
+                            let handshake = Handshake::new(
+                                act.protocol_version,
+                                act.node_id(),
+                                act.peer_id().unwrap(),
+                                act.node_info.addr_port(),
+                                PeerChainInfoV2 { genesis_id, height, tracked_shards, archival },
+                                act.edge_info.as_ref().unwrap().clone(),
+                                act.compression.is_configured(),
+                            );
+                            act.sent_handshake = Some(handshake.try_to_vec().unwrap());
+                            PeerMessage::Handshake(handshake)
+                        }
                         34..=38 => PeerMessage::HandshakeV2(HandshakeV2::new(
                             act.protocol_version,
                             act.node_id(),
@@ -340,6 +399,10 @@ impl Peer {
                     };
 
                     act.send_message(&handshake);
+                    // The inbound peer has both handshakes once it replied.
+                    if act.awaiting_encryption && act.noise_handshake.is_none() {
+                        act.start_noise_handshake(ctx);
+                    }
                     actix::fut::ready(())
                 }
                 Err(err) => {
@@ -351,6 +414,101 @@ impl Peer {
             .spawn(ctx);
     }
 
+    /// Starts encrypting the connection once both `Handshake`s were exchanged. The outbound
+    /// peer knows the key of the inbound one, so it sends the first message.
+    fn start_noise_handshake(&mut self, ctx: &mut Context<Peer>) {
+        let peer_id = self.peer_id().unwrap();
+        let (outbound_handshake, inbound_handshake) = match self.peer_type {
+            PeerType::Outbound => (&self.sent_handshake, &self.received_handshake),
+            PeerType::Inbound => (&self.received_handshake, &self.sent_handshake),
+        };
+        let prologue = encryption::noise_prologue(
+            outbound_handshake.as_deref().unwrap_or_default(),
+            inbound_handshake.as_deref().unwrap_or_default(),
+        );
+        let noise_handshake = match self.peer_type {
+            PeerType::Outbound => NoiseHandshake::initiator(&self.secret_key, &peer_id, &prologue),
+            PeerType::Inbound => NoiseHandshake::responder(&self.secret_key, &peer_id, &prologue),
+        };
+        let mut noise_handshake = match noise_handshake {
+            Ok(noise_handshake) => noise_handshake,
+            Err(err) => {
+                error!(target: "network", "Failed to start noise handshake with {}: {}", self.peer_info, err);
+                ctx.stop();
+                return;
+            }
+        };
+        if self.peer_type == PeerType::Outbound {
+            match noise_handshake.write_message() {
+                Ok(message) => self.send_message(&PeerMessage::NoiseHandshake(message)),
+                Err(err) => {
+                    error!(target: "network", "Failed to start noise handshake with {}: {}", self.peer_info, err);
+                    ctx.stop();
+                    return;
+                }
+            }
+        }
+        self.noise_handshake = Some(noise_handshake);
+    }
+
+    fn receive_noise_handshake(&mut self, ctx: &mut Context<Peer>, message: Vec<u8>) {
+        let noise_handshake = match self.noise_handshake.take() {
+            Some(noise_handshake) => noise_handshake,
+            None => {
+                warn!(target: "network", "Unexpected noise handshake from {}. Disconnecting.", self.peer_info);
+                ctx.stop();
+                return;
+            }
+        };
+        match self.complete_noise_handshake(noise_handshake, &message) {
+            Ok(transport) => {
+                debug!(target: "network", "Encrypted connection with {}", self.peer_info);
+                *self.transport.lock().unwrap() = Some(transport);
+                self.awaiting_encryption = false;
+                for msg in std::mem::take(&mut self.pending_messages) {
+                    self.send_message(&msg);
+                }
+            }
+            Err(err) => {
+                warn!(target: "network", "Noise handshake with {} failed: {}. Disconnecting.", self.peer_info, err);
+                ctx.stop();
+            }
+        }
+    }
+
+    fn complete_noise_handshake(
+        &mut self,
+        mut noise_handshake: NoiseHandshake,
+        message: &[u8],
+    ) -> io::Result<TransportState> {
+        noise_handshake.read_message(message)?;
+        // The inbound peer replies. The reply is encoded before the codec starts encrypting.
+        if !noise_handshake.is_finished() {
+            let reply = noise_handshake.write_message()?;
+            self.send_message(&PeerMessage::NoiseHandshake(reply));
+        }
+        noise_handshake.into_transport_state()
+    }
+
+    /// Whether the message is within the rate limits of the peer. Messages over the limits are
+    /// dropped, and peers which keep sending them get banned.
+    fn check_rate_limit(
+        &mut self,
+        ctx: &mut Context<Peer>,
+        msg_variant: &str,
+        size: usize,
+    ) -> bool {
+        if self.peer_status != PeerStatus::Ready || self.rate_limiter.allow(msg_variant, size) {
+            return true;
+        }
+        debug!(target: "network", "Dropping {} from {} over the rate limit", msg_variant, self.peer_info);
+        self.network_metrics.inc(NetworkMetrics::peer_message_rate_limited(msg_variant).as_ref());
+        if self.rate_limiter.is_abusive() {
+            self.ban_peer(ctx, ReasonForBan::Abusive);
+        }
+        false
+    }
+
     fn ban_peer(&mut self, ctx: &mut Context<Peer>, ban_reason: ReasonForBan) {
         warn!(target: "network", "Banning peer {} for {:?}", self.peer_info, ban_reason);
         self.peer_status = PeerStatus::Banned(ban_reason);
@@ -377,54 +535,15 @@ impl Peer {
     }
 
     fn receive_view_client_message(&mut self, ctx: &mut Context<Peer>, msg: PeerMessage) {
-        let mut msg_hash = None;
-        let view_client_message = match msg {
-            PeerMessage::Routed(message) => {
-                msg_hash = Some(message.hash());
-                match message.body {
-                    RoutedMessageBody::TxStatusRequest(account_id, tx_hash) => {
-                        NetworkViewClientMessages::TxStatus {
-                            tx_hash,
-                            signer_account_id: account_id,
-                        }
-                    }
-                    RoutedMessageBody::TxStatusResponse(tx_result) => {
-                        NetworkViewClientMessages::TxStatusResponse(Box::new(tx_result))
-                    }
-                    RoutedMessageBody::ReceiptOutcomeRequest(receipt_id) => {
-                        NetworkViewClientMessages::ReceiptOutcomeRequest(receipt_id)
-                    }
-                    RoutedMessageBody::ReceiptOutComeResponse(response) => {
-                        NetworkViewClientMessages::ReceiptOutcomeResponse(Box::new(response))
-                    }
-                    RoutedMessageBody::StateRequestHeader(shard_id, sync_hash) => {
-                        NetworkViewClientMessages::StateRequestHeader { shard_id, sync_hash }
-                    }
-                    RoutedMessageBody::StateRequestPart(shard_id, sync_hash, part_id) => {
-                        NetworkViewClientMessages::StateRequestPart { shard_id, sync_hash, part_id }
-                    }
-                    body => {
-                        error!(target: "network", "Peer receive_view_client_message received unexpected type: {:?}", body);
-                        return;
-                    }
-                }
-            }
-            PeerMessage::BlockRequest(hash) => NetworkViewClientMessages::BlockRequest(hash),
-            PeerMessage::BlockHeadersRequest(hashes) => {
-                NetworkViewClientMessages::BlockHeadersRequest(hashes)
-            }
-            PeerMessage::EpochSyncRequest(epoch_id) => {
+        let msg_hash = match &msg {
+            PeerMessage::Routed(message) => Some(message.hash()),
+            PeerMessage::EpochSyncRequest(_) => {
                 self.last_time_received_epoch_sync_request = Instant::now();
-                NetworkViewClientMessages::EpochSyncRequest { epoch_id }
-            }
-            PeerMessage::EpochSyncFinalizationRequest(epoch_id) => {
-                NetworkViewClientMessages::EpochSyncFinalizationRequest { epoch_id }
-            }
-            peer_message => {
-                error!(target: "network", "Peer receive_view_client_message received unexpected type: {:?}", peer_message);
-                return;
+                None
             }
+            _ => None,
         };
+        let view_client_message = unwrap_option_or_return!(view_client_message(msg));
 
         self.view_client_addr
             .send(view_client_message)
@@ -493,106 +612,22 @@ impl Peer {
         near_metrics::inc_counter(&metrics::PEER_CLIENT_MESSAGE_RECEIVED_TOTAL);
         let peer_id = unwrap_option_or_return!(self.peer_id());
 
-        // Wrap peer message into what client expects.
-        let network_client_msg = match msg {
+        let mut was_requested = false;
+        match &msg {
             PeerMessage::Block(block) => {
                 near_metrics::inc_counter(&metrics::PEER_BLOCK_RECEIVED_TOTAL);
                 let block_hash = *block.hash();
                 self.tracker.push_received(block_hash);
                 self.chain_info.height = max(self.chain_info.height, block.header().height());
-                NetworkClientMessages::Block(block, peer_id, self.tracker.has_request(&block_hash))
+                was_requested = self.tracker.has_request(&block_hash);
             }
-            PeerMessage::Transaction(transaction) => {
+            PeerMessage::Transaction(_) => {
                 near_metrics::inc_counter(&metrics::PEER_TRANSACTION_RECEIVED_TOTAL);
-                NetworkClientMessages::Transaction {
-                    transaction,
-                    is_forwarded: false,
-                    check_only: false,
-                }
-            }
-            PeerMessage::BlockHeaders(headers) => {
-                NetworkClientMessages::BlockHeaders(headers, peer_id)
-            }
-            // All Routed messages received at this point are for us.
-            PeerMessage::Routed(routed_message) => {
-                let msg_hash = routed_message.hash();
-
-                match routed_message.body {
-                    RoutedMessageBody::BlockApproval(approval) => {
-                        NetworkClientMessages::BlockApproval(approval, peer_id)
-                    }
-                    RoutedMessageBody::ForwardTx(transaction) => {
-                        NetworkClientMessages::Transaction {
-                            transaction,
-                            is_forwarded: true,
-                            check_only: false,
-                        }
-                    }
-
-                    RoutedMessageBody::StateResponse(info) => {
-                        NetworkClientMessages::StateResponse(StateResponseInfo::V1(info))
-                    }
-                    RoutedMessageBody::VersionedStateResponse(info) => {
-                        NetworkClientMessages::StateResponse(info)
-                    }
-                    RoutedMessageBody::PartialEncodedChunkRequest(request) => {
-                        NetworkClientMessages::PartialEncodedChunkRequest(request, msg_hash)
-                    }
-                    RoutedMessageBody::PartialEncodedChunkResponse(response) => {
-                        NetworkClientMessages::PartialEncodedChunkResponse(response)
-                    }
-                    RoutedMessageBody::PartialEncodedChunk(partial_encoded_chunk) => {
-                        NetworkClientMessages::PartialEncodedChunk(PartialEncodedChunk::V1(
-                            partial_encoded_chunk,
-                        ))
-                    }
-                    RoutedMessageBody::VersionedPartialEncodedChunk(chunk) => {
-                        NetworkClientMessages::PartialEncodedChunk(chunk)
-                    }
-                    #[cfg(feature = "protocol_feature_forward_chunk_parts")]
-                    RoutedMessageBody::PartialEncodedChunkForward(forward) => {
-                        NetworkClientMessages::PartialEncodedChunkForward(forward)
-                    }
-                    RoutedMessageBody::Ping(_)
-                    | RoutedMessageBody::Pong(_)
-                    | RoutedMessageBody::TxStatusRequest(_, _)
-                    | RoutedMessageBody::TxStatusResponse(_)
-                    | RoutedMessageBody::QueryRequest { .. }
-                    | RoutedMessageBody::QueryResponse { .. }
-                    | RoutedMessageBody::ReceiptOutcomeRequest(_)
-                    | RoutedMessageBody::ReceiptOutComeResponse(_)
-                    | RoutedMessageBody::StateRequestHeader(_, _)
-                    | RoutedMessageBody::StateRequestPart(_, _, _) => {
-                        error!(target: "network", "Peer receive_client_message received unexpected type: {:?}", routed_message);
-                        return;
-                    }
-                }
-            }
-            PeerMessage::Challenge(challenge) => NetworkClientMessages::Challenge(challenge),
-            PeerMessage::EpochSyncResponse(response) => {
-                NetworkClientMessages::EpochSyncResponse(peer_id, response)
             }
-            PeerMessage::EpochSyncFinalizationResponse(response) => {
-                NetworkClientMessages::EpochSyncFinalizationResponse(peer_id, response)
-            }
-            PeerMessage::Handshake(_)
-            | PeerMessage::HandshakeV2(_)
-            | PeerMessage::HandshakeFailure(_, _)
-            | PeerMessage::PeersRequest
-            | PeerMessage::PeersResponse(_)
-            | PeerMessage::RoutingTableSync(_)
-            | PeerMessage::LastEdge(_)
-            | PeerMessage::Disconnect
-            | PeerMessage::RequestUpdateNonce(_)
-            | PeerMessage::ResponseUpdateNonce(_)
-            | PeerMessage::BlockRequest(_)
-            | PeerMessage::BlockHeadersRequest(_)
-            | PeerMessage::EpochSyncRequest(_)
-            | PeerMessage::EpochSyncFinalizationRequest(_) => {
-                error!(target: "network", "Peer receive_client_message received unexpected type: {:?}", msg);
-                return;
-            }
-        };
+            _ => {}
+        }
+        let network_client_msg =
+            unwrap_option_or_return!(client_message(msg, peer_id, was_requested));
 
         self.client_addr
             .send(network_client_msg)
@@ -622,6 +657,19 @@ impl Peer {
             .spawn(ctx);
     }
 
+    /// Sends the message to the peer manager to write it to the traffic capture. Messages
+    /// exchanged before the id of the peer is known are not captured.
+    #[cfg(feature = "traffic_capture")]
+    fn capture(&self, direction: Direction, bytes: &[u8]) {
+        if let Some(peer_id) = self.peer_id() {
+            self.peer_manager_addr.do_send(CapturedMessage::new(
+                peer_id,
+                direction,
+                bytes.to_vec(),
+            ));
+        }
+    }
+
     /// Hook called on every valid message received from this peer from the network.
     fn on_receive_message(&mut self) {
         if let Some(peer_id) = self.peer_id() {
@@ -638,6 +686,183 @@ impl Peer {
     }
 }
 
+/// Converts a message received from a peer into the message for the view client, if it is one.
+/// `Routed` messages must be addressed to this node.
+pub(crate) fn view_client_message(msg: PeerMessage) -> Option<NetworkViewClientMessages> {
+    Some(match msg {
+        PeerMessage::Routed(message) => {
+            let peer_id = message.author;
+            match message.body {
+                RoutedMessageBody::TxStatusRequest(account_id, tx_hash) => {
+                    NetworkViewClientMessages::TxStatus { tx_hash, signer_account_id: account_id }
+                }
+                RoutedMessageBody::TxStatusResponse(tx_result) => {
+                    NetworkViewClientMessages::TxStatusResponse(Box::new(tx_result))
+                }
+                RoutedMessageBody::ReceiptOutcomeRequest(receipt_id) => {
+                    NetworkViewClientMessages::ReceiptOutcomeRequest(receipt_id)
+                }
+                RoutedMessageBody::ReceiptOutComeResponse(response) => {
+                    NetworkViewClientMessages::ReceiptOutcomeResponse(Box::new(response))
+                }
+                RoutedMessageBody::StateRequestHeader(shard_id, sync_hash) => {
+                    NetworkViewClientMessages::StateRequestHeader { shard_id, sync_hash, peer_id }
+                }
+                RoutedMessageBody::StateRequestPart(shard_id, sync_hash, part_id) => {
+                    NetworkViewClientMessages::StateRequestPart {
+                        shard_id,
+                        sync_hash,
+                        part_id,
+                        peer_id,
+                    }
+                }
+                #[cfg(feature = "protocol_feature_state_part_chunks")]
+                RoutedMessageBody::StateRequestPartChunk(
+                    shard_id,
+                    sync_hash,
+                    part_id,
+                    chunk_id,
+                ) => NetworkViewClientMessages::StateRequestPartChunk {
+                    shard_id,
+                    sync_hash,
+                    part_id,
+                    chunk_id,
+                    peer_id,
+                },
+                body => {
+                    error!(target: "network", "Peer receive_view_client_message received unexpected type: {:?}", body);
+                    return None;
+                }
+            }
+        }
+        PeerMessage::BlockRequest(hash) => NetworkViewClientMessages::BlockRequest(hash),
+        PeerMessage::BlockHeadersRequest(hashes) => {
+            NetworkViewClientMessages::BlockHeadersRequest(hashes)
+        }
+        PeerMessage::EpochSyncRequest(epoch_id) => {
+            NetworkViewClientMessages::EpochSyncRequest { epoch_id }
+        }
+        PeerMessage::EpochSyncFinalizationRequest(epoch_id) => {
+            NetworkViewClientMessages::EpochSyncFinalizationRequest { epoch_id }
+        }
+        peer_message => {
+            error!(target: "network", "Peer receive_view_client_message received unexpected type: {:?}", peer_message);
+            return None;
+        }
+    })
+}
+
+/// Converts a message received from `peer_id` into the message for the client, if it is one.
+/// `was_requested` tells whether the block of `PeerMessage::Block` was requested from this peer.
+pub(crate) fn client_message(
+    msg: PeerMessage,
+    peer_id: PeerId,
+    was_requested: bool,
+) -> Option<NetworkClientMessages> {
+    Some(match msg {
+        PeerMessage::Block(block) => NetworkClientMessages::Block(block, peer_id, was_requested),
+        PeerMessage::Transaction(transaction) => NetworkClientMessages::Transaction {
+            transaction,
+            is_forwarded: false,
+            check_only: false,
+        },
+        PeerMessage::BlockHeaders(headers) => NetworkClientMessages::BlockHeaders(headers, peer_id),
+        // All Routed messages received at this point are for us.
+        PeerMessage::Routed(routed_message) => {
+            let msg_hash = routed_message.hash();
+
+            match routed_message.body {
+                RoutedMessageBody::BlockApproval(approval) => {
+                    NetworkClientMessages::BlockApproval(approval, peer_id)
+                }
+                RoutedMessageBody::ForwardTx(transaction) => NetworkClientMessages::Transaction {
+                    transaction,
+                    is_forwarded: true,
+                    check_only: false,
+                },
+
+                RoutedMessageBody::StateResponse(info) => {
+                    NetworkClientMessages::StateResponse(StateResponseInfo::V1(info))
+                }
+                RoutedMessageBody::VersionedStateResponse(info) => {
+                    NetworkClientMessages::StateResponse(info)
+                }
+                RoutedMessageBody::PartialEncodedChunkRequest(request) => {
+                    NetworkClientMessages::PartialEncodedChunkRequest(request, msg_hash)
+                }
+                RoutedMessageBody::PartialEncodedChunkResponse(response) => {
+                    NetworkClientMessages::PartialEncodedChunkResponse(response)
+                }
+                RoutedMessageBody::PartialEncodedChunk(partial_encoded_chunk) => {
+                    NetworkClientMessages::PartialEncodedChunk(PartialEncodedChunk::V1(
+                        partial_encoded_chunk,
+                    ))
+                }
+                RoutedMessageBody::VersionedPartialEncodedChunk(chunk) => {
+                    NetworkClientMessages::PartialEncodedChunk(chunk)
+                }
+                #[cfg(feature = "protocol_feature_forward_chunk_parts")]
+                RoutedMessageBody::PartialEncodedChunkForward(forward) => {
+                    NetworkClientMessages::PartialEncodedChunkForward(forward)
+                }
+                RoutedMessageBody::Ping(_)
+                | RoutedMessageBody::Pong(_)
+                | RoutedMessageBody::TxStatusRequest(_, _)
+                | RoutedMessageBody::TxStatusResponse(_)
+                | RoutedMessageBody::QueryRequest { .. }
+                | RoutedMessageBody::QueryResponse { .. }
+                | RoutedMessageBody::ReceiptOutcomeRequest(_)
+                | RoutedMessageBody::ReceiptOutComeResponse(_)
+                | RoutedMessageBody::StateRequestHeader(_, _)
+                | RoutedMessageBody::StateRequestPart(_, _, _) => {
+                    error!(target: "network", "Peer receive_client_message received unexpected type: {:?}", routed_message);
+                    return None;
+                }
+                #[cfg(feature = "protocol_feature_state_part_chunks")]
+                RoutedMessageBody::StateRequestPartChunk(_, _, _, _) => {
+                    error!(target: "network", "Peer receive_client_message received unexpected type: {:?}", routed_message);
+                    return None;
+                }
+                #[cfg(all(
+                    feature = "protocol_feature_state_part_chunks",
+                    not(feature = "protocol_feature_forward_chunk_parts")
+                ))]
+                RoutedMessageBody::_PartialEncodedChunkForward => {
+                    error!(target: "network", "Peer receive_client_message received unexpected type: {:?}", routed_message);
+                    return None;
+                }
+            }
+        }
+        PeerMessage::Challenge(challenge) => NetworkClientMessages::Challenge(challenge),
+        PeerMessage::EpochSyncResponse(response) => {
+            NetworkClientMessages::EpochSyncResponse(peer_id, response)
+        }
+        PeerMessage::EpochSyncFinalizationResponse(response) => {
+            NetworkClientMessages::EpochSyncFinalizationResponse(peer_id, response)
+        }
+        PeerMessage::Handshake(_)
+        | PeerMessage::HandshakeV2(_)
+        | PeerMessage::HandshakeFailure(_, _)
+        | PeerMessage::PeersRequest
+        | PeerMessage::PeersResponse(_)
+        | PeerMessage::PeersResponseV2(_)
+        | PeerMessage::RoutingTableSync(_)
+        | PeerMessage::LastEdge(_)
+        | PeerMessage::Disconnect
+        | PeerMessage::RequestUpdateNonce(_)
+        | PeerMessage::ResponseUpdateNonce(_)
+        | PeerMessage::BlockRequest(_)
+        | PeerMessage::BlockHeadersRequest(_)
+        | PeerMessage::EpochSyncRequest(_)
+        | PeerMessage::EpochSyncFinalizationRequest(_)
+        | PeerMessage::NoiseHandshake(_)
+        | PeerMessage::Compressed(_) => {
+            error!(target: "network", "Peer receive_client_message received unexpected type: {:?}", msg);
+            return None;
+        }
+    })
+}
+
 impl Actor for Peer {
     type Context = Context<Peer>;
 
@@ -655,7 +880,7 @@ impl Actor for Peer {
             line!(),
             self.handshake_timeout,
             move |act, ctx| {
-                if act.peer_status != PeerStatus::Ready {
+                if act.peer_status != PeerStatus::Ready || act.awaiting_encryption {
                     info!(target: "network", "Handshake timeout expired for {}", act.peer_info);
                     ctx.stop();
                 }
@@ -716,7 +941,45 @@ impl StreamHandler<Result<Vec<u8>, ReasonForBan>> for Peer {
         #[cfg(feature = "metric_recorder")]
         let msg_size = msg.len();
 
+        #[cfg(feature = "traffic_capture")]
+        self.capture(Direction::Inbound, &msg);
+
         self.tracker.increment_received(msg.len() as u64);
+
+        // The compressed size counts against the bandwidth limit before the message is
+        // decompressed, the decompressed one is then only limited by its type.
+        let compressed = compression::is_compressed(&msg);
+        let msg = if compressed {
+            if !self.compression.is_enabled() {
+                warn!(target: "network", "Received compressed message from {} which didn't negotiate compression", self.peer_info);
+                self.ban_peer(ctx, ReasonForBan::Abusive);
+                return;
+            }
+            if !self.check_rate_limit(ctx, compression::COMPRESSED_MSG_VARIANT, msg.len()) {
+                return;
+            }
+            match compression::decompress(&msg, compression::MAX_DECOMPRESSED_LEN) {
+                Ok(decompressed) => {
+                    near_metrics::inc_counter_by(
+                        &metrics::DECOMPRESSED_MESSAGES_BYTES,
+                        msg.len() as u64,
+                    );
+                    near_metrics::inc_counter_by(
+                        &metrics::DECOMPRESSED_MESSAGES_RAW_BYTES,
+                        decompressed.len() as u64,
+                    );
+                    decompressed
+                }
+                Err(err) => {
+                    info!(target: "network", "Failed to decompress message from {}: {}", self.peer_info, err);
+                    ctx.stop();
+                    return;
+                }
+            }
+        } else {
+            msg
+        };
+
         if codec::is_forward_tx(&msg).unwrap_or(false) {
             let r = self.txns_since_last_block.load(Ordering::Acquire);
             if r > MAX_TXNS_PER_BLOCK_MESSAGE {
@@ -749,10 +1012,23 @@ impl StreamHandler<Result<Vec<u8>, ReasonForBan>> for Peer {
                     ));
                 } else {
                     info!(target: "network", "Received invalid data {:?} from {}: {}", msg, self.peer_info, err);
+                    if let Some(peer_id) = self.peer_id() {
+                        self.peer_manager_addr.do_send(NetworkRequests::UpdatePeerScore {
+                            peer_id,
+                            event: PeerScoreEvent::InvalidMessage,
+                        });
+                    }
                 }
                 return;
             }
         };
+        if !self.check_rate_limit(
+            ctx,
+            peer_msg.msg_variant(),
+            if compressed { 0 } else { msg.len() },
+        ) {
+            return;
+        }
         if let PeerMessage::Routed(RoutedMessage {
             body: RoutedMessageBody::ForwardTx(_), ..
         }) = &peer_msg
@@ -834,6 +1110,7 @@ impl StreamHandler<Result<Vec<u8>, ReasonForBan>> for Peer {
 
                 let target_version = std::cmp::min(handshake.version, PROTOCOL_VERSION);
                 self.protocol_version = target_version;
+                self.received_handshake = Some(handshake.try_to_vec().unwrap());
 
                 if handshake.chain_info.genesis_id != self.genesis_id {
                     debug!(target: "network", "Received connection from node with different genesis.");
@@ -892,6 +1169,7 @@ impl StreamHandler<Result<Vec<u8>, ReasonForBan>> for Peer {
                         .listen_port
                         .map(|port| SocketAddr::new(self.peer_addr.ip(), port)),
                     account_id: None,
+                    extra_addrs: vec![],
                 };
                 self.chain_info = handshake.chain_info.clone();
                 self.peer_manager_addr
@@ -909,6 +1187,17 @@ impl StreamHandler<Result<Vec<u8>, ReasonForBan>> for Peer {
                             Ok(ConsolidateResponse::Accept(edge_info)) => {
                                 act.peer_info = Some(peer_info).into();
                                 act.peer_status = PeerStatus::Ready;
+                                if encryption::is_encryption_enabled(act.protocol_version, &act.node_id(), &handshake.peer_id) {
+                                    act.awaiting_encryption = true;
+                                    // The outbound peer sent its handshake before, the inbound
+                                    // one starts once it replied.
+                                    if act.peer_type == PeerType::Outbound {
+                                        act.start_noise_handshake(ctx);
+                                    }
+                                }
+                                if handshake.compression && act.compression.is_configured() {
+                                    act.compression.enable();
+                                }
                                 // Respond to handshake if it's inbound and connection was consolidated.
                                 if act.peer_type == PeerType::Inbound {
                                     act.edge_info = edge_info;
@@ -960,6 +1249,13 @@ impl StreamHandler<Result<Vec<u8>, ReasonForBan>> for Peer {
                     })
                     .spawn(ctx);
             }
+            (_, PeerStatus::Ready, PeerMessage::NoiseHandshake(message)) => {
+                self.receive_noise_handshake(ctx, message);
+            }
+            (_, PeerStatus::Ready, msg) if self.awaiting_encryption => {
+                warn!(target: "network", "Received {} from {} before the connection was encrypted. Disconnecting.", msg, self.peer_info);
+                ctx.stop();
+            }
             (_, PeerStatus::Ready, PeerMessage::Disconnect) => {
                 debug!(target: "network", "Disconnect signal. Me: {:?} Peer: {:?}", self.node_info.id, self.peer_id());
                 ctx.stop();
@@ -973,7 +1269,12 @@ impl StreamHandler<Result<Vec<u8>, ReasonForBan>> for Peer {
                     if let Ok(peers) = res {
                         if !peers.peers.is_empty() {
                             debug!(target: "network", "Peers request from {}: sending {} peers.", act.peer_info, peers.peers.len());
-                            act.send_message(&PeerMessage::PeersResponse(peers.peers));
+                            let msg = if checked_feature!("protocol_feature_multi_address_peer_info", MultiAddressPeerInfo, act.protocol_version) {
+                                PeerMessage::PeersResponseV2(peers.peers.into_iter().map(Into::into).collect())
+                            } else {
+                                PeerMessage::PeersResponse(peers.peers)
+                            };
+                            act.send_message(&msg);
                         }
                     }
                     actix::fut::ready(())
@@ -983,6 +1284,11 @@ impl StreamHandler<Result<Vec<u8>, ReasonForBan>> for Peer {
                 debug!(target: "network", "Received peers from {}: {} peers.", self.peer_info, peers.len());
                 self.peer_manager_addr.do_send(PeersResponse { peers });
             }
+            (_, PeerStatus::Ready, PeerMessage::PeersResponseV2(peers)) => {
+                debug!(target: "network", "Received peers from {}: {} peers.", self.peer_info, peers.len());
+                self.peer_manager_addr
+                    .do_send(PeersResponse { peers: peers.into_iter().map(Into::into).collect() });
+            }
             (_, PeerStatus::Ready, PeerMessage::RequestUpdateNonce(edge_info)) => self
                 .peer_manager_addr
                 .send(NetworkRequests::RequestUpdateNonce(self.peer_id().unwrap(), edge_info))
@@ -11,6 +11,7 @@ use actix::{
     Actor, ActorFuture, Addr, Arbiter, AsyncContext, Context, ContextFutureSpawner, Handler,
     Recipient, Running, StreamHandler, SyncArbiter, SyncContext, WrapFuture,
 };
+use cached::{Cached, SizedCache};
 use chrono::Utc;
 use futures::task::Poll;
 use futures::{future, Stream, StreamExt};
@@ -24,10 +25,15 @@ use near_primitives::types::AccountId;
 use near_primitives::utils::from_timestamp;
 use near_store::Store;
 
+#[cfg(feature = "traffic_capture")]
+use crate::capture::{CapturedMessage, TrafficCapture};
 use crate::codec::Codec;
+use crate::compression::SharedCompression;
+use crate::encryption::SharedTransportState;
 use crate::metrics;
 use crate::peer::Peer;
 use crate::peer_store::{PeerStore, TrustLevel};
+use crate::rate_limiter::RateLimiter;
 #[cfg(feature = "metric_recorder")]
 use crate::recorder::{MetricRecorder, PeerMessageMetadata};
 use crate::routing::{Edge, EdgeInfo, EdgeType, ProcessEdgeResult, RoutingTable, MAX_NUM_PEERS};
@@ -35,9 +41,9 @@ use crate::types::{
     AccountOrPeerIdOrHash, Ban, BlockedPorts, Consolidate, ConsolidateResponse, FullPeerInfo,
     InboundTcpConnect, KnownPeerStatus, KnownProducer, NetworkInfo, NetworkViewClientMessages,
     NetworkViewClientResponses, OutboundTcpConnect, PeerIdOrHash, PeerList, PeerManagerRequest,
-    PeerMessage, PeerRequest, PeerResponse, PeerType, PeersRequest, PeersResponse, Ping, Pong,
-    QueryPeerStats, RawRoutedMessage, ReasonForBan, RoutedMessage, RoutedMessageBody,
-    RoutedMessageFrom, SendMessage, StateResponseInfo, SyncData, Unregister,
+    PeerMessage, PeerRequest, PeerResponse, PeerScoreEvent, PeerType, PeersRequest, PeersResponse,
+    Ping, Pong, QueryPeerStats, RawRoutedMessage, ReasonForBan, RoutedMessage, RoutedMessageBody,
+    RoutedMessageFrom, SendMessage, StateResponseInfo, SyncData, Unregister, BAN_PEER_SCORE,
 };
 use crate::types::{
     EdgeList, KnownPeerState, NetworkClientMessages, NetworkConfig, NetworkRequests,
@@ -76,6 +82,13 @@ const EXPONENTIAL_BACKOFF_LIMIT: u64 = 91;
 const WAIT_BEFORE_PING: u64 = 20_000;
 /// Limit number of pending Peer actors to avoid OOM.
 const LIMIT_PENDING_PEERS: usize = 60;
+/// How often the changed peer scores are persisted.
+const PEER_SCORES_FLUSH_PERIOD: Duration = Duration::from_secs(10);
+/// Number of requests sent by this node whose responses are tracked to score the peers.
+const REQUESTS_AWAITING_RESPONSE_CACHE_SIZE: usize = 10_000;
+/// How often the traffic capture is flushed to the file.
+#[cfg(feature = "traffic_capture")]
+const TRAFFIC_CAPTURE_FLUSH_PERIOD: Duration = Duration::from_secs(1);
 
 macro_rules! unwrap_or_error(($obj: expr, $error: expr) => (match $obj {
     Ok(result) => result,
@@ -142,11 +155,17 @@ pub struct PeerManagerActor {
     monitor_peers_attempts: u64,
     /// Active peers we have sent new edge update, but we haven't received response so far.
     pending_update_nonce_request: HashMap<PeerId, u64>,
+    /// Hashes of the routed requests sent by this node which expect a response. Only responses
+    /// to these requests change the score of the peer.
+    requests_awaiting_response: SizedCache<CryptoHash, ()>,
     /// Dynamic Prometheus metrics
     network_metrics: NetworkMetrics,
     /// Store all collected metrics from a node.
     #[cfg(feature = "metric_recorder")]
     metric_recorder: MetricRecorder,
+    /// Writes the messages exchanged with peers, if `traffic_capture_path` is configured.
+    #[cfg(feature = "traffic_capture")]
+    traffic_capture: Option<TrafficCapture>,
     edge_verifier_pool: Addr<EdgeVerifier>,
     txns_since_last_block: Arc<AtomicUsize>,
     pending_incoming_connections_counter: Arc<AtomicUsize>,
@@ -177,6 +196,14 @@ impl PeerManagerActor {
         #[cfg(feature = "metric_recorder")]
         let metric_recorder = MetricRecorder::default().set_me(me.clone());
 
+        #[cfg(feature = "traffic_capture")]
+        let traffic_capture = match &config.traffic_capture_path {
+            Some(path) => {
+                Some(TrafficCapture::create(path, me.clone(), config.traffic_capture_max_size)?)
+            }
+            None => None,
+        };
+
         let txns_since_last_block = Arc::new(AtomicUsize::new(0));
 
         Ok(PeerManagerActor {
@@ -191,10 +218,15 @@ impl PeerManagerActor {
             monitor_peers_attempts: 0,
             started_connect_attempts: false,
             pending_update_nonce_request: HashMap::new(),
+            requests_awaiting_response: SizedCache::with_size(
+                REQUESTS_AWAITING_RESPONSE_CACHE_SIZE,
+            ),
             network_metrics: NetworkMetrics::new(),
             edge_verifier_pool,
             #[cfg(feature = "metric_recorder")]
             metric_recorder,
+            #[cfg(feature = "traffic_capture")]
+            traffic_capture,
             txns_since_last_block,
             pending_incoming_connections_counter: Arc::new(AtomicUsize::new(0)),
             peer_counter: Arc::new(AtomicUsize::new(0)),
@@ -397,6 +429,59 @@ impl PeerManagerActor {
         }
     }
 
+    /// Updates the reputation score of the peer, banning it once the score gets too low.
+    fn update_peer_score(
+        &mut self,
+        ctx: &mut Context<Self>,
+        peer_id: &PeerId,
+        event: PeerScoreEvent,
+    ) {
+        let score = match self.peer_store.update_score(peer_id, event) {
+            Ok(score) => score,
+            Err(err) => {
+                debug!(target: "network", "Failed to update score of peer {}: {}", peer_id, err);
+                return;
+            }
+        };
+        trace!(target: "network", "Score of peer {} changed to {} on {:?}", peer_id, score, event);
+        if let Some(active_peer) = self.active_peers.get_mut(peer_id) {
+            active_peer.full_peer_info.score = score;
+        }
+        if score <= BAN_PEER_SCORE && !self.peer_store.is_banned(peer_id) {
+            self.try_ban_peer(ctx, peer_id, ReasonForBan::LowScore);
+        }
+    }
+
+    /// Adds a boot node at runtime. The node is trusted as if it was in the config, and
+    /// we connect to it once outbound connections are needed.
+    fn add_boot_node(&mut self, peer_info: PeerInfo) {
+        info!(target: "network", "Adding boot node {}", peer_info);
+        if let Err(err) = self.peer_store.add_trusted_peer(peer_info.clone(), TrustLevel::Signed) {
+            error!(target: "network", "Failed to add boot node {}: {}", peer_info, err);
+            return;
+        }
+        if !self.config.boot_nodes.contains(&peer_info) {
+            self.config.boot_nodes.push(peer_info);
+        }
+    }
+
+    /// Removes a boot node at runtime. Returns false if there is no such boot node.
+    /// An active connection to the node is kept, otherwise the node is forgotten.
+    fn remove_boot_node(&mut self, peer_id: &PeerId) -> bool {
+        let num_boot_nodes = self.config.boot_nodes.len();
+        self.config.boot_nodes.retain(|peer_info| &peer_info.id != peer_id);
+        if self.config.boot_nodes.len() == num_boot_nodes {
+            return false;
+        }
+        info!(target: "network", "Removing boot node {}", peer_id);
+        if !self.active_peers.contains_key(peer_id) {
+            if let Err(err) = self.peer_store.remove_peer(peer_id) {
+                warn!(target: "network", "Failed to remove boot node {}: {}", peer_id, err);
+            }
+        }
+        true
+    }
+
     /// Connects peer with given TcpStream and optional information if it's outbound.
     /// This might fail if the other peers drop listener at its endpoint while establishing connection.
     fn try_connect_peer(
@@ -410,9 +495,15 @@ impl PeerManagerActor {
         let peer_id = self.peer_id.clone();
         let account_id = self.config.account_id.clone();
         let server_addr = self.config.addr;
+        // Nodes behind sentries don't announce the port they listen on in their handshakes, so
+        // not even their sentries learn an address to share.
+        let announce_addr = self.config.sentries.is_empty();
         let handshake_timeout = self.config.handshake_timeout;
         let client_addr = self.client_addr.clone();
         let view_client_addr = self.view_client_addr.clone();
+        let secret_key = self.config.secret_key.clone();
+        let rate_limiter = RateLimiter::new(&self.config.rate_limits);
+        let compression = SharedCompression::new(self.config.compression_threshold);
 
         let server_addr = match server_addr {
             Some(server_addr) => server_addr,
@@ -443,10 +534,11 @@ impl PeerManagerActor {
 
         Peer::start_in_arbiter(&arbiter.handle(), move |ctx| {
             let (read, write) = tokio::io::split(stream);
+            let transport = SharedTransportState::default();
 
             // TODO: check if peer is banned or known based on IP address and port.
             Peer::add_stream(
-                FramedRead::new(read, Codec::new())
+                FramedRead::new(read, Codec::with_transport(transport.clone()))
                     .take_while(|x| match x {
                         Ok(_) => future::ready(true),
                         Err(e) => {
@@ -459,11 +551,21 @@ impl PeerManagerActor {
             );
 
             Peer::new(
-                PeerInfo { id: peer_id, addr: Some(server_addr), account_id },
+                PeerInfo {
+                    id: peer_id,
+                    addr: Some(server_addr).filter(|_| announce_addr),
+                    account_id,
+                    extra_addrs: vec![],
+                },
                 remote_addr,
                 peer_info,
                 peer_type,
-                FramedWrite::new(write, Codec::new(), Codec::new(), ctx),
+                FramedWrite::new(
+                    write,
+                    Codec::with_transport(transport.clone()).with_compression(compression.clone()),
+                    Codec::new(),
+                    ctx,
+                ),
                 handshake_timeout,
                 recipient,
                 client_addr,
@@ -472,6 +574,10 @@ impl PeerManagerActor {
                 network_metrics,
                 txns_since_last_block,
                 peer_counter,
+                secret_key,
+                transport,
+                rate_limiter,
+                compression,
             )
         });
     }
@@ -505,6 +611,11 @@ impl PeerManagerActor {
             && !self.config.outbound_disabled
     }
 
+    /// Nodes behind sentries only connect to their sentries.
+    fn is_peer_allowed(&self, peer_id: &PeerId) -> bool {
+        self.config.sentries.is_empty() || self.config.sentries.contains(peer_id)
+    }
+
     fn is_inbound_allowed(&self) -> bool {
         self.active_peers.len() + self.outgoing_peers.len() < self.config.max_num_peers as usize
     }
@@ -543,10 +654,16 @@ impl PeerManagerActor {
         (sent_bps, received_bps)
     }
 
-    /// Get a random peer we are not connected to from the known list.
+    /// Get a random peer we are not connected to from the known list, preferring peers with
+    /// higher reputation score.
     fn sample_random_peer(&self, ignore_fn: impl Fn(&KnownPeerState) -> bool) -> Option<PeerInfo> {
         let unconnected_peers = self.peer_store.unconnected_peers(ignore_fn);
-        unconnected_peers.choose(&mut rand::thread_rng()).cloned()
+        unconnected_peers
+            .choose_weighted(&mut rand::thread_rng(), |peer_info| {
+                self.peer_store.score_weight(&peer_info.id)
+            })
+            .ok()
+            .cloned()
     }
 
     /// Query current peers for more peers.
@@ -836,6 +953,8 @@ impl PeerManagerActor {
                     || self.config.addr == peer_state.peer_info.addr
                     // Or to peers we are currently trying to connect to
                     || self.outgoing_peers.contains(&peer_state.peer_info.id)
+                    // Or to peers other than our sentries
+                    || !self.is_peer_allowed(&peer_state.peer_info.id)
             }) {
                 // Start monitor_peers_attempts from start after we discover the first healthy peer
                 if !self.started_connect_attempts {
@@ -987,6 +1106,7 @@ impl PeerManagerActor {
                 if msg.author == self.peer_id && msg.expect_response() {
                     trace!(target: "network", "initiate route back {:?}", msg);
                     self.routing_table.add_route_back(msg.hash(), self.peer_id.clone());
+                    self.requests_awaiting_response.cache_set(msg.hash(), ());
                 }
 
                 self.send_message(ctx, peer_id, PeerMessage::Routed(msg))
@@ -1137,6 +1257,40 @@ impl PeerManagerActor {
         }
     }
 
+    /// Periodically persists the changed peer scores.
+    fn flush_peer_scores(&mut self, ctx: &mut Context<Self>) {
+        if let Err(err) = self.peer_store.flush_scores() {
+            error!(target: "network", "Failed to persist peer scores: {}", err);
+        }
+        near_performance_metrics::actix::run_later(
+            ctx,
+            file!(),
+            line!(),
+            PEER_SCORES_FLUSH_PERIOD,
+            move |act, ctx| {
+                act.flush_peer_scores(ctx);
+            },
+        );
+    }
+
+    #[cfg(feature = "traffic_capture")]
+    fn flush_traffic_capture(&mut self, ctx: &mut Context<Self>) {
+        if let Some(traffic_capture) = self.traffic_capture.as_mut() {
+            if let Err(err) = traffic_capture.flush() {
+                error!(target: "network", "Failed to flush traffic capture: {}", err);
+            }
+            near_performance_metrics::actix::run_later(
+                ctx,
+                file!(),
+                line!(),
+                TRAFFIC_CAPTURE_FLUSH_PERIOD,
+                move |act, ctx| {
+                    act.flush_traffic_capture(ctx);
+                },
+            );
+        }
+    }
+
     fn push_network_info(&mut self, ctx: &mut Context<Self>) {
         let network_info = self.get_network_info();
 
@@ -1221,6 +1375,13 @@ impl Actor for PeerManagerActor {
         // Periodically ping all peers to determine latencies between pair of peers.
         #[cfg(feature = "metric_recorder")]
         self.ping_all_peers(ctx);
+
+        // Periodically persist the peer scores.
+        self.flush_peer_scores(ctx);
+
+        // Periodically flush the traffic capture, so that it can be read while the node runs.
+        #[cfg(feature = "traffic_capture")]
+        self.flush_traffic_capture(ctx);
     }
 
     /// Try to gracefully disconnect from active peers.
@@ -1231,6 +1392,17 @@ impl Actor for PeerManagerActor {
             active_peer.addr.do_send(msg.clone());
         }
 
+        if let Err(err) = self.peer_store.flush_scores() {
+            error!(target: "network", "Failed to persist peer scores: {}", err);
+        }
+
+        #[cfg(feature = "traffic_capture")]
+        if let Some(traffic_capture) = self.traffic_capture.as_mut() {
+            if let Err(err) = traffic_capture.flush() {
+                error!(target: "network", "Failed to flush traffic capture: {}", err);
+            }
+        }
+
         Running::Stop
     }
 }
@@ -1291,6 +1463,26 @@ impl Handler<NetworkRequests> for PeerManagerActor {
                     NetworkResponses::RouteNotFound
                 }
             }
+            #[cfg(feature = "protocol_feature_state_part_chunks")]
+            NetworkRequests::StateRequestPartChunk {
+                shard_id,
+                sync_hash,
+                part_id,
+                chunk_id,
+                target,
+            } => {
+                if self.send_message_to_account_or_peer_or_hash(
+                    ctx,
+                    &target,
+                    RoutedMessageBody::StateRequestPartChunk(
+                        shard_id, sync_hash, part_id, chunk_id,
+                    ),
+                ) {
+                    NetworkResponses::NoResponse
+                } else {
+                    NetworkResponses::RouteNotFound
+                }
+            }
             NetworkRequests::StateResponse { route_back, response } => {
                 let body = match response {
                     StateResponseInfo::V1(response) => RoutedMessageBody::StateResponse(response),
@@ -1326,9 +1518,36 @@ impl Handler<NetworkRequests> for PeerManagerActor {
                 }
             }
             NetworkRequests::BanPeer { peer_id, ban_reason } => {
+                if !self.active_peers.contains_key(&peer_id) && !self.peer_store.contains(&peer_id)
+                {
+                    return NetworkResponses::PeerNotFound;
+                }
                 self.try_ban_peer(ctx, &peer_id, ban_reason);
                 NetworkResponses::NoResponse
             }
+            NetworkRequests::UnbanPeer { peer_id } => {
+                if let Err(err) = self.peer_store.peer_unban(&peer_id) {
+                    warn!(target: "network", "Failed to unban peer {}: {}", peer_id, err);
+                    return NetworkResponses::PeerNotFound;
+                }
+                info!(target: "network", "Unbanned peer {}", peer_id);
+                NetworkResponses::NoResponse
+            }
+            NetworkRequests::UpdatePeerScore { peer_id, event } => {
+                self.update_peer_score(ctx, &peer_id, event);
+                NetworkResponses::NoResponse
+            }
+            NetworkRequests::AddBootNode(peer_info) => {
+                self.add_boot_node(peer_info);
+                NetworkResponses::NoResponse
+            }
+            NetworkRequests::RemoveBootNode(peer_id) => {
+                if self.remove_boot_node(&peer_id) {
+                    NetworkResponses::NoResponse
+                } else {
+                    NetworkResponses::PeerNotFound
+                }
+            }
             NetworkRequests::AnnounceAccount(announce_account) => {
                 self.announce_account(ctx, announce_account);
                 NetworkResponses::NoResponse
@@ -1671,48 +1890,73 @@ impl Handler<OutboundTcpConnect> for PeerManagerActor {
         #[cfg(feature = "delay_detector")]
         let _d = DelayDetector::new("outbound tcp connect".into());
         debug!(target: "network", "Trying to connect to {}", msg.peer_info);
-        if let Some(addr) = msg.peer_info.addr {
-            // The `connect` may take several minutes. This happens when the
-            // `SYN` packet for establishing a TCP connection gets silently
-            // dropped, in which case the default TCP timeout is applied. That's
-            // too long for us, so we shorten it to one second.
-            //
-            // Why exactly a second? It was hard-coded in a library we used
-            // before, so we keep it to preserve behavior. Removing the timeout
-            // completely was observed to break stuff for real on the testnet.
-            tokio::time::timeout(Duration::from_secs(1), TcpStream::connect(addr))
-                .into_actor(self)
-                .then(move |res, act, ctx| match res {
-                    Ok(res) => match res {
-                        Ok(stream) => {
-                            debug!(target: "network", "Connecting to {}", msg.peer_info);
-                            let edge_info = act.propose_edge(msg.peer_info.id.clone(), None);
-
-                            act.try_connect_peer(
-                                ctx.address(),
-                                stream,
-                                PeerType::Outbound,
-                                Some(msg.peer_info),
-                                Some(edge_info),
-                            );
-                            actix::fut::ready(())
-                        }
-                        Err(err) => {
-                            info!(target: "network", "Error connecting to {}: {}", addr, err);
-                            act.outgoing_peers.remove(&msg.peer_info.id);
-                            actix::fut::ready(())
-                        }
-                    },
+        if !self.is_peer_allowed(&msg.peer_info.id) {
+            debug!(target: "network", "Not connecting to {} which is not a sentry of this node", msg.peer_info.id);
+            self.outgoing_peers.remove(&msg.peer_info.id);
+            return;
+        }
+        let addrs: Vec<SocketAddr> = msg.peer_info.addrs().cloned().collect();
+        if addrs.is_empty() {
+            warn!(target: "network", "Trying to connect to peer with no public address: {:?}", msg.peer_info);
+            return;
+        }
+        // Addresses are tried in order until one of them accepts the connection. The connection
+        // attempts run in the background, so the peer manager keeps handling other messages.
+        async move {
+            let mut failed_addrs = vec![];
+            for addr in addrs {
+                // The `connect` may take several minutes. This happens when the
+                // `SYN` packet for establishing a TCP connection gets silently
+                // dropped, in which case the default TCP timeout is applied. That's
+                // too long for us, so we shorten it to one second.
+                //
+                // Why exactly a second? It was hard-coded in a library we used
+                // before, so we keep it to preserve behavior. Removing the timeout
+                // completely was observed to break stuff for real on the testnet.
+                match tokio::time::timeout(Duration::from_secs(1), TcpStream::connect(addr)).await {
+                    Ok(Ok(stream)) => return (Some(stream), failed_addrs),
+                    Ok(Err(err)) => {
+                        info!(target: "network", "Error connecting to {}: {}", addr, err);
+                    }
                     Err(err) => {
                         info!(target: "network", "Error connecting to {}: {}", addr, err);
-                        act.outgoing_peers.remove(&msg.peer_info.id);
-                        actix::fut::ready(())
                     }
-                })
-                .wait(ctx);
-        } else {
-            warn!(target: "network", "Trying to connect to peer with no public address: {:?}", msg.peer_info);
+                }
+                failed_addrs.push(addr);
+            }
+            (None, failed_addrs)
         }
+        .into_actor(self)
+        .then(move |(res, failed_addrs), act, ctx| {
+            match res {
+                Some(stream) => {
+                    debug!(target: "network", "Connecting to {}", msg.peer_info);
+                    // The peer is reachable, so the addresses which failed are stale or were
+                    // never its own.
+                    if !failed_addrs.is_empty() {
+                        if let Err(err) =
+                            act.peer_store.remove_failed_addrs(&msg.peer_info.id, &failed_addrs)
+                        {
+                            debug!(target: "network", "Failed to remove addresses of {}: {}", msg.peer_info.id, err);
+                        }
+                    }
+                    let edge_info = act.propose_edge(msg.peer_info.id.clone(), None);
+
+                    act.try_connect_peer(
+                        ctx.address(),
+                        stream,
+                        PeerType::Outbound,
+                        Some(msg.peer_info),
+                        Some(edge_info),
+                    );
+                }
+                None => {
+                    act.outgoing_peers.remove(&msg.peer_info.id);
+                }
+            }
+            actix::fut::ready(())
+        })
+        .spawn(ctx);
     }
 }
 
@@ -1734,6 +1978,11 @@ impl Handler<Consolidate> for PeerManagerActor {
             return ConsolidateResponse::Reject;
         }
 
+        if !self.is_peer_allowed(&msg.peer_info.id) {
+            debug!(target: "network", "Dropping connection from peer which is not a sentry: {:?}", msg.peer_info.id);
+            return ConsolidateResponse::Reject;
+        }
+
         // We already connected to this peer.
         if self.active_peers.contains_key(&msg.peer_info.id) {
             debug!(target: "network", "Dropping handshake (Active Peer). {:?} {:?}", self.peer_id, msg.peer_info.id);
@@ -1784,12 +2033,14 @@ impl Handler<Consolidate> for PeerManagerActor {
 
         let edge_info_response = if require_response { Some(edge_info.clone()) } else { None };
 
+        let score = self.peer_store.score(&msg.peer_info.id);
         // TODO: double check that address is connectable and add account id.
         self.register_peer(
             FullPeerInfo {
                 peer_info: msg.peer_info,
                 chain_info: msg.chain_info,
                 edge_info: msg.other_edge_info,
+                score,
             },
             edge_info,
             msg.peer_type,
@@ -1830,7 +2081,21 @@ impl Handler<PeersRequest> for PeerManagerActor {
     fn handle(&mut self, msg: PeersRequest, _ctx: &mut Self::Context) -> Self::Result {
         #[cfg(feature = "delay_detector")]
         let _d = DelayDetector::new("peers request".into());
-        PeerList { peers: self.peer_store.healthy_peers(self.config.max_send_peers) }
+        let mut peers = self.peer_store.healthy_peers(self.config.max_send_peers);
+        // Addresses of the nodes behind this sentry stay private.
+        peers.retain(|peer_info| !self.config.private_peers.contains(&peer_info.id));
+        // Advertise the addresses other peers can't learn from the connections with this node,
+        // unless it hides behind sentries.
+        let mut external_addrs = self.config.external_addrs.iter().cloned();
+        if let Some(addr) = external_addrs.next().filter(|_| self.config.sentries.is_empty()) {
+            peers.push(PeerInfo {
+                id: self.peer_id.clone(),
+                addr: Some(addr),
+                account_id: None,
+                extra_addrs: external_addrs.collect(),
+            });
+        }
+        PeerList { peers }
     }
 }
 
@@ -1841,9 +2106,17 @@ impl Handler<PeersResponse> for PeerManagerActor {
     fn handle(&mut self, msg: PeersResponse, _ctx: &mut Self::Context) {
         #[cfg(feature = "delay_detector")]
         let _d = DelayDetector::new("peers response".into());
+        // Other peers may still know the addresses of the nodes behind this sentry, they are
+        // not learned from them.
+        let private_peers = &self.config.private_peers;
         unwrap_or_error!(
             self.peer_store.add_indirect_peers(
-                msg.peers.into_iter().filter(|peer_info| peer_info.id != self.peer_id).collect()
+                msg.peers
+                    .into_iter()
+                    .filter(|peer_info| {
+                        peer_info.id != self.peer_id && !private_peers.contains(&peer_info.id)
+                    })
+                    .collect()
             ),
             "Fail to update peer store"
         );
@@ -1874,6 +2147,17 @@ impl Handler<RoutedMessageFrom> for PeerManagerActor {
             match &msg.body {
                 RoutedMessageBody::Ping(ping) => self.handle_ping(ctx, ping.clone(), msg.hash()),
                 RoutedMessageBody::Pong(pong) => self.handle_pong(ctx, pong.clone()),
+                RoutedMessageBody::PartialEncodedChunkResponse(_)
+                | RoutedMessageBody::StateResponse(_)
+                | RoutedMessageBody::VersionedStateResponse(_) => {
+                    // Unsolicited or repeated responses don't earn any score.
+                    if let PeerIdOrHash::Hash(hash) = &msg.target {
+                        if self.requests_awaiting_response.cache_remove(hash).is_some() {
+                            self.update_peer_score(ctx, &msg.author, PeerScoreEvent::PartsServed);
+                        }
+                    }
+                    return true;
+                }
                 _ => return true,
             }
 
@@ -1951,3 +2235,14 @@ impl Handler<PeerMessageMetadata> for PeerManagerActor {
         self.metric_recorder.handle_peer_message(msg);
     }
 }
+
+#[cfg(feature = "traffic_capture")]
+impl Handler<CapturedMessage> for PeerManagerActor {
+    type Result = ();
+    #[perf]
+    fn handle(&mut self, msg: CapturedMessage, _ctx: &mut Self::Context) -> Self::Result {
+        if let Some(traffic_capture) = self.traffic_capture.as_mut() {
+            traffic_capture.write(&msg);
+        }
+    }
+}
@@ -1,6 +1,6 @@
 use std::collections::{
     hash_map::{Entry, Iter},
-    HashMap,
+    HashMap, HashSet,
 };
 use std::convert::TryInto;
 use std::net::SocketAddr;
@@ -16,7 +16,10 @@ use near_primitives::network::PeerId;
 use near_primitives::utils::to_timestamp;
 use near_store::{ColPeers, Store};
 
-use crate::types::{KnownPeerState, KnownPeerStatus, NetworkConfig, PeerInfo, ReasonForBan};
+use crate::types::{
+    peer_score_weight, KnownPeerState, KnownPeerStatus, NetworkConfig, PeerInfo, PeerScoreEvent,
+    ReasonForBan, MAX_PEER_ADDRS, MAX_PEER_SCORE, MIN_PEER_SCORE,
+};
 
 /// Level of trust we have about a new (PeerId, Addr) pair.
 #[derive(Eq, PartialEq, Debug, Clone)]
@@ -44,6 +47,18 @@ impl VerifiedPeer {
     }
 }
 
+/// Adds the addresses the peer doesn't have yet to its extra addresses, up to `MAX_PEER_ADDRS`.
+fn add_extra_addrs(peer_info: &mut PeerInfo, addrs: impl Iterator<Item = SocketAddr>) {
+    for addr in addrs {
+        if peer_info.addrs().count() >= MAX_PEER_ADDRS {
+            break;
+        }
+        if !peer_info.addrs().any(|known_addr| known_addr == &addr) {
+            peer_info.extra_addrs.push(addr);
+        }
+    }
+}
+
 /// Known peers store, maintaining cache of known peers and connection to storage to save/load them.
 pub struct PeerStore {
     store: Arc<Store>,
@@ -52,6 +67,8 @@ pub struct PeerStore {
     // It can happens that some peers don't have known address, so
     // they will not be present in this list, otherwise they will be present.
     addr_peers: HashMap<SocketAddr, VerifiedPeer>,
+    // Peers whose score changed since the last `flush_scores`.
+    dirty_scores: HashSet<PeerId>,
 }
 
 impl PeerStore {
@@ -59,24 +76,23 @@ impl PeerStore {
         store: Arc<Store>,
         boot_nodes: &[PeerInfo],
     ) -> Result<Self, Box<dyn std::error::Error>> {
-        let mut peer_states = HashMap::default();
+        let mut peer_states: HashMap<PeerId, KnownPeerState> = HashMap::default();
         let mut addr_peers = HashMap::default();
 
         for peer_info in boot_nodes.iter() {
-            if !peer_states.contains_key(&peer_info.id) {
-                if let Some(peer_addr) = peer_info.addr {
-                    match addr_peers.entry(peer_addr) {
-                        Entry::Occupied(entry) => {
-                            // There is already a different peer_id with this address.
-                            error!(target: "network", "Two boot nodes have the same address {:?}", entry.key());
-                        }
-                        Entry::Vacant(entry) => {
-                            entry.insert(VerifiedPeer::signed(peer_info.id.clone()));
-                            peer_states.insert(
-                                peer_info.id.clone(),
-                                KnownPeerState::new(peer_info.clone()),
-                            );
-                        }
+            if let Some(peer_state) = peer_states.get_mut(&peer_info.id) {
+                // Boot node listed once per address, e.g. for its IPv4 and IPv6 ones.
+                add_extra_addrs(&mut peer_state.peer_info, peer_info.addrs().cloned());
+            } else if let Some(peer_addr) = peer_info.addr {
+                match addr_peers.entry(peer_addr) {
+                    Entry::Occupied(entry) => {
+                        // There is already a different peer_id with this address.
+                        error!(target: "network", "Two boot nodes have the same address {:?}", entry.key());
+                    }
+                    Entry::Vacant(entry) => {
+                        entry.insert(VerifiedPeer::signed(peer_info.id.clone()));
+                        peer_states
+                            .insert(peer_info.id.clone(), KnownPeerState::new(peer_info.clone()));
                     }
                 }
             }
@@ -99,6 +115,7 @@ impl PeerStore {
                 if peer_state.status.is_banned() {
                     current_peer_state.status = peer_state.status;
                 }
+                current_peer_state.score = peer_state.score;
                 continue;
             }
 
@@ -109,7 +126,7 @@ impl PeerStore {
                 }
             }
         }
-        Ok(PeerStore { store, peer_states, addr_peers })
+        Ok(PeerStore { store, peer_states, addr_peers, dirty_scores: HashSet::default() })
     }
 
     pub fn len(&self) -> usize {
@@ -120,6 +137,10 @@ impl PeerStore {
         self.peer_states.is_empty()
     }
 
+    pub fn contains(&self, peer_id: &PeerId) -> bool {
+        self.peer_states.contains_key(peer_id)
+    }
+
     pub fn is_banned(&self, peer_id: &PeerId) -> bool {
         self.peer_states
             .get(&peer_id)
@@ -170,9 +191,11 @@ impl PeerStore {
         }
     }
 
+    /// Lifts the ban and gives the peer a fresh start, so it isn't banned again for its old score.
     pub fn peer_unban(&mut self, peer_id: &PeerId) -> Result<(), Box<dyn std::error::Error>> {
         if let Some(peer_state) = self.peer_states.get_mut(peer_id) {
             peer_state.status = KnownPeerStatus::NotConnected;
+            peer_state.score = 0;
             let mut store_update = self.store.store_update();
             store_update.set_ser(ColPeers, &peer_id.try_to_vec()?, peer_state)?;
             store_update.commit().map_err(|err| err.into())
@@ -181,6 +204,47 @@ impl PeerStore {
         }
     }
 
+    /// Reputation score of the peer, peers we know nothing about have the neutral score of 0.
+    pub fn score(&self, peer_id: &PeerId) -> i32 {
+        self.peer_states.get(peer_id).map_or(0, |peer_state| peer_state.score)
+    }
+
+    /// Weight of the peer when picking whom to connect to, see `peer_score_weight`.
+    pub fn score_weight(&self, peer_id: &PeerId) -> u64 {
+        peer_score_weight(self.score(peer_id))
+    }
+
+    /// Changes the reputation score of the peer according to its behaviour and returns the new score.
+    /// The score is persisted by the next `flush_scores`.
+    pub fn update_score(
+        &mut self,
+        peer_id: &PeerId,
+        event: PeerScoreEvent,
+    ) -> Result<i32, Box<dyn std::error::Error>> {
+        if let Some(peer_state) = self.peer_states.get_mut(peer_id) {
+            peer_state.score =
+                (peer_state.score + event.score_delta()).max(MIN_PEER_SCORE).min(MAX_PEER_SCORE);
+            self.dirty_scores.insert(peer_id.clone());
+            Ok(peer_state.score)
+        } else {
+            Err(format!("Peer {} is missing in the peer store", peer_id).into())
+        }
+    }
+
+    /// Persists all the scores changed since the last flush in a single store update.
+    pub fn flush_scores(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        if self.dirty_scores.is_empty() {
+            return Ok(());
+        }
+        let mut store_update = self.store.store_update();
+        for peer_id in self.dirty_scores.drain() {
+            if let Some(peer_state) = self.peer_states.get(&peer_id) {
+                store_update.set_ser(ColPeers, &peer_id.try_to_vec()?, peer_state)?;
+            }
+        }
+        store_update.commit().map_err(|err| err.into())
+    }
+
     fn find_peers<F>(&self, mut filter: F, count: u32) -> Vec<PeerInfo>
     where
         F: FnMut(&KnownPeerState) -> bool,
@@ -204,7 +268,7 @@ impl PeerStore {
             |p| {
                 (p.status == KnownPeerStatus::NotConnected || p.status == KnownPeerStatus::Unknown)
                     && !ignore_fn(p)
-                    && p.peer_info.addr.is_some()
+                    && p.peer_info.addrs().next().is_some()
             },
             0,
         )
@@ -250,6 +314,20 @@ impl PeerStore {
         store_update.commit().map_err(|err| err.into())
     }
 
+    /// Forgets about the peer, removing it from the storage as well.
+    pub fn remove_peer(&mut self, peer_id: &PeerId) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(peer_state) = self.peer_states.remove(peer_id) {
+            if let Some(addr) = peer_state.peer_info.addr {
+                self.addr_peers.remove(&addr);
+            }
+            let mut store_update = self.store.store_update();
+            store_update.delete(ColPeers, &peer_id.try_to_vec()?);
+            store_update.commit().map_err(|err| err.into())
+        } else {
+            Err(format!("Peer {} is missing in the peer store", peer_id).into())
+        }
+    }
+
     fn touch(&mut self, peer_id: &PeerId) -> Result<(), Box<dyn std::error::Error>> {
         if let Some(peer_state) = self.peer_states.get(&peer_id) {
             let mut store_update = self.store.store_update();
@@ -278,10 +356,14 @@ impl PeerStore {
             });
         }
 
-        // If this peer already has an address, remove that pair from the index.
+        // If this peer already has an address, remove that pair from the index. The address is
+        // kept among the extra ones, as it may still work, e.g. if it's of another IP version.
         if let Some(peer_state) = self.peer_states.get_mut(&peer_info.id) {
             if let Some(cur_addr) = peer_state.peer_info.addr.take() {
                 self.addr_peers.remove(&cur_addr);
+                if cur_addr != peer_addr {
+                    peer_state.peer_info.extra_addrs.insert(0, cur_addr);
+                }
             }
         }
 
@@ -292,7 +374,12 @@ impl PeerStore {
         // Update peer_id addr
         self.peer_states
             .entry(peer_info.id.clone())
-            .and_modify(|peer_state| peer_state.peer_info.addr = Some(peer_addr))
+            .and_modify(|peer_state| {
+                peer_state.peer_info.addr = Some(peer_addr);
+                peer_state.peer_info.extra_addrs.retain(|addr| addr != &peer_addr);
+                peer_state.peer_info.extra_addrs.truncate(MAX_PEER_ADDRS - 1);
+                add_extra_addrs(&mut peer_state.peer_info, peer_info.extra_addrs.iter().cloned());
+            })
             .or_insert_with(|| KnownPeerState::new(peer_info.clone()));
 
         self.touch(&peer_info.id)?;
@@ -339,6 +426,9 @@ impl PeerStore {
                         && !self.addr_peers.contains_key(&peer_addr)
                     {
                         self.update_peer_info(peer_info, peer_addr, TrustLevel::Indirect)?;
+                    } else {
+                        // Other addresses of known peers can only be learned from other peers.
+                        self.add_unverified_addrs(&peer_info);
                     }
                 }
             }
@@ -352,6 +442,44 @@ impl PeerStore {
         Ok(())
     }
 
+    /// Adds the addresses other peers know the peer at. Addresses of other known peers are
+    /// ignored, the rest is dropped once it fails to accept a connection to a reachable peer.
+    fn add_unverified_addrs(&mut self, peer_info: &PeerInfo) {
+        let addr_peers = &self.addr_peers;
+        if let Some(peer_state) = self.peer_states.get_mut(&peer_info.id) {
+            let addrs = peer_info
+                .addrs()
+                .filter(|addr| {
+                    addr_peers
+                        .get(addr)
+                        .map_or(true, |verified_peer| verified_peer.peer_id == peer_info.id)
+                })
+                .cloned();
+            add_extra_addrs(&mut peer_state.peer_info, addrs);
+        }
+    }
+
+    /// Drops the extra addresses of the peer which failed to accept a connection. The main
+    /// address is kept, it's replaced once the peer connects from another one.
+    pub fn remove_failed_addrs(
+        &mut self,
+        peer_id: &PeerId,
+        failed_addrs: &[SocketAddr],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(peer_state) = self.peer_states.get_mut(peer_id) {
+            let num_addrs = peer_state.peer_info.extra_addrs.len();
+            peer_state.peer_info.extra_addrs.retain(|addr| !failed_addrs.contains(addr));
+            if peer_state.peer_info.extra_addrs.len() == num_addrs {
+                return Ok(());
+            }
+            let mut store_update = self.store.store_update();
+            store_update.set_ser(ColPeers, &peer_id.try_to_vec()?, peer_state)?;
+            store_update.commit().map_err(|err| err.into())
+        } else {
+            Err(format!("Peer {} is missing in the peer store", peer_id).into())
+        }
+    }
+
     pub fn add_indirect_peers(
         &mut self,
         peers: Vec<PeerInfo>,
@@ -374,8 +502,8 @@ impl PeerStore {
 #[cfg(test)]
 mod test {
     use near_crypto::{KeyType, SecretKey};
-    use near_store::create_store;
     use near_store::test_utils::create_test_store;
+    use near_store::{create_store, StoreConfig};
 
     use super::*;
 
@@ -388,7 +516,7 @@ mod test {
     }
 
     fn get_peer_info(peer_id: PeerId, addr: Option<SocketAddr>) -> PeerInfo {
-        PeerInfo { id: peer_id, addr, account_id: None }
+        PeerInfo { id: peer_id, addr, account_id: None, extra_addrs: vec![] }
     }
 
     fn gen_peer_info(port: u8) -> PeerInfo {
@@ -396,6 +524,7 @@ mod test {
             id: PeerId::from(SecretKey::from_random(KeyType::ED25519).public_key()),
             addr: Some(get_addr(port)),
             account_id: None,
+            extra_addrs: vec![],
         }
     }
 
@@ -406,19 +535,52 @@ mod test {
         let peer_info_to_ban = gen_peer_info(1);
         let boot_nodes = vec![peer_info_a.clone(), peer_info_to_ban.clone()];
         {
-            let store = create_store(tmp_dir.path().to_str().unwrap());
+            let store = create_store(tmp_dir.path().to_str().unwrap(), &StoreConfig::default());
             let mut peer_store = PeerStore::new(store, &boot_nodes).unwrap();
             assert_eq!(peer_store.healthy_peers(3).iter().count(), 2);
             peer_store.peer_ban(&peer_info_to_ban.id, ReasonForBan::Abusive).unwrap();
             assert_eq!(peer_store.healthy_peers(3).iter().count(), 1);
         }
         {
-            let store_new = create_store(tmp_dir.path().to_str().unwrap());
+            let store_new = create_store(tmp_dir.path().to_str().unwrap(), &StoreConfig::default());
             let peer_store_new = PeerStore::new(store_new, &boot_nodes).unwrap();
             assert_eq!(peer_store_new.healthy_peers(3).iter().count(), 1);
         }
     }
 
+    #[test]
+    fn score_store() {
+        let tmp_dir = tempfile::Builder::new().prefix("_test_store_score").tempdir().unwrap();
+        let peer_info = gen_peer_info(0);
+        let boot_nodes = vec![peer_info.clone()];
+        {
+            let store = create_store(tmp_dir.path().to_str().unwrap(), &StoreConfig::default());
+            let mut peer_store = PeerStore::new(store, &boot_nodes).unwrap();
+            assert_eq!(peer_store.score(&peer_info.id), 0);
+            peer_store.update_score(&peer_info.id, PeerScoreEvent::HeadersDelivered).unwrap();
+            peer_store.update_score(&peer_info.id, PeerScoreEvent::Timeout).unwrap();
+            assert_eq!(peer_store.score(&peer_info.id), -10);
+            assert!(peer_store
+                .update_score(&gen_peer_info(1).id, PeerScoreEvent::Timeout)
+                .is_err());
+            peer_store.flush_scores().unwrap();
+            peer_store.update_score(&peer_info.id, PeerScoreEvent::Timeout).unwrap();
+        }
+        {
+            // Only the flushed score is persisted.
+            let store = create_store(tmp_dir.path().to_str().unwrap(), &StoreConfig::default());
+            let mut peer_store = PeerStore::new(store, &boot_nodes).unwrap();
+            assert_eq!(peer_store.score(&peer_info.id), -10);
+            for _ in 0..20 {
+                peer_store.update_score(&peer_info.id, PeerScoreEvent::InvalidMessage).unwrap();
+            }
+            assert_eq!(peer_store.score(&peer_info.id), MIN_PEER_SCORE);
+            peer_store.peer_ban(&peer_info.id, ReasonForBan::LowScore).unwrap();
+            peer_store.peer_unban(&peer_info.id).unwrap();
+            assert_eq!(peer_store.score(&peer_info.id), 0);
+        }
+    }
+
     fn check_exist(
         peer_store: &PeerStore,
         peer_id: &PeerId,
@@ -456,6 +618,47 @@ mod test {
         })
     }
 
+    /// Boot nodes and peers can have several addresses. The one a signed connection was
+    /// established at is tried first, the others are kept until they fail.
+    #[test]
+    fn extra_addrs() {
+        let store = create_test_store();
+        let peer_id = get_peer_id("node0".to_string());
+        let addrs = (0..4).map(|ix| get_addr(ix)).collect::<Vec<_>>();
+        let boot_nodes = vec![
+            get_peer_info(peer_id.clone(), Some(addrs[0])),
+            get_peer_info(peer_id.clone(), Some(addrs[1])),
+        ];
+        let mut peer_store = PeerStore::new(store.clone(), &boot_nodes).unwrap();
+        let addrs_of = |peer_store: &PeerStore| {
+            peer_store.peer_states[&peer_id].peer_info.addrs().cloned().collect::<Vec<_>>()
+        };
+        assert_eq!(addrs_of(&peer_store), vec![addrs[0], addrs[1]]);
+
+        let mut peer_info = get_peer_info(peer_id.clone(), Some(addrs[2]));
+        peer_info.extra_addrs = vec![addrs[1], addrs[3]];
+        peer_store.add_indirect_peers(vec![peer_info]).unwrap();
+        assert_eq!(addrs_of(&peer_store), vec![addrs[0], addrs[1], addrs[2], addrs[3]]);
+
+        peer_store.peer_connected(&get_peer_info(peer_id.clone(), Some(addrs[2]))).unwrap();
+        assert_eq!(addrs_of(&peer_store), vec![addrs[2], addrs[0], addrs[1], addrs[3]]);
+        assert!(check_exist(&peer_store, &peer_id, Some((addrs[2], TrustLevel::Signed))));
+        assert!(check_integrity(&peer_store));
+
+        // Addresses of other peers are ignored.
+        let other_peer_info = get_peer_info(get_peer_id("node1".to_string()), Some(get_addr(4)));
+        peer_store.add_indirect_peers(vec![other_peer_info]).unwrap();
+        let peer_info = get_peer_info(peer_id.clone(), Some(get_addr(4)));
+        peer_store.add_indirect_peers(vec![peer_info]).unwrap();
+        assert_eq!(addrs_of(&peer_store), vec![addrs[2], addrs[0], addrs[1], addrs[3]]);
+
+        // Failed extra addresses are dropped, the remaining ones are persisted.
+        peer_store.remove_failed_addrs(&peer_id, &[addrs[2], addrs[1]]).unwrap();
+        assert_eq!(addrs_of(&peer_store), vec![addrs[2], addrs[0], addrs[3]]);
+        let peer_store = PeerStore::new(store, &[]).unwrap();
+        assert_eq!(addrs_of(&peer_store), vec![addrs[2], addrs[0], addrs[3]]);
+    }
+
     /// If we know there is a peer_id A at address #A, and after some time
     /// we learn that there is a new peer B at address #A, we discard address of A
     #[test]
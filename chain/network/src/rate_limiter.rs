@@ -0,0 +1,185 @@
+//! Limits on the messages a single peer can send to this node.
+//!
+//! Every peer gets its own `RateLimiter` with a token bucket per limited message type, and
+//! optionally one on the bytes received across all messages. Messages which exceed the limits
+//! are dropped by `Peer` before they reach the client actors.
+use std::collections::HashMap;
+use std::time::Instant;
+
+use serde::{Deserialize, Serialize};
+use strum::VariantNames;
+
+use crate::rate_counter::RateCounter;
+use crate::types::{PeerMessage, RoutedMessageBody};
+
+/// Token bucket, refilled with `rate` tokens per second up to `burst` tokens.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct RateLimit {
+    /// Tokens added per second.
+    pub rate: f64,
+    /// Maximum number of tokens, i.e. the largest burst allowed.
+    pub burst: f64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct RateLimitsConfig {
+    /// Limits on the number of messages, by `PeerMessage` or `RoutedMessageBody` variant name,
+    /// e.g. `BlockRequest` or `StateRequestPart`. Other messages are not limited. Unknown names
+    /// are rejected when the config is loaded.
+    #[serde(default)]
+    pub messages: HashMap<String, RateLimit>,
+    /// Limit on the number of bytes received from a peer across all messages. Compressed
+    /// messages count with their compressed size.
+    #[serde(default)]
+    pub bandwidth: Option<RateLimit>,
+    /// Peers which had more messages dropped in the last minute are banned as abusive.
+    #[serde(default)]
+    pub ban_dropped_per_min: Option<u64>,
+}
+
+impl RateLimitsConfig {
+    /// Checks that the limited messages exist, so that a typo doesn't silently leave a message
+    /// unlimited.
+    pub fn validate(&self) -> Result<(), String> {
+        // Routed messages are limited by the variant of their body.
+        let is_known = |name: &str| {
+            (name != "Routed" && PeerMessage::VARIANTS.contains(&name))
+                || RoutedMessageBody::VARIANTS.contains(&name)
+        };
+        match self.messages.keys().find(|name| !is_known(name)) {
+            Some(name) => Err(format!("unknown message {:?} in messages", name)),
+            None => Ok(()),
+        }
+    }
+}
+
+struct TokenBucket {
+    limit: RateLimit,
+    tokens: f64,
+    last_update: Instant,
+}
+
+impl TokenBucket {
+    fn new(limit: RateLimit, now: Instant) -> Self {
+        Self { tokens: limit.burst, limit, last_update: now }
+    }
+
+    /// Takes `amount` tokens if there are enough. Amounts above the burst size are capped,
+    /// so they pass once the bucket is full.
+    fn try_take(&mut self, amount: f64, now: Instant) -> bool {
+        let elapsed = now.saturating_duration_since(self.last_update).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.limit.rate).min(self.limit.burst);
+        self.last_update = now;
+        let amount = amount.min(self.limit.burst);
+        if self.tokens >= amount {
+            self.tokens -= amount;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// Rate limits applied to the messages received from one peer.
+pub struct RateLimiter {
+    messages: HashMap<String, TokenBucket>,
+    bandwidth: Option<TokenBucket>,
+    dropped: RateCounter,
+    ban_dropped_per_min: Option<u64>,
+}
+
+impl RateLimiter {
+    pub fn new(config: &RateLimitsConfig) -> Self {
+        let now = Instant::now();
+        Self {
+            messages: config
+                .messages
+                .iter()
+                .map(|(name, limit)| (name.clone(), TokenBucket::new(limit.clone(), now)))
+                .collect(),
+            bandwidth: config.bandwidth.clone().map(|limit| TokenBucket::new(limit, now)),
+            dropped: RateCounter::new(),
+            ban_dropped_per_min: config.ban_dropped_per_min,
+        }
+    }
+
+    /// Whether the message of given variant and size is within the limits. Messages which
+    /// aren't are counted as dropped.
+    pub fn allow(&mut self, msg_variant: &str, size: usize) -> bool {
+        self.allow_at(msg_variant, size, Instant::now())
+    }
+
+    fn allow_at(&mut self, msg_variant: &str, size: usize, now: Instant) -> bool {
+        let allowed =
+            self.messages.get_mut(msg_variant).map_or(true, |bucket| bucket.try_take(1.0, now))
+                && self.bandwidth.as_mut().map_or(true, |bucket| bucket.try_take(size as f64, now));
+        if !allowed {
+            self.dropped.increment(size as u64);
+        }
+        allowed
+    }
+
+    /// Whether the peer had so many messages dropped recently that it should be banned.
+    pub fn is_abusive(&self) -> bool {
+        self.ban_dropped_per_min
+            .map_or(false, |max_dropped| self.dropped.count_per_min() > max_dropped)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn test_validate() {
+        let config = |name: &str| RateLimitsConfig {
+            messages: vec![(name.to_string(), RateLimit { rate: 1.0, burst: 1.0 })]
+                .into_iter()
+                .collect(),
+            ..Default::default()
+        };
+        assert!(RateLimitsConfig::default().validate().is_ok());
+        assert!(config("BlockRequest").validate().is_ok());
+        assert!(config("StateRequestPart").validate().is_ok());
+        assert!(config("Compressed").validate().is_ok());
+        assert!(config("Routed").validate().is_err());
+        assert!(config("BlockRequests").validate().is_err());
+        assert!(config("block_request").validate().is_err());
+    }
+
+    #[test]
+    fn test_rate_limiter() {
+        let config = RateLimitsConfig {
+            messages: vec![("BlockRequest".to_string(), RateLimit { rate: 2.0, burst: 3.0 })]
+                .into_iter()
+                .collect(),
+            bandwidth: Some(RateLimit { rate: 1000.0, burst: 2000.0 }),
+            ban_dropped_per_min: Some(2),
+        };
+        let mut limiter = RateLimiter::new(&config);
+        let now = Instant::now();
+
+        for _ in 0..3 {
+            assert!(limiter.allow_at("BlockRequest", 10, now));
+        }
+        assert!(!limiter.allow_at("BlockRequest", 10, now));
+        // Other messages are only limited by the bandwidth.
+        assert!(limiter.allow_at("Block", 1500, now));
+        assert!(!limiter.allow_at("Block", 1500, now));
+        // Decompressed messages are only limited by their type, their bytes are already counted.
+        assert!(limiter.allow_at("Block", 0, now));
+        assert!(!limiter.is_abusive());
+
+        // Half a second later one more request is allowed.
+        let now = now + Duration::from_millis(500);
+        assert!(limiter.allow_at("BlockRequest", 10, now));
+        assert!(!limiter.allow_at("BlockRequest", 10, now));
+        assert!(limiter.is_abusive());
+
+        // Messages larger than the burst pass once the bucket is full.
+        let now = now + Duration::from_secs(10);
+        assert!(limiter.allow_at("Block", 5000, now));
+    }
+}
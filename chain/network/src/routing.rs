@@ -1,4 +1,6 @@
-use std::collections::{hash_map::Entry, HashMap, HashSet, VecDeque};
+use std::collections::{hash_map::Entry, BTreeMap, HashMap, HashSet, VecDeque};
+use std::convert::TryFrom;
+use std::fmt::Write;
 use std::ops::Sub;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
@@ -622,7 +624,11 @@ impl RoutingTable {
             .into_iter()
             .map(|announce_account| (announce_account.account_id, announce_account.peer_id))
             .collect();
-        RoutingTableInfo { account_peers, peer_forwarding: self.peer_forwarding.clone() }
+        RoutingTableInfo {
+            account_peers,
+            peer_forwarding: self.peer_forwarding.clone(),
+            edges: self.edges_info.values().cloned().collect(),
+        }
     }
 
     fn try_save_edges(&mut self) {
@@ -767,6 +773,73 @@ pub struct ProcessEdgeResult {
 pub struct RoutingTableInfo {
     pub account_peers: HashMap<AccountId, PeerId>,
     pub peer_forwarding: HashMap<PeerId, Vec<PeerId>>,
+    /// Last known state of every edge kept in memory, including the removed ones.
+    pub edges: Vec<Edge>,
+}
+
+/// Component of the network saved on disk by `RoutingTable` once its peers were unreachable
+/// for `SAVE_PEERS_AFTER_TIME`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoredComponent {
+    pub nonce: u64,
+    pub peers: Vec<PeerId>,
+    pub edges: Vec<Edge>,
+}
+
+/// Reads the components saved in `ColPeerComponent` and `ColComponentEdges`, ordered by nonce.
+pub fn load_stored_components(
+    store: &Store,
+) -> Result<Vec<StoredComponent>, Box<dyn std::error::Error>> {
+    let mut components = BTreeMap::new();
+    for (key, value) in store.iter(ColComponentEdges) {
+        let nonce = u64::from_le_bytes(<[u8; 8]>::try_from(&key[..])?);
+        let edges = Vec::<Edge>::try_from_slice(&value)?;
+        components.insert(nonce, StoredComponent { nonce, peers: vec![], edges });
+    }
+    for (key, value) in store.iter(ColPeerComponent) {
+        let peer_id = PeerId::try_from(key.to_vec())?;
+        let nonce = u64::try_from_slice(&value)?;
+        components
+            .entry(nonce)
+            .or_insert_with(|| StoredComponent { nonce, peers: vec![], edges: vec![] })
+            .peers
+            .push(peer_id);
+    }
+    Ok(components
+        .into_iter()
+        .map(|(_, mut component)| {
+            component.peers.sort();
+            component
+        })
+        .collect())
+}
+
+/// Renders the components as a Graphviz DOT graph with a cluster per component. Edges are
+/// labeled with their nonce, the removed ones are dashed.
+pub fn components_to_dot(components: &[StoredComponent]) -> String {
+    let mut dot = String::from("graph routing {\n");
+    for component in components {
+        writeln!(dot, "    subgraph cluster_{} {{", component.nonce).unwrap();
+        writeln!(dot, "        label=\"component {}\";", component.nonce).unwrap();
+        for peer_id in component.peers.iter() {
+            writeln!(dot, "        \"{}\";", peer_id).unwrap();
+        }
+        for edge in component.edges.iter() {
+            let style = match edge.edge_type() {
+                EdgeType::Added => "solid",
+                EdgeType::Removed => "dashed",
+            };
+            writeln!(
+                dot,
+                "        \"{}\" -- \"{}\" [label=\"{}\", style={}];",
+                edge.peer0, edge.peer1, edge.nonce, style
+            )
+            .unwrap();
+        }
+        dot.push_str("    }\n");
+    }
+    dot.push_str("}\n");
+    dot
 }
 
 #[derive(Clone)]
@@ -951,7 +1024,12 @@ impl Graph {
 
 #[cfg(test)]
 mod test {
-    use crate::routing::Graph;
+    use near_crypto::{KeyType, Signature};
+    use near_primitives::utils::index_to_bytes;
+    use near_store::test_utils::create_test_store;
+    use near_store::{ColComponentEdges, ColPeerComponent};
+
+    use crate::routing::{components_to_dot, load_stored_components, Edge, Graph, StoredComponent};
     use crate::test_utils::{expected_routing_tables, random_peer_id};
 
     #[test]
@@ -1098,4 +1176,40 @@ mod test {
 
         assert!(expected_routing_tables(graph.calculate_distance(), next_hops));
     }
+
+    #[test]
+    fn stored_components_to_dot() {
+        let mut peers: Vec<_> = (0..3).map(|_| random_peer_id()).collect();
+        peers.sort();
+        let signature = Signature::empty(KeyType::ED25519);
+        let edge = Edge::new(peers[0].clone(), peers[1].clone(), 2, signature.clone(), signature);
+
+        let store = create_test_store();
+        let mut update = store.store_update();
+        update.set_ser(ColComponentEdges, &index_to_bytes(3), &vec![edge.clone()]).unwrap();
+        for peer_id in peers[..2].iter() {
+            update.set_ser(ColPeerComponent, &Vec::from(peer_id.clone()), &3u64).unwrap();
+        }
+        update.set_ser(ColPeerComponent, &Vec::from(peers[2].clone()), &5u64).unwrap();
+        update.commit().unwrap();
+
+        let components = load_stored_components(&store).unwrap();
+        assert_eq!(
+            components,
+            vec![
+                StoredComponent { nonce: 3, peers: peers[..2].to_vec(), edges: vec![edge] },
+                StoredComponent { nonce: 5, peers: vec![peers[2].clone()], edges: vec![] },
+            ]
+        );
+        let dot = components_to_dot(&components);
+        assert!(dot.starts_with("graph routing {\n    subgraph cluster_3 {\n"));
+        assert!(dot.contains(&format!(
+            "\"{}\" -- \"{}\" [label=\"2\", style=solid];",
+            peers[0], peers[1]
+        )));
+        assert!(dot.contains(&format!(
+            "subgraph cluster_5 {{\n        label=\"component 5\";\n        \"{}\";",
+            peers[2]
+        )));
+    }
 }
@@ -79,6 +79,13 @@ impl NetworkConfig {
             blacklist: HashMap::new(),
             outbound_disabled: false,
             archive: false,
+            traffic_capture_path: None,
+            traffic_capture_max_size: 0,
+            rate_limits: Default::default(),
+            compression_threshold: Some(4096),
+            external_addrs: vec![],
+            sentries: vec![],
+            private_peers: vec![],
         }
     }
 }
@@ -99,7 +106,7 @@ pub fn convert_boot_nodes(boot_nodes: Vec<(&str, u16)>) -> Vec<PeerInfo> {
 impl PeerInfo {
     /// Creates random peer info.
     pub fn random() -> Self {
-        PeerInfo { id: PeerId::random(), addr: None, account_id: None }
+        PeerInfo { id: PeerId::random(), addr: None, account_id: None, extra_addrs: vec![] }
     }
 }
 
@@ -2,6 +2,7 @@ use std::collections::{HashMap, HashSet};
 use std::convert::{Into, TryFrom, TryInto};
 use std::fmt;
 use std::net::{AddrParseError, IpAddr, SocketAddr};
+use std::path::PathBuf;
 use std::str::FromStr;
 use std::sync::RwLock;
 use std::time::{Duration, Instant};
@@ -39,7 +40,9 @@ use near_primitives::version::{
 };
 use near_primitives::views::{FinalExecutionOutcomeView, QueryRequest, QueryResponse};
 
+use crate::compression;
 use crate::peer::Peer;
+use crate::rate_limiter::RateLimitsConfig;
 #[cfg(feature = "metric_recorder")]
 use crate::recorder::MetricRecorder;
 use crate::routing::{Edge, EdgeInfo, RoutingTableInfo};
@@ -59,23 +62,65 @@ pub const ROUTED_MESSAGE_TTL: u8 = 100;
 /// Peer and PeerManager.
 pub const UPDATE_INTERVAL_LAST_TIME_RECEIVED_MESSAGE: Duration = Duration::from_secs(60);
 
+/// Maximum number of addresses kept for a single peer.
+pub const MAX_PEER_ADDRS: usize = 8;
+
 /// Peer information.
 #[derive(BorshSerialize, BorshDeserialize, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
 pub struct PeerInfo {
     pub id: PeerId,
+    /// Address tried first when connecting to the peer. Known peers are indexed by it.
     pub addr: Option<SocketAddr>,
     pub account_id: Option<AccountId>,
+    /// Other addresses of the peer, e.g. of another IP version or the public one of a peer behind
+    /// NAT. They are not part of the Borsh encoding, so they are only sent and persisted in the
+    /// peer store as `PeerInfoV2`.
+    #[borsh_skip]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub extra_addrs: Vec<SocketAddr>,
 }
 
 impl PeerInfo {
     pub fn addr_port(&self) -> Option<u16> {
         self.addr.map(|addr| addr.port())
     }
+
+    /// All addresses of the peer, in the order they are tried.
+    pub fn addrs(&self) -> impl Iterator<Item = &SocketAddr> {
+        self.addr.iter().chain(self.extra_addrs.iter())
+    }
 }
 
 impl PeerInfo {
     pub fn new(id: PeerId, addr: SocketAddr) -> Self {
-        PeerInfo { id, addr: Some(addr), account_id: None }
+        PeerInfo { id, addr: Some(addr), account_id: None, extra_addrs: vec![] }
+    }
+}
+
+/// Peer information with all the addresses of the peer, exchanged in `PeersResponseV2`.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, Eq, PartialEq)]
+pub struct PeerInfoV2 {
+    pub id: PeerId,
+    pub addrs: Vec<SocketAddr>,
+    pub account_id: Option<AccountId>,
+}
+
+impl From<PeerInfo> for PeerInfoV2 {
+    fn from(peer_info: PeerInfo) -> Self {
+        let addrs = peer_info.addrs().cloned().collect();
+        PeerInfoV2 { id: peer_info.id, addrs, account_id: peer_info.account_id }
+    }
+}
+
+impl From<PeerInfoV2> for PeerInfo {
+    fn from(peer_info: PeerInfoV2) -> Self {
+        let mut addrs = peer_info.addrs.into_iter().take(MAX_PEER_ADDRS);
+        PeerInfo {
+            id: peer_info.id,
+            addr: addrs.next(),
+            account_id: peer_info.account_id,
+            extra_addrs: addrs.collect(),
+        }
     }
 }
 
@@ -120,7 +165,7 @@ impl FromStr for PeerInfo {
                 format!("Invalid PeerInfo format: {:?}", chunks),
             )));
         }
-        Ok(PeerInfo { id: PeerId(chunks[0].parse()?), addr, account_id })
+        Ok(PeerInfo { id: PeerId(chunks[0].parse()?), addr, account_id, extra_addrs: vec![] })
     }
 }
 
@@ -203,7 +248,7 @@ impl fmt::Display for HandshakeFailureReason {
 
 impl std::error::Error for HandshakeFailureReason {}
 
-#[derive(BorshSerialize, Serialize, PartialEq, Eq, Clone, Debug)]
+#[derive(Serialize, PartialEq, Eq, Clone, Debug)]
 pub struct Handshake {
     pub version: u32,
     /// Oldest supported protocol version.
@@ -218,6 +263,10 @@ pub struct Handshake {
     pub chain_info: PeerChainInfoV2,
     /// Info for new edge.
     pub edge_info: EdgeInfo,
+    /// Whether the sender compresses large messages and accepts `PeerMessage::Compressed`.
+    /// Compression is used only if both peers set it. Only serialized for protocol versions
+    /// with `PeerMessageCompression`, older versions always have it unset.
+    pub compression: bool,
 }
 
 /// Struct describing the layout for Handshake.
@@ -250,6 +299,7 @@ impl Handshake {
         listen_port: Option<u16>,
         chain_info: PeerChainInfoV2,
         edge_info: EdgeInfo,
+        compression: bool,
     ) -> Self {
         Handshake {
             version,
@@ -259,10 +309,27 @@ impl Handshake {
             listen_port,
             chain_info,
             edge_info,
+            compression: compression && compression::is_compression_supported(version),
         }
     }
 }
 
+impl BorshSerialize for Handshake {
+    fn serialize<W: io::Write>(&self, writer: &mut W) -> io::Result<()> {
+        self.version.serialize(writer)?;
+        self.oldest_supported_version.serialize(writer)?;
+        self.peer_id.serialize(writer)?;
+        self.target_peer_id.serialize(writer)?;
+        self.listen_port.serialize(writer)?;
+        self.chain_info.serialize(writer)?;
+        self.edge_info.serialize(writer)?;
+        if compression::is_compression_supported(self.version) {
+            self.compression.serialize(writer)?;
+        }
+        Ok(())
+    }
+}
+
 // Use custom deserializer for HandshakeV2. Try to read version of the other peer from the header.
 // If the version is supported then fallback to standard deserializer.
 impl BorshDeserialize for Handshake {
@@ -279,7 +346,11 @@ impl BorshDeserialize for Handshake {
 
         if OLDEST_BACKWARD_COMPATIBLE_PROTOCOL_VERSION <= version && version <= PROTOCOL_VERSION {
             // If we support this version, then try to deserialize with custom deserializer
-            HandshakeAutoDes::deserialize(buf).map(Into::into)
+            let mut handshake: Handshake = HandshakeAutoDes::deserialize(buf)?.into();
+            if compression::is_compression_supported(version) {
+                handshake.compression = bool::deserialize(buf)?;
+            }
+            Ok(handshake)
         } else {
             Err(std::io::Error::new(
                 std::io::ErrorKind::InvalidData,
@@ -302,6 +373,7 @@ impl From<HandshakeAutoDes> for Handshake {
             listen_port: handshake.listen_port,
             chain_info: handshake.chain_info,
             edge_info: handshake.edge_info,
+            compression: false,
         }
     }
 }
@@ -408,6 +480,7 @@ impl From<HandshakeV2> for Handshake {
             listen_port: handshake.listen_port,
             chain_info: handshake.chain_info.into(),
             edge_info: handshake.edge_info,
+            compression: false,
         }
     }
 }
@@ -473,6 +546,15 @@ pub enum RoutedMessageBody {
     VersionedStateResponse(StateResponseInfo),
     #[cfg(feature = "protocol_feature_forward_chunk_parts")]
     PartialEncodedChunkForward(PartialEncodedChunkForwardMsg),
+    /// Takes the place of `PartialEncodedChunkForward` when it is not compiled in, so that the
+    /// variants after it keep their position. Never sent.
+    #[cfg(all(
+        feature = "protocol_feature_state_part_chunks",
+        not(feature = "protocol_feature_forward_chunk_parts")
+    ))]
+    _PartialEncodedChunkForward,
+    #[cfg(feature = "protocol_feature_state_part_chunks")]
+    StateRequestPartChunk(ShardId, CryptoHash, u64, u64),
 }
 
 impl From<PartialEncodedChunkWithArcReceipts> for RoutedMessageBody {
@@ -516,6 +598,14 @@ impl Debug for RoutedMessageBody {
             RoutedMessageBody::StateRequestPart(shard_id, sync_hash, part_id) => {
                 write!(f, "StateRequestPart({}, {}, {})", shard_id, sync_hash, part_id)
             }
+            #[cfg(feature = "protocol_feature_state_part_chunks")]
+            RoutedMessageBody::StateRequestPartChunk(shard_id, sync_hash, part_id, chunk_id) => {
+                write!(
+                    f,
+                    "StateRequestPartChunk({}, {}, {}, {})",
+                    shard_id, sync_hash, part_id, chunk_id
+                )
+            }
             RoutedMessageBody::StateResponse(response) => {
                 write!(f, "StateResponse({}, {})", response.shard_id, response.sync_hash)
             }
@@ -547,6 +637,11 @@ impl Debug for RoutedMessageBody {
                 forward.chunk_hash,
                 forward.parts.iter().map(|p| p.part_ord).collect::<Vec<_>>(),
             ),
+            #[cfg(all(
+                feature = "protocol_feature_state_part_chunks",
+                not(feature = "protocol_feature_forward_chunk_parts")
+            ))]
+            RoutedMessageBody::_PartialEncodedChunkForward => write!(f, "PartialChunkForward"),
             RoutedMessageBody::Ping(_) => write!(f, "Ping"),
             RoutedMessageBody::Pong(_) => write!(f, "Pong"),
         }
@@ -680,6 +775,8 @@ impl RoutedMessage {
             | RoutedMessageBody::PartialEncodedChunkRequest(_)
             | RoutedMessageBody::QueryRequest { .. }
             | RoutedMessageBody::ReceiptOutcomeRequest(_) => true,
+            #[cfg(feature = "protocol_feature_state_part_chunks")]
+            RoutedMessageBody::StateRequestPartChunk(_, _, _, _) => true,
             _ => false,
         }
     }
@@ -769,6 +866,14 @@ pub enum PeerMessage {
     EpochSyncResponse(EpochSyncResponse),
     EpochSyncFinalizationRequest(EpochId),
     EpochSyncFinalizationResponse(EpochSyncFinalizationResponse),
+
+    /// Noise handshake message, exchanged after `Handshake` to encrypt the connection.
+    NoiseHandshake(Vec<u8>),
+    /// Another `PeerMessage` compressed with zstd, see `compression` module. Only appears on
+    /// the wire, `Codec` decompresses it before the message is handled.
+    Compressed(Vec<u8>),
+    /// `PeersResponse` with all the addresses of every peer.
+    PeersResponseV2(Vec<PeerInfoV2>),
 }
 
 impl fmt::Display for PeerMessage {
@@ -823,6 +928,8 @@ impl PeerMessage {
                 | RoutedMessageBody::ReceiptOutComeResponse(_)
                 | RoutedMessageBody::StateRequestHeader(_, _)
                 | RoutedMessageBody::StateRequestPart(_, _, _) => true,
+                #[cfg(feature = "protocol_feature_state_part_chunks")]
+                RoutedMessageBody::StateRequestPartChunk(_, _, _, _) => true,
                 _ => false,
             },
             PeerMessage::BlockHeadersRequest(_) => true,
@@ -899,6 +1006,26 @@ pub struct NetworkConfig {
     pub outbound_disabled: bool,
     /// Not clear old data, set `true` for archive nodes.
     pub archive: bool,
+    /// File to capture all messages exchanged with peers to, see `capture` module.
+    /// Only used by nodes built with `traffic_capture` feature.
+    pub traffic_capture_path: Option<PathBuf>,
+    /// Maximum size of the traffic capture file in bytes.
+    pub traffic_capture_max_size: u64,
+    /// Limits on the messages received from each peer.
+    pub rate_limits: RateLimitsConfig,
+    /// Messages larger than this many bytes are compressed on connections to peers which also
+    /// advertised compression. Compression is neither advertised nor accepted if not set.
+    pub compression_threshold: Option<usize>,
+    /// Addresses advertised to other peers for them to connect to this node, in addition to the
+    /// one they see the connection from. Independent of `addr`, which is the one listened at.
+    pub external_addrs: Vec<SocketAddr>,
+    /// Sentry nodes of this node. If set, the node only connects to and accepts connections
+    /// from them, doesn't announce its address, and they relay its messages to the rest of
+    /// the network.
+    pub sentries: Vec<PeerId>,
+    /// Nodes this node is a sentry for. Their addresses are never shared with other peers, nor
+    /// learned from them.
+    pub private_peers: Vec<PeerId>,
 }
 
 impl NetworkConfig {
@@ -984,13 +1111,15 @@ impl KnownPeerStatus {
     }
 }
 
-/// Information node stores about known peers.
-#[derive(BorshSerialize, BorshDeserialize, Serialize, Debug, Clone)]
+/// Information node stores about known peers. Stored as `VersionedKnownPeerState`.
+#[derive(Serialize, Debug, Clone)]
 pub struct KnownPeerState {
     pub peer_info: PeerInfo,
     pub status: KnownPeerStatus,
     pub first_seen: u64,
     pub last_seen: u64,
+    /// Reputation of the peer, see `PeerScoreEvent`.
+    pub score: i32,
 }
 
 impl KnownPeerState {
@@ -1000,6 +1129,7 @@ impl KnownPeerState {
             status: KnownPeerStatus::Unknown,
             first_seen: to_timestamp(Utc::now()),
             last_seen: to_timestamp(Utc::now()),
+            score: 0,
         }
     }
 
@@ -1012,6 +1142,66 @@ impl KnownPeerState {
     }
 }
 
+/// `KnownPeerState` stored before peers had several addresses, it only has the first one.
+#[derive(BorshSerialize, BorshDeserialize)]
+struct KnownPeerStateV1 {
+    peer_info: PeerInfo,
+    status: KnownPeerStatus,
+    first_seen: u64,
+    last_seen: u64,
+    score: i32,
+}
+
+/// `KnownPeerState` with all the addresses of the peer.
+#[derive(BorshSerialize, BorshDeserialize)]
+struct KnownPeerStateV2 {
+    peer_info: PeerInfoV2,
+    status: KnownPeerStatus,
+    first_seen: u64,
+    last_seen: u64,
+    score: i32,
+}
+
+#[derive(BorshSerialize, BorshDeserialize)]
+enum VersionedKnownPeerState {
+    V1(KnownPeerStateV1),
+    V2(KnownPeerStateV2),
+}
+
+impl BorshSerialize for KnownPeerState {
+    fn serialize<W: io::Write>(&self, writer: &mut W) -> io::Result<()> {
+        VersionedKnownPeerState::V2(KnownPeerStateV2 {
+            peer_info: self.peer_info.clone().into(),
+            status: self.status.clone(),
+            first_seen: self.first_seen,
+            last_seen: self.last_seen,
+            score: self.score,
+        })
+        .serialize(writer)
+    }
+}
+
+impl BorshDeserialize for KnownPeerState {
+    fn deserialize(buf: &mut &[u8]) -> io::Result<Self> {
+        Ok(match VersionedKnownPeerState::deserialize(buf)? {
+            VersionedKnownPeerState::V1(state) => KnownPeerState {
+                peer_info: state.peer_info,
+                status: state.status,
+                first_seen: state.first_seen,
+                last_seen: state.last_seen,
+                score: state.score,
+            },
+            VersionedKnownPeerState::V2(state) => KnownPeerState {
+                peer_info: state.peer_info.into(),
+                status: state.status,
+                first_seen: state.first_seen,
+                last_seen: state.last_seen,
+                score: state.score,
+            },
+        })
+    }
+}
+
 impl TryFrom<Vec<u8>> for KnownPeerState {
     type Error = Box<dyn std::error::Error>;
 
@@ -1134,7 +1324,9 @@ where
 }
 
 /// Ban reason.
-#[derive(BorshSerialize, BorshDeserialize, Serialize, Debug, Clone, PartialEq, Eq, Copy)]
+#[derive(
+    BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Copy,
+)]
 pub enum ReasonForBan {
     None = 0,
     BadBlock = 1,
@@ -1150,6 +1342,50 @@ pub enum ReasonForBan {
     EpochSyncNoResponse = 11,
     EpochSyncInvalidResponse = 12,
     EpochSyncInvalidFinalizationResponse = 13,
+    /// Banned by the node operator.
+    Manual = 14,
+    /// Reputation score of the peer dropped to `BAN_PEER_SCORE`.
+    LowScore = 15,
+}
+
+/// Lowest reputation score a peer can have.
+pub const MIN_PEER_SCORE: i32 = -1000;
+/// Highest reputation score a peer can have.
+pub const MAX_PEER_SCORE: i32 = 1000;
+/// Peers whose score drops to this value get banned.
+pub const BAN_PEER_SCORE: i32 = -500;
+
+/// Behaviour of a peer which changes its reputation score.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PeerScoreEvent {
+    /// Peer sent the block headers requested during header sync.
+    HeadersDelivered,
+    /// Peer sent the chunk parts or state parts requested by this node.
+    PartsServed,
+    /// Peer responded to the request, but with nothing useful.
+    UselessResponse,
+    /// Peer didn't respond to the request in time.
+    Timeout,
+    /// Peer sent a message which can't be decoded.
+    InvalidMessage,
+}
+
+impl PeerScoreEvent {
+    pub fn score_delta(&self) -> i32 {
+        match self {
+            PeerScoreEvent::HeadersDelivered => 10,
+            PeerScoreEvent::PartsServed => 5,
+            PeerScoreEvent::UselessResponse => -5,
+            PeerScoreEvent::Timeout => -20,
+            PeerScoreEvent::InvalidMessage => -100,
+        }
+    }
+}
+
+/// Weight of the peer when picking whom to connect or to sync from. Peers with higher score are
+/// picked more often, but even the ones with the lowest score are not ruled out.
+pub fn peer_score_weight(score: i32) -> u64 {
+    (score.max(MIN_PEER_SCORE) - MIN_PEER_SCORE) as u64 + 1
 }
 
 /// Banning signal sent from Peer instance to PeerManager
@@ -1196,6 +1432,15 @@ pub enum NetworkRequests {
         part_id: u64,
         target: AccountOrPeerIdOrHash,
     },
+    /// Request a chunk of state part for given shard at given state root.
+    #[cfg(feature = "protocol_feature_state_part_chunks")]
+    StateRequestPartChunk {
+        shard_id: ShardId,
+        sync_hash: CryptoHash,
+        part_id: u64,
+        chunk_id: u64,
+        target: AccountOrPeerIdOrHash,
+    },
     /// Response to state request.
     StateResponse {
         route_back: CryptoHash,
@@ -1214,6 +1459,19 @@ pub enum NetworkRequests {
         peer_id: PeerId,
         ban_reason: ReasonForBan,
     },
+    /// Lift the ban of given peer.
+    UnbanPeer {
+        peer_id: PeerId,
+    },
+    /// Change the reputation score of given peer.
+    UpdatePeerScore {
+        peer_id: PeerId,
+        event: PeerScoreEvent,
+    },
+    /// Add a boot node to the known peers at runtime.
+    AddBootNode(PeerInfo),
+    /// Remove a boot node from the known peers.
+    RemoveBootNode(PeerId),
     /// Announce account
     AnnounceAccount(AnnounceAccount),
 
@@ -1294,6 +1552,8 @@ pub struct FullPeerInfo {
     pub peer_info: PeerInfo,
     pub chain_info: PeerChainInfoV2,
     pub edge_info: EdgeInfo,
+    /// Reputation score of the peer when the info was collected.
+    pub score: i32,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -1338,6 +1598,7 @@ pub enum NetworkResponses {
     BanPeer(ReasonForBan),
     EdgeUpdate(Box<Edge>),
     RouteNotFound,
+    PeerNotFound,
 }
 
 impl<A, M> MessageResponse<A, M> for NetworkResponses
@@ -1474,6 +1735,8 @@ pub enum NetworkClientResponses {
     /// The node being queried does not track the shard needed and therefore cannot provide userful
     /// response.
     DoesNotTrackShard,
+    /// The transaction pool of the shard is full and the transaction was not accepted.
+    TxPoolFull,
     /// Ban peer for malicious behavior.
     Ban { ban_reason: ReasonForBan },
 }
@@ -1511,10 +1774,19 @@ pub enum NetworkViewClientMessages {
     BlockRequest(CryptoHash),
     /// Request headers.
     BlockHeadersRequest(Vec<CryptoHash>),
-    /// State request header.
-    StateRequestHeader { shard_id: ShardId, sync_hash: CryptoHash },
-    /// State request part.
-    StateRequestPart { shard_id: ShardId, sync_hash: CryptoHash, part_id: u64 },
+    /// State request header. `peer_id` is the author of the request.
+    StateRequestHeader { shard_id: ShardId, sync_hash: CryptoHash, peer_id: PeerId },
+    /// State request part. `peer_id` is the author of the request.
+    StateRequestPart { shard_id: ShardId, sync_hash: CryptoHash, part_id: u64, peer_id: PeerId },
+    /// State request part chunk. `peer_id` is the author of the request.
+    #[cfg(feature = "protocol_feature_state_part_chunks")]
+    StateRequestPartChunk {
+        shard_id: ShardId,
+        sync_hash: CryptoHash,
+        part_id: u64,
+        chunk_id: u64,
+        peer_id: PeerId,
+    },
     /// A request for a light client info during Epoch Sync
     EpochSyncRequest { epoch_id: EpochId },
     /// A request for headers and proofs during Epoch Sync
@@ -1735,6 +2007,16 @@ mod tests {
         };
     }
 
+    /// The position of `StateRequestPartChunk` doesn't depend on the other features.
+    #[cfg(feature = "protocol_feature_state_part_chunks")]
+    #[test]
+    fn test_state_request_part_chunk_index() {
+        let body = RoutedMessageBody::StateRequestPartChunk(1, CryptoHash::default(), 2, 3);
+        let bytes = body.try_to_vec().unwrap();
+        assert_eq!(bytes[0], 19);
+        assert_eq!(RoutedMessageBody::try_from_slice(&bytes).unwrap(), body);
+    }
+
     #[test]
     fn test_enum_size() {
         assert_size!(PeerType);
@@ -1785,4 +2067,32 @@ mod tests {
         assert_size!(QueryPeerStats);
         assert_size!(PartialEncodedChunkRequestMsg);
     }
+
+    #[test]
+    fn test_known_peer_state_versions() {
+        let mut peer_info = PeerInfo::new(
+            PeerId::new(PublicKey::empty(near_crypto::KeyType::ED25519)),
+            "127.0.0.1:24567".parse().unwrap(),
+        );
+        peer_info.extra_addrs = vec!["[::1]:24567".parse().unwrap()];
+        let mut peer_state = KnownPeerState::new(peer_info.clone());
+        peer_state.score = 10;
+
+        let decoded = KnownPeerState::try_from(peer_state.try_to_vec().unwrap()).unwrap();
+        assert_eq!(decoded.peer_info, peer_info);
+        assert_eq!(decoded.score, 10);
+
+        // States stored before the extra addresses only have the first address.
+        let v1 = VersionedKnownPeerState::V1(KnownPeerStateV1 {
+            peer_info: peer_info.clone(),
+            status: KnownPeerStatus::NotConnected,
+            first_seen: peer_state.first_seen,
+            last_seen: peer_state.last_seen,
+            score: 10,
+        });
+        let decoded = KnownPeerState::try_from(v1.try_to_vec().unwrap()).unwrap();
+        assert_eq!(decoded.peer_info, PeerInfo { extra_addrs: vec![], ..peer_info });
+        assert_eq!(decoded.status, KnownPeerStatus::NotConnected);
+        assert_eq!(decoded.score, 10);
+    }
 }
@@ -1,6 +1,9 @@
 pub use runner::*;
 use std::time::Duration;
 
+use near_network::rate_limiter::{RateLimit, RateLimitsConfig};
+use near_network::types::{PeerScoreEvent, BAN_PEER_SCORE};
+
 mod runner;
 
 /// Check we don't try to connect to a banned peer and we don't accept
@@ -51,3 +54,65 @@ fn connect_to_unbanned_peer() {
 
     start_test(runner);
 }
+
+/// Check a peer gets banned and disconnected once its score drops to `BAN_PEER_SCORE`.
+#[test]
+fn ban_peer_with_low_score() {
+    let mut runner = Runner::new(2, 2)
+        .enable_outbound()
+        .use_boot_nodes(vec![0, 1])
+        .ban_window(Duration::from_secs(60));
+
+    runner.push(Action::CheckRoutingTable(0, vec![(1, vec![1])]));
+    runner.push(Action::CheckRoutingTable(1, vec![(0, vec![0])]));
+
+    let event = PeerScoreEvent::InvalidMessage;
+    for _ in 0..BAN_PEER_SCORE / event.score_delta() {
+        runner.push_action(update_peer_score(0, 1, event));
+    }
+    runner.push(Action::Wait(1000));
+
+    runner.push(Action::CheckRoutingTable(0, vec![]));
+    runner.push(Action::CheckRoutingTable(1, vec![]));
+
+    start_test(runner);
+}
+
+/// Check the messages of a peer over the rate limit are dropped, and the peer gets banned once it
+/// has more than `ban_dropped_per_min` of them dropped.
+#[test]
+fn ban_peer_over_rate_limit() {
+    let rate_limits = RateLimitsConfig {
+        // Pings are not refilled during the test.
+        messages: vec![("Ping".to_string(), RateLimit { rate: 0.001, burst: 2.0 })]
+            .into_iter()
+            .collect(),
+        bandwidth: None,
+        ban_dropped_per_min: Some(2),
+    };
+    let mut runner = Runner::new(2, 2)
+        .enable_outbound()
+        .use_boot_nodes(vec![0, 1])
+        .ban_window(Duration::from_secs(60))
+        .rate_limits(0, rate_limits);
+
+    runner.push(Action::CheckRoutingTable(0, vec![(1, vec![1])]));
+    runner.push(Action::CheckRoutingTable(1, vec![(0, vec![0])]));
+
+    for nonce in 0..4 {
+        runner.push(Action::PingTo(1, nonce, 0));
+    }
+    runner.push(Action::Wait(500));
+    // Only the pings within the burst are received, the dropped ones don't get the peer banned yet.
+    runner.push(Action::CheckPingPong(0, vec![(0, 1), (1, 1)], vec![]));
+    runner.push(Action::CheckPingPong(1, vec![], vec![(0, 0), (1, 0)]));
+    runner.push(Action::CheckRoutingTable(0, vec![(1, vec![1])]));
+
+    runner.push(Action::PingTo(1, 4, 0));
+    runner.push(Action::Wait(1000));
+
+    runner.push(Action::CheckRoutingTable(0, vec![]));
+    runner.push(Action::CheckRoutingTable(1, vec![]));
+
+    start_test(runner);
+}
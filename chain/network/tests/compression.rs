@@ -0,0 +1,37 @@
+#![cfg(feature = "protocol_feature_peer_message_compression")]
+pub use runner::*;
+
+mod runner;
+
+/// Sends a ping each way and checks the peers stay connected. Node 1 compresses every message
+/// which gets smaller once compression is negotiated, and node 0 would ban it for compressed
+/// messages it didn't agree to.
+fn ping_both_ways(node0_compression_threshold: Option<usize>) {
+    let mut runner = Runner::new(2, 1)
+        .compression_threshold(0, node0_compression_threshold)
+        .compression_threshold(1, Some(1));
+
+    runner.push(Action::AddEdge(0, 1));
+    runner.push(Action::CheckRoutingTable(0, vec![(1, vec![1])]));
+    runner.push(Action::CheckRoutingTable(1, vec![(0, vec![0])]));
+    runner.push(Action::PingTo(1, 0, 0));
+    runner.push(Action::PingTo(0, 1, 1));
+    runner.push(Action::CheckPingPong(0, vec![(0, 1)], vec![(1, 1)]));
+    runner.push(Action::CheckPingPong(1, vec![(1, 0)], vec![(0, 0)]));
+    runner.push(Action::Wait(500));
+    runner.push(Action::CheckRoutingTable(0, vec![(1, vec![1])]));
+    runner.push(Action::CheckRoutingTable(1, vec![(0, vec![0])]));
+
+    start_test(runner);
+}
+
+#[test]
+fn compressed_connection() {
+    ping_both_ways(Some(1));
+}
+
+/// Node 1 doesn't compress the messages to node 0, which didn't advertise compression.
+#[test]
+fn compression_not_negotiated() {
+    ping_both_ways(None);
+}
@@ -0,0 +1,121 @@
+#![cfg(feature = "protocol_feature_encrypted_peer_connections")]
+pub use runner::*;
+
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+
+use borsh::BorshSerialize;
+
+use near_network::types::PeerMessage;
+
+mod runner;
+
+const PING_NONCE: usize = 0x5eed_f00d;
+
+/// Proxy in front of a node which records the bytes sent to it, and holds its first Noise
+/// handshake message until released.
+#[derive(Clone, Default)]
+struct Proxy {
+    sent: Arc<Mutex<Vec<u8>>>,
+    holding: Arc<AtomicBool>,
+    released: Arc<(Mutex<bool>, Condvar)>,
+}
+
+impl Proxy {
+    /// Accepts a single connection and forwards it to `target`, returns the address to connect to.
+    fn start(&self, target: SocketAddr) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let proxy = self.clone();
+        thread::spawn(move || {
+            let (client, _) = listener.accept().unwrap();
+            let server = TcpStream::connect(target).unwrap();
+            let recorder = proxy.clone();
+            let (client_read, server_write) =
+                (client.try_clone().unwrap(), server.try_clone().unwrap());
+            thread::spawn(move || recorder.forward_recording(client_read, server_write));
+            proxy.forward_holding(server, client);
+        });
+        addr
+    }
+
+    fn forward_recording(&self, mut from: TcpStream, mut to: TcpStream) {
+        let mut buf = [0u8; 4096];
+        loop {
+            let len = match from.read(&mut buf) {
+                Ok(0) | Err(_) => break,
+                Ok(len) => len,
+            };
+            self.sent.lock().unwrap().extend_from_slice(&buf[..len]);
+            if to.write_all(&buf[..len]).is_err() {
+                break;
+            }
+        }
+    }
+
+    /// Forwards the frames one by one, the plaintext ones start with the `PeerMessage` variant.
+    fn forward_holding(&self, mut from: TcpStream, mut to: TcpStream) {
+        let noise_variant = PeerMessage::NoiseHandshake(vec![]).try_to_vec().unwrap()[0];
+        let mut held = false;
+        loop {
+            let mut len = [0u8; 4];
+            if from.read_exact(&mut len).is_err() {
+                break;
+            }
+            let mut frame = vec![0u8; u32::from_le_bytes(len) as usize];
+            if from.read_exact(&mut frame).is_err() {
+                break;
+            }
+            if !held && frame.first() == Some(&noise_variant) {
+                held = true;
+                self.holding.store(true, Ordering::SeqCst);
+                let (released, condvar) = &*self.released;
+                let _released =
+                    condvar.wait_while(released.lock().unwrap(), |released| !*released).unwrap();
+            }
+            if to.write_all(&len).and_then(|_| to.write_all(&frame)).is_err() {
+                break;
+            }
+        }
+    }
+
+    fn release(&self) {
+        let (released, condvar) = &*self.released;
+        *released.lock().unwrap() = true;
+        condvar.notify_all();
+    }
+}
+
+/// Messages sent while the connection is being encrypted are kept until it is, then they are
+/// delivered encrypted.
+#[test]
+fn encrypted_connection_delivers_pending_messages() {
+    let mut runner = Runner::new(2, 2);
+    let proxy = Proxy::default();
+
+    let proxy1 = proxy.clone();
+    runner.push_action(add_edge_through(0, 1, move |addr| proxy1.start(addr)));
+    // Node 0 is connected, but waits for the reply to its Noise handshake.
+    let proxy1 = proxy.clone();
+    runner.push_action(wait_for(move || proxy1.holding.load(Ordering::SeqCst)));
+    runner.push(Action::CheckRoutingTable(0, vec![(1, vec![1])]));
+    runner.push(Action::PingTo(0, PING_NONCE, 1));
+    runner.push(Action::Wait(200));
+    let proxy1 = proxy.clone();
+    runner.push_action(wait_for(move || {
+        proxy1.release();
+        true
+    }));
+    runner.push(Action::CheckPingPong(1, vec![(PING_NONCE, 0)], vec![]));
+    runner.push(Action::CheckPingPong(0, vec![], vec![(PING_NONCE, 1)]));
+    runner.push_action(wait_for(move || {
+        let nonce = (PING_NONCE as u64).to_le_bytes();
+        assert!(!proxy.sent.lock().unwrap().windows(nonce.len()).any(|bytes| bytes == nonce));
+        true
+    }));
+
+    start_test(runner);
+}
@@ -222,6 +222,55 @@ fn blacklist_all() {
     start_test(runner);
 }
 
+/// Node 0 is behind sentry 1, so node 2 reaches it only through the sentry, and it rejects the
+/// direct connection.
+#[test]
+fn sentry() {
+    let mut runner = Runner::new(3, 3).add_sentry(0, 1);
+
+    runner.push(Action::AddEdge(0, 1));
+    runner.push(Action::AddEdge(1, 2));
+    runner.push(Action::AddEdge(2, 0));
+    runner.push(Action::Wait(100));
+    runner.push(Action::CheckRoutingTable(0, vec![(1, vec![1]), (2, vec![1])]));
+    runner.push(Action::CheckRoutingTable(2, vec![(1, vec![1]), (0, vec![1])]));
+    runner.push(Action::CheckAccountId(2, vec![0, 1]));
+    runner.push(Action::PingTo(2, 0, 0));
+    runner.push(Action::CheckPingPong(0, vec![(0, 2)], vec![]));
+
+    start_test(runner);
+}
+
+/// Node 0 is behind sentry 1, neither the sentry nor node 2 connected to it learns its address.
+#[test]
+fn sentry_keeps_address_private() {
+    let mut runner = Runner::new(3, 3).add_sentry(0, 1);
+
+    runner.push(Action::AddEdge(0, 1));
+    runner.push(Action::AddEdge(1, 2));
+    runner.push(Action::CheckRoutingTable(2, vec![(1, vec![1]), (0, vec![1])]));
+    // Peers are requested a second after connecting.
+    runner.push(Action::Wait(1500));
+    runner.push_action(check_unknown_address(1, 0));
+    runner.push_action(check_unknown_address(2, 0));
+
+    start_test(runner);
+}
+
+/// Approvals of node 0, which is behind sentry 1, reach node 2 through the sentry.
+#[test]
+fn sentry_relays_approvals() {
+    let mut runner = Runner::new(3, 3).add_sentry(0, 1);
+
+    runner.push(Action::AddEdge(0, 1));
+    runner.push(Action::AddEdge(1, 2));
+    runner.push(Action::CheckAccountId(0, vec![2]));
+    runner.push_action(send_approval(0, 2));
+    runner.push_action(check_approval(2, 0));
+
+    start_test(runner);
+}
+
 /// Spawn 4 nodes with max peers required equal 2. Connect first three peers in a triangle.
 /// Try to connect peer3 to peer0 and see it fail since first three peer are at max capacity.
 #[test]
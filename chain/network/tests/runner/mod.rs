@@ -1,10 +1,13 @@
 use std::collections::HashSet;
 use std::iter::Iterator;
+use std::net::SocketAddr;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, RwLock};
 use std::time::Duration;
 
-use actix::{Actor, Addr, AsyncContext, Context, Handler, Message, System};
+use actix::{
+    Actor, Addr, AsyncContext, Context, Handler, Message, Recipient, ResponseFuture, System,
+};
 use chrono::{DateTime, Utc};
 use futures::{future, FutureExt, TryFutureExt};
 
@@ -12,18 +15,24 @@ use near_actix_test_utils::run_actix_until_stop;
 use near_chain::test_utils::KeyValueRuntime;
 use near_chain::ChainGenesis;
 use near_chain_configs::ClientConfig;
-use near_client::{start_view_client, ClientActor};
+use near_client::{start_view_client, ClientActor, PendingTransactionsSnapshot};
 use near_crypto::KeyType;
 use near_logger_utils::init_test_logger;
+use near_network::rate_limiter::RateLimitsConfig;
 use near_network::test_utils::{
     convert_boot_nodes, expected_routing_tables, open_port, peer_id_from_seed, BanPeerSignal,
     GetInfo, StopSignal, WaitOrTimeout,
 };
-use near_network::types::{OutboundTcpConnect, ROUTED_MESSAGE_TTL};
+use near_network::types::{
+    NetworkClientMessages, NetworkClientResponses, OutboundTcpConnect, PeerScoreEvent,
+    PeersRequest, ROUTED_MESSAGE_TTL,
+};
 use near_network::utils::blacklist_from_iter;
 use near_network::{
     NetworkConfig, NetworkRecipient, NetworkRequests, NetworkResponses, PeerInfo, PeerManagerActor,
 };
+use near_primitives::block::{Approval, ApprovalMessage};
+use near_primitives::hash::CryptoHash;
 use near_primitives::types::{AccountId, ValidatorId};
 use near_primitives::validator_signer::InMemoryValidatorSigner;
 use near_store::test_utils::create_test_store;
@@ -34,12 +43,39 @@ pub type SharedRunningInfo = Arc<RwLock<RunningInfo>>;
 pub type ActionFn =
     Box<dyn FnMut(SharedRunningInfo, Arc<AtomicBool>, &mut Context<WaitOrTimeout>, Addr<Runner>)>;
 
+pub type Approvals = Arc<RwLock<Vec<Approval>>>;
+
+/// Forwards the messages to the client, recording the approvals it receives.
+struct ApprovalRecorder {
+    client: Recipient<NetworkClientMessages>,
+    approvals: Approvals,
+}
+
+impl Actor for ApprovalRecorder {
+    type Context = Context<Self>;
+}
+
+impl Handler<NetworkClientMessages> for ApprovalRecorder {
+    type Result = ResponseFuture<NetworkClientResponses>;
+
+    fn handle(&mut self, msg: NetworkClientMessages, _ctx: &mut Self::Context) -> Self::Result {
+        if let NetworkClientMessages::BlockApproval(approval, _) = &msg {
+            self.approvals.write().unwrap().push(approval.clone());
+        }
+        let client = self.client.clone();
+        Box::pin(
+            async move { client.send(msg).await.unwrap_or(NetworkClientResponses::NoResponse) },
+        )
+    }
+}
+
 /// Sets up a node with a valid Client, Peer
 pub fn setup_network_node(
     account_id: String,
     validators: Vec<String>,
     genesis_time: DateTime<Utc>,
     config: NetworkConfig,
+    approvals: Approvals,
 ) -> Addr<PeerManagerActor> {
     let store = create_test_store();
 
@@ -70,6 +106,7 @@ pub fn setup_network_node(
         let network_adapter = Arc::new(network_adapter);
         #[cfg(feature = "adversarial")]
         let adv = Arc::new(RwLock::new(Default::default()));
+        let pending_transactions = Arc::new(RwLock::new(PendingTransactionsSnapshot::default()));
 
         let client_actor = ClientActor::new(
             client_config.clone(),
@@ -80,6 +117,7 @@ pub fn setup_network_node(
             Some(signer),
             telemetry_actor,
             false,
+            pending_transactions.clone(),
             #[cfg(feature = "adversarial")]
             adv.clone(),
         )
@@ -91,14 +129,18 @@ pub fn setup_network_node(
             runtime.clone(),
             network_adapter.clone(),
             client_config,
+            pending_transactions,
             #[cfg(feature = "adversarial")]
             adv.clone(),
         );
 
+        let client_recipient =
+            ApprovalRecorder { client: client_actor.recipient(), approvals }.start().recipient();
+
         PeerManagerActor::new(
             store.clone(),
             config,
-            client_actor.recipient(),
+            client_recipient,
             view_client_actor.recipient(),
         )
         .unwrap()
@@ -126,6 +168,7 @@ pub enum Action {
 pub struct RunningInfo {
     pm_addr: Vec<Addr<PeerManagerActor>>,
     peers_info: Vec<PeerInfo>,
+    approvals: Vec<Approvals>,
 }
 
 struct StateMachine {
@@ -366,6 +409,10 @@ struct TestConfig {
     minimum_outbound_peers: Option<u32>,
     safe_set_size: Option<u32>,
     archive: bool,
+    sentries: Vec<usize>,
+    private_peers: Vec<usize>,
+    rate_limits: RateLimitsConfig,
+    compression_threshold: Option<Option<usize>>,
 }
 
 impl TestConfig {
@@ -381,6 +428,10 @@ impl TestConfig {
             minimum_outbound_peers: None,
             safe_set_size: None,
             archive: false,
+            sentries: vec![],
+            private_peers: vec![],
+            rate_limits: RateLimitsConfig::default(),
+            compression_threshold: None,
         }
     }
 }
@@ -390,6 +441,8 @@ pub struct Runner {
     num_validators: usize,
     test_config: Vec<TestConfig>,
     state_machine: Option<StateMachine>,
+    /// Approvals received by the client of each node.
+    approvals: Vec<Approvals>,
 
     info: Option<Arc<RwLock<RunningInfo>>>,
 
@@ -406,6 +459,7 @@ impl Runner {
             num_validators,
             test_config: (0..num_nodes).map(|_| TestConfig::new()).collect(),
             state_machine: Some(StateMachine::new()),
+            approvals: (0..num_nodes).map(|_| Default::default()).collect(),
             info: None,
             accounts_id: None,
             ports: None,
@@ -435,6 +489,25 @@ impl Runner {
         self
     }
 
+    /// Set node `v` as the sentry of node `u`.
+    pub fn add_sentry(mut self, u: usize, v: usize) -> Self {
+        self.test_config[u].sentries.push(v);
+        self.test_config[v].private_peers.push(u);
+        self
+    }
+
+    /// Set the limits on the messages node `u` receives from each peer.
+    pub fn rate_limits(mut self, u: usize, rate_limits: RateLimitsConfig) -> Self {
+        self.test_config[u].rate_limits = rate_limits;
+        self
+    }
+
+    /// Set the compression threshold of node `u`, `None` disables compression.
+    pub fn compression_threshold(mut self, u: usize, compression_threshold: Option<usize>) -> Self {
+        self.test_config[u].compression_threshold = Some(compression_threshold);
+        self
+    }
+
     /// Specify boot nodes. By default there are no boot nodes.
     pub fn use_boot_nodes(mut self, boot_nodes: Vec<usize>) -> Self {
         self.apply_all(move |test_config| {
@@ -550,6 +623,14 @@ impl Runner {
         network_config.outbound_disabled = test_config.outbound_disabled;
         network_config.boot_nodes = boot_nodes;
         network_config.archive = test_config.archive;
+        network_config.sentries =
+            test_config.sentries.iter().map(|ix| peer_id_from_seed(&accounts_id[*ix])).collect();
+        network_config.private_peers = test_config
+            .private_peers
+            .iter()
+            .map(|ix| peer_id_from_seed(&accounts_id[*ix]))
+            .collect();
+        network_config.rate_limits = test_config.rate_limits.clone();
 
         network_config.ideal_connections_lo =
             test_config.ideal_connections.map_or(network_config.ideal_connections_lo, |(lo, _)| lo);
@@ -559,12 +640,15 @@ impl Runner {
             test_config.safe_set_size.unwrap_or(network_config.safe_set_size);
         network_config.minimum_outbound_peers =
             test_config.minimum_outbound_peers.unwrap_or(network_config.minimum_outbound_peers);
+        network_config.compression_threshold =
+            test_config.compression_threshold.unwrap_or(network_config.compression_threshold);
 
         setup_network_node(
             accounts_id[node_id].clone(),
             self.validators.clone().unwrap(),
             self.genesis_time.clone().unwrap(),
             network_config,
+            self.approvals[node_id].clone(),
         )
     }
 
@@ -594,7 +678,7 @@ impl Runner {
             .map(|(node_id, _)| self.setup_node(node_id))
             .collect();
 
-        RunningInfo { pm_addr, peers_info }
+        RunningInfo { pm_addr, peers_info, approvals: self.approvals.clone() }
     }
 }
 
@@ -753,6 +837,106 @@ pub fn check_direct_connection(node_id: usize, target_id: usize) -> ActionFn {
     )
 }
 
+/// Connect node `u` to node `v` through the address returned by `proxy`, which is given the
+/// address of `v`, e.g. to observe or delay the traffic between them.
+pub fn add_edge_through<F>(u: usize, v: usize, proxy: F) -> ActionFn
+where
+    F: FnOnce(SocketAddr) -> SocketAddr + 'static,
+{
+    let mut proxy = Some(proxy);
+    Box::new(
+        move |info: SharedRunningInfo,
+              flag: Arc<AtomicBool>,
+              _ctx: &mut Context<WaitOrTimeout>,
+              _runner| {
+            let proxy = match proxy.take() {
+                Some(proxy) => proxy,
+                None => return,
+            };
+            let addr = info.read().unwrap().pm_addr[u].clone();
+            let mut peer_info = info.read().unwrap().peers_info[v].clone();
+            peer_info.addr = Some(proxy(peer_info.addr.unwrap()));
+            actix::spawn(addr.send(OutboundTcpConnect { peer_info }).then(move |res| {
+                res.expect("Error adding edge");
+                flag.store(true, Ordering::Relaxed);
+                future::ready(())
+            }));
+        },
+    )
+}
+
+/// Check that `node_id` knows no address of `target_id`, neither among the peers it would share
+/// nor among its active peers. Panics otherwise, as it must never learn one.
+pub fn check_unknown_address(node_id: usize, target_id: usize) -> ActionFn {
+    Box::new(
+        move |info: SharedRunningInfo,
+              flag: Arc<AtomicBool>,
+              _ctx: &mut Context<WaitOrTimeout>,
+              _runner| {
+            let info = info.read().unwrap();
+            let target_peer_id = info.peers_info[target_id].id.clone();
+            let pm_addr = info.pm_addr[node_id].clone();
+
+            actix::spawn(async move {
+                let peers = pm_addr.send(PeersRequest {}).await.unwrap().peers;
+                let network_info = pm_addr.send(GetInfo {}).await.unwrap();
+                let known_peers = peers
+                    .into_iter()
+                    .chain(network_info.active_peers.into_iter().map(|peer| peer.peer_info));
+                for peer_info in known_peers.filter(|peer_info| peer_info.id == target_peer_id) {
+                    assert!(peer_info.addrs().next().is_none(), "Address leaked: {}", peer_info);
+                }
+                flag.store(true, Ordering::Relaxed);
+            });
+        },
+    )
+}
+
+/// Send an approval signed by validator `source` to validator `target`.
+pub fn send_approval(source: usize, target: usize) -> ActionFn {
+    Box::new(
+        move |info: SharedRunningInfo,
+              flag: Arc<AtomicBool>,
+              _ctx: &mut Context<WaitOrTimeout>,
+              _runner| {
+            let info = info.read().unwrap();
+            let source_account_id = info.peers_info[source].account_id.clone().unwrap();
+            let signer = InMemoryValidatorSigner::from_seed(
+                &source_account_id,
+                KeyType::ED25519,
+                &source_account_id,
+            );
+            let approval = Approval::new(CryptoHash::default(), 0, 1, &signer);
+            let target = info.peers_info[target].account_id.clone().unwrap();
+            info.pm_addr[source].do_send(NetworkRequests::Approval {
+                approval_message: ApprovalMessage::new(approval, target),
+            });
+            flag.store(true, Ordering::Relaxed);
+        },
+    )
+}
+
+/// Check that the client of `node_id` received an approval from validator `source`.
+pub fn check_approval(node_id: usize, source: usize) -> ActionFn {
+    Box::new(
+        move |info: SharedRunningInfo,
+              flag: Arc<AtomicBool>,
+              _ctx: &mut Context<WaitOrTimeout>,
+              _runner| {
+            let info = info.read().unwrap();
+            let source_account_id = info.peers_info[source].account_id.as_ref().unwrap();
+            if info.approvals[node_id]
+                .read()
+                .unwrap()
+                .iter()
+                .any(|approval| &approval.account_id == source_account_id)
+            {
+                flag.store(true, Ordering::Relaxed);
+            }
+        },
+    )
+}
+
 /// Restart a node that was already stopped.
 pub fn restart(node_id: usize) -> ActionFn {
     Box::new(
@@ -799,6 +983,35 @@ pub fn ban_peer(target_peer: usize, banned_peer: usize) -> ActionFn {
     )
 }
 
+/// Report the behaviour of `scored_peer` to `target_peer`, changing its score.
+pub fn update_peer_score(
+    target_peer: usize,
+    scored_peer: usize,
+    event: PeerScoreEvent,
+) -> ActionFn {
+    Box::new(
+        move |info: SharedRunningInfo,
+              flag: Arc<AtomicBool>,
+              _ctx: &mut Context<WaitOrTimeout>,
+              _runner| {
+            let info = info.read().unwrap();
+            let peer_id = info.peers_info[scored_peer].id.clone();
+            actix::spawn(
+                info.pm_addr
+                    .get(target_peer)
+                    .unwrap()
+                    .send(NetworkRequests::UpdatePeerScore { peer_id, event })
+                    .map_err(|_| ())
+                    .and_then(move |_| {
+                        flag.store(true, Ordering::Relaxed);
+                        future::ok(())
+                    })
+                    .map(drop),
+            );
+        },
+    )
+}
+
 /// Change account id from a stopped peer. Notice this will also change its peer id, since
 /// peer_id is derived from account id with NetworkConfig::from_seed
 pub fn change_account_id(node_id: usize, account_id: String) -> ActionFn {
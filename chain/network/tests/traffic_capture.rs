@@ -0,0 +1,195 @@
+use std::sync::{Arc, RwLock};
+
+use actix::actors::mocker::Mocker;
+use actix::{Actor, System};
+use borsh::BorshSerialize;
+
+use near_actix_test_utils::run_actix_until_stop;
+use near_client::{ClientActor, ViewClientActor};
+use near_logger_utils::init_test_logger;
+use near_network::capture::{replay, CaptureReader, CapturedMessage, Direction, TrafficCapture};
+#[cfg(feature = "traffic_capture")]
+use near_network::test_utils::{convert_boot_nodes, WaitOrTimeout};
+use near_network::test_utils::{open_port, peer_id_from_seed};
+use near_network::types::{NetworkViewClientMessages, NetworkViewClientResponses, PeerMessage};
+use near_network::{
+    NetworkClientMessages, NetworkClientResponses, NetworkConfig, PeerManagerActor,
+};
+use near_primitives::hash::hash;
+use near_primitives::transaction::SignedTransaction;
+use near_store::test_utils::create_test_store;
+
+type ClientMock = Mocker<ClientActor>;
+type ViewClientMock = Mocker<ViewClientActor>;
+
+fn transaction_message(seed: &[u8]) -> Vec<u8> {
+    PeerMessage::Transaction(SignedTransaction::empty(hash(seed))).try_to_vec().unwrap()
+}
+
+#[cfg(feature = "traffic_capture")]
+fn make_peer_manager(config: NetworkConfig) -> PeerManagerActor {
+    let client_addr = ClientMock::mock(Box::new(move |_msg, _ctx| {
+        Box::new(Some(NetworkClientResponses::NoResponse))
+    }))
+    .start();
+    let view_client_addr = ViewClientMock::mock(Box::new(move |msg, _ctx| {
+        let msg = msg.downcast_ref::<NetworkViewClientMessages>().unwrap();
+        match msg {
+            NetworkViewClientMessages::GetChainInfo => {
+                Box::new(Some(NetworkViewClientResponses::ChainInfo {
+                    genesis_id: Default::default(),
+                    height: 1,
+                    tracked_shards: vec![],
+                    archival: false,
+                }))
+            }
+            _ => Box::new(Some(NetworkViewClientResponses::NoResponse)),
+        }
+    }))
+    .start();
+    PeerManagerActor::new(
+        create_test_store(),
+        config,
+        client_addr.recipient(),
+        view_client_addr.recipient(),
+    )
+    .unwrap()
+}
+
+/// Messages exchanged with a connected peer are captured in both directions.
+#[cfg(feature = "traffic_capture")]
+#[test]
+fn test_capture_peer_traffic() {
+    init_test_logger();
+
+    let dir = tempfile::Builder::new().prefix("traffic_capture").tempdir().unwrap();
+    let path = dir.path().join("capture");
+    run_actix_until_stop(async move {
+        let (port1, port2) = (open_port(), open_port());
+        let mut config = NetworkConfig::from_seed("test1", port1);
+        config.boot_nodes = convert_boot_nodes(vec![("test2", port2)]);
+        config.traffic_capture_path = Some(path.clone());
+        config.traffic_capture_max_size = u64::max_value();
+        let _pm1 = make_peer_manager(config).start();
+        let _pm2 = make_peer_manager(NetworkConfig::from_seed("test2", port2)).start();
+        WaitOrTimeout::new(
+            Box::new(move |_| {
+                // The capture is flushed periodically, the last message may be cut off.
+                let directions = CaptureReader::open(&path)
+                    .map(|reader| {
+                        reader
+                            .filter_map(Result::ok)
+                            .filter(|captured| captured.peer_id == peer_id_from_seed("test2"))
+                            .map(|captured| captured.direction)
+                            .collect::<Vec<_>>()
+                    })
+                    .unwrap_or_default();
+                if directions.contains(&Direction::Inbound)
+                    && directions.contains(&Direction::Outbound)
+                {
+                    System::current().stop();
+                }
+            }),
+            100,
+            10000,
+        )
+        .start();
+    });
+}
+
+/// Messages which don't fit into the size limit of the capture are dropped.
+#[test]
+fn test_traffic_capture_size_limit() {
+    let dir = tempfile::Builder::new().prefix("traffic_capture").tempdir().unwrap();
+    let path = dir.path().join("capture");
+    let peer_id = peer_id_from_seed("test2");
+    let message = |seed: &[u8]| {
+        CapturedMessage::new(peer_id.clone(), Direction::Inbound, transaction_message(seed))
+    };
+    let mut capture = TrafficCapture::create(&path, peer_id_from_seed("test1"), 0).unwrap();
+    capture.write(&message(b"1"));
+    drop(capture);
+    let empty_size = std::fs::metadata(&path).unwrap().len();
+    assert_eq!(CaptureReader::open(&path).unwrap().count(), 0);
+
+    // Room for exactly two messages.
+    let frame_size = 4 + message(b"1").try_to_vec().unwrap().len() as u64;
+    let max_size = empty_size + 2 * frame_size;
+    let mut capture = TrafficCapture::create(&path, peer_id_from_seed("test1"), max_size).unwrap();
+    let captured = vec![message(b"1"), message(b"2"), message(b"3")];
+    for message in captured.iter() {
+        capture.write(message);
+    }
+    drop(capture);
+    assert_eq!(std::fs::metadata(&path).unwrap().len(), max_size);
+    assert_eq!(
+        CaptureReader::open(&path).unwrap().map(Result::unwrap).collect::<Vec<_>>(),
+        captured[..2].to_vec()
+    );
+}
+
+/// Only the messages received by the capturing node are replayed, in the captured order.
+#[test]
+fn test_replay_traffic_capture() {
+    init_test_logger();
+
+    let dir = tempfile::Builder::new().prefix("traffic_capture").tempdir().unwrap();
+    let path = dir.path().join("capture");
+    let peer_id = peer_id_from_seed("test2");
+    let mut capture =
+        TrafficCapture::create(&path, peer_id_from_seed("test1"), u64::max_value()).unwrap();
+    let captured = vec![
+        CapturedMessage::new(peer_id.clone(), Direction::Inbound, transaction_message(b"1")),
+        CapturedMessage::new(peer_id.clone(), Direction::Outbound, transaction_message(b"2")),
+        CapturedMessage::new(peer_id.clone(), Direction::Inbound, vec![255]),
+        CapturedMessage::new(peer_id, Direction::Inbound, transaction_message(b"3")),
+    ];
+    for message in captured.iter() {
+        capture.write(message);
+    }
+    drop(capture);
+
+    let reader = CaptureReader::open(&path).unwrap();
+    assert_eq!(reader.header().peer_id, peer_id_from_seed("test1"));
+    assert_eq!(reader.map(Result::unwrap).collect::<Vec<_>>(), captured);
+
+    run_actix_until_stop(async move {
+        let received = Arc::new(RwLock::new(vec![]));
+        let received1 = received.clone();
+        let client_addr = ClientMock::mock(Box::new(move |msg, _ctx| {
+            if let Some(NetworkClientMessages::Transaction { transaction, .. }) =
+                msg.downcast_ref::<NetworkClientMessages>()
+            {
+                received1.write().unwrap().push(transaction.transaction.block_hash);
+            }
+            Box::new(Some(NetworkClientResponses::NoResponse))
+        }))
+        .start();
+        let view_client_addr = ViewClientMock::mock(Box::new(move |_msg, _ctx| {
+            Box::new(Some(NetworkViewClientResponses::NoResponse))
+        }))
+        .start();
+        let peer_manager_addr = PeerManagerActor::new(
+            create_test_store(),
+            NetworkConfig::from_seed("test1", open_port()),
+            client_addr.clone().recipient(),
+            view_client_addr.clone().recipient(),
+        )
+        .unwrap()
+        .start();
+
+        actix::spawn(async move {
+            replay(
+                CaptureReader::open(&path).unwrap(),
+                peer_manager_addr,
+                client_addr.recipient(),
+                view_client_addr.recipient(),
+                false,
+            )
+            .await
+            .unwrap();
+            assert_eq!(*received.read().unwrap(), vec![hash(b"1"), hash(b"3")]);
+            System::current().stop();
+        });
+    });
+}
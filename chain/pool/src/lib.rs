@@ -1,4 +1,9 @@
-use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+#[macro_use]
+extern crate lazy_static;
+
+use std::collections::{
+    hash_map::Entry, BTreeMap, BTreeSet, BinaryHeap, HashMap, HashSet, VecDeque,
+};
 
 use crate::types::{PoolIterator, PoolKey, TransactionGroup};
 use borsh::BorshSerialize;
@@ -9,8 +14,35 @@ use near_primitives::types::AccountId;
 use rand::RngCore;
 use std::ops::Bound;
 
+mod metrics;
 pub mod types;
 
+/// Limits on the transaction pool of a single shard. The pool is unbounded by default.
+#[derive(Clone, Debug, Default)]
+pub struct PoolConfig {
+    /// Maximum total size of the Borsh encoded transactions in the pool.
+    pub max_size_bytes: Option<u64>,
+    /// Maximum number of transactions signed by the same account, across all its access keys.
+    pub max_transactions_per_signer: Option<usize>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum InsertTransactionResult {
+    /// Transaction was inserted, possibly evicting other transactions.
+    Success,
+    /// Transaction is already in the pool.
+    Duplicate,
+    /// Transaction doesn't fit into the pool limits.
+    NoSpaceLeft,
+}
+
+/// Accounting of a transaction in the pool, used to enforce the limits.
+struct PoolEntry {
+    key: PoolKey,
+    signer_id: AccountId,
+    size: u64,
+}
+
 /// Transaction pool: keeps track of transactions that were not yet accepted into the block chain.
 pub struct TransactionPool {
     /// Transactions are grouped by a pair of (account ID, signer public key).
@@ -23,15 +55,32 @@ pub struct TransactionPool {
     key_seed: Vec<u8>,
     /// The key after which the pool iterator starts. Doesn't have to be present in the pool.
     last_used_key: PoolKey,
+    config: PoolConfig,
+    /// Accounting of every transaction in the pool, by hash.
+    entries: HashMap<CryptoHash, PoolEntry>,
+    /// Number of transactions in the pool by signer.
+    signer_counts: HashMap<AccountId, usize>,
+    /// Number of transactions in the pool by group.
+    group_lens: HashMap<PoolKey, usize>,
+    /// Groups ordered by their number of transactions, to find the largest ones quickly.
+    groups_by_len: BTreeSet<(usize, PoolKey)>,
+    /// Total size of the transactions in the pool.
+    total_size: u64,
 }
 
 impl TransactionPool {
-    pub fn new() -> Self {
+    pub fn new(config: PoolConfig) -> Self {
         Self {
             key_seed: rand::thread_rng().next_u64().to_le_bytes().to_vec(),
             transactions: BTreeMap::new(),
             unique_transactions: HashSet::new(),
             last_used_key: CryptoHash::default(),
+            config,
+            entries: HashMap::new(),
+            signer_counts: HashMap::new(),
+            group_lens: HashMap::new(),
+            groups_by_len: BTreeSet::new(),
+            total_size: 0,
         }
     }
 
@@ -43,19 +92,157 @@ impl TransactionPool {
     }
 
     /// Insert a signed transaction into the pool that passed validation.
-    pub fn insert_transaction(&mut self, signed_transaction: SignedTransaction) -> bool {
-        if !self.unique_transactions.insert(signed_transaction.get_hash()) {
-            return false;
+    pub fn insert_transaction(
+        &mut self,
+        signed_transaction: SignedTransaction,
+    ) -> InsertTransactionResult {
+        let tx_hash = signed_transaction.get_hash();
+        if self.unique_transactions.contains(&tx_hash) {
+            return InsertTransactionResult::Duplicate;
         }
         let signer_id = &signed_transaction.transaction.signer_id;
         let signer_public_key = &signed_transaction.transaction.public_key;
-        self.transactions
-            .entry(self.key(signer_id, signer_public_key))
-            .or_insert_with(Vec::new)
-            .push(signed_transaction);
+        let key = self.key(signer_id, signer_public_key);
+        let size = signed_transaction.try_to_vec().unwrap().len() as u64;
+        if !self.make_space(&key, &signed_transaction, size) {
+            near_metrics::inc_counter(&metrics::TRANSACTION_POOL_REJECTED_TOTAL);
+            return InsertTransactionResult::NoSpaceLeft;
+        }
+
+        self.unique_transactions.insert(tx_hash);
+        self.entries.insert(tx_hash, PoolEntry { key, signer_id: signer_id.clone(), size });
+        *self.signer_counts.entry(signer_id.clone()).or_insert(0) += 1;
+        self.set_group_len(key, self.group_len(&key) + 1);
+        self.total_size += size;
+        near_metrics::inc_gauge(&metrics::TRANSACTION_POOL_TRANSACTIONS);
+        if let Ok(gauge) = &*metrics::TRANSACTION_POOL_SIZE_BYTES {
+            gauge.add(size as i64);
+        }
+
+        self.transactions.entry(key).or_insert_with(Vec::new).push(signed_transaction);
+        InsertTransactionResult::Success
+    }
+
+    /// Evicts transactions for the new one to fit into the limits. Returns false if it can't fit,
+    /// and then nothing is evicted.
+    ///
+    /// Transactions of the incoming transaction's group are never evicted, and a signer at its
+    /// limit is rejected. Otherwise the space is taken from the largest other group, as long as it
+    /// stays at least as large as the incoming group. The evicted transaction is always the one
+    /// with the highest nonce in its group, as it would be executed last, so evictions never leave
+    /// a nonce gap in the pool.
+    fn make_space(&mut self, key: &PoolKey, transaction: &SignedTransaction, size: u64) -> bool {
+        let signer_id = &transaction.transaction.signer_id;
+        if let Some(max_transactions) = self.config.max_transactions_per_signer {
+            if self.signer_counts.get(signer_id).map_or(false, |count| *count >= max_transactions) {
+                return false;
+            }
+        }
+        if let Some(max_size) = self.config.max_size_bytes {
+            match self.transactions_to_evict(key, size, max_size) {
+                Some(tx_hashes) => {
+                    for tx_hash in tx_hashes {
+                        self.evict(&tx_hash);
+                    }
+                }
+                None => return false,
+            }
+        }
         true
     }
 
+    /// Returns the transactions to evict for the new one of given size to fit into `max_size`,
+    /// or `None` if it can't fit.
+    fn transactions_to_evict(
+        &self,
+        key: &PoolKey,
+        size: u64,
+        max_size: u64,
+    ) -> Option<Vec<CryptoHash>> {
+        if size > max_size {
+            return None;
+        }
+        let needed = (self.total_size + size).saturating_sub(max_size);
+        let incoming_group_len = self.group_len(key) + 1;
+        let mut freed = 0;
+        let mut tx_hashes = vec![];
+        // Groups with planned evictions, by their length after them, and their remaining
+        // transactions from the lowest nonce.
+        let mut touched_groups = BinaryHeap::new();
+        let mut remaining: HashMap<PoolKey, Vec<CryptoHash>> = HashMap::new();
+        let mut untouched_groups =
+            self.groups_by_len.iter().rev().filter(|(_, k)| k != key).peekable();
+        while freed < needed {
+            let touched_len = touched_groups.peek().map(|(len, _)| *len);
+            let untouched_len = untouched_groups.peek().map(|(len, _)| *len);
+            let (len, group_key) = if untouched_len > touched_len {
+                let (len, group_key) = untouched_groups.next()?;
+                let mut group = self.transactions.get(group_key)?.iter().collect::<Vec<_>>();
+                group.sort_by_key(|tx| tx.transaction.nonce);
+                remaining.insert(*group_key, group.iter().map(|tx| tx.get_hash()).collect());
+                (*len, *group_key)
+            } else {
+                touched_groups.pop()?
+            };
+            if len <= incoming_group_len {
+                return None;
+            }
+            let tx_hash = remaining.get_mut(&group_key)?.pop()?;
+            freed += self.entries.get(&tx_hash)?.size;
+            tx_hashes.push(tx_hash);
+            touched_groups.push((len - 1, group_key));
+        }
+        Some(tx_hashes)
+    }
+
+    fn group_len(&self, key: &PoolKey) -> usize {
+        self.group_lens.get(key).copied().unwrap_or(0)
+    }
+
+    fn set_group_len(&mut self, key: PoolKey, len: usize) {
+        if let Some(old_len) = self.group_lens.remove(&key) {
+            self.groups_by_len.remove(&(old_len, key));
+        }
+        if len > 0 {
+            self.group_lens.insert(key, len);
+            self.groups_by_len.insert((len, key));
+        }
+    }
+
+    fn evict(&mut self, tx_hash: &CryptoHash) {
+        if let Some(key) = self.entries.get(tx_hash).map(|entry| entry.key) {
+            let mut remove_entry = false;
+            if let Some(v) = self.transactions.get_mut(&key) {
+                v.retain(|tx| &tx.get_hash() != tx_hash);
+                remove_entry = v.is_empty();
+            }
+            if remove_entry {
+                self.transactions.remove(&key);
+            }
+        }
+        self.forget_transaction(tx_hash);
+        near_metrics::inc_counter(&metrics::TRANSACTION_POOL_EVICTED_TOTAL);
+    }
+
+    /// Removes the transaction, which is no longer in any group, from the pool accounting.
+    fn forget_transaction(&mut self, tx_hash: &CryptoHash) {
+        self.unique_transactions.remove(tx_hash);
+        if let Some(entry) = self.entries.remove(tx_hash) {
+            self.total_size -= entry.size;
+            self.set_group_len(entry.key, self.group_len(&entry.key) - 1);
+            if let Entry::Occupied(mut count) = self.signer_counts.entry(entry.signer_id) {
+                *count.get_mut() -= 1;
+                if *count.get() == 0 {
+                    count.remove();
+                }
+            }
+            near_metrics::dec_gauge(&metrics::TRANSACTION_POOL_TRANSACTIONS);
+            if let Ok(gauge) = &*metrics::TRANSACTION_POOL_SIZE_BYTES {
+                gauge.sub(entry.size as i64);
+            }
+        }
+    }
+
     /// Returns a pool iterator wrapper that implements an iterator like trait to iterate over
     /// transaction groups in the proper order defined by the protocol.
     /// When the iterator is dropped, all remaining groups are inserted back into the pool.
@@ -87,7 +274,7 @@ impl TransactionPool {
                 self.transactions.remove(&key);
             }
             for hash in hashes {
-                self.unique_transactions.remove(&hash);
+                self.forget_transaction(&hash);
             }
         }
     }
@@ -133,14 +320,14 @@ impl<'a> PoolIteratorWrapper<'a> {
 /// If the pool is empty, the iterator gets the group from the front of the sorted groups queue.
 ///
 /// If this group is empty (no transactions left inside), then the iterator discards it and
-/// updates `unique_transactions` in the pool. Then gets the next one.
+/// removes the pulled transactions from the pool accounting. Then gets the next one.
 ///
 /// Once a non-empty group is found, this group is pushed to the back of the sorted groups queue
 /// and the iterator returns a mutable reference to this group.
 ///
 /// If the sorted groups queue is empty, the iterator returns None.
 ///
-/// When the iterator is dropped, `unique_transactions` in the pool is updated for every group.
+/// When the iterator is dropped, the pool accounting is updated for every group.
 /// And all non-empty group from the sorted groups queue are inserted back into the pool.
 impl<'a> PoolIterator for PoolIteratorWrapper<'a> {
     fn next(&mut self) -> Option<&mut TransactionGroup> {
@@ -172,7 +359,7 @@ impl<'a> PoolIterator for PoolIteratorWrapper<'a> {
             while let Some(sorted_group) = self.sorted_groups.pop_front() {
                 if sorted_group.transactions.is_empty() {
                     for hash in sorted_group.removed_transaction_hashes {
-                        self.pool.unique_transactions.remove(&hash);
+                        self.pool.forget_transaction(&hash);
                     }
                 } else {
                     self.sorted_groups.push_back(sorted_group);
@@ -186,12 +373,12 @@ impl<'a> PoolIterator for PoolIteratorWrapper<'a> {
 
 /// When a pool iterator is dropped, all remaining non empty transaction groups from the sorted
 /// groups queue are inserted back into the pool. And removed transactions hashes from groups are
-/// removed from the pool's unique_transactions.
+/// removed from the pool accounting.
 impl<'a> Drop for PoolIteratorWrapper<'a> {
     fn drop(&mut self) {
         for group in self.sorted_groups.drain(..) {
             for hash in group.removed_transaction_hashes {
-                self.pool.unique_transactions.remove(&hash);
+                self.pool.forget_transaction(&hash);
             }
             if !group.transactions.is_empty() {
                 self.pool.transactions.insert(group.key, group.transactions);
@@ -239,7 +426,7 @@ mod tests {
         mut transactions: Vec<SignedTransaction>,
         expected_weight: u32,
     ) -> (Vec<u64>, TransactionPool) {
-        let mut pool = TransactionPool::new();
+        let mut pool = TransactionPool::new(PoolConfig::default());
         let mut rng = thread_rng();
         transactions.shuffle(&mut rng);
         for tx in transactions {
@@ -350,7 +537,7 @@ mod tests {
             })
             .collect::<Vec<_>>();
 
-        let mut pool = TransactionPool::new();
+        let mut pool = TransactionPool::new(PoolConfig::default());
         let mut rng = thread_rng();
         transactions.shuffle(&mut rng);
         for tx in transactions.clone() {
@@ -450,4 +637,188 @@ mod tests {
         new_nonces.sort();
         assert_ne!(nonces, new_nonces);
     }
+
+    fn pool_nonces(pool: &TransactionPool) -> Vec<u64> {
+        let mut nonces: Vec<_> =
+            pool.transactions.values().flatten().map(|tx| tx.transaction.nonce).collect();
+        nonces.sort();
+        nonces
+    }
+
+    /// The pool evicts the highest nonce transaction of the largest other group.
+    #[test]
+    fn test_pool_size_limit() {
+        let transactions = generate_transactions("alice.near", "alice.near", 1, 3);
+        let size = transactions[0].try_to_vec().unwrap().len() as u64;
+        let mut pool = TransactionPool::new(PoolConfig {
+            max_size_bytes: Some(3 * size),
+            max_transactions_per_signer: None,
+        });
+        for tx in transactions {
+            assert_eq!(pool.insert_transaction(tx), InsertTransactionResult::Success);
+        }
+        let bob_tx = generate_transactions("bob.near", "bob.near", 11, 11).pop().unwrap();
+        assert_eq!(pool.insert_transaction(bob_tx.clone()), InsertTransactionResult::Success);
+        assert_eq!(pool.insert_transaction(bob_tx), InsertTransactionResult::Duplicate);
+        assert_eq!(pool.len(), 3);
+        assert_eq!(pool_nonces(&pool), vec![1, 2, 11]);
+
+        let txs = prepare_transactions(&mut pool, 3);
+        assert_eq!(txs.len(), 3);
+        assert!(pool.is_empty());
+        assert_eq!(pool.total_size, 0);
+        assert!(pool.entries.is_empty() && pool.signer_counts.is_empty());
+        assert!(pool.group_lens.is_empty() && pool.groups_by_len.is_empty());
+    }
+
+    /// Transactions of the incoming transaction's group are never evicted, so a full pool can't
+    /// get a nonce gap in that group.
+    #[test]
+    fn test_pool_size_limit_no_gap() {
+        let transactions = generate_transactions("alice.near", "alice.near", 1, 4);
+        let size = transactions[0].try_to_vec().unwrap().len() as u64;
+        let mut pool = TransactionPool::new(PoolConfig {
+            max_size_bytes: Some(3 * size),
+            max_transactions_per_signer: None,
+        });
+        for tx in transactions[..3].iter() {
+            assert_eq!(pool.insert_transaction(tx.clone()), InsertTransactionResult::Success);
+        }
+        assert_eq!(
+            pool.insert_transaction(transactions[3].clone()),
+            InsertTransactionResult::NoSpaceLeft
+        );
+        assert_eq!(pool_nonces(&pool), vec![1, 2, 3]);
+    }
+
+    /// The space is taken from the largest group rather than from the group holding the oldest
+    /// transaction, and only while that group stays at least as large as the incoming one.
+    #[test]
+    fn test_pool_size_limit_evicts_largest_group() {
+        let alice_txs = generate_transactions("alice.near", "alice.near", 1, 2);
+        let carol_txs = generate_transactions("carol.near", "carol.near", 11, 13);
+        let david_txs = generate_transactions("david.near", "david.near", 21, 22);
+        let size = alice_txs[0].try_to_vec().unwrap().len() as u64;
+        let mut pool = TransactionPool::new(PoolConfig {
+            max_size_bytes: Some(5 * size),
+            max_transactions_per_signer: None,
+        });
+        for tx in alice_txs.into_iter().chain(carol_txs.into_iter()) {
+            assert_eq!(pool.insert_transaction(tx), InsertTransactionResult::Success);
+        }
+        assert_eq!(pool.insert_transaction(david_txs[0].clone()), InsertTransactionResult::Success);
+        assert_eq!(pool_nonces(&pool), vec![1, 2, 11, 12, 21]);
+        assert_eq!(
+            pool.insert_transaction(david_txs[1].clone()),
+            InsertTransactionResult::NoSpaceLeft
+        );
+        assert_eq!(pool_nonces(&pool), vec![1, 2, 11, 12, 21]);
+    }
+
+    /// A transaction which needs more space than the groups can give while staying at least as
+    /// large as its group is rejected without evicting anything.
+    #[test]
+    fn test_pool_size_limit_different_sizes() {
+        let alice_txs = generate_transactions("alice.near", "alice.near", 1, 3);
+        let carol_txs = generate_transactions("carol.near", "carol.near", 11, 12);
+        let size = alice_txs[0].try_to_vec().unwrap().len() as u64;
+        let signer = InMemorySigner::from_seed("carol.near", KeyType::ED25519, "carol.near");
+        // Needs the space of two transactions of alice
+        let large_tx = SignedTransaction::call(
+            12,
+            "carol.near".to_string(),
+            "bob.near".to_string(),
+            &signer,
+            0,
+            "method".to_string(),
+            vec![0; size as usize / 2],
+            100,
+            CryptoHash::default(),
+        );
+        let large_size = large_tx.try_to_vec().unwrap().len() as u64;
+        assert!(large_size > size && large_size <= 2 * size);
+
+        let mut pool = TransactionPool::new(PoolConfig {
+            max_size_bytes: Some(4 * size),
+            max_transactions_per_signer: None,
+        });
+        for tx in alice_txs.into_iter().chain(carol_txs[..1].iter().cloned()) {
+            assert_eq!(pool.insert_transaction(tx), InsertTransactionResult::Success);
+        }
+        assert_eq!(pool.total_size, 4 * size);
+        // Alice's group would get smaller than carol's after the first eviction
+        assert_eq!(pool.insert_transaction(large_tx), InsertTransactionResult::NoSpaceLeft);
+        assert_eq!(pool_nonces(&pool), vec![1, 2, 3, 11]);
+        assert_eq!(pool.total_size, 4 * size);
+
+        // A transaction of the usual size only needs one eviction
+        assert_eq!(pool.insert_transaction(carol_txs[1].clone()), InsertTransactionResult::Success);
+        assert_eq!(pool_nonces(&pool), vec![1, 2, 11, 12]);
+    }
+
+    /// Evictions are spread over the largest groups, so they are left equally large.
+    #[test]
+    fn test_pool_size_limit_evicts_from_several_groups() {
+        let alice_txs = generate_transactions("alice.near", "alice.near", 1, 3);
+        let carol_txs = generate_transactions("carol.near", "carol.near", 11, 13);
+        let david_txs = generate_transactions("david.near", "david.near", 21, 21);
+        let size = alice_txs[0].try_to_vec().unwrap().len() as u64;
+        let signer = InMemorySigner::from_seed("bob.near", KeyType::ED25519, "bob.near");
+        let large_tx = SignedTransaction::call(
+            1,
+            "bob.near".to_string(),
+            "carol.near".to_string(),
+            &signer,
+            0,
+            "method".to_string(),
+            vec![0; 2 * size as usize],
+            100,
+            CryptoHash::default(),
+        );
+        let large_size = large_tx.try_to_vec().unwrap().len() as u64;
+        assert!(large_size > 2 * size && large_size <= 3 * size);
+
+        let mut pool = TransactionPool::new(PoolConfig {
+            max_size_bytes: Some(7 * size),
+            max_transactions_per_signer: None,
+        });
+        for tx in alice_txs.into_iter().chain(carol_txs.into_iter()).chain(david_txs.into_iter()) {
+            assert_eq!(pool.insert_transaction(tx), InsertTransactionResult::Success);
+        }
+        assert_eq!(pool.insert_transaction(large_tx), InsertTransactionResult::Success);
+        let mut group_lens: Vec<_> = pool.group_lens.values().copied().collect();
+        group_lens.sort();
+        assert_eq!(group_lens, vec![1, 1, 1, 2]);
+        assert_eq!(pool.len(), 5);
+        assert!(pool.total_size <= 7 * size);
+    }
+
+    /// A signer at its limit is rejected, its own transactions are never evicted.
+    #[test]
+    fn test_pool_signer_limit() {
+        let transactions = generate_transactions("alice.near", "alice.near", 1, 3);
+        let mut pool = TransactionPool::new(PoolConfig {
+            max_size_bytes: None,
+            max_transactions_per_signer: Some(2),
+        });
+        for tx in transactions[..2].iter() {
+            assert_eq!(pool.insert_transaction(tx.clone()), InsertTransactionResult::Success);
+        }
+        assert_eq!(
+            pool.insert_transaction(transactions[2].clone()),
+            InsertTransactionResult::NoSpaceLeft
+        );
+        assert_eq!(pool_nonces(&pool), vec![1, 2]);
+
+        // Other signers are not affected.
+        for tx in generate_transactions("bob.near", "bob.near", 1, 2) {
+            assert_eq!(pool.insert_transaction(tx), InsertTransactionResult::Success);
+        }
+        pool.remove_transactions(&transactions[..1]);
+        assert_eq!(
+            pool.insert_transaction(transactions[2].clone()),
+            InsertTransactionResult::Success
+        );
+        assert_eq!(pool_nonces(&pool), vec![1, 2, 2, 3]);
+    }
 }
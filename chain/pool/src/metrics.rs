@@ -0,0 +1,24 @@
+use near_metrics::{try_create_int_counter, try_create_int_gauge, IntCounter, IntGauge};
+
+lazy_static! {
+    pub static ref TRANSACTION_POOL_TRANSACTIONS: near_metrics::Result<IntGauge> =
+        try_create_int_gauge(
+            "near_transaction_pool_transactions",
+            "Number of transactions in the transaction pools of all shards"
+        );
+    pub static ref TRANSACTION_POOL_SIZE_BYTES: near_metrics::Result<IntGauge> =
+        try_create_int_gauge(
+            "near_transaction_pool_size_bytes",
+            "Total size of the transactions in the transaction pools of all shards"
+        );
+    pub static ref TRANSACTION_POOL_EVICTED_TOTAL: near_metrics::Result<IntCounter> =
+        try_create_int_counter(
+            "near_transaction_pool_evicted_total",
+            "Number of transactions evicted from the transaction pool to make space for new ones"
+        );
+    pub static ref TRANSACTION_POOL_REJECTED_TOTAL: near_metrics::Result<IntCounter> =
+        try_create_int_counter(
+            "near_transaction_pool_rejected_total",
+            "Number of transactions which didn't fit into the transaction pool"
+        );
+}
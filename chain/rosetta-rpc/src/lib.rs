@@ -1,4 +1,4 @@
-use std::convert::{AsRef, TryInto};
+use std::convert::{AsRef, TryFrom, TryInto};
 use std::sync::Arc;
 
 use actix::Addr;
@@ -26,6 +26,8 @@ mod utils;
 
 pub const BASE_PATH: &str = "";
 pub const API_VERSION: &str = "1.4.4";
+/// Maximum number of transaction identifiers returned by /mempool.
+const MEMPOOL_TRANSACTIONS_LIMIT: usize = 1000;
 
 /// Get List of Available Networks
 ///
@@ -413,20 +415,54 @@ async fn account_balance(
 }
 
 #[api_v2_operation]
-/// Get All Mempool Transactions (not implemented)
+/// Get All Mempool Transactions
 ///
 /// Get all Transaction Identifiers in the mempool
 ///
-/// NOTE: The mempool is short-lived, so it is currently not implemented.
+/// NOTE: Only the transactions in the pools of the shards tracked by this node are returned, as of
+/// the last snapshot of the pools, and at most `MEMPOOL_TRANSACTIONS_LIMIT` of them.
 async fn mempool(
-    _client_addr: web::Data<Addr<ClientActor>>,
-    _body: Json<models::NetworkRequest>,
+    client_addr: web::Data<Addr<ClientActor>>,
+    view_client_addr: web::Data<Addr<ViewClientActor>>,
+    body: Json<models::NetworkRequest>,
 ) -> Result<Json<models::MempoolResponse>, models::Error> {
-    Ok(Json(models::MempoolResponse { transaction_identifiers: vec![] }))
+    let Json(models::NetworkRequest { network_identifier }) = body;
+
+    // TODO: reduce copy-paste
+    let status = client_addr
+        .send(near_client::Status { is_health_check: false })
+        .await?
+        .map_err(errors::ErrorKind::InternalError)?;
+    if status.chain_id != network_identifier.network {
+        return Err(models::Error {
+            code: 2,
+            message: "Wrong network (chain id)".to_string(),
+            retriable: true,
+        });
+    }
+
+    let pending_transactions = view_client_addr
+        .send(near_client::GetPendingTransactions {
+            signer_id: None,
+            transaction_hash: None,
+            after: None,
+            limit: MEMPOOL_TRANSACTIONS_LIMIT,
+        })
+        .await?
+        .map_err(errors::ErrorKind::InternalError)?;
+
+    Ok(Json(models::MempoolResponse {
+        transaction_identifiers: pending_transactions
+            .into_iter()
+            .map(|pending_transaction| models::TransactionIdentifier {
+                hash: pending_transaction.transaction.hash.to_base(),
+            })
+            .collect(),
+    }))
 }
 
 #[api_v2_operation]
-/// Get a Mempool Transaction (not implemented)
+/// Get a Mempool Transaction
 ///
 /// Get a transaction in the mempool by its Transaction Identifier. This is a
 /// separate request than fetching a block transaction (/block/transaction)
@@ -437,13 +473,67 @@ async fn mempool(
 /// endpoint, it is ok that returned transactions are only estimates of what may
 /// actually be included in a block.
 ///
-/// NOTE: The mempool is short-lived, so this method does not make a lot of
-/// sense to be implemented.
+/// NOTE: The operations are derived from the transaction actions, the same way
+/// /construction/parse does, so they don't include the fees.
 async fn mempool_transaction(
-    _client_addr: web::Data<Addr<ClientActor>>,
-    _body: Json<models::MempoolTransactionRequest>,
+    client_addr: web::Data<Addr<ClientActor>>,
+    view_client_addr: web::Data<Addr<ViewClientActor>>,
+    body: Json<models::MempoolTransactionRequest>,
 ) -> Result<Json<models::MempoolTransactionResponse>, models::Error> {
-    Err(errors::ErrorKind::InternalError("Not implemented yet".to_string()).into())
+    let Json(models::MempoolTransactionRequest { network_identifier, transaction_identifier }) =
+        body;
+
+    // TODO: reduce copy-paste
+    let status = client_addr
+        .send(near_client::Status { is_health_check: false })
+        .await?
+        .map_err(errors::ErrorKind::InternalError)?;
+    if status.chain_id != network_identifier.network {
+        return Err(models::Error {
+            code: 2,
+            message: "Wrong network (chain id)".to_string(),
+            retriable: true,
+        });
+    }
+
+    let transaction_hash = near_primitives::hash::CryptoHash::try_from(
+        transaction_identifier.hash.as_str(),
+    )
+    .map_err(|err| {
+        errors::ErrorKind::InvalidInput(format!("Could not parse transaction hash: {}", err))
+    })?;
+    let transaction = view_client_addr
+        .send(near_client::GetPendingTransactions {
+            signer_id: None,
+            transaction_hash: Some(transaction_hash),
+            after: None,
+            limit: 1,
+        })
+        .await?
+        .map_err(errors::ErrorKind::InternalError)?
+        .pop()
+        .ok_or_else(|| errors::ErrorKind::NotFound("Transaction not found".into()))?
+        .transaction;
+
+    let actions = transaction
+        .actions
+        .into_iter()
+        .map(near_primitives::transaction::Action::try_from)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|err| errors::ErrorKind::InternalInvariantError(err.to_string()))?;
+    let near_actions = crate::adapters::NearActions {
+        sender_account_id: transaction.signer_id,
+        receiver_account_id: transaction.receiver_id,
+        actions,
+    };
+
+    Ok(Json(models::MempoolTransactionResponse {
+        transaction: models::Transaction {
+            transaction_identifier,
+            operations: near_actions.into(),
+            metadata: models::TransactionMetadata { type_: models::TransactionType::Transaction },
+        },
+    }))
 }
 
 #[api_v2_operation]
@@ -860,6 +950,10 @@ async fn construction_submit(
         near_network::NetworkClientResponses::InvalidTx(error) => {
             Err(errors::ErrorKind::InvalidInput(error.to_string()).into())
         }
+        near_network::NetworkClientResponses::TxPoolFull => Err(errors::ErrorKind::InternalError(
+            "Transaction pool is full, try again later".to_string(),
+        )
+        .into()),
         _ => Err(errors::ErrorKind::InternalInvariantError(format!(
             "Transaction submition return unexpected result: {:?}",
             transaction_submittion
@@ -89,12 +89,21 @@ pub struct ClientConfig {
     pub tracked_shards: Vec<ShardId>,
     /// Not clear old data, set `true` for archive nodes.
     pub archive: bool,
+    /// Index transactions and receipts by account, only used by archive nodes.
+    pub account_history_index: bool,
     /// Number of threads for ViewClientActor pool.
     pub view_client_threads: usize,
     /// Run Epoch Sync on the start.
     pub epoch_sync_enabled: bool,
     /// Number of seconds between state requests for view client.
     pub view_client_throttle_period: Duration,
+    /// Maximum number of bytes of state responses served to a single peer
+    /// per `view_client_throttle_period`.
+    pub state_request_peer_quota_bytes: u64,
+    /// Maximum total size in bytes of the transactions in the pool of each shard.
+    pub transaction_pool_size_limit: Option<u64>,
+    /// Maximum number of transactions of a single signer in the pool of each shard.
+    pub transaction_pool_signer_limit: Option<usize>,
 }
 
 impl ClientConfig {
@@ -147,10 +156,14 @@ impl ClientConfig {
             tracked_accounts: vec![],
             tracked_shards: vec![],
             archive,
+            account_history_index: false,
             log_summary_style: LogSummaryStyle::Colored,
             view_client_threads: 1,
             epoch_sync_enabled,
             view_client_throttle_period: Duration::from_secs(1),
+            state_request_peer_quota_bytes: 10 * 1024 * 1024,
+            transaction_pool_size_limit: None,
+            transaction_pool_signer_limit: None,
         }
     }
 }
@@ -1,5 +1,6 @@
 use crate::config::{ActionCosts, ExtCosts};
 use crate::types::Gas;
+use borsh::{BorshDeserialize, BorshSerialize};
 use num_rational::Ratio;
 use std::{cell::RefCell, fmt, rc::Rc};
 
@@ -133,6 +134,56 @@ impl ProfileData {
     pub fn set_burnt_gas(&self, burnt_gas: u64) {
         self.with_data(|data| data[0] = burnt_gas);
     }
+
+    /// Whether the costs are counted, i.e. the profile is enabled and `costs_counting` is on
+    pub fn is_enabled(&self) -> bool {
+        match &self.repr {
+            #[cfg(feature = "costs_counting")]
+            Repr::Enabled { .. } => true,
+            Repr::Disabled => false,
+        }
+    }
+
+    /// Snapshot of the external and action costs counted so far
+    pub fn gas_profile(&self) -> GasProfile {
+        GasProfile {
+            ext_costs: (0..ExtCosts::count()).map(|e| self.get_ext_cost(e)).collect(),
+            action_costs: (0..ActionCosts::count()).map(|e| self.get_action_cost(e)).collect(),
+        }
+    }
+}
+
+/// Gas spent on each external and action cost, indexed by `ExtCosts` and `ActionCosts`.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct GasProfile {
+    pub ext_costs: Vec<Gas>,
+    pub action_costs: Vec<Gas>,
+}
+
+impl GasProfile {
+    /// Gas spent after the `earlier` snapshot of the same profile was taken
+    pub fn since(&self, earlier: &GasProfile) -> GasProfile {
+        fn diff(later: &[Gas], earlier: &[Gas]) -> Vec<Gas> {
+            later
+                .iter()
+                .enumerate()
+                .map(|(i, gas)| gas.saturating_sub(earlier.get(i).copied().unwrap_or_default()))
+                .collect()
+        }
+        GasProfile {
+            ext_costs: diff(&self.ext_costs, &earlier.ext_costs),
+            action_costs: diff(&self.action_costs, &earlier.action_costs),
+        }
+    }
+}
+
+/// Gas profile of a single action of a receipt
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct ActionGasProfile {
+    /// All the gas burnt by the action, including its exec fee
+    pub gas_burnt: Gas,
+    /// Costs counted while executing the action, which only function calls have
+    pub profile: GasProfile,
 }
 
 impl fmt::Debug for ProfileData {
@@ -202,3 +253,18 @@ impl fmt::Debug for ProfileData {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gas_profile_since() {
+        let earlier = GasProfile { ext_costs: vec![1, 2], action_costs: vec![3] };
+        let later = GasProfile { ext_costs: vec![1, 5], action_costs: vec![10] };
+        assert_eq!(
+            later.since(&earlier),
+            GasProfile { ext_costs: vec![0, 3], action_costs: vec![7] }
+        );
+    }
+}
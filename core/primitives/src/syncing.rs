@@ -149,10 +149,28 @@ pub struct ShardStateSyncResponseV2 {
     pub part: Option<(u64, Vec<u8>)>,
 }
 
+/// A piece of a state part small enough to be sent in a single message.
+/// Chunks of a part are ordered so that each trie node is preceded by the node referencing it,
+/// which lets the receiver verify and apply them one by one.
+#[derive(Debug, Clone, PartialEq, Eq, BorshSerialize, BorshDeserialize, Serialize)]
+pub struct StatePartChunk {
+    pub part_id: u64,
+    pub chunk_id: u64,
+    pub num_chunks: u64,
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, BorshSerialize, BorshDeserialize, Serialize)]
+pub struct ShardStateSyncResponseV3 {
+    pub header: Option<ShardStateSyncResponseHeaderV2>,
+    pub part_chunk: Option<StatePartChunk>,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, BorshSerialize, BorshDeserialize, Serialize)]
 pub enum ShardStateSyncResponse {
     V1(ShardStateSyncResponseV1),
     V2(ShardStateSyncResponseV2),
+    V3(ShardStateSyncResponseV3),
 }
 
 impl ShardStateSyncResponse {
@@ -160,6 +178,7 @@ impl ShardStateSyncResponse {
         match self {
             Self::V1(response) => response.part_id(),
             Self::V2(response) => response.part.as_ref().map(|(part_id, _)| *part_id),
+            Self::V3(response) => response.part_chunk.as_ref().map(|chunk| chunk.part_id),
         }
     }
 
@@ -167,6 +186,7 @@ impl ShardStateSyncResponse {
         match self {
             Self::V1(response) => response.header.map(ShardStateSyncResponseHeader::V1),
             Self::V2(response) => response.header.map(ShardStateSyncResponseHeader::V2),
+            Self::V3(response) => response.header.map(ShardStateSyncResponseHeader::V2),
         }
     }
 
@@ -174,6 +194,7 @@ impl ShardStateSyncResponse {
         match self {
             Self::V1(response) => &response.part,
             Self::V2(response) => &response.part,
+            Self::V3(_) => &None,
         }
     }
 
@@ -181,6 +202,21 @@ impl ShardStateSyncResponse {
         match self {
             Self::V1(response) => response.part,
             Self::V2(response) => response.part,
+            Self::V3(_) => None,
+        }
+    }
+
+    pub fn part_chunk(&self) -> Option<&StatePartChunk> {
+        match self {
+            Self::V1(_) | Self::V2(_) => None,
+            Self::V3(response) => response.part_chunk.as_ref(),
+        }
+    }
+
+    pub fn take_part_chunk(self) -> Option<StatePartChunk> {
+        match self {
+            Self::V1(_) | Self::V2(_) => None,
+            Self::V3(response) => response.part_chunk,
         }
     }
 }
@@ -191,6 +227,41 @@ impl ShardStateSyncResponseV1 {
     }
 }
 
+/// Download progress of a single state part, persisted so that state sync can resume
+/// applying a part from the next chunk after a restart.
+#[derive(BorshSerialize, BorshDeserialize, Eq, PartialEq, Debug, Clone)]
+pub struct StatePartSyncProgress {
+    pub part_id: u64,
+    pub num_chunks: u64,
+    pub next_chunk_id: u64,
+    /// Hashes of trie nodes referenced by already applied nodes of the part
+    /// that haven't been received yet.
+    pub expected_nodes: Vec<CryptoHash>,
+    /// Hashes of values referenced by already applied nodes of the part
+    /// that haven't been received yet.
+    pub expected_values: Vec<CryptoHash>,
+    /// Hashes of the nodes and values written to the state so far, so that their
+    /// refcounts can be released if the download of the part is abandoned.
+    pub applied_nodes: Vec<CryptoHash>,
+}
+
+impl StatePartSyncProgress {
+    pub fn new(part_id: u64, num_chunks: u64, state_root: &StateRoot) -> Self {
+        Self {
+            part_id,
+            num_chunks,
+            next_chunk_id: 0,
+            expected_nodes: vec![*state_root],
+            expected_values: vec![],
+            applied_nodes: vec![],
+        }
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.next_chunk_id >= self.num_chunks
+    }
+}
+
 #[derive(BorshSerialize, BorshDeserialize, Eq, PartialEq, Debug, Clone)]
 pub struct EpochSyncFinalizationResponse {
     pub cur_epoch_header: BlockHeader,
@@ -226,3 +297,6 @@ pub fn get_num_state_parts(memory_usage: u64) -> u64 {
     // TODO #1708
     memory_usage / (1024 * 1024) + 3
 }
+
+/// Maximum size of trie nodes sent in a single `StatePartChunk`.
+pub const STATE_PART_CHUNK_SIZE: usize = 128 * 1024;
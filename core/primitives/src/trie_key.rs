@@ -357,6 +357,91 @@ pub mod trie_key_parsers {
         })
     }
 
+    fn parse_account_id_and_hash_key(
+        column: &[u8],
+        raw_key: &[u8],
+    ) -> Result<(AccountId, CryptoHash), std::io::Error> {
+        let account_id_prefix = parse_account_id_prefix(column, raw_key)?;
+        let hash_len = size_of::<CryptoHash>();
+        if account_id_prefix.len() < ACCOUNT_DATA_SEPARATOR.len() + hash_len {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "raw key is too short to contain AccountId and CryptoHash",
+            ));
+        }
+        let (account_id, hash) = account_id_prefix.split_at(account_id_prefix.len() - hash_len);
+        if !account_id.ends_with(ACCOUNT_DATA_SEPARATOR) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "raw key does not have ACCOUNT_DATA_SEPARATOR before CryptoHash",
+            ));
+        }
+        let account_id = &account_id[..account_id.len() - ACCOUNT_DATA_SEPARATOR.len()];
+        let account_id = AccountId::from(std::str::from_utf8(account_id).map_err(|_| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "raw key does not have a valid AccountId",
+            )
+        })?);
+        let hash = CryptoHash::try_from(hash).map_err(|_| {
+            std::io::Error::new(std::io::ErrorKind::InvalidData, "Can't parse CryptoHash")
+        })?;
+        Ok((account_id, hash))
+    }
+
+    /// Restores `TrieKey` from its raw representation produced by `TrieKey::to_vec`.
+    pub fn parse_trie_key(raw_key: &[u8]) -> Result<TrieKey, std::io::Error> {
+        if raw_key.starts_with(col::ACCOUNT) {
+            Ok(TrieKey::Account { account_id: parse_account_id_from_account_key(raw_key)? })
+        } else if raw_key.starts_with(col::CONTRACT_CODE) {
+            Ok(TrieKey::ContractCode {
+                account_id: parse_account_id_from_contract_code_key(raw_key)?,
+            })
+        } else if raw_key.starts_with(col::ACCESS_KEY) {
+            parse_trie_key_access_key_from_raw_key(raw_key)
+        } else if raw_key.starts_with(col::RECEIVED_DATA) {
+            let (receiver_id, data_id) =
+                parse_account_id_and_hash_key(col::RECEIVED_DATA, raw_key)?;
+            Ok(TrieKey::ReceivedData { receiver_id, data_id })
+        } else if raw_key.starts_with(col::POSTPONED_RECEIPT_ID) {
+            let (receiver_id, data_id) =
+                parse_account_id_and_hash_key(col::POSTPONED_RECEIPT_ID, raw_key)?;
+            Ok(TrieKey::PostponedReceiptId { receiver_id, data_id })
+        } else if raw_key.starts_with(col::PENDING_DATA_COUNT) {
+            let (receiver_id, receipt_id) =
+                parse_account_id_and_hash_key(col::PENDING_DATA_COUNT, raw_key)?;
+            Ok(TrieKey::PendingDataCount { receiver_id, receipt_id })
+        } else if raw_key.starts_with(col::POSTPONED_RECEIPT) {
+            let (receiver_id, receipt_id) =
+                parse_account_id_and_hash_key(col::POSTPONED_RECEIPT, raw_key)?;
+            Ok(TrieKey::PostponedReceipt { receiver_id, receipt_id })
+        } else if raw_key.starts_with(col::DELAYED_RECEIPT_INDICES) {
+            // Delayed receipts are stored under the column of the queue indices, see `to_vec`.
+            let index = &raw_key[col::DELAYED_RECEIPT_INDICES.len()..];
+            if index.is_empty() {
+                Ok(TrieKey::DelayedReceiptIndices)
+            } else if index.len() == size_of::<u64>() {
+                let mut bytes = [0u8; 8];
+                bytes.copy_from_slice(index);
+                Ok(TrieKey::DelayedReceipt { index: u64::from_le_bytes(bytes) })
+            } else {
+                Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    "raw key has invalid length to be TrieKey::DelayedReceipt",
+                ))
+            }
+        } else if raw_key.starts_with(col::CONTRACT_DATA) {
+            let account_id = parse_account_id_from_contract_data_key(raw_key)?;
+            let key = parse_data_key_from_contract_data_key(raw_key, &account_id)?.to_vec();
+            Ok(TrieKey::ContractData { account_id, key })
+        } else {
+            Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "raw key does not start with a known column marker",
+            ))
+        }
+    }
+
     pub fn get_raw_prefix_for_access_keys(account_id: &AccountId) -> Vec<u8> {
         let mut res = Vec::with_capacity(col::ACCESS_KEY.len() * 2 + account_id.len());
         res.extend(col::ACCESS_KEY);
@@ -469,6 +554,33 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_parse_trie_key() {
+        let hash = CryptoHash::default();
+        for account_id in OK_ACCOUNT_IDS.iter().map(|x| AccountId::from(*x)) {
+            let keys = vec![
+                TrieKey::Account { account_id: account_id.clone() },
+                TrieKey::ContractCode { account_id: account_id.clone() },
+                TrieKey::AccessKey {
+                    account_id: account_id.clone(),
+                    public_key: PublicKey::empty(KeyType::ED25519),
+                },
+                TrieKey::ReceivedData { receiver_id: account_id.clone(), data_id: hash },
+                TrieKey::PostponedReceiptId { receiver_id: account_id.clone(), data_id: hash },
+                TrieKey::PendingDataCount { receiver_id: account_id.clone(), receipt_id: hash },
+                TrieKey::PostponedReceipt { receiver_id: account_id.clone(), receipt_id: hash },
+                TrieKey::DelayedReceiptIndices,
+                TrieKey::DelayedReceipt { index: 42 },
+                TrieKey::ContractData { account_id: account_id.clone(), key: b",key".to_vec() },
+            ];
+            for key in keys {
+                assert_eq!(trie_key_parsers::parse_trie_key(&key.to_vec()).unwrap(), key);
+            }
+        }
+        assert!(trie_key_parsers::parse_trie_key(&[]).is_err());
+        assert!(trie_key_parsers::parse_trie_key(&[3, b'a', b'b']).is_err());
+    }
+
     #[test]
     fn test_key_for_code_consistency() {
         for account_id in OK_ACCOUNT_IDS.iter().map(|x| AccountId::from(*x)) {
@@ -16,7 +16,7 @@ pub struct Version {
 pub type DbVersion = u32;
 
 /// Current version of the database.
-pub const DB_VERSION: DbVersion = 18;
+pub const DB_VERSION: DbVersion = 20;
 
 /// Protocol version type.
 pub use near_primitives_core::types::ProtocolVersion;
@@ -94,6 +94,18 @@ pub enum ProtocolFeature {
     AltBn128,
     #[cfg(feature = "protocol_feature_access_key_nonce_range")]
     AccessKeyNonceRange,
+    /// Encrypts the connections between peers with the Noise protocol.
+    #[cfg(feature = "protocol_feature_encrypted_peer_connections")]
+    EncryptedPeerConnections,
+    /// Compresses large messages between peers with zstd.
+    #[cfg(feature = "protocol_feature_peer_message_compression")]
+    PeerMessageCompression,
+    /// Exchanges all the addresses of every peer in `PeersResponseV2`.
+    #[cfg(feature = "protocol_feature_multi_address_peer_info")]
+    MultiAddressPeerInfo,
+    /// Downloads state parts in chunks which are verified and applied one by one.
+    #[cfg(feature = "protocol_feature_state_part_chunks")]
+    StatePartChunks,
 }
 
 /// Current latest stable version of the protocol.
@@ -102,7 +114,7 @@ pub const PROTOCOL_VERSION: ProtocolVersion = 42;
 
 /// Current latest nightly version of the protocol.
 #[cfg(feature = "nightly_protocol")]
-pub const PROTOCOL_VERSION: ProtocolVersion = 106;
+pub const PROTOCOL_VERSION: ProtocolVersion = 110;
 
 lazy_static! {
     static ref STABLE_PROTOCOL_FEATURES_TO_VERSION_MAPPING: HashMap<ProtocolFeature, ProtocolVersion> =
@@ -142,6 +154,14 @@ lazy_static! {
             (ProtocolFeature::AltBn128, 105),
             #[cfg(feature = "protocol_feature_access_key_nonce_range")]
             (ProtocolFeature::AccessKeyNonceRange, 106),
+            #[cfg(feature = "protocol_feature_encrypted_peer_connections")]
+            (ProtocolFeature::EncryptedPeerConnections, 107),
+            #[cfg(feature = "protocol_feature_peer_message_compression")]
+            (ProtocolFeature::PeerMessageCompression, 108),
+            #[cfg(feature = "protocol_feature_multi_address_peer_info")]
+            (ProtocolFeature::MultiAddressPeerInfo, 109),
+            #[cfg(feature = "protocol_feature_state_part_chunks")]
+            (ProtocolFeature::StatePartChunks, 110),
         ]
         .into_iter()
         .collect();
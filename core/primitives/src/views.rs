@@ -22,11 +22,13 @@ use crate::block_header::{
 #[cfg(feature = "protocol_feature_block_header_v3")]
 use crate::block_header::{BlockHeaderInnerRestV3, BlockHeaderV3};
 use crate::challenge::{Challenge, ChallengesResult};
+use crate::config::{ActionCosts, ExtCosts};
 use crate::contract::ContractCode;
 use crate::errors::TxExecutionError;
 use crate::hash::{hash, CryptoHash};
 use crate::logging;
 use crate::merkle::MerklePath;
+use crate::profile::{ActionGasProfile, GasProfile};
 use crate::receipt::{ActionReceipt, DataReceipt, DataReceiver, Receipt, ReceiptEnum};
 use crate::serialize::{
     base64_format, from_base64, option_base64_format, option_u128_dec_format, to_base64,
@@ -40,6 +42,7 @@ use crate::transaction::{
     DeployContractAction, ExecutionOutcome, ExecutionOutcomeWithIdAndProof, ExecutionStatus,
     FunctionCallAction, SignedTransaction, StakeAction, TransferAction,
 };
+use crate::trie_key::{trie_key_parsers, TrieKey};
 use crate::types::{
     AccountId, AccountWithPublicKey, Balance, BlockHeight, CompiledContractCache, EpochHeight,
     EpochId, FunctionArgs, Gas, Nonce, NumBlocks, ShardId, StateChangeCause, StateChangeKind,
@@ -215,6 +218,16 @@ pub struct ViewStateResult {
     pub proof: TrieProofPath,
 }
 
+/// Page of contract state returned for `QueryRequest::ViewStatePage`.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct ViewStatePageResult {
+    pub values: Vec<StateItem>,
+    /// Base64-encoded key to request the next page from, `None` on the last page.
+    pub next_key: Option<String>,
+    /// Base64-encoded trie nodes visited while reading the page. Empty unless requested.
+    pub proof_nodes: Vec<String>,
+}
+
 #[derive(
     BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Default,
 )]
@@ -254,6 +267,7 @@ pub enum QueryResponseKind {
     CallResult(CallResult),
     AccessKey(AccessKeyView),
     AccessKeyList(AccessKeyList),
+    ViewStatePage(ViewStatePageResult),
 }
 
 #[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
@@ -283,6 +297,19 @@ pub enum QueryRequest {
         #[serde(rename = "args_base64", with = "base64_format")]
         args: FunctionArgs,
     },
+    /// Paginated version of `ViewState`, which can also prove the returned items.
+    ViewStatePage {
+        account_id: AccountId,
+        #[serde(rename = "prefix_base64", with = "base64_format")]
+        prefix: StoreKey,
+        /// Key to start the page from, as returned in `next_key` of the previous page.
+        #[serde(default, rename = "start_key_base64", with = "option_base64_format")]
+        start_key: Option<Vec<u8>>,
+        #[serde(default)]
+        limit: Option<u64>,
+        #[serde(default)]
+        include_proof: bool,
+    },
 }
 
 #[derive(BorshSerialize, BorshDeserialize, Debug, PartialEq, Eq, Clone)]
@@ -1360,7 +1387,7 @@ impl From<StateChangeKind> for StateChangeKindView {
 pub type StateChangesKindsView = Vec<StateChangeKindView>;
 
 /// See crate::types::StateChangeCause for details.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(rename_all = "snake_case", tag = "type")]
 pub enum StateChangeCauseView {
     NotWritableToDisk,
@@ -1400,7 +1427,7 @@ impl From<StateChangeCause> for StateChangeCauseView {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(rename_all = "snake_case", tag = "type", content = "change")]
 pub enum StateChangeValueView {
     AccountUpdate {
@@ -1473,7 +1500,7 @@ impl From<StateChangeValue> for StateChangeValueView {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct StateChangeWithCauseView {
     pub cause: StateChangeCauseView,
     #[serde(flatten)]
@@ -1488,3 +1515,178 @@ impl From<StateChangeWithCause> for StateChangeWithCauseView {
 }
 
 pub type StateChangesView = Vec<StateChangeWithCauseView>;
+
+/// Whether an account history item is a transaction or a receipt.
+#[derive(
+    BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum AccountHistoryItemKind {
+    Transaction,
+    Receipt,
+}
+
+/// Transaction or receipt which touched an account, either as the signer / predecessor or as
+/// the receiver.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct AccountHistoryItemView {
+    /// Height at which the chunk including the transaction or receipt was created.
+    pub block_height: BlockHeight,
+    /// Transaction hash or receipt id.
+    pub id: CryptoHash,
+    pub kind: AccountHistoryItemKind,
+}
+
+/// Transaction waiting in the pool of a shard to be included into a chunk.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct PendingTransactionView {
+    pub shard_id: ShardId,
+    pub transaction: SignedTransactionView,
+}
+
+/// Decoded key of a state trie record, mirrors `TrieKey`.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+#[serde(rename_all = "snake_case", tag = "type")]
+pub enum TrieKeyView {
+    Account {
+        account_id: AccountId,
+    },
+    ContractCode {
+        account_id: AccountId,
+    },
+    AccessKey {
+        account_id: AccountId,
+        public_key: PublicKey,
+    },
+    ReceivedData {
+        receiver_id: AccountId,
+        data_id: CryptoHash,
+    },
+    PostponedReceiptId {
+        receiver_id: AccountId,
+        data_id: CryptoHash,
+    },
+    PendingDataCount {
+        receiver_id: AccountId,
+        receipt_id: CryptoHash,
+    },
+    PostponedReceipt {
+        receiver_id: AccountId,
+        receipt_id: CryptoHash,
+    },
+    DelayedReceiptIndices,
+    DelayedReceipt {
+        index: u64,
+    },
+    ContractData {
+        account_id: AccountId,
+        #[serde(rename = "key_base64", with = "base64_format")]
+        key: StoreKey,
+    },
+    /// Key which doesn't correspond to any known `TrieKey`.
+    Raw {
+        #[serde(rename = "key_base64", with = "base64_format")]
+        key: Vec<u8>,
+    },
+}
+
+impl From<TrieKey> for TrieKeyView {
+    fn from(trie_key: TrieKey) -> Self {
+        match trie_key {
+            TrieKey::Account { account_id } => Self::Account { account_id },
+            TrieKey::ContractCode { account_id } => Self::ContractCode { account_id },
+            TrieKey::AccessKey { account_id, public_key } => {
+                Self::AccessKey { account_id, public_key }
+            }
+            TrieKey::ReceivedData { receiver_id, data_id } => {
+                Self::ReceivedData { receiver_id, data_id }
+            }
+            TrieKey::PostponedReceiptId { receiver_id, data_id } => {
+                Self::PostponedReceiptId { receiver_id, data_id }
+            }
+            TrieKey::PendingDataCount { receiver_id, receipt_id } => {
+                Self::PendingDataCount { receiver_id, receipt_id }
+            }
+            TrieKey::PostponedReceipt { receiver_id, receipt_id } => {
+                Self::PostponedReceipt { receiver_id, receipt_id }
+            }
+            TrieKey::DelayedReceiptIndices => Self::DelayedReceiptIndices,
+            TrieKey::DelayedReceipt { index } => Self::DelayedReceipt { index },
+            TrieKey::ContractData { account_id, key } => Self::ContractData { account_id, key },
+        }
+    }
+}
+
+impl TrieKeyView {
+    /// Decodes raw trie key, keys which can't be parsed are kept as is.
+    pub fn from_raw_key(raw_key: &[u8]) -> Self {
+        match trie_key_parsers::parse_trie_key(raw_key) {
+            Ok(trie_key) => trie_key.into(),
+            Err(_) => Self::Raw { key: raw_key.to_vec() },
+        }
+    }
+}
+
+/// Record which differs between two state roots. Missing value means that the key is absent
+/// in the corresponding state.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct StateDiffItemView {
+    pub key: TrieKeyView,
+    #[serde(rename = "old_value_base64", with = "option_base64_format")]
+    pub old_value: Option<Vec<u8>>,
+    #[serde(rename = "new_value_base64", with = "option_base64_format")]
+    pub new_value: Option<Vec<u8>>,
+}
+
+impl StateDiffItemView {
+    pub fn new(raw_key: &[u8], old_value: Option<Vec<u8>>, new_value: Option<Vec<u8>>) -> Self {
+        Self { key: TrieKeyView::from_raw_key(raw_key), old_value, new_value }
+    }
+}
+
+/// Gas spent on a single external or action cost.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct CostGasUsedView {
+    /// Either "ACTION_COST" or "WASM_HOST_COST".
+    pub cost_category: String,
+    /// Name of the `ActionCosts` or `ExtCosts` variant.
+    pub cost: String,
+    pub gas_used: Gas,
+}
+
+/// Gas burnt by an action of a receipt, broken down by the costs it was spent on. Only the costs
+/// with non-zero gas are listed. Only the execution of function calls is broken down: the exec fee
+/// of the action itself, which is all the other actions burn, is only included in `gas_burnt`.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct ActionGasProfileView {
+    pub gas_burnt: Gas,
+    pub costs: Vec<CostGasUsedView>,
+}
+
+impl CostGasUsedView {
+    /// Lists the costs of the profile with non-zero gas, action costs first.
+    pub fn from_gas_profile(profile: GasProfile) -> Vec<Self> {
+        let action_costs =
+            profile.action_costs.into_iter().enumerate().map(|(index, gas_used)| CostGasUsedView {
+                cost_category: "ACTION_COST".to_string(),
+                cost: ActionCosts::name_of(index).to_string(),
+                gas_used,
+            });
+        let ext_costs =
+            profile.ext_costs.into_iter().enumerate().map(|(index, gas_used)| CostGasUsedView {
+                cost_category: "WASM_HOST_COST".to_string(),
+                cost: ExtCosts::name_of(index).to_string(),
+                gas_used,
+            });
+        action_costs.chain(ext_costs).filter(|cost| cost.gas_used > 0).collect()
+    }
+}
+
+impl From<ActionGasProfile> for ActionGasProfileView {
+    fn from(action_gas_profile: ActionGasProfile) -> Self {
+        Self {
+            gas_burnt: action_gas_profile.gas_burnt,
+            costs: CostGasUsedView::from_gas_profile(action_gas_profile.profile),
+        }
+    }
+}
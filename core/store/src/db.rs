@@ -3,26 +3,33 @@ use std::cmp;
 use std::collections::HashMap;
 use std::io;
 use std::marker::PhantomPinned;
+use std::path::Path;
 use std::sync::RwLock;
 
 use borsh::{BorshDeserialize, BorshSerialize};
+use rocksdb::checkpoint::Checkpoint;
 #[cfg(feature = "single_thread_rocksdb")]
 use rocksdb::Env;
 use rocksdb::{
-    BlockBasedOptions, Cache, ColumnFamily, ColumnFamilyDescriptor, Direction, IteratorMode,
-    Options, ReadOptions, WriteBatch, DB,
+    BlockBasedOptions, Cache, ColumnFamily, ColumnFamilyDescriptor, DBCompressionType, Direction,
+    IteratorMode, Options, ReadOptions, WriteBatch, DB,
 };
+use serde::{Deserialize, Serialize};
 use strum::EnumIter;
 
 use near_primitives::version::DbVersion;
 
 use crate::db::refcount::merge_refcounted_records;
+pub use crate::db::sled_db::SledDB;
+use crate::metrics;
+use crate::StoreConfig;
 
 pub(crate) mod refcount;
+mod sled_db;
 pub(crate) mod v6_to_v7;
 
 #[derive(Debug, Clone, PartialEq)]
-pub struct DBError(rocksdb::Error);
+pub struct DBError(String);
 
 impl std::fmt::Display for DBError {
     fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
@@ -34,7 +41,13 @@ impl std::error::Error for DBError {}
 
 impl From<rocksdb::Error> for DBError {
     fn from(err: rocksdb::Error) -> Self {
-        DBError(err)
+        DBError(err.into_string())
+    }
+}
+
+impl From<sled::Error> for DBError {
+    fn from(err: sled::Error) -> Self {
+        DBError(err.to_string())
     }
 }
 
@@ -44,7 +57,19 @@ impl Into<io::Error> for DBError {
     }
 }
 
-#[derive(PartialEq, Debug, Copy, Clone, EnumIter, BorshDeserialize, BorshSerialize, Hash, Eq)]
+#[derive(
+    PartialEq,
+    Debug,
+    Copy,
+    Clone,
+    EnumIter,
+    BorshDeserialize,
+    BorshSerialize,
+    Serialize,
+    Deserialize,
+    Hash,
+    Eq,
+)]
 pub enum DBCol {
     /// Column to indicate which version of database this is.
     ColDbVersion = 0,
@@ -117,10 +142,22 @@ pub enum DBCol {
     ColEpochValidatorInfo = 47,
     /// Header Hashes indexed by Height
     ColHeaderHashesByHeight = 48,
+    /// Transactions and receipts touching an account, indexed by account id and block height.
+    /// Only maintained by archival nodes with `account_history_index` enabled.
+    ColAccountHistory = 49,
+    /// Values of all trie keys of a shard at the flat state head block, indexed by shard id and
+    /// raw trie key. Only maintained with `flat_state` enabled in the store config.
+    ColFlatState = 50,
+    /// Changes of trie values made by blocks after the flat state head, indexed by block hash and
+    /// shard id.
+    ColFlatStateDeltas = 51,
+    /// Gas profiles of the actions of executed receipts, indexed by block hash and receipt id.
+    /// Only maintained by nodes recording gas profiles and built with `costs_counting`.
+    ColReceiptGasProfiles = 52,
 }
 
 // Do not move this line from enum DBCol
-pub const NUM_COLS: usize = 49;
+pub const NUM_COLS: usize = 53;
 
 impl std::fmt::Display for DBCol {
     fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
@@ -174,6 +211,10 @@ impl std::fmt::Display for DBCol {
             Self::ColCachedContractCode => "cached code",
             Self::ColEpochValidatorInfo => "epoch validator info",
             Self::ColHeaderHashesByHeight => "header hashes indexed by their height",
+            Self::ColAccountHistory => "transactions and receipts indexed by account",
+            Self::ColFlatState => "flat state",
+            Self::ColFlatStateDeltas => "flat state deltas",
+            Self::ColReceiptGasProfiles => "gas profiles of receipts",
         };
         write!(formatter, "{}", desc)
     }
@@ -207,6 +248,8 @@ lazy_static! {
         col_gc[DBCol::ColEpochValidatorInfo as usize] = false; // https://github.com/nearprotocol/nearcore/pull/2952
         col_gc[DBCol::ColEpochStart as usize] = false; // https://github.com/nearprotocol/nearcore/pull/2952
         col_gc[DBCol::ColCachedContractCode as usize] = false;
+        col_gc[DBCol::ColAccountHistory as usize] = false; // only maintained on archival nodes
+        col_gc[DBCol::ColFlatState as usize] = false; // updated in place when flat head moves
         col_gc
     };
 }
@@ -247,6 +290,138 @@ pub const LARGEST_TARGET_HEIGHT_KEY: &[u8; 21] = b"LARGEST_TARGET_HEIGHT";
 pub const VERSION_KEY: &[u8; 7] = b"VERSION";
 pub const GENESIS_JSON_HASH_KEY: &[u8; 17] = b"GENESIS_JSON_HASH";
 pub const GENESIS_STATE_ROOTS_KEY: &[u8; 19] = b"GENESIS_STATE_ROOTS";
+/// Followed by shard id, points to the block at which `ColFlatState` holds the state of the shard.
+pub const FLAT_STATE_HEAD_KEY: &[u8; 15] = b"FLAT_STATE_HEAD";
+
+/// On-disk database implementations that can back a `Store`.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum StoreBackend {
+    RocksDB,
+    Sled,
+}
+
+impl Default for StoreBackend {
+    fn default() -> Self {
+        StoreBackend::RocksDB
+    }
+}
+
+impl StoreBackend {
+    /// Returns backend of the database which already exists at `path`, if any.
+    pub fn detect<P: AsRef<Path>>(path: P) -> Option<StoreBackend> {
+        let path = path.as_ref();
+        if path.join("CURRENT").exists() {
+            Some(StoreBackend::RocksDB)
+        } else if path.join(sled_db::SLED_CONF_FILE).exists() {
+            Some(StoreBackend::Sled)
+        } else {
+            None
+        }
+    }
+}
+
+/// Compression algorithm of a RocksDB column.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CompressionType {
+    None,
+    Snappy,
+    Lz4,
+    Zstd,
+}
+
+impl From<CompressionType> for DBCompressionType {
+    fn from(compression: CompressionType) -> Self {
+        match compression {
+            CompressionType::None => DBCompressionType::None,
+            CompressionType::Snappy => DBCompressionType::Snappy,
+            CompressionType::Lz4 => DBCompressionType::Lz4,
+            CompressionType::Zstd => DBCompressionType::Zstd,
+        }
+    }
+}
+
+/// RocksDB options of a single column.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ColumnConfig {
+    pub compression: CompressionType,
+    /// Size of uncompressed data blocks in bytes.
+    pub block_size: usize,
+    /// Size of the block cache of the column in bytes.
+    pub block_cache_size: usize,
+    /// Bits per key of the bloom filter, 0 disables the filter.
+    pub bloom_filter_bits: i32,
+}
+
+impl Default for ColumnConfig {
+    /// Options of the columns without their own defaults in `ColumnConfig::default_for`.
+    fn default() -> Self {
+        ColumnConfig {
+            compression: CompressionType::Snappy,
+            block_size: 1024 * 16,
+            block_cache_size: 1024 * 1024 * 32,
+            bloom_filter_bits: 10,
+        }
+    }
+}
+
+impl ColumnConfig {
+    /// Default options of the column, based on how it is accessed. Every column has its own
+    /// block cache, so the total cache size is the sum over all columns.
+    pub fn default_for(col: DBCol) -> Self {
+        let default = ColumnConfig::default();
+        match col {
+            // Trie nodes are read by hash on every state access, so this is the hottest column and
+            // a more precise bloom filter saves disk reads for missing keys.
+            DBCol::ColState => ColumnConfig {
+                block_cache_size: 1024 * 1024 * 256,
+                bloom_filter_bits: 16,
+                ..default
+            },
+            // Replaces trie walks with single lookups for the runtime, so it is almost as hot.
+            DBCol::ColFlatState => ColumnConfig { block_cache_size: 1024 * 1024 * 128, ..default },
+            // Written once per block and only read back by GC or to serve RPC, so trade CPU for
+            // disk space and keep the cache small.
+            DBCol::ColTrieChanges | DBCol::ColStateChanges | DBCol::ColTransactionResult => {
+                ColumnConfig {
+                    compression: CompressionType::Zstd,
+                    block_cache_size: 1024 * 1024 * 8,
+                    ..default
+                }
+            }
+            // Large values which are rarely read after the block is processed.
+            DBCol::ColBlock | DBCol::ColChunks | DBCol::ColPartialChunks => {
+                ColumnConfig { compression: CompressionType::Lz4, block_size: 1024 * 32, ..default }
+            }
+            _ => default,
+        }
+    }
+}
+
+/// Overrides of the `ColumnConfig::default_for` a column. Unset fields keep the default value.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct ColumnConfigOverride {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compression: Option<CompressionType>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub block_size: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub block_cache_size: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bloom_filter_bits: Option<i32>,
+}
+
+impl ColumnConfigOverride {
+    pub fn apply(&self, config: ColumnConfig) -> ColumnConfig {
+        ColumnConfig {
+            compression: self.compression.unwrap_or(config.compression),
+            block_size: self.block_size.unwrap_or(config.block_size),
+            block_cache_size: self.block_cache_size.unwrap_or(config.block_cache_size),
+            bloom_filter_bits: self.bloom_filter_bits.unwrap_or(config.bloom_filter_bits),
+        }
+    }
+}
 
 pub struct DBTransaction {
     pub ops: Vec<DBOp>,
@@ -320,7 +495,19 @@ pub trait Database: Sync + Send {
         col: DBCol,
         key_prefix: &'a [u8],
     ) -> Box<dyn Iterator<Item = (Box<[u8]>, Box<[u8]>)> + 'a>;
+    /// Iterates over keys starting with `key_prefix` in key order, beginning with the first
+    /// key which is not less than `from`.
+    fn iter_prefix_from<'a>(
+        &'a self,
+        col: DBCol,
+        key_prefix: &'a [u8],
+        from: &'a [u8],
+    ) -> Box<dyn Iterator<Item = (Box<[u8]>, Box<[u8]>)> + 'a>;
     fn write(&self, batch: DBTransaction) -> Result<(), DBError>;
+    /// Creates a consistent point-in-time copy of the whole database in a new directory at `path`.
+    fn checkpoint(&self, _path: &Path) -> Result<(), DBError> {
+        Err(DBError("Checkpoints are not supported by this database".to_string()))
+    }
     fn as_rocksdb(&self) -> Option<&RocksDB> {
         None
     }
@@ -379,6 +566,28 @@ impl Database for RocksDB {
         }
     }
 
+    fn iter_prefix_from<'a>(
+        &'a self,
+        col: DBCol,
+        key_prefix: &'a [u8],
+        from: &'a [u8],
+    ) -> Box<dyn Iterator<Item = (Box<[u8]>, Box<[u8]>)> + 'a> {
+        let from = if from < key_prefix { key_prefix } else { from };
+        let read_options = rocksdb_read_options();
+        unsafe {
+            let cf_handle = &*self.cfs[col as usize];
+            let iterator = self
+                .db
+                .iterator_cf_opt(
+                    cf_handle,
+                    read_options,
+                    IteratorMode::From(from, Direction::Forward),
+                )
+                .take_while(move |(key, _value)| key.starts_with(key_prefix));
+            RocksDB::iter_with_rc_logic(col, iterator)
+        }
+    }
+
     fn write(&self, transaction: DBTransaction) -> Result<(), DBError> {
         let mut batch = WriteBatch::default();
         for op in transaction.ops {
@@ -409,6 +618,13 @@ impl Database for RocksDB {
         Ok(self.db.write(batch)?)
     }
 
+    fn checkpoint(&self, path: &Path) -> Result<(), DBError> {
+        // Checkpoint hard links immutable SST files, so it is cheap when `path` is on the same
+        // filesystem as the database.
+        let checkpoint = Checkpoint::new(&self.db)?;
+        Ok(checkpoint.create_checkpoint(path)?)
+    }
+
     fn as_rocksdb(&self) -> Option<&RocksDB> {
         Some(self)
     }
@@ -447,6 +663,20 @@ impl Database for TestDB {
         )
     }
 
+    fn iter_prefix_from<'a>(
+        &'a self,
+        col: DBCol,
+        key_prefix: &'a [u8],
+        from: &'a [u8],
+    ) -> Box<dyn Iterator<Item = (Box<[u8]>, Box<[u8]>)> + 'a> {
+        let mut items: Vec<_> = self
+            .iter_prefix(col, key_prefix)
+            .filter(|(key, _value)| key.as_ref() >= from)
+            .collect();
+        items.sort();
+        Box::new(items.into_iter())
+    }
+
     fn write(&self, transaction: DBTransaction) -> Result<(), DBError> {
         let mut db = self.db.write().unwrap();
         for op in transaction.ops {
@@ -510,25 +740,27 @@ fn rocksdb_options() -> Options {
     return opts;
 }
 
-fn rocksdb_block_based_options() -> BlockBasedOptions {
+fn rocksdb_block_based_options(config: &ColumnConfig) -> BlockBasedOptions {
     let mut block_opts = BlockBasedOptions::default();
-    block_opts.set_block_size(1024 * 16);
-    // We create block_cache for each of 47 columns, so the total cache size is 32 * 47 = 1504mb
-    let cache_size = 1024 * 1024 * 32;
-    block_opts.set_block_cache(&Cache::new_lru_cache(cache_size).unwrap());
+    block_opts.set_block_size(config.block_size);
+    // Every column has its own block cache, see `ColumnConfig::default_for` for the sizes.
+    block_opts.set_block_cache(&Cache::new_lru_cache(config.block_cache_size).unwrap());
     block_opts.set_pin_l0_filter_and_index_blocks_in_cache(true);
     block_opts.set_cache_index_and_filter_blocks(true);
-    block_opts.set_bloom_filter(10, true);
+    if config.bloom_filter_bits > 0 {
+        block_opts.set_bloom_filter(config.bloom_filter_bits, true);
+    }
     block_opts
 }
 
-fn rocksdb_column_options(col: DBCol) -> Options {
+fn rocksdb_column_options(col: DBCol, config: &ColumnConfig) -> Options {
     let mut opts = Options::default();
     opts.set_level_compaction_dynamic_level_bytes(true);
-    opts.set_block_based_table_factory(&rocksdb_block_based_options());
+    opts.set_block_based_table_factory(&rocksdb_block_based_options(config));
     opts.optimize_level_style_compaction(1024 * 1024 * 128);
     opts.set_target_file_size_base(1024 * 1024 * 64);
     opts.set_compression_per_level(&[]);
+    opts.set_compression_type(config.compression.into());
     if col.is_rc() {
         opts.set_merge_operator("refcount merge", RocksDB::refcount_merge, None);
         opts.set_compaction_filter("empty value filter", RocksDB::empty_value_compaction_filter);
@@ -559,11 +791,21 @@ impl RocksDB {
     }
 
     pub fn new<P: AsRef<std::path::Path>>(path: P) -> Result<Self, DBError> {
+        Self::new_with_config(path, &StoreConfig::default())
+    }
+
+    pub fn new_with_config<P: AsRef<std::path::Path>>(
+        path: P,
+        config: &StoreConfig,
+    ) -> Result<Self, DBError> {
         use strum::IntoEnumIterator;
         let options = rocksdb_options();
         let cf_names: Vec<_> = DBCol::iter().map(|col| format!("col{}", col as usize)).collect();
         let cf_descriptors = DBCol::iter().map(|col| {
-            ColumnFamilyDescriptor::new(format!("col{}", col as usize), rocksdb_column_options(col))
+            ColumnFamilyDescriptor::new(
+                format!("col{}", col as usize),
+                rocksdb_column_options(col, &config.column_config(col)),
+            )
         });
         let db = DB::open_cf_descriptors(&options, path, cf_descriptors)?;
         #[cfg(feature = "single_thread_rocksdb")]
@@ -582,6 +824,28 @@ impl RocksDB {
     }
 }
 
+impl RocksDB {
+    /// Exports size and cache usage of every column to prometheus.
+    pub fn update_column_metrics(&self) {
+        use strum::IntoEnumIterator;
+        for col in DBCol::iter() {
+            let cf_handle = unsafe { &*self.cfs[col as usize] };
+            let label = format!("{:?}", col);
+            for (gauge, property) in [
+                (&*metrics::COLUMN_ESTIMATED_NUM_KEYS, "rocksdb.estimate-num-keys"),
+                (&*metrics::COLUMN_LIVE_SST_FILES_SIZE, "rocksdb.live-sst-files-size"),
+                (&*metrics::COLUMN_BLOCK_CACHE_USAGE, "rocksdb.block-cache-usage"),
+            ]
+            .iter()
+            {
+                if let Ok(Some(value)) = self.db.property_int_value_cf(cf_handle, property) {
+                    near_metrics::set_gauge_vec(gauge, &[label.as_str()], value as i64);
+                }
+            }
+        }
+    }
+}
+
 #[cfg(feature = "single_thread_rocksdb")]
 impl Drop for RocksDB {
     fn drop(&mut self) {
@@ -601,9 +865,13 @@ impl TestDB {
 
 #[cfg(test)]
 mod tests {
-    use crate::db::DBCol::ColState;
-    use crate::db::{rocksdb_read_options, DBError, Database, RocksDB};
-    use crate::{create_store, DBCol};
+    use crate::db::DBCol::{ColBlockMisc, ColState, ColTransactionResult, ColTrieChanges};
+    use crate::db::{
+        rocksdb_read_options, ColumnConfig, CompressionType, DBError, Database, RocksDB,
+        StoreBackend,
+    };
+    use crate::test_utils::{create_test_store_with_backend, with_test_stores};
+    use crate::{create_store, create_store_with_config, DBCol, Store, StoreConfig};
 
     impl RocksDB {
         #[cfg(not(feature = "single_thread_rocksdb"))]
@@ -627,10 +895,7 @@ mod tests {
         }
     }
 
-    #[test]
-    fn test_clear_column() {
-        let tmp_dir = tempfile::Builder::new().prefix("_test_clear_column").tempdir().unwrap();
-        let store = create_store(tmp_dir.path().to_str().unwrap());
+    fn check_clear_column(store: &Store) {
         assert_eq!(store.get(ColState, &[1]).unwrap(), None);
         {
             let mut store_update = store.store_update();
@@ -648,10 +913,115 @@ mod tests {
         assert_eq!(store.get(ColState, &[1]).unwrap(), None);
     }
 
+    #[test]
+    fn test_clear_column() {
+        for backend in [StoreBackend::RocksDB, StoreBackend::Sled].iter() {
+            let (_tmp_dir, store) = create_test_store_with_backend(*backend);
+            check_clear_column(&store);
+        }
+    }
+
+    #[test]
+    fn test_iter_prefix_from() {
+        with_test_stores(|store| {
+            let mut store_update = store.store_update();
+            for key in [[1, 3], [2, 1], [1, 1], [1, 2], [0, 5]].iter() {
+                store_update.set(ColBlockMisc, key, &[key[1]]);
+            }
+            store_update.commit().unwrap();
+            let keys = |from: &[u8]| {
+                store
+                    .iter_prefix_from(ColBlockMisc, &[1], from)
+                    .map(|(key, _)| key.to_vec())
+                    .collect::<Vec<_>>()
+            };
+            assert_eq!(keys(&[1, 2]), vec![vec![1, 2], vec![1, 3]]);
+            assert_eq!(keys(&[1]), vec![vec![1, 1], vec![1, 2], vec![1, 3]]);
+            assert_eq!(keys(&[0]), vec![vec![1, 1], vec![1, 2], vec![1, 3]]);
+            assert_eq!(keys(&[1, 4]), Vec::<Vec<u8>>::new());
+        });
+    }
+
+    #[test]
+    fn sled_merge_sanity() {
+        let (_tmp_dir, store) = create_test_store_with_backend(StoreBackend::Sled);
+        for _ in 0..2 {
+            let mut store_update = store.store_update();
+            store_update.update_refcount(ColState, &[1], &[1], 1);
+            store_update.commit().unwrap();
+        }
+        assert_eq!(store.get(ColState, &[1]).unwrap(), Some(vec![1]));
+        let raw: Vec<_> = store.iter_without_rc_logic(ColState).collect();
+        assert_eq!(raw, vec![(vec![1].into_boxed_slice(), vec![1, 2, 0, 0, 0, 0, 0, 0, 0].into())]);
+        for _ in 0..2 {
+            let mut store_update = store.store_update();
+            store_update.update_refcount(ColState, &[1], &[1], -1);
+            store_update.commit().unwrap();
+        }
+        // Refcount goes to 0 -> the record is removed right away
+        assert_eq!(store.get(ColState, &[1]).unwrap(), None);
+        assert_eq!(store.iter_without_rc_logic(ColState).count(), 0);
+    }
+
+    #[test]
+    fn sled_delete_all_in_same_transaction() {
+        let tmp_dir = tempfile::Builder::new().prefix("_test_sled_delete_all").tempdir().unwrap();
+        let path = tmp_dir.path().to_str().unwrap();
+        {
+            let store = create_store_with_config(
+                path,
+                &StoreConfig { backend: StoreBackend::Sled, ..Default::default() },
+            );
+            let mut store_update = store.store_update();
+            store_update.set(ColBlockMisc, &[1, 1], &[1]);
+            store_update.commit().unwrap();
+            let mut store_update = store.store_update();
+            store_update.set(ColBlockMisc, &[1, 2], &[2]);
+            store_update.delete_all(ColBlockMisc);
+            store_update.set(ColBlockMisc, &[1, 3], &[3]);
+            store_update.commit().unwrap();
+        }
+        // Reopened store detects the backend on its own.
+        assert_eq!(StoreBackend::detect(path), Some(StoreBackend::Sled));
+        let store = create_store(path, &StoreConfig::default());
+        let values: Vec<_> = store.iter_prefix(ColBlockMisc, &[1]).map(|(_, v)| v).collect();
+        assert_eq!(values, vec![vec![3].into_boxed_slice()]);
+    }
+
+    fn check_checkpoint(store: &Store, snapshot_path: &std::path::Path) {
+        let mut store_update = store.store_update();
+        store_update.set(ColBlockMisc, &[1], &[1]);
+        store_update.update_refcount(ColState, &[2], &[2], 1);
+        store_update.commit().unwrap();
+        store.checkpoint(snapshot_path).unwrap();
+        let mut store_update = store.store_update();
+        store_update.set(ColBlockMisc, &[3], &[3]);
+        store_update.commit().unwrap();
+        // Path is taken now.
+        assert!(store.checkpoint(snapshot_path).is_err());
+
+        let snapshot = create_store(snapshot_path.to_str().unwrap(), &StoreConfig::default());
+        assert_eq!(snapshot.get(ColBlockMisc, &[1]).unwrap(), Some(vec![1]));
+        assert_eq!(snapshot.get(ColState, &[2]).unwrap(), Some(vec![2]));
+        assert_eq!(snapshot.get(ColBlockMisc, &[3]).unwrap(), None);
+    }
+
+    #[test]
+    fn test_checkpoint() {
+        for backend in [StoreBackend::RocksDB, StoreBackend::Sled].iter() {
+            let tmp_dir = tempfile::Builder::new().prefix("_test_checkpoint").tempdir().unwrap();
+            let store = create_store_with_config(
+                tmp_dir.path().join("data").to_str().unwrap(),
+                &StoreConfig { backend: *backend, ..Default::default() },
+            );
+            check_checkpoint(&store, &tmp_dir.path().join("snapshot"));
+        }
+    }
+
     #[test]
     fn rocksdb_merge_sanity() {
         let tmp_dir = tempfile::Builder::new().prefix("_test_snapshot_sanity").tempdir().unwrap();
-        let store = create_store(tmp_dir.path().to_str().unwrap());
+        let store = create_store(tmp_dir.path().to_str().unwrap(), &StoreConfig::default());
         let ptr = (&*store.storage) as *const (dyn Database + 'static);
         let rocksdb = unsafe { &*(ptr as *const RocksDB) };
         assert_eq!(store.get(ColState, &[1]).unwrap(), None);
@@ -701,4 +1071,47 @@ mod tests {
             assert_eq!(store.get(ColState, &[1]).unwrap(), None);
         }
     }
+
+    #[test]
+    fn test_column_config_defaults() {
+        let config = StoreConfig::default();
+        let state_config = config.column_config(ColState);
+        assert!(state_config.block_cache_size > ColumnConfig::default().block_cache_size);
+        assert!(state_config.bloom_filter_bits > ColumnConfig::default().bloom_filter_bits);
+        for col in &[ColTrieChanges, ColTransactionResult] {
+            assert_eq!(config.column_config(*col).compression, CompressionType::Zstd);
+        }
+        assert_eq!(config.column_config(ColBlockMisc), ColumnConfig::default());
+    }
+
+    #[test]
+    fn test_column_config_overrides() {
+        let config: StoreConfig = serde_json::from_str(
+            r#"{"columns": {"ColState": {"compression": "zstd", "bloom_filter_bits": 0}}}"#,
+        )
+        .unwrap();
+        let state_config = config.column_config(ColState);
+        assert_eq!(state_config.compression, CompressionType::Zstd);
+        assert_eq!(state_config.bloom_filter_bits, 0);
+        assert_eq!(
+            state_config.block_cache_size,
+            ColumnConfig::default_for(ColState).block_cache_size
+        );
+        assert_eq!(config.column_config(ColBlockMisc), ColumnConfig::default_for(ColBlockMisc));
+
+        // Overrides keep the other options of the column default.
+        let config: StoreConfig =
+            serde_json::from_str(r#"{"columns": {"ColTrieChanges": {"block_size": 4096}}}"#)
+                .unwrap();
+        let trie_changes_config = config.column_config(ColTrieChanges);
+        assert_eq!(trie_changes_config.block_size, 4096);
+        assert_eq!(trie_changes_config.compression, CompressionType::Zstd);
+
+        let tmp_dir = tempfile::Builder::new().prefix("_test_column_config").tempdir().unwrap();
+        let store = create_store_with_config(tmp_dir.path().to_str().unwrap(), &config);
+        let mut store_update = store.store_update();
+        store_update.update_refcount(ColState, &[1], &[1], 1);
+        store_update.commit().unwrap();
+        assert_eq!(store.get(ColState, &[1]).unwrap(), Some(vec![1]));
+    }
 }
@@ -0,0 +1,267 @@
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::sync::Mutex;
+
+use sled::transaction::{ConflictableTransactionResult, TransactionError};
+use sled::{Batch, IVec, Transactional, Tree};
+use strum::IntoEnumIterator;
+use tracing::error;
+
+use near_primitives::version::DbVersion;
+
+use crate::db::refcount::merge_refcounted_records;
+use crate::db::{DBCol, DBError, DBOp, DBTransaction, Database, RocksDB, VERSION_KEY};
+
+/// File which sled creates in the root of every database directory.
+pub(crate) const SLED_CONF_FILE: &str = "conf";
+/// Number of records copied into a checkpoint with one batch.
+const CHECKPOINT_BATCH_SIZE: usize = 10_000;
+
+/// Embedded on-disk database which doesn't require a native toolchain.
+/// Each column is a separate sled tree and every `DBTransaction` is applied as a single
+/// multi-tree sled transaction, so writes are atomic across columns.
+pub struct SledDB {
+    db: sled::Db,
+    trees: Vec<Tree>,
+    /// Serializes writers, so keys collected for `DBOp::DeleteAll` are still
+    /// the full content of the column when the transaction is committed.
+    write_lock: Mutex<()>,
+    /// Serializes checkpoints, only one of them can track overwritten values at a time.
+    checkpoint_lock: Mutex<()>,
+    /// While a checkpoint is copied, values keys had when it started. Writers record the value
+    /// before changing a key for the first time, `None` if the key didn't exist.
+    checkpoint_undo_log: Mutex<Option<Vec<HashMap<IVec, Option<IVec>>>>>,
+}
+
+impl SledDB {
+    /// Returns version of the database state on disk.
+    pub fn get_version<P: AsRef<std::path::Path>>(path: P) -> Result<DbVersion, DBError> {
+        let db = SledDB::new(path)?;
+        db.get(DBCol::ColDbVersion, VERSION_KEY).map(|result| {
+            serde_json::from_slice(
+                &result
+                    .expect("Failed to find version in first column. Database must be corrupted."),
+            )
+            .expect("Failed to parse version. Database must be corrupted.")
+        })
+    }
+
+    pub fn new<P: AsRef<std::path::Path>>(path: P) -> Result<Self, DBError> {
+        let db = sled::Config::new().path(path).open()?;
+        let trees = DBCol::iter()
+            .map(|col| db.open_tree(format!("col{}", col as usize)))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self {
+            db,
+            trees,
+            write_lock: Mutex::new(()),
+            checkpoint_lock: Mutex::new(()),
+            checkpoint_undo_log: Mutex::new(None),
+        })
+    }
+
+    /// Records the current values of keys changed by `transaction` into the undo log
+    /// of the running checkpoint. Must be called with `write_lock` held.
+    fn record_checkpoint_undo(
+        &self,
+        transaction: &DBTransaction,
+        keys_to_clear: &HashMap<DBCol, Vec<IVec>>,
+    ) -> Result<(), DBError> {
+        let mut undo_log = self.checkpoint_undo_log.lock().expect("Sled undo log is poisoned");
+        let undo_log = match undo_log.as_mut() {
+            Some(undo_log) => undo_log,
+            None => return Ok(()),
+        };
+        for op in transaction.ops.iter() {
+            let (col, keys): (DBCol, Vec<IVec>) = match op {
+                DBOp::Insert { col, key, .. }
+                | DBOp::UpdateRefcount { col, key, .. }
+                | DBOp::Delete { col, key } => (*col, vec![IVec::from(key.as_slice())]),
+                DBOp::DeleteAll { col } => {
+                    (*col, keys_to_clear.get(col).cloned().unwrap_or_default())
+                }
+            };
+            let col_undo_log = &mut undo_log[col as usize];
+            for key in keys {
+                if !col_undo_log.contains_key(&key) {
+                    let value = self.trees[col as usize].get(&key)?;
+                    col_undo_log.insert(key, value);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Copies the current content of all columns into `checkpoint` without blocking writers.
+    fn copy_trees(&self, checkpoint: &SledDB) -> Result<(), DBError> {
+        for (tree, checkpoint_tree) in self.trees.iter().zip(checkpoint.trees.iter()) {
+            let mut batch = Batch::default();
+            let mut batch_len = 0;
+            for item in tree.iter() {
+                let (key, value) = item?;
+                batch.insert(key, value);
+                batch_len += 1;
+                if batch_len == CHECKPOINT_BATCH_SIZE {
+                    checkpoint_tree.apply_batch(std::mem::take(&mut batch))?;
+                    batch_len = 0;
+                }
+            }
+            checkpoint_tree.apply_batch(batch)?;
+        }
+        Ok(())
+    }
+
+    /// Keys currently stored in each column cleared by `transaction`.
+    fn keys_to_clear(
+        &self,
+        transaction: &DBTransaction,
+    ) -> Result<HashMap<DBCol, Vec<IVec>>, DBError> {
+        let mut result = HashMap::new();
+        for op in transaction.ops.iter() {
+            if let DBOp::DeleteAll { col } = op {
+                if !result.contains_key(col) {
+                    let keys = self.trees[*col as usize].iter().keys().collect::<Result<_, _>>()?;
+                    result.insert(*col, keys);
+                }
+            }
+        }
+        Ok(result)
+    }
+}
+
+fn sled_item_to_boxed(item: sled::Result<(IVec, IVec)>) -> (Box<[u8]>, Box<[u8]>) {
+    let (key, value) = item.expect("Failed to read from sled database");
+    (key.to_vec().into_boxed_slice(), value.to_vec().into_boxed_slice())
+}
+
+impl Database for SledDB {
+    fn get(&self, col: DBCol, key: &[u8]) -> Result<Option<Vec<u8>>, DBError> {
+        let result = self.trees[col as usize].get(key)?.map(|value| value.to_vec());
+        Ok(RocksDB::get_with_rc_logic(col, result))
+    }
+
+    fn iter<'a>(&'a self, col: DBCol) -> Box<dyn Iterator<Item = (Box<[u8]>, Box<[u8]>)> + 'a> {
+        RocksDB::iter_with_rc_logic(col, self.iter_without_rc_logic(col))
+    }
+
+    fn iter_without_rc_logic<'a>(
+        &'a self,
+        col: DBCol,
+    ) -> Box<dyn Iterator<Item = (Box<[u8]>, Box<[u8]>)> + 'a> {
+        Box::new(self.trees[col as usize].iter().map(sled_item_to_boxed))
+    }
+
+    fn iter_prefix<'a>(
+        &'a self,
+        col: DBCol,
+        key_prefix: &'a [u8],
+    ) -> Box<dyn Iterator<Item = (Box<[u8]>, Box<[u8]>)> + 'a> {
+        let iterator = self.trees[col as usize].scan_prefix(key_prefix).map(sled_item_to_boxed);
+        RocksDB::iter_with_rc_logic(col, iterator)
+    }
+
+    fn iter_prefix_from<'a>(
+        &'a self,
+        col: DBCol,
+        key_prefix: &'a [u8],
+        from: &'a [u8],
+    ) -> Box<dyn Iterator<Item = (Box<[u8]>, Box<[u8]>)> + 'a> {
+        let from = if from < key_prefix { key_prefix } else { from };
+        let iterator = self.trees[col as usize]
+            .range(from..)
+            .map(sled_item_to_boxed)
+            .take_while(move |(key, _value)| key.starts_with(key_prefix));
+        RocksDB::iter_with_rc_logic(col, iterator)
+    }
+
+    fn write(&self, transaction: DBTransaction) -> Result<(), DBError> {
+        let _guard = self.write_lock.lock().expect("Sled write lock is poisoned");
+        let keys_to_clear = self.keys_to_clear(&transaction)?;
+        self.record_checkpoint_undo(&transaction, &keys_to_clear)?;
+        let result =
+            self.trees.as_slice().transaction(|trees| -> ConflictableTransactionResult<(), ()> {
+                // Keys written earlier in this transaction, `DBOp::DeleteAll` removes them too.
+                let mut written: HashMap<DBCol, HashSet<&[u8]>> = HashMap::new();
+                for op in transaction.ops.iter() {
+                    match op {
+                        DBOp::Insert { col, key, value } => {
+                            trees[*col as usize].insert(key.as_slice(), value.as_slice())?;
+                            written.entry(*col).or_default().insert(key.as_slice());
+                        }
+                        DBOp::UpdateRefcount { col, key, value } => {
+                            assert!(col.is_rc());
+                            let tree = &trees[*col as usize];
+                            let mut val =
+                                tree.get(key.as_slice())?.map(|v| v.to_vec()).unwrap_or_default();
+                            merge_refcounted_records(&mut val, value);
+                            if val.len() != 0 {
+                                tree.insert(key.as_slice(), val)?;
+                            } else {
+                                tree.remove(key.as_slice())?;
+                            }
+                            written.entry(*col).or_default().insert(key.as_slice());
+                        }
+                        DBOp::Delete { col, key } => {
+                            trees[*col as usize].remove(key.as_slice())?;
+                        }
+                        DBOp::DeleteAll { col } => {
+                            let tree = &trees[*col as usize];
+                            for key in keys_to_clear.get(col).into_iter().flatten() {
+                                tree.remove(key.clone())?;
+                            }
+                            for key in written.remove(col).into_iter().flatten() {
+                                tree.remove(key)?;
+                            }
+                        }
+                    }
+                }
+                Ok(())
+            });
+        match result {
+            Ok(()) => Ok(()),
+            Err(TransactionError::Abort(())) => unreachable!("Sled transaction is never aborted"),
+            Err(TransactionError::Storage(err)) => Err(err.into()),
+        }
+    }
+
+    fn checkpoint(&self, path: &Path) -> Result<(), DBError> {
+        let _checkpoint_guard =
+            self.checkpoint_lock.lock().expect("Sled checkpoint lock is poisoned");
+        let checkpoint = SledDB::new(path)?;
+        // Writes keep going while the columns are copied. Writers log the values which they
+        // overwrite, so the copy is brought back to the moment the checkpoint started afterwards.
+        {
+            let _guard = self.write_lock.lock().expect("Sled write lock is poisoned");
+            *self.checkpoint_undo_log.lock().expect("Sled undo log is poisoned") =
+                Some(vec![HashMap::new(); self.trees.len()]);
+        }
+        let copy_result = self.copy_trees(&checkpoint);
+        let undo_log = {
+            let _guard = self.write_lock.lock().expect("Sled write lock is poisoned");
+            self.checkpoint_undo_log.lock().expect("Sled undo log is poisoned").take()
+        };
+        copy_result?;
+        let undo_log = undo_log.expect("Undo log is set for the running checkpoint");
+        for (col_undo_log, checkpoint_tree) in undo_log.into_iter().zip(checkpoint.trees.iter()) {
+            let mut batch = Batch::default();
+            for (key, value) in col_undo_log {
+                match value {
+                    Some(value) => batch.insert(key, value),
+                    None => batch.remove(key),
+                }
+            }
+            checkpoint_tree.apply_batch(batch)?;
+        }
+        checkpoint.db.flush()?;
+        Ok(())
+    }
+}
+
+impl Drop for SledDB {
+    fn drop(&mut self) {
+        // Sled flushes in background periodically, make sure the tail of writes reaches the disk.
+        if let Err(err) = self.db.flush() {
+            error!(target: "store", "Failed to flush sled database: {}", err);
+        }
+    }
+}
@@ -5,7 +5,7 @@ use byteorder::{LittleEndian, ReadBytesExt};
 use rocksdb::{ColumnFamily, ColumnFamilyDescriptor, MergeOperands, Options, DB};
 use strum::IntoEnumIterator;
 
-use crate::db::{rocksdb_column_options, rocksdb_options, DBError, RocksDB};
+use crate::db::{rocksdb_column_options, rocksdb_options, ColumnConfig, DBError, RocksDB};
 use crate::DBCol;
 
 fn refcount_merge_v6(
@@ -48,7 +48,10 @@ fn merge_refcounted_records_v6(result: &mut Vec<u8>, val: &[u8]) {
 }
 
 fn rocksdb_column_options_v6(col: DBCol) -> Options {
-    let mut opts = rocksdb_column_options(DBCol::ColDbVersion);
+    let mut opts = rocksdb_column_options(
+        DBCol::ColDbVersion,
+        &ColumnConfig::default_for(DBCol::ColDbVersion),
+    );
 
     if col == DBCol::ColState {
         opts.set_merge_operator("refcount merge", refcount_merge_v6, None);
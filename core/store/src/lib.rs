@@ -1,6 +1,7 @@
 #[macro_use]
 extern crate lazy_static;
 
+use std::collections::HashMap;
 use std::fs::File;
 use std::io::{Read, Write};
 use std::ops::Deref;
@@ -12,11 +13,13 @@ use std::{fmt, io};
 use borsh::{BorshDeserialize, BorshSerialize};
 use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
 use cached::{Cached, SizedCache};
+use serde::{Deserialize, Serialize};
 
 pub use db::DBCol::{self, *};
 pub use db::{
-    CHUNK_TAIL_KEY, FINAL_HEAD_KEY, FORK_TAIL_KEY, HEADER_HEAD_KEY, HEAD_KEY,
-    LARGEST_TARGET_HEIGHT_KEY, LATEST_KNOWN_KEY, NUM_COLS, SHOULD_COL_GC, SKIP_COL_GC, TAIL_KEY,
+    ColumnConfig, ColumnConfigOverride, CompressionType, StoreBackend, CHUNK_TAIL_KEY,
+    FINAL_HEAD_KEY, FORK_TAIL_KEY, HEADER_HEAD_KEY, HEAD_KEY, LARGEST_TARGET_HEIGHT_KEY,
+    LATEST_KNOWN_KEY, NUM_COLS, SHOULD_COL_GC, SKIP_COL_GC, TAIL_KEY,
 };
 use near_crypto::PublicKey;
 use near_primitives::account::{AccessKey, Account};
@@ -31,15 +34,17 @@ use near_primitives::types::{AccountId, CompiledContractCache, StateRoot};
 pub use crate::db::refcount::decode_value_with_rc;
 use crate::db::refcount::encode_value_with_rc;
 use crate::db::{
-    DBOp, DBTransaction, Database, RocksDB, GENESIS_JSON_HASH_KEY, GENESIS_STATE_ROOTS_KEY,
+    DBOp, DBTransaction, Database, RocksDB, SledDB, GENESIS_JSON_HASH_KEY, GENESIS_STATE_ROOTS_KEY,
 };
 pub use crate::trie::{
-    iterator::TrieIterator, update::TrieUpdate, update::TrieUpdateIterator,
-    update::TrieUpdateValuePtr, KeyForStateChanges, PartialStorage, ShardTries, Trie, TrieChanges,
-    WrappedTrieChanges,
+    get_flat_state_head, get_flat_state_key, iterator::TrieIterator, update::TrieUpdate,
+    update::TrieUpdateIterator, update::TrieUpdateValuePtr, FlatState, FlatStateDelta,
+    FlatStateHeadUpdate, KeyForStateChanges, PartialStorage, ShardTries, Trie, TrieChanges,
+    TrieDiffItem, WrappedTrieChanges,
 };
 
 pub mod db;
+mod metrics;
 pub mod migrations;
 pub mod test_utils;
 mod trie;
@@ -103,6 +108,16 @@ impl Store {
         self.storage.iter_prefix(column, key_prefix)
     }
 
+    /// Iterates over keys with `key_prefix` in key order, starting from the first key `>= from`.
+    pub fn iter_prefix_from<'a>(
+        &'a self,
+        column: DBCol,
+        key_prefix: &'a [u8],
+        from: &'a [u8],
+    ) -> Box<dyn Iterator<Item = (Box<[u8]>, Box<[u8]>)> + 'a> {
+        self.storage.iter_prefix_from(column, key_prefix, from)
+    }
+
     pub fn iter_prefix_ser<'a, T: BorshDeserialize>(
         &'a self,
         column: DBCol,
@@ -145,6 +160,18 @@ impl Store {
         self.storage.write(transaction).map_err(|e| e.into())
     }
 
+    /// Creates a consistent snapshot of the whole store in a new directory at `path`.
+    /// The snapshot can be opened with `create_store` as a regular store.
+    pub fn checkpoint(&self, path: &Path) -> Result<(), std::io::Error> {
+        if path.exists() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("Snapshot path {} already exists", path.display()),
+            ));
+        }
+        self.storage.checkpoint(path).map_err(|e| e.into())
+    }
+
     pub fn get_rocksdb(&self) -> Option<&RocksDB> {
         self.storage.as_rocksdb()
     }
@@ -295,8 +322,59 @@ pub fn read_with_cache<'a, T: BorshDeserialize + 'a>(
     Ok(None)
 }
 
-pub fn create_store(path: &str) -> Arc<Store> {
-    let db = Arc::pin(RocksDB::new(path).expect("Failed to open the database"));
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct StoreConfig {
+    /// Database used for newly created stores. Existing stores are always opened
+    /// with the backend they were created with.
+    #[serde(default)]
+    pub backend: StoreBackend,
+    /// Overrides of the default RocksDB options of individual columns.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub columns: HashMap<DBCol, ColumnConfigOverride>,
+    /// Keep values of all trie keys in `ColFlatState`, so that the runtime reads them with a single
+    /// lookup instead of walking trie nodes. Existing state is migrated on startup.
+    #[serde(default)]
+    pub flat_state: bool,
+}
+
+impl StoreConfig {
+    /// RocksDB options of `col`, the defaults with configured overrides applied.
+    pub fn column_config(&self, col: DBCol) -> ColumnConfig {
+        let default = ColumnConfig::default_for(col);
+        match self.columns.get(&col) {
+            Some(config_override) => config_override.apply(default),
+            None => default,
+        }
+    }
+}
+
+/// Opens the store at `path` with the column options of `config`, using the backend the database
+/// was created with. New databases are created with the configured backend.
+pub fn create_store(path: &str, config: &StoreConfig) -> Arc<Store> {
+    let backend = StoreBackend::detect(path).unwrap_or(config.backend);
+    open_store(path, &StoreConfig { backend, ..config.clone() })
+}
+
+/// Opens the store at `path`, creating a new database with the configured backend if there is none.
+/// Panics if the existing database uses a different backend than the configured one.
+pub fn create_store_with_config(path: &str, config: &StoreConfig) -> Arc<Store> {
+    if let Some(backend) = StoreBackend::detect(path) {
+        assert_eq!(
+            backend, config.backend,
+            "Database at {} was created with {:?} backend, but {:?} is configured",
+            path, backend, config.backend
+        );
+    }
+    open_store(path, config)
+}
+
+fn open_store(path: &str, config: &StoreConfig) -> Arc<Store> {
+    let db: Pin<Arc<dyn Database>> = match config.backend {
+        StoreBackend::RocksDB => {
+            Arc::pin(RocksDB::new_with_config(path, config).expect("Failed to open the database"))
+        }
+        StoreBackend::Sled => Arc::pin(SledDB::new(path).expect("Failed to open the database")),
+    };
     Arc::new(Store::new(db))
 }
 
@@ -0,0 +1,22 @@
+use near_metrics::{try_create_int_gauge_vec, IntGaugeVec};
+
+lazy_static! {
+    pub static ref COLUMN_ESTIMATED_NUM_KEYS: near_metrics::Result<IntGaugeVec> =
+        try_create_int_gauge_vec(
+            "near_store_column_estimated_num_keys",
+            "Estimated number of keys in the database column",
+            &["column"]
+        );
+    pub static ref COLUMN_LIVE_SST_FILES_SIZE: near_metrics::Result<IntGaugeVec> =
+        try_create_int_gauge_vec(
+            "near_store_column_live_sst_files_size",
+            "Total size in bytes of SST files of the database column",
+            &["column"]
+        );
+    pub static ref COLUMN_BLOCK_CACHE_USAGE: near_metrics::Result<IntGaugeVec> =
+        try_create_int_gauge_vec(
+            "near_store_column_block_cache_usage",
+            "Memory in bytes used by the block cache of the database column",
+            &["column"]
+        );
+}
@@ -10,10 +10,12 @@ use near_primitives::sharding::{
     ReceiptList, ReceiptProof, ReedSolomonWrapper, ShardChunk, ShardChunkV1, ShardProof,
 };
 use near_primitives::transaction::ExecutionOutcomeWithIdAndProof;
+use near_primitives::types::{ChunkExtra, NumShards};
 use near_primitives::version::DbVersion;
+use tracing::info;
 
 use crate::db::DBCol::{ColBlockHeader, ColBlockMisc, ColChunks, ColPartialChunks, ColStateParts};
-use crate::db::{DBCol, RocksDB, VERSION_KEY};
+use crate::db::{DBCol, RocksDB, SledDB, StoreBackend, VERSION_KEY};
 use crate::migrations::v6_to_v7::{
     col_state_refcount_8byte, migrate_col_transaction_refcount, migrate_receipts_refcount,
 };
@@ -21,7 +23,8 @@ use crate::migrations::v8_to_v9::{
     recompute_col_rc, repair_col_receipt_id_to_shard_id, repair_col_transactions,
 };
 use crate::{
-    create_store, Store, StoreUpdate, Trie, TrieUpdate, CHUNK_TAIL_KEY, FINAL_HEAD_KEY, HEAD_KEY,
+    create_store, get_flat_state_head, ShardTries, Store, StoreConfig, StoreUpdate, Trie,
+    TrieUpdate, CHUNK_TAIL_KEY, FINAL_HEAD_KEY, HEAD_KEY,
 };
 
 use crate::trie::{TrieCache, TrieCachingStorage};
@@ -41,7 +44,11 @@ pub mod v6_to_v7;
 pub mod v8_to_v9;
 
 pub fn get_store_version(path: &str) -> DbVersion {
-    RocksDB::get_version(path).expect("Failed to open the database")
+    match StoreBackend::detect(path).unwrap_or_default() {
+        StoreBackend::RocksDB => RocksDB::get_version(path),
+        StoreBackend::Sled => SledDB::get_version(path),
+    }
+    .expect("Failed to open the database")
 }
 
 fn set_store_version_inner(store_update: &mut StoreUpdate, db_version: u32) {
@@ -128,8 +135,8 @@ pub fn migrate_6_to_7(path: &String) {
     store_update.commit().expect("Failed to migrate")
 }
 
-pub fn migrate_7_to_8(path: &String) {
-    let store = create_store(path);
+pub fn migrate_7_to_8(path: &String, store_config: &StoreConfig) {
+    let store = create_store(path, store_config);
     let mut store_update = store.store_update();
     for (key, _) in store.iter_without_rc_logic(ColStateParts) {
         store_update.delete(ColStateParts, &key);
@@ -139,15 +146,15 @@ pub fn migrate_7_to_8(path: &String) {
 }
 
 // No format change. Recompute ColTransactions and ColReceiptIdToShardId because they could be inconsistent.
-pub fn migrate_8_to_9(path: &String) {
-    let store = create_store(path);
+pub fn migrate_8_to_9(path: &String, store_config: &StoreConfig) {
+    let store = create_store(path, store_config);
     repair_col_transactions(&store);
     repair_col_receipt_id_to_shard_id(&store);
     set_store_version(&store, 9);
 }
 
-pub fn migrate_9_to_10(path: &String, is_archival: bool) {
-    let store = create_store(path);
+pub fn migrate_9_to_10(path: &String, store_config: &StoreConfig, is_archival: bool) {
+    let store = create_store(path, store_config);
     let protocol_version = 38; // protocol_version at the time this migration was written
     if is_archival {
         // Hard code the number of parts there. These numbers are only used for this migration.
@@ -232,8 +239,8 @@ pub fn migrate_9_to_10(path: &String, is_archival: bool) {
     set_store_version(&store, 10);
 }
 
-pub fn migrate_10_to_11(path: &String) {
-    let store = create_store(path);
+pub fn migrate_10_to_11(path: &String, store_config: &StoreConfig) {
+    let store = create_store(path, store_config);
     let mut store_update = store.store_update();
     let head = store.get_ser::<Tip>(ColBlockMisc, HEAD_KEY).unwrap().expect("head must exist");
     let block_header = store
@@ -262,8 +269,8 @@ pub fn migrate_10_to_11(path: &String) {
     set_store_version(&store, 11);
 }
 
-pub fn migrate_11_to_12(path: &String) {
-    let store = create_store(path);
+pub fn migrate_11_to_12(path: &String, store_config: &StoreConfig) {
+    let store = create_store(path, store_config);
     recompute_col_rc(
         &store,
         DBCol::ColReceipts,
@@ -339,8 +346,8 @@ where
 }
 
 /// Lift all chunks to the versioned structure
-pub fn migrate_13_to_14(path: &String) {
-    let store = create_store(path);
+pub fn migrate_13_to_14(path: &String, store_config: &StoreConfig) {
+    let store = create_store(path, store_config);
 
     map_col(&store, DBCol::ColPartialChunks, |pec: PartialEncodedChunkV1| {
         PartialEncodedChunk::V1(pec)
@@ -360,8 +367,8 @@ pub fn migrate_13_to_14(path: &String) {
 }
 
 /// Make execution outcome ids in `ColOutcomeIds` ordered by replaying the chunks.
-pub fn migrate_14_to_15(path: &String) {
-    let store = create_store(path);
+pub fn migrate_14_to_15(path: &String, store_config: &StoreConfig) {
+    let store = create_store(path, store_config);
     let trie_store = Box::new(TrieCachingStorage::new(store.clone(), TrieCache::new(), 0));
     let trie = Rc::new(Trie::new(trie_store, 0));
 
@@ -514,7 +521,7 @@ pub fn migrate_14_to_15(path: &String) {
     set_store_version(&store, 15);
 }
 
-pub fn migrate_17_to_18(path: &String) {
+pub fn migrate_17_to_18(path: &String, store_config: &StoreConfig) {
     use std::convert::TryFrom;
 
     use near_primitives::challenge::SlashedValidator;
@@ -552,7 +559,7 @@ pub fn migrate_17_to_18(path: &String) {
         pub slashed: Vec<SlashedValidator>,
         pub total_supply: Balance,
     }
-    let store = create_store(path);
+    let store = create_store(path, store_config);
     map_col_from_key(&store, DBCol::ColBlockInfo, |key| {
         let hash = CryptoHash::try_from(key).unwrap();
         let old_block_info =
@@ -601,8 +608,70 @@ pub fn migrate_17_to_18(path: &String) {
     set_store_version(&store, 18);
 }
 
+/// Add `score` to `KnownPeerState` in ColPeers. It's the last field, so appending the default
+/// score to the serialized state is enough.
+pub fn migrate_18_to_19(path: &String, store_config: &StoreConfig) {
+    let store = create_store(path, store_config);
+    let mut store_update = store.store_update();
+    for (key, value) in store.iter(DBCol::ColPeers) {
+        let mut value = value.into_vec();
+        value.extend(0i32.try_to_vec().unwrap());
+        store_update.set(DBCol::ColPeers, &key, &value);
+    }
+    store_update.commit().expect("storage update should not fail");
+    set_store_version(&store, 19);
+}
+
+/// Store `KnownPeerState` in ColPeers as a versioned enum, so the extra addresses of the peers
+/// can be persisted. The existing states become its first variant.
+pub fn migrate_19_to_20(path: &String, store_config: &StoreConfig) {
+    let store = create_store(path, store_config);
+    let mut store_update = store.store_update();
+    for (key, value) in store.iter(DBCol::ColPeers) {
+        let mut versioned_value = vec![0u8];
+        versioned_value.extend_from_slice(&value);
+        store_update.set(DBCol::ColPeers, &key, &versioned_value);
+    }
+    store_update.commit().expect("storage update should not fail");
+    set_store_version(&store, 20);
+}
+
+/// Builds flat state at the final head for the shards which don't have it, which is the case
+/// when `StoreConfig::flat_state` gets enabled or flat state was dropped. Doesn't change the
+/// version, as flat state is optional.
+pub fn migrate_flat_state(path: &String, store_config: &StoreConfig, num_shards: NumShards) {
+    let store = create_store(path, store_config);
+    let final_head = match store.get_ser::<Tip>(ColBlockMisc, FINAL_HEAD_KEY).unwrap() {
+        Some(final_head) => final_head,
+        // Flat state of a new node is built together with the genesis state.
+        None => return,
+    };
+    let tries = ShardTries::new(store.clone(), num_shards);
+    for shard_id in 0..num_shards {
+        if get_flat_state_head(&store, shard_id).unwrap().is_some() {
+            continue;
+        }
+        let chunk_extra = match store
+            .get_ser::<ChunkExtra>(
+                DBCol::ColChunkExtra,
+                &get_block_shard_id(&final_head.last_block_hash, shard_id),
+            )
+            .unwrap()
+        {
+            Some(chunk_extra) => chunk_extra,
+            // The shard is not tracked.
+            None => continue,
+        };
+        info!(target: "near", "Building flat state of shard {} at {}", shard_id, final_head.last_block_hash);
+        let num_values = tries
+            .build_flat_state(shard_id, &final_head.last_block_hash, &chunk_extra.state_root)
+            .expect("Failed to build flat state");
+        info!(target: "near", "Built flat state of shard {} with {} values", shard_id, num_values);
+    }
+}
+
 #[cfg(feature = "protocol_feature_rectify_inflation")]
-pub fn migrate_18_to_rectify_inflation(path: &String) {
+pub fn migrate_18_to_rectify_inflation(path: &String, store_config: &StoreConfig) {
     use near_primitives::epoch_manager::BlockInfo;
     use near_primitives::epoch_manager::SlashState;
     use near_primitives::types::{AccountId, Balance, BlockHeight, EpochId, ValidatorStake};
@@ -622,7 +691,7 @@ pub fn migrate_18_to_rectify_inflation(path: &String) {
         pub slashed: HashMap<AccountId, SlashState>,
         pub total_supply: Balance,
     }
-    let store = create_store(path);
+    let store = create_store(path, store_config);
     map_col_from_key(&store, DBCol::ColBlockInfo, |key| {
         let block_header =
             store.get_ser::<BlockHeader>(DBCol::ColBlockHeader, key).unwrap().unwrap();
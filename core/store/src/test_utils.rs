@@ -4,7 +4,11 @@ use std::sync::Arc;
 use rand::seq::SliceRandom;
 use rand::Rng;
 
+#[cfg(test)]
+use crate::db::StoreBackend;
 use crate::db::TestDB;
+#[cfg(test)]
+use crate::{create_store_with_config, StoreConfig};
 use crate::{ShardTries, Store};
 use near_primitives::hash::CryptoHash;
 use near_primitives::types::ShardId;
@@ -15,6 +19,28 @@ pub fn create_test_store() -> Arc<Store> {
     Arc::new(Store::new(db))
 }
 
+/// Creates a database with the given backend in a temporary directory, which is removed when
+/// the returned `TempDir` is dropped.
+#[cfg(test)]
+pub fn create_test_store_with_backend(backend: StoreBackend) -> (tempfile::TempDir, Arc<Store>) {
+    let tmp_dir = tempfile::Builder::new().prefix("_test_store").tempdir().unwrap();
+    let store = create_store_with_config(
+        tmp_dir.path().to_str().unwrap(),
+        &StoreConfig { backend, ..Default::default() },
+    );
+    (tmp_dir, store)
+}
+
+/// Runs `test` against an in-memory database and against each of the on-disk backends.
+#[cfg(test)]
+pub fn with_test_stores(mut test: impl FnMut(Arc<Store>)) {
+    test(create_test_store());
+    for backend in [StoreBackend::RocksDB, StoreBackend::Sled].iter() {
+        let (_tmp_dir, store) = create_test_store_with_backend(*backend);
+        test(store);
+    }
+}
+
 /// Creates a Trie using an in-memory database.
 pub fn create_tries() -> ShardTries {
     let store = create_test_store();
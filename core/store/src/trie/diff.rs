@@ -0,0 +1,297 @@
+use near_primitives::hash::CryptoHash;
+use near_primitives::types::StateRoot;
+
+use crate::trie::nibble_slice::NibbleSlice;
+use crate::trie::{TrieNode, ValueHandle};
+use crate::{StorageError, Trie};
+
+/// Single key which has different values in two versions of the state.
+/// `None` means the key is absent in the corresponding state.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TrieDiffItem {
+    pub key: Vec<u8>,
+    pub old_value: Option<Vec<u8>>,
+    pub new_value: Option<Vec<u8>>,
+}
+
+/// Position in one of the tries being compared. Paths and keys are in nibbles.
+#[derive(Debug)]
+enum DiffEntry {
+    /// Not yet expanded subtree with all keys starting with `path`.
+    Node { path: Vec<u8>, hash: CryptoHash },
+    /// Value stored under `key`.
+    Value { key: Vec<u8>, hash: CryptoHash },
+}
+
+impl DiffEntry {
+    fn path(&self) -> &[u8] {
+        match self {
+            DiffEntry::Node { path, .. } => path,
+            DiffEntry::Value { key, .. } => key,
+        }
+    }
+
+    fn is_node(&self) -> bool {
+        match self {
+            DiffEntry::Node { .. } => true,
+            DiffEntry::Value { .. } => false,
+        }
+    }
+}
+
+/// Side of the diff, entries are kept in reverse key order so that the smallest one is on top.
+type DiffStack = Vec<DiffEntry>;
+
+fn nibbles_to_bytes(nibbles: &[u8]) -> Vec<u8> {
+    assert_eq!(nibbles.len() % 2, 0, "Trie value key must consist of whole bytes");
+    nibbles.chunks(2).map(|pair| pair[0] * 16 + pair[1]).collect()
+}
+
+fn append_encoded(path: &[u8], encoded_key: &[u8]) -> Vec<u8> {
+    let mut result = path.to_vec();
+    result.extend(NibbleSlice::from_encoded(encoded_key).0.iter());
+    result
+}
+
+fn value_hash(value: &ValueHandle) -> CryptoHash {
+    match value {
+        ValueHandle::HashAndSize(_, hash) => *hash,
+        ValueHandle::InMemory(_) => unreachable!("Stored trie nodes never have in-memory values"),
+    }
+}
+
+impl Trie {
+    /// Returns all keys whose values differ between the states at `old_root` and `new_root`,
+    /// sorted by key. Both tries are walked simultaneously and subtrees with the same hash at
+    /// the same path are skipped, so the cost is proportional to the size of the difference.
+    ///
+    /// If `after` is given, only keys greater than it are returned, and subtrees with all keys
+    /// not greater than it are not visited. If `limit` is given, at most `limit` smallest changed
+    /// keys are returned, so the next page starts after the last returned key.
+    pub fn diff(
+        &self,
+        old_root: &StateRoot,
+        new_root: &StateRoot,
+        after: Option<&[u8]>,
+        limit: Option<usize>,
+    ) -> Result<Vec<TrieDiffItem>, StorageError> {
+        let mut old: DiffStack = vec![DiffEntry::Node { path: vec![], hash: *old_root }];
+        let mut new: DiffStack = vec![DiffEntry::Node { path: vec![], hash: *new_root }];
+        if let Some(after) = after {
+            let after = NibbleSlice::new(after).iter().collect::<Vec<_>>();
+            self.skip_until_after(&mut old, &after)?;
+            self.skip_until_after(&mut new, &after)?;
+        }
+        let mut result = vec![];
+        while limit.map_or(true, |limit| result.len() < limit) {
+            let (old_top, new_top) = match (old.last(), new.last()) {
+                (None, None) => break,
+                (Some(_), None) => {
+                    self.advance_one_side(&mut old, &mut result, true)?;
+                    continue;
+                }
+                (None, Some(_)) => {
+                    self.advance_one_side(&mut new, &mut result, false)?;
+                    continue;
+                }
+                (Some(old_top), Some(new_top)) => (old_top, new_top),
+            };
+            match (old_top, new_top) {
+                (
+                    DiffEntry::Node { path: old_path, hash: old_hash },
+                    DiffEntry::Node { path: new_path, hash: new_hash },
+                ) if old_path == new_path && old_hash == new_hash => {
+                    old.pop();
+                    new.pop();
+                    continue;
+                }
+                (
+                    DiffEntry::Value { key: old_key, hash: old_hash },
+                    DiffEntry::Value { key: new_key, hash: new_hash },
+                ) if old_key == new_key => {
+                    if old_hash != new_hash {
+                        result.push(TrieDiffItem {
+                            key: nibbles_to_bytes(old_key),
+                            old_value: Some(self.retrieve_raw_bytes(old_hash)?),
+                            new_value: Some(self.retrieve_raw_bytes(new_hash)?),
+                        });
+                    }
+                    old.pop();
+                    new.pop();
+                    continue;
+                }
+                _ => {}
+            }
+            // A node has to be expanded if it may contain the key of the other side.
+            if old_top.is_node() && new_top.path().starts_with(old_top.path()) {
+                self.expand(&mut old)?;
+            } else if new_top.is_node() && old_top.path().starts_with(new_top.path()) {
+                self.expand(&mut new)?;
+            } else if old_top.path() < new_top.path() {
+                self.advance_one_side(&mut old, &mut result, true)?;
+            } else {
+                self.advance_one_side(&mut new, &mut result, false)?;
+            }
+        }
+        Ok(result)
+    }
+
+    /// Drops the entries with keys not greater than `after` from the top of the stack, expanding
+    /// the nodes which have keys on both sides of it.
+    fn skip_until_after(&self, stack: &mut DiffStack, after: &[u8]) -> Result<(), StorageError> {
+        loop {
+            match stack.last() {
+                Some(DiffEntry::Value { key, .. }) if key.as_slice() <= after => {
+                    stack.pop();
+                }
+                Some(DiffEntry::Node { path, .. }) if after.starts_with(path) => {
+                    self.expand(stack)?
+                }
+                Some(DiffEntry::Node { path, .. }) if path.as_slice() < after => {
+                    stack.pop();
+                }
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    /// Handles the top entry of one side, which is known to have no counterpart on the other.
+    fn advance_one_side(
+        &self,
+        stack: &mut DiffStack,
+        result: &mut Vec<TrieDiffItem>,
+        is_old: bool,
+    ) -> Result<(), StorageError> {
+        match stack.last() {
+            Some(DiffEntry::Node { .. }) => self.expand(stack),
+            Some(DiffEntry::Value { key, hash }) => {
+                let key = nibbles_to_bytes(key);
+                let value = Some(self.retrieve_raw_bytes(hash)?);
+                let (old_value, new_value) = if is_old { (value, None) } else { (None, value) };
+                result.push(TrieDiffItem { key, old_value, new_value });
+                stack.pop();
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Replaces the node on top of the stack with its children.
+    fn expand(&self, stack: &mut DiffStack) -> Result<(), StorageError> {
+        let (path, hash) = match stack.pop() {
+            Some(DiffEntry::Node { path, hash }) => (path, hash),
+            entry => unreachable!("Only nodes can be expanded, got {:?}", entry),
+        };
+        match self.retrieve_node(&hash)?.node {
+            TrieNode::Empty => {}
+            TrieNode::Leaf(key, value) => {
+                stack.push(DiffEntry::Value {
+                    key: append_encoded(&path, &key),
+                    hash: value_hash(&value),
+                });
+            }
+            TrieNode::Extension(key, child) => {
+                stack.push(DiffEntry::Node {
+                    path: append_encoded(&path, &key),
+                    hash: *child.unwrap_hash(),
+                });
+            }
+            TrieNode::Branch(children, value) => {
+                for (i, child) in children.iter().enumerate().rev() {
+                    if let Some(child) = child {
+                        let mut child_path = path.clone();
+                        child_path.push(i as u8);
+                        stack
+                            .push(DiffEntry::Node { path: child_path, hash: *child.unwrap_hash() });
+                    }
+                }
+                if let Some(value) = value {
+                    stack.push(DiffEntry::Value { key: path, hash: value_hash(&value) });
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use rand::Rng;
+
+    use crate::test_utils::{create_tries, gen_changes, simplify_changes, test_populate_trie};
+    use crate::Trie;
+
+    use super::TrieDiffItem;
+
+    #[test]
+    fn test_trie_diff() {
+        let mut rng = rand::thread_rng();
+        for _ in 0..50 {
+            let tries = create_tries();
+            let trie = tries.get_trie_for_shard(0);
+            let old_changes = simplify_changes(&gen_changes(&mut rng, 30));
+            let old_root = test_populate_trie(&tries, &Trie::empty_root(), 0, old_changes.clone());
+            let new_changes = gen_changes(&mut rng, 30);
+            let new_root = test_populate_trie(&tries, &old_root, 0, new_changes.clone());
+
+            let mut old_state: BTreeMap<Vec<u8>, Vec<u8>> = BTreeMap::new();
+            for (key, value) in old_changes {
+                old_state.insert(key, value.unwrap());
+            }
+            let mut new_state = old_state.clone();
+            for (key, value) in new_changes {
+                match value {
+                    Some(value) => new_state.insert(key, value),
+                    None => new_state.remove(&key),
+                };
+            }
+            let mut keys: Vec<_> = old_state.keys().chain(new_state.keys()).cloned().collect();
+            keys.sort();
+            keys.dedup();
+            let expected: Vec<_> = keys
+                .into_iter()
+                .filter_map(|key| {
+                    let old_value = old_state.get(&key).cloned();
+                    let new_value = new_state.get(&key).cloned();
+                    if old_value == new_value {
+                        None
+                    } else {
+                        Some(TrieDiffItem { key, old_value, new_value })
+                    }
+                })
+                .collect();
+
+            assert_eq!(trie.diff(&old_root, &new_root, None, None).unwrap(), expected);
+            assert!(trie.diff(&new_root, &new_root, None, None).unwrap().is_empty());
+            let limit = rng.gen_range(0, expected.len() + 1);
+            assert_eq!(
+                trie.diff(&old_root, &new_root, None, Some(limit)).unwrap(),
+                expected[..limit]
+            );
+
+            // Paging through the diff with the last returned key as the cursor yields all of it.
+            let mut pages = vec![];
+            let mut after: Option<Vec<u8>> = None;
+            loop {
+                let page = trie.diff(&old_root, &new_root, after.as_deref(), Some(3)).unwrap();
+                if page.is_empty() {
+                    break;
+                }
+                after = Some(page.last().unwrap().key.clone());
+                pages.extend(page);
+            }
+            assert_eq!(pages, expected);
+            // The cursor doesn't have to be a changed key.
+            let after: Vec<u8> =
+                (0..rng.gen_range(1, 4)).map(|_| b"abcdefgh"[rng.gen_range(0, 8)]).collect();
+            let expected_after: Vec<_> =
+                expected.iter().filter(|item| item.key > after).cloned().collect();
+            assert_eq!(
+                trie.diff(&old_root, &new_root, Some(&after), None).unwrap(),
+                expected_after
+            );
+        }
+    }
+}
@@ -0,0 +1,405 @@
+use std::collections::BTreeMap;
+use std::io;
+use std::sync::Arc;
+
+use borsh::{BorshDeserialize, BorshSerialize};
+
+use near_primitives::hash::CryptoHash;
+use near_primitives::types::{RawStateChange, RawStateChangesWithTrieKey, ShardId, StateRoot};
+use near_primitives::utils::get_block_shard_id;
+
+use crate::db::FLAT_STATE_HEAD_KEY;
+use crate::{DBCol, ShardTries, StorageError, Store, StoreUpdate};
+
+/// Number of values written to the store at once while building flat state from the trie.
+const FLAT_STATE_MIGRATION_BATCH_SIZE: usize = 10_000;
+
+/// Values of trie keys changed by applying the chunk of a shard in a block.
+/// `None` means the key was removed.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct FlatStateDelta {
+    pub prev_block_hash: CryptoHash,
+    pub changes: BTreeMap<Vec<u8>, Option<Vec<u8>>>,
+}
+
+impl FlatStateDelta {
+    pub fn from_state_changes(
+        prev_block_hash: CryptoHash,
+        state_changes: &[RawStateChangesWithTrieKey],
+    ) -> Self {
+        let changes = state_changes
+            .iter()
+            .filter_map(|changes_with_trie_key| {
+                let RawStateChange { data, .. } = changes_with_trie_key.changes.last()?;
+                Some((changes_with_trie_key.trie_key.to_vec(), data.clone()))
+            })
+            .collect();
+        FlatStateDelta { prev_block_hash, changes }
+    }
+
+    /// Applies changes of a later block on top of this one.
+    fn merge(&mut self, other: FlatStateDelta) {
+        self.changes.extend(other.changes);
+    }
+}
+
+/// Result of moving flat state head of a shard to a new final block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlatStateHeadUpdate {
+    /// Flat state head is at the block now.
+    Moved,
+    /// There is no flat state for the shard, it has to be built from the trie.
+    Missing,
+    /// The block doesn't descend from the flat state head through stored deltas,
+    /// so flat state of the shard is dropped and has to be built again.
+    Dropped,
+}
+
+pub fn get_flat_state_key(shard_id: ShardId, raw_key: &[u8]) -> Vec<u8> {
+    let mut key = Vec::with_capacity(8 + raw_key.len());
+    key.extend_from_slice(&shard_id.to_le_bytes());
+    key.extend_from_slice(raw_key);
+    key
+}
+
+fn get_flat_state_head_key(shard_id: ShardId) -> Vec<u8> {
+    let mut key = FLAT_STATE_HEAD_KEY.to_vec();
+    key.extend_from_slice(&shard_id.to_le_bytes());
+    key
+}
+
+/// Block at which `ColFlatState` holds the state of the shard, if flat state is built for it.
+pub fn get_flat_state_head(store: &Store, shard_id: ShardId) -> io::Result<Option<CryptoHash>> {
+    store.get_ser(DBCol::ColBlockMisc, &get_flat_state_head_key(shard_id))
+}
+
+fn get_flat_state_delta(
+    store: &Store,
+    shard_id: ShardId,
+    block_hash: &CryptoHash,
+) -> io::Result<Option<FlatStateDelta>> {
+    store.get_ser(DBCol::ColFlatStateDeltas, &get_block_shard_id(block_hash, shard_id))
+}
+
+pub fn set_flat_state_delta(
+    store_update: &mut StoreUpdate,
+    shard_id: ShardId,
+    block_hash: &CryptoHash,
+    delta: &FlatStateDelta,
+) -> io::Result<()> {
+    store_update.set_ser(
+        DBCol::ColFlatStateDeltas,
+        &get_block_shard_id(block_hash, shard_id),
+        delta,
+    )
+}
+
+/// Collects deltas of blocks from `block_hash` back to the flat state head, newest first.
+/// Returns `None` if a delta on the way is missing.
+fn get_deltas_since_head(
+    store: &Store,
+    shard_id: ShardId,
+    flat_head: &CryptoHash,
+    block_hash: &CryptoHash,
+) -> io::Result<Option<Vec<(CryptoHash, FlatStateDelta)>>> {
+    let mut deltas = vec![];
+    let mut block_hash = *block_hash;
+    while &block_hash != flat_head {
+        match get_flat_state_delta(store, shard_id, &block_hash)? {
+            Some(delta) => {
+                let prev_block_hash = delta.prev_block_hash;
+                deltas.push((block_hash, delta));
+                block_hash = prev_block_hash;
+            }
+            None => return Ok(None),
+        }
+    }
+    Ok(Some(deltas))
+}
+
+/// Read-only view of the state of a shard at a block, served from `ColFlatState` and deltas of
+/// the blocks after the flat state head instead of trie nodes.
+pub struct FlatState {
+    store: Arc<Store>,
+    shard_id: ShardId,
+    state_root: StateRoot,
+    /// Deltas of blocks after the flat state head, newest first.
+    deltas: Vec<FlatStateDelta>,
+}
+
+impl FlatState {
+    /// Returns `None` if there is no flat state for the shard or the block can't be reached from
+    /// its head, in which case the state has to be read from the trie.
+    pub fn new(
+        store: Arc<Store>,
+        shard_id: ShardId,
+        block_hash: &CryptoHash,
+        state_root: StateRoot,
+    ) -> Result<Option<Self>, StorageError> {
+        let flat_head = match get_flat_state_head(&store, shard_id)
+            .map_err(|_| StorageError::StorageInternalError)?
+        {
+            Some(flat_head) => flat_head,
+            None => return Ok(None),
+        };
+        let deltas = match get_deltas_since_head(&store, shard_id, &flat_head, block_hash)
+            .map_err(|_| StorageError::StorageInternalError)?
+        {
+            Some(deltas) => deltas.into_iter().map(|(_, delta)| delta).collect(),
+            None => return Ok(None),
+        };
+        Ok(Some(FlatState { store, shard_id, state_root, deltas }))
+    }
+
+    /// State root of the block this view was created for.
+    pub fn state_root(&self) -> &StateRoot {
+        &self.state_root
+    }
+
+    pub fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, StorageError> {
+        for delta in self.deltas.iter() {
+            if let Some(value) = delta.changes.get(key) {
+                return Ok(value.clone());
+            }
+        }
+        self.store
+            .get(DBCol::ColFlatState, &get_flat_state_key(self.shard_id, key))
+            .map_err(|_| StorageError::StorageInternalError)
+    }
+}
+
+impl ShardTries {
+    /// Makes this instance save flat state deltas of applied chunks and read the state through
+    /// flat state where it is available.
+    pub fn enable_flat_state(&mut self) {
+        self.flat_state_enabled = true;
+    }
+
+    pub fn is_flat_state_enabled(&self) -> bool {
+        self.flat_state_enabled
+    }
+
+    /// Moves flat state head of the shard to the new final block `block_hash`, merging deltas of
+    /// the blocks in between into `ColFlatState`.
+    pub fn update_flat_state_head(
+        &self,
+        shard_id: ShardId,
+        block_hash: &CryptoHash,
+        store_update: &mut StoreUpdate,
+    ) -> Result<FlatStateHeadUpdate, StorageError> {
+        let flat_head = match get_flat_state_head(&self.store, shard_id)
+            .map_err(|_| StorageError::StorageInternalError)?
+        {
+            Some(flat_head) => flat_head,
+            None => return Ok(FlatStateHeadUpdate::Missing),
+        };
+        let deltas = match get_deltas_since_head(&self.store, shard_id, &flat_head, block_hash)
+            .map_err(|_| StorageError::StorageInternalError)?
+        {
+            Some(deltas) => deltas,
+            None => {
+                store_update.delete(DBCol::ColBlockMisc, &get_flat_state_head_key(shard_id));
+                return Ok(FlatStateHeadUpdate::Dropped);
+            }
+        };
+        let mut merged = FlatStateDelta::default();
+        for (delta_block_hash, delta) in deltas.into_iter().rev() {
+            store_update.delete(
+                DBCol::ColFlatStateDeltas,
+                &get_block_shard_id(&delta_block_hash, shard_id),
+            );
+            merged.merge(delta);
+        }
+        for (key, value) in merged.changes {
+            let key = get_flat_state_key(shard_id, &key);
+            match value {
+                Some(value) => store_update.set(DBCol::ColFlatState, &key, &value),
+                None => store_update.delete(DBCol::ColFlatState, &key),
+            }
+        }
+        store_update
+            .set_ser(DBCol::ColBlockMisc, &get_flat_state_head_key(shard_id), block_hash)
+            .map_err(|_| StorageError::StorageInternalError)?;
+        Ok(FlatStateHeadUpdate::Moved)
+    }
+
+    /// Writes all values of the trie with `state_root` into `ColFlatState` and sets the flat
+    /// state head of the shard to `block_hash`. Returns the number of written values.
+    /// Run by the store migration on startup, so that existing nodes can switch to flat state.
+    pub fn build_flat_state(
+        &self,
+        shard_id: ShardId,
+        block_hash: &CryptoHash,
+        state_root: &StateRoot,
+    ) -> Result<u64, StorageError> {
+        let prefix = shard_id.to_le_bytes();
+
+        // Values left from an interrupted migration or a dropped flat state.
+        let stale_keys: Vec<_> =
+            self.store.iter_prefix(DBCol::ColFlatState, &prefix).map(|(key, _)| key).collect();
+        for keys in stale_keys.chunks(FLAT_STATE_MIGRATION_BATCH_SIZE) {
+            let mut store_update = self.store.store_update();
+            for key in keys {
+                store_update.delete(DBCol::ColFlatState, key);
+            }
+            store_update.commit().map_err(|_| StorageError::StorageInternalError)?;
+        }
+
+        let trie = self.get_view_trie_for_shard(shard_id);
+        let mut num_values = 0;
+        let mut store_update = self.store.store_update();
+        for item in trie.iter(state_root)? {
+            let (key, value) = item?;
+            store_update.set(DBCol::ColFlatState, &get_flat_state_key(shard_id, &key), &value);
+            num_values += 1;
+            if num_values % FLAT_STATE_MIGRATION_BATCH_SIZE as u64 == 0 {
+                std::mem::replace(&mut store_update, self.store.store_update())
+                    .commit()
+                    .map_err(|_| StorageError::StorageInternalError)?;
+            }
+        }
+        store_update
+            .set_ser(DBCol::ColBlockMisc, &get_flat_state_head_key(shard_id), block_hash)
+            .map_err(|_| StorageError::StorageInternalError)?;
+        store_update.commit().map_err(|_| StorageError::StorageInternalError)?;
+        Ok(num_values)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::rc::Rc;
+
+    use rand::Rng;
+
+    use near_primitives::hash::{hash, CryptoHash};
+    use near_primitives::trie_key::TrieKey;
+
+    use crate::test_utils::{create_tries, gen_changes, simplify_changes, test_populate_trie};
+    use crate::trie::flat_state::{set_flat_state_delta, FlatState, FlatStateDelta};
+    use crate::{FlatStateHeadUpdate, Trie, TrieUpdate, TrieUpdateValuePtr};
+
+    #[test]
+    fn test_flat_state_matches_trie() {
+        let mut rng = rand::thread_rng();
+        for _ in 0..20 {
+            let mut tries = create_tries();
+            tries.enable_flat_state();
+            let store = tries.get_store();
+            let trie = tries.get_trie_for_shard(0);
+            let genesis_hash = CryptoHash::default();
+            let mut root = test_populate_trie(
+                &tries,
+                &Trie::empty_root(),
+                0,
+                simplify_changes(&gen_changes(&mut rng, 30)),
+            );
+            let num_values = tries.build_flat_state(0, &genesis_hash, &root).unwrap();
+            assert_eq!(num_values, trie.iter(&root).unwrap().count() as u64);
+
+            let mut prev_block_hash = genesis_hash;
+            let mut blocks = vec![];
+            for height in 1..5u64 {
+                let block_hash = hash(&height.to_le_bytes());
+                let changes = gen_changes(&mut rng, 20);
+                root = test_populate_trie(&tries, &root, 0, changes.clone());
+                let delta = FlatStateDelta {
+                    prev_block_hash,
+                    changes: changes.into_iter().collect::<BTreeMap<_, _>>(),
+                };
+                let mut store_update = store.store_update();
+                set_flat_state_delta(&mut store_update, 0, &block_hash, &delta).unwrap();
+                store_update.commit().unwrap();
+                blocks.push((block_hash, root));
+                prev_block_hash = block_hash;
+            }
+
+            let check = |block_hash, root| {
+                let flat_state =
+                    FlatState::new(store.clone(), 0, block_hash, root).unwrap().unwrap();
+                for item in trie.iter(&root).unwrap() {
+                    let (key, value) = item.unwrap();
+                    assert_eq!(flat_state.get(&key).unwrap(), Some(value));
+                }
+            };
+            for (block_hash, root) in blocks.iter() {
+                check(block_hash, *root);
+            }
+
+            let (final_hash, _) = blocks[1];
+            let mut store_update = store.store_update();
+            assert_eq!(
+                tries.update_flat_state_head(0, &final_hash, &mut store_update).unwrap(),
+                FlatStateHeadUpdate::Moved
+            );
+            store_update.commit().unwrap();
+            assert!(FlatState::new(store.clone(), 0, &genesis_hash, root).unwrap().is_none());
+            for (block_hash, root) in blocks[1..].iter() {
+                check(block_hash, *root);
+            }
+
+            let mut store_update = store.store_update();
+            assert_eq!(
+                tries.update_flat_state_head(0, &hash(b"unknown"), &mut store_update).unwrap(),
+                FlatStateHeadUpdate::Dropped
+            );
+            store_update.commit().unwrap();
+            let mut store_update = store.store_update();
+            assert_eq!(
+                tries.update_flat_state_head(0, &final_hash, &mut store_update).unwrap(),
+                FlatStateHeadUpdate::Missing
+            );
+        }
+    }
+
+    #[test]
+    fn test_flat_state_reads_match_trie_reads() {
+        let mut rng = rand::thread_rng();
+        let contract_key =
+            |key: Vec<u8>| TrieKey::ContractData { account_id: "alice".to_string(), key };
+        for _ in 0..20 {
+            let mut tries = create_tries();
+            tries.enable_flat_state();
+            let changes = simplify_changes(&gen_changes(&mut rng, 30));
+            let root = test_populate_trie(
+                &tries,
+                &Trie::empty_root(),
+                0,
+                changes
+                    .iter()
+                    .map(|(key, value)| (contract_key(key.clone()).to_vec(), value.clone()))
+                    .collect(),
+            );
+            let block_hash = hash(b"block");
+            tries.build_flat_state(0, &block_hash, &root).unwrap();
+
+            let trie_update = tries.new_trie_update(0, root);
+            let flat_update = TrieUpdate::new(
+                Rc::new(tries.get_trie_with_flat_state_for_shard(0, &block_hash, &root).unwrap()),
+                root,
+            );
+            // Both keys present in the state and missing ones.
+            let keys = changes.into_iter().chain(gen_changes(&mut rng, 30)).map(|(key, _)| key);
+            for key in keys {
+                let key = contract_key(key);
+                assert_eq!(flat_update.get(&key).unwrap(), trie_update.get(&key).unwrap());
+
+                // Contract storage reads touch the same number of nodes with flat state.
+                trie_update.trie.counter.reset();
+                flat_update.trie.counter.reset();
+                let trie_value = trie_update
+                    .get_ref(&key)
+                    .unwrap()
+                    .map(|ptr| (ptr.len(), ptr.deref_value().unwrap()));
+                let flat_ptr = flat_update.get_ref(&key).unwrap();
+                if let Some(ptr) = flat_ptr.as_ref() {
+                    assert!(matches!(ptr, TrieUpdateValuePtr::FlatState(..)));
+                }
+                let flat_value = flat_ptr.map(|ptr| (ptr.len(), ptr.deref_value().unwrap()));
+                assert_eq!(flat_value, trie_value);
+                assert_eq!(flat_update.trie.counter.get(), trie_update.trie.counter.get());
+            }
+        }
+    }
+}
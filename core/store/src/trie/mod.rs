@@ -11,8 +11,13 @@ use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
 
 use near_primitives::challenge::PartialState;
 use near_primitives::hash::{hash, CryptoHash};
+use near_primitives::serialize::to_base;
 use near_primitives::types::{ShardId, StateRoot, StateRootNode};
 
+pub use crate::trie::diff::TrieDiffItem;
+pub use crate::trie::flat_state::{
+    get_flat_state_head, get_flat_state_key, FlatState, FlatStateDelta, FlatStateHeadUpdate,
+};
 use crate::trie::insert_delete::NodesStorage;
 use crate::trie::iterator::TrieIterator;
 use crate::trie::nibble_slice::NibbleSlice;
@@ -23,6 +28,8 @@ use crate::trie::trie_storage::{
 pub(crate) use crate::trie::trie_storage::{TrieCache, TrieCachingStorage};
 use crate::StorageError;
 
+mod diff;
+pub(crate) mod flat_state;
 mod insert_delete;
 pub mod iterator;
 mod nibble_slice;
@@ -407,6 +414,8 @@ impl RawTrieNodeWithSize {
 pub struct Trie {
     pub(crate) storage: Box<dyn TrieStorage>,
     pub counter: TouchedNodesCounter,
+    /// Serves `TrieUpdate::get` for the state root it was created for, bypassing trie nodes.
+    pub(crate) flat_state: Option<FlatState>,
 }
 
 ///
@@ -448,18 +457,31 @@ impl TrieChanges {
 
 impl Trie {
     pub fn new(store: Box<dyn TrieStorage>, _shard_id: ShardId) -> Self {
-        Trie { storage: store, counter: TouchedNodesCounter::default() }
+        Trie { storage: store, counter: TouchedNodesCounter::default(), flat_state: None }
+    }
+
+    pub fn with_flat_state(self, flat_state: FlatState) -> Self {
+        Trie { flat_state: Some(flat_state), ..self }
     }
 
     pub fn recording_reads(&self) -> Self {
-        let storage =
-            self.storage.as_caching_storage().expect("Storage should be TrieCachingStorage");
+        self.try_recording_reads().expect("Storage should be TrieCachingStorage")
+    }
+
+    /// Same as `recording_reads`, but returns `None` if the trie isn't backed by
+    /// `TrieCachingStorage`, e.g. if it is itself built from recorded storage.
+    pub fn try_recording_reads(&self) -> Option<Self> {
+        let storage = self.storage.as_caching_storage()?;
         let storage = TrieRecordingStorage {
             store: Arc::clone(&storage.store),
             shard_id: storage.shard_id,
             recorded: RefCell::new(Default::default()),
         };
-        Trie { storage: Box::new(storage), counter: TouchedNodesCounter::default() }
+        Some(Trie {
+            storage: Box::new(storage),
+            counter: TouchedNodesCounter::default(),
+            flat_state: None,
+        })
     }
 
     pub fn empty_root() -> StateRoot {
@@ -483,6 +505,7 @@ impl Trie {
                 visited_nodes: Default::default(),
             }),
             counter: TouchedNodesCounter::default(),
+            flat_state: None,
         }
     }
 
@@ -593,6 +616,19 @@ impl Trie {
         self.storage.retrieve_raw_bytes(hash)
     }
 
+    /// Reads the value of an existing key from flat state. Touches a node like reading the value
+    /// by its hash would, so that gas doesn't depend on whether flat state is used.
+    pub(crate) fn retrieve_flat_state_value(&self, key: &[u8]) -> Result<Vec<u8>, StorageError> {
+        self.counter.increment();
+        let flat_state = self.flat_state.as_ref().ok_or(StorageError::StorageInternalError)?;
+        flat_state.get(key)?.ok_or_else(|| {
+            StorageError::StorageInconsistentState(format!(
+                "Key {} is in the trie but not in flat state",
+                to_base(key)
+            ))
+        })
+    }
+
     pub fn retrieve_root_node(&self, root: &StateRoot) -> Result<StateRootNode, StorageError> {
         if *root == Trie::empty_root() {
             return Ok(StateRootNode::empty());
@@ -736,6 +772,7 @@ mod tests {
     use crate::db::DBCol::ColState;
     use crate::test_utils::{
         create_test_store, create_tries, gen_changes, simplify_changes, test_populate_trie,
+        with_test_stores,
     };
 
     use super::*;
@@ -786,45 +823,49 @@ mod tests {
 
     #[test]
     fn test_basic_trie() {
-        let tries = create_tries();
-        let trie = tries.get_trie_for_shard(0);
-        let empty_root = Trie::empty_root();
-        assert_eq!(trie.get(&empty_root, &[122]), Ok(None));
-        let changes = vec![
-            (b"doge".to_vec(), Some(b"coin".to_vec())),
-            (b"docu".to_vec(), Some(b"value".to_vec())),
-            (b"do".to_vec(), Some(b"verb".to_vec())),
-            (b"horse".to_vec(), Some(b"stallion".to_vec())),
-            (b"dog".to_vec(), Some(b"puppy".to_vec())),
-            (b"h".to_vec(), Some(b"value".to_vec())),
-        ];
-        let root = test_populate_trie(&tries, &empty_root, 0, changes.clone());
-        let new_root = test_clear_trie(&tries, &root, 0, changes);
-        assert_eq!(new_root, empty_root);
-        assert_eq!(trie.iter(&new_root).unwrap().fold(0, |acc, _| acc + 1), 0);
+        with_test_stores(|store| {
+            let tries = ShardTries::new(store, 1);
+            let trie = tries.get_trie_for_shard(0);
+            let empty_root = Trie::empty_root();
+            assert_eq!(trie.get(&empty_root, &[122]), Ok(None));
+            let changes = vec![
+                (b"doge".to_vec(), Some(b"coin".to_vec())),
+                (b"docu".to_vec(), Some(b"value".to_vec())),
+                (b"do".to_vec(), Some(b"verb".to_vec())),
+                (b"horse".to_vec(), Some(b"stallion".to_vec())),
+                (b"dog".to_vec(), Some(b"puppy".to_vec())),
+                (b"h".to_vec(), Some(b"value".to_vec())),
+            ];
+            let root = test_populate_trie(&tries, &empty_root, 0, changes.clone());
+            let new_root = test_clear_trie(&tries, &root, 0, changes);
+            assert_eq!(new_root, empty_root);
+            assert_eq!(trie.iter(&new_root).unwrap().fold(0, |acc, _| acc + 1), 0);
+        });
     }
 
     #[test]
     fn test_trie_iter() {
-        let tries = create_tries();
-        let trie = tries.get_trie_for_shard(0);
-        let pairs = vec![
-            (b"a".to_vec(), Some(b"111".to_vec())),
-            (b"b".to_vec(), Some(b"222".to_vec())),
-            (b"x".to_vec(), Some(b"333".to_vec())),
-            (b"y".to_vec(), Some(b"444".to_vec())),
-        ];
-        let root = test_populate_trie(&tries, &Trie::empty_root(), 0, pairs.clone());
-        let mut iter_pairs = vec![];
-        for pair in trie.iter(&root).unwrap() {
-            let (key, value) = pair.unwrap();
-            iter_pairs.push((key, Some(value.to_vec())));
-        }
-        assert_eq!(pairs, iter_pairs);
+        with_test_stores(|store| {
+            let tries = ShardTries::new(store, 1);
+            let trie = tries.get_trie_for_shard(0);
+            let pairs = vec![
+                (b"a".to_vec(), Some(b"111".to_vec())),
+                (b"b".to_vec(), Some(b"222".to_vec())),
+                (b"x".to_vec(), Some(b"333".to_vec())),
+                (b"y".to_vec(), Some(b"444".to_vec())),
+            ];
+            let root = test_populate_trie(&tries, &Trie::empty_root(), 0, pairs.clone());
+            let mut iter_pairs = vec![];
+            for pair in trie.iter(&root).unwrap() {
+                let (key, value) = pair.unwrap();
+                iter_pairs.push((key, Some(value.to_vec())));
+            }
+            assert_eq!(pairs, iter_pairs);
 
-        let mut other_iter = trie.iter(&root).unwrap();
-        other_iter.seek(b"r").unwrap();
-        assert_eq!(other_iter.next().unwrap().unwrap().0, b"x".to_vec());
+            let mut other_iter = trie.iter(&root).unwrap();
+            other_iter.seek(b"r").unwrap();
+            assert_eq!(other_iter.next().unwrap().unwrap().0, b"x".to_vec());
+        });
     }
 
     #[test]
@@ -986,90 +1027,94 @@ mod tests {
     fn test_refcounts() {
         let mut rng = rand::thread_rng();
         for _test_run in 0..10 {
-            let num_iterations = rng.gen_range(1, 20);
-            let tries = create_tries();
-            let trie = tries.get_trie_for_shard(0);
-            let mut state_root = Trie::empty_root();
-            for _ in 0..num_iterations {
-                let trie_changes = gen_changes(&mut rng, 20);
-                state_root = test_populate_trie(&tries, &state_root, 0, trie_changes);
-                println!(
-                    "New memory_usage: {}",
-                    trie.retrieve_root_node(&state_root).unwrap().memory_usage
-                );
-            }
-            {
-                let trie_changes = trie
-                    .iter(&state_root)
-                    .unwrap()
-                    .map(|item| {
-                        let (key, _) = item.unwrap();
-                        (key, None)
-                    })
-                    .collect::<Vec<_>>();
-                state_root = test_populate_trie(&tries, &state_root, 0, trie_changes);
-                assert_eq!(state_root, Trie::empty_root(), "Trie must be empty");
-                assert!(
-                    trie.storage
-                        .as_caching_storage()
+            with_test_stores(|store| {
+                let num_iterations = rng.gen_range(1, 20);
+                let tries = ShardTries::new(store, 1);
+                let trie = tries.get_trie_for_shard(0);
+                let mut state_root = Trie::empty_root();
+                for _ in 0..num_iterations {
+                    let trie_changes = gen_changes(&mut rng, 20);
+                    state_root = test_populate_trie(&tries, &state_root, 0, trie_changes);
+                    println!(
+                        "New memory_usage: {}",
+                        trie.retrieve_root_node(&state_root).unwrap().memory_usage
+                    );
+                }
+                {
+                    let trie_changes = trie
+                        .iter(&state_root)
                         .unwrap()
-                        .store
-                        .iter(ColState)
-                        .peekable()
-                        .peek()
-                        .is_none(),
-                    "Storage must be empty"
-                );
-            }
+                        .map(|item| {
+                            let (key, _) = item.unwrap();
+                            (key, None)
+                        })
+                        .collect::<Vec<_>>();
+                    state_root = test_populate_trie(&tries, &state_root, 0, trie_changes);
+                    assert_eq!(state_root, Trie::empty_root(), "Trie must be empty");
+                    assert!(
+                        trie.storage
+                            .as_caching_storage()
+                            .unwrap()
+                            .store
+                            .iter(ColState)
+                            .peekable()
+                            .peek()
+                            .is_none(),
+                        "Storage must be empty"
+                    );
+                }
+            });
         }
     }
 
     #[test]
     fn test_trie_restart() {
-        let store = create_test_store();
-        let tries = ShardTries::new(store.clone(), 1);
-        let empty_root = Trie::empty_root();
-        let changes = vec![
-            (b"doge".to_vec(), Some(b"coin".to_vec())),
-            (b"docu".to_vec(), Some(b"value".to_vec())),
-            (b"do".to_vec(), Some(b"verb".to_vec())),
-            (b"horse".to_vec(), Some(b"stallion".to_vec())),
-            (b"dog".to_vec(), Some(b"puppy".to_vec())),
-            (b"h".to_vec(), Some(b"value".to_vec())),
-        ];
-        let root = test_populate_trie(&tries, &empty_root, 0, changes.clone());
-
-        let tries2 = ShardTries::new(store, 1);
-        let trie2 = tries2.get_trie_for_shard(0);
-        assert_eq!(trie2.get(&root, b"doge"), Ok(Some(b"coin".to_vec())));
+        with_test_stores(|store| {
+            let tries = ShardTries::new(store.clone(), 1);
+            let empty_root = Trie::empty_root();
+            let changes = vec![
+                (b"doge".to_vec(), Some(b"coin".to_vec())),
+                (b"docu".to_vec(), Some(b"value".to_vec())),
+                (b"do".to_vec(), Some(b"verb".to_vec())),
+                (b"horse".to_vec(), Some(b"stallion".to_vec())),
+                (b"dog".to_vec(), Some(b"puppy".to_vec())),
+                (b"h".to_vec(), Some(b"value".to_vec())),
+            ];
+            let root = test_populate_trie(&tries, &empty_root, 0, changes.clone());
+
+            let tries2 = ShardTries::new(store, 1);
+            let trie2 = tries2.get_trie_for_shard(0);
+            assert_eq!(trie2.get(&root, b"doge"), Ok(Some(b"coin".to_vec())));
+        });
     }
 
     // TODO: somehow also test that we don't record unnecessary nodes
     #[test]
     fn test_trie_recording_reads() {
-        let store = create_test_store();
-        let tries = ShardTries::new(store.clone(), 1);
-        let empty_root = Trie::empty_root();
-        let changes = vec![
-            (b"doge".to_vec(), Some(b"coin".to_vec())),
-            (b"docu".to_vec(), Some(b"value".to_vec())),
-            (b"do".to_vec(), Some(b"verb".to_vec())),
-            (b"horse".to_vec(), Some(b"stallion".to_vec())),
-            (b"dog".to_vec(), Some(b"puppy".to_vec())),
-            (b"h".to_vec(), Some(b"value".to_vec())),
-        ];
-        let root = test_populate_trie(&tries, &empty_root, 0, changes.clone());
+        with_test_stores(|store| {
+            let tries = ShardTries::new(store.clone(), 1);
+            let empty_root = Trie::empty_root();
+            let changes = vec![
+                (b"doge".to_vec(), Some(b"coin".to_vec())),
+                (b"docu".to_vec(), Some(b"value".to_vec())),
+                (b"do".to_vec(), Some(b"verb".to_vec())),
+                (b"horse".to_vec(), Some(b"stallion".to_vec())),
+                (b"dog".to_vec(), Some(b"puppy".to_vec())),
+                (b"h".to_vec(), Some(b"value".to_vec())),
+            ];
+            let root = test_populate_trie(&tries, &empty_root, 0, changes.clone());
 
-        let trie2 = tries.get_trie_for_shard(0).recording_reads();
-        trie2.get(&root, b"dog").unwrap();
-        trie2.get(&root, b"horse").unwrap();
-        let partial_storage = trie2.recorded_storage();
+            let trie2 = tries.get_trie_for_shard(0).recording_reads();
+            trie2.get(&root, b"dog").unwrap();
+            trie2.get(&root, b"horse").unwrap();
+            let partial_storage = trie2.recorded_storage();
 
-        let trie3 = Trie::from_recorded_storage(partial_storage.unwrap());
+            let trie3 = Trie::from_recorded_storage(partial_storage.unwrap());
 
-        assert_eq!(trie3.get(&root, b"dog"), Ok(Some(b"puppy".to_vec())));
-        assert_eq!(trie3.get(&root, b"horse"), Ok(Some(b"stallion".to_vec())));
-        assert_eq!(trie3.get(&root, b"doge"), Err(StorageError::TrieNodeMissing));
+            assert_eq!(trie3.get(&root, b"dog"), Ok(Some(b"puppy".to_vec())));
+            assert_eq!(trie3.get(&root, b"horse"), Ok(Some(b"stallion".to_vec())));
+            assert_eq!(trie3.get(&root, b"doge"), Err(StorageError::TrieNodeMissing));
+        });
     }
 
     #[test]
@@ -1109,20 +1154,21 @@ mod tests {
 
     #[test]
     fn test_dump_load_trie() {
-        let store = create_test_store();
-        let tries = ShardTries::new(store.clone(), 1);
-        let empty_root = Trie::empty_root();
-        let changes = vec![
-            (b"doge".to_vec(), Some(b"coin".to_vec())),
-            (b"docu".to_vec(), Some(b"value".to_vec())),
-        ];
-        let root = test_populate_trie(&tries, &empty_root, 0, changes.clone());
-        let dir = tempfile::Builder::new().prefix("test_dump_load_trie").tempdir().unwrap();
-        store.save_to_file(ColState, &dir.path().join("test.bin")).unwrap();
-        let store2 = create_test_store();
-        store2.load_from_file(ColState, &dir.path().join("test.bin")).unwrap();
-        let tries2 = ShardTries::new(store2.clone(), 1);
-        let trie2 = tries2.get_trie_for_shard(0);
-        assert_eq!(trie2.get(&root, b"doge").unwrap().unwrap(), b"coin");
+        with_test_stores(|store| {
+            let tries = ShardTries::new(store.clone(), 1);
+            let empty_root = Trie::empty_root();
+            let changes = vec![
+                (b"doge".to_vec(), Some(b"coin".to_vec())),
+                (b"docu".to_vec(), Some(b"value".to_vec())),
+            ];
+            let root = test_populate_trie(&tries, &empty_root, 0, changes.clone());
+            let dir = tempfile::Builder::new().prefix("test_dump_load_trie").tempdir().unwrap();
+            store.save_to_file(ColState, &dir.path().join("test.bin")).unwrap();
+            let store2 = create_test_store();
+            store2.load_from_file(ColState, &dir.path().join("test.bin")).unwrap();
+            let tries2 = ShardTries::new(store2.clone(), 1);
+            let trie2 = tries2.get_trie_for_shard(0);
+            assert_eq!(trie2.get(&root, b"doge").unwrap().unwrap(), b"coin");
+        });
     }
 }
@@ -1,4 +1,5 @@
 use crate::db::{DBCol, DBOp, DBTransaction};
+use crate::trie::flat_state::{set_flat_state_delta, FlatState, FlatStateDelta};
 use crate::trie::trie_storage::{TrieCache, TrieCachingStorage};
 use crate::{StorageError, Store, StoreUpdate, Trie, TrieChanges, TrieUpdate};
 use borsh::BorshSerialize;
@@ -18,6 +19,8 @@ pub struct ShardTries {
     pub(crate) caches: Arc<Vec<TrieCache>>,
     /// Cache for readers.
     pub(crate) view_caches: Arc<Vec<TrieCache>>,
+    /// Whether flat state deltas are saved and applied chunks read the state through flat state.
+    pub(crate) flat_state_enabled: bool,
 }
 
 impl ShardTries {
@@ -31,6 +34,7 @@ impl ShardTries {
             store,
             caches: Self::get_new_cache(num_shards),
             view_caches: Self::get_new_cache(num_shards),
+            flat_state_enabled: false,
         }
     }
 
@@ -60,6 +64,24 @@ impl ShardTries {
         self.get_trie_for_shard_internal(shard_id, true)
     }
 
+    /// Trie for applying a chunk on top of the state of `block_hash` with `state_root`.
+    /// Reads go through flat state if it is enabled and available for the block.
+    pub fn get_trie_with_flat_state_for_shard(
+        &self,
+        shard_id: ShardId,
+        block_hash: &CryptoHash,
+        state_root: &StateRoot,
+    ) -> Result<Trie, StorageError> {
+        let trie = self.get_trie_for_shard(shard_id);
+        if !self.flat_state_enabled {
+            return Ok(trie);
+        }
+        Ok(match FlatState::new(self.store.clone(), shard_id, block_hash, *state_root)? {
+            Some(flat_state) => trie.with_flat_state(flat_state),
+            None => trie,
+        })
+    }
+
     pub fn get_store(&self) -> Arc<Store> {
         self.store.clone()
     }
@@ -214,6 +236,7 @@ pub struct WrappedTrieChanges {
     shard_id: ShardId,
     trie_changes: TrieChanges,
     state_changes: Vec<RawStateChangesWithTrieKey>,
+    prev_block_hash: CryptoHash,
     block_hash: CryptoHash,
 }
 
@@ -223,15 +246,29 @@ impl WrappedTrieChanges {
         shard_id: ShardId,
         trie_changes: TrieChanges,
         state_changes: Vec<RawStateChangesWithTrieKey>,
+        prev_block_hash: CryptoHash,
         block_hash: CryptoHash,
     ) -> Self {
-        WrappedTrieChanges { tries, shard_id, trie_changes, state_changes, block_hash }
+        WrappedTrieChanges {
+            tries,
+            shard_id,
+            trie_changes,
+            state_changes,
+            prev_block_hash,
+            block_hash,
+        }
     }
 
     pub fn insertions_into(&self, store_update: &mut StoreUpdate) -> Result<(), StorageError> {
         self.tries.apply_insertions(&self.trie_changes, self.shard_id, store_update)
     }
 
+    /// Save final values of changed keys as the flat state delta of the block.
+    pub fn flat_state_delta_into(&self, store_update: &mut StoreUpdate) -> std::io::Result<()> {
+        let delta = FlatStateDelta::from_state_changes(self.prev_block_hash, &self.state_changes);
+        set_flat_state_delta(store_update, self.shard_id, &self.block_hash, &delta)
+    }
+
     /// Save state changes into Store.
     ///
     /// NOTE: the changes are drained from `self`.
@@ -271,6 +308,9 @@ impl WrappedTrieChanges {
         mut store_update: &mut StoreUpdate,
     ) -> Result<(), Box<dyn std::error::Error>> {
         self.insertions_into(&mut store_update)?;
+        if self.tries.flat_state_enabled {
+            self.flat_state_delta_into(&mut store_update)?;
+        }
         self.state_changes_into(&mut store_update);
         store_update.set_ser(
             DBCol::ColTrieChanges,
@@ -1,11 +1,12 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use near_primitives::challenge::PartialState;
-use near_primitives::hash::CryptoHash;
+use near_primitives::hash::{hash, CryptoHash};
+use near_primitives::syncing::StatePartSyncProgress;
 use near_primitives::types::StateRoot;
 
 use crate::trie::nibble_slice::NibbleSlice;
-use crate::trie::{NodeHandle, RawTrieNodeWithSize, TrieNode, TrieNodeWithSize};
+use crate::trie::{NodeHandle, RawTrieNode, RawTrieNodeWithSize, TrieNode, TrieNodeWithSize};
 use crate::{PartialStorage, StorageError, Trie, TrieChanges, TrieIterator};
 
 impl Trie {
@@ -206,6 +207,161 @@ impl Trie {
         })
     }
 
+    /// Splits a state part into chunks of at most `max_chunk_size` bytes (a chunk always has at
+    /// least one node). Nodes are laid out in dfs order, so every node comes after the node
+    /// referencing it and chunks can be verified one by one with `apply_state_part_chunk`.
+    ///
+    /// # Errors
+    /// StorageError if the part has nodes which are not reachable from the state root
+    pub fn split_state_part(
+        state_root: &StateRoot,
+        part: PartialState,
+        max_chunk_size: usize,
+    ) -> Result<Vec<PartialState>, StorageError> {
+        let mut nodes: HashMap<CryptoHash, Vec<u8>> =
+            part.0.into_iter().map(|value| (hash(&value), value)).collect();
+        let mut chunks = vec![];
+        let mut chunk = vec![];
+        let mut chunk_size = 0;
+        // (hash, is_value)
+        let mut stack = vec![(*state_root, false)];
+        while let Some((node_hash, is_value)) = stack.pop() {
+            let data = match nodes.remove(&node_hash) {
+                Some(data) => data,
+                None => continue,
+            };
+            if !is_value {
+                // Children are pushed in reverse, so the leftmost one is laid out first.
+                match Self::decode_raw_node(&data)?.node {
+                    RawTrieNode::Leaf(_, _, value_hash) => stack.push((value_hash, true)),
+                    RawTrieNode::Branch(children, value) => {
+                        for child in children.iter().rev().flatten() {
+                            stack.push((*child, false));
+                        }
+                        if let Some((_, value_hash)) = value {
+                            stack.push((value_hash, true));
+                        }
+                    }
+                    RawTrieNode::Extension(_, child) => stack.push((child, false)),
+                }
+            }
+            if !chunk.is_empty() && chunk_size + data.len() > max_chunk_size {
+                chunks.push(PartialState(std::mem::take(&mut chunk)));
+                chunk_size = 0;
+            }
+            chunk_size += data.len();
+            chunk.push(data);
+        }
+        if !nodes.is_empty() {
+            return Err(StorageError::StorageInconsistentState(format!(
+                "State part has {} nodes unreachable from the state root",
+                nodes.len()
+            )));
+        }
+        if !chunk.is_empty() || chunks.is_empty() {
+            chunks.push(PartialState(chunk));
+        }
+        Ok(chunks)
+    }
+
+    /// Verifies the next chunk of a state part and returns the storage changes inserting its
+    /// nodes. Every node of the chunk must be referenced by the state root or by a node applied
+    /// earlier, so nothing but the actual state is ever written. `progress` keeps the set of
+    /// nodes we are still waiting for and is advanced to the next chunk.
+    ///
+    /// Once all chunks are applied, `validate_applied_state_part` checks that nothing is missing.
+    pub fn apply_state_part_chunk(
+        state_root: &StateRoot,
+        progress: &mut StatePartSyncProgress,
+        chunk: PartialState,
+    ) -> Result<TrieChanges, StorageError> {
+        if state_root == &CryptoHash::default() {
+            progress.next_chunk_id += 1;
+            return Ok(TrieChanges::empty(CryptoHash::default()));
+        }
+        let mut expected_nodes: HashSet<CryptoHash> =
+            progress.expected_nodes.iter().cloned().collect();
+        let mut expected_values: HashSet<CryptoHash> =
+            progress.expected_values.iter().cloned().collect();
+        let mut insertions = Vec::with_capacity(chunk.0.len());
+        for data in chunk.0 {
+            let node_hash = hash(&data);
+            if expected_nodes.remove(&node_hash) {
+                match Self::decode_raw_node(&data)?.node {
+                    RawTrieNode::Leaf(_, _, value_hash) => {
+                        expected_values.insert(value_hash);
+                    }
+                    RawTrieNode::Branch(children, value) => {
+                        expected_nodes.extend(children.iter().flatten());
+                        if let Some((_, value_hash)) = value {
+                            expected_values.insert(value_hash);
+                        }
+                    }
+                    RawTrieNode::Extension(_, child) => {
+                        expected_nodes.insert(child);
+                    }
+                }
+            } else if !expected_values.remove(&node_hash) {
+                return Err(StorageError::StorageInconsistentState(format!(
+                    "Unexpected node {} in chunk {} of state part {}",
+                    node_hash, progress.next_chunk_id, progress.part_id
+                )));
+            }
+            progress.applied_nodes.push(node_hash);
+            insertions.push((node_hash, data, 1));
+        }
+        insertions.sort();
+        progress.expected_nodes = expected_nodes.into_iter().collect();
+        progress.expected_values = expected_values.into_iter().collect();
+        progress.next_chunk_id += 1;
+        Ok(TrieChanges {
+            old_root: CryptoHash::default(),
+            new_root: *state_root,
+            insertions,
+            deletions: vec![],
+        })
+    }
+
+    /// Returns the changes which inserted the nodes applied so far for the state part, so that
+    /// they can be reverted with `ShardTries::revert_insertions` if the download is abandoned.
+    /// Values are not needed to decrement refcounts and are left empty.
+    pub fn applied_state_part_changes(progress: &StatePartSyncProgress) -> TrieChanges {
+        TrieChanges {
+            old_root: CryptoHash::default(),
+            new_root: CryptoHash::default(),
+            insertions: progress
+                .applied_nodes
+                .iter()
+                .map(|node_hash| (*node_hash, vec![], 1))
+                .collect(),
+            deletions: vec![],
+        }
+    }
+
+    /// Checks that all nodes of a state part applied by chunks are present in the storage,
+    /// i.e. the part is complete up to its boundaries.
+    ///
+    /// # Errors
+    /// StorageError::TrieNodeMissing if some nodes are missing
+    pub fn validate_applied_state_part(
+        &self,
+        state_root: &StateRoot,
+        part_id: u64,
+        num_parts: u64,
+    ) -> Result<(), StorageError> {
+        assert!(part_id < num_parts);
+        if state_root == &CryptoHash::default() {
+            return Ok(());
+        }
+        self.visit_nodes_for_state_part(state_root, part_id, num_parts)
+    }
+
+    fn decode_raw_node(bytes: &[u8]) -> Result<RawTrieNodeWithSize, StorageError> {
+        RawTrieNodeWithSize::decode(bytes).map_err(|_| {
+            StorageError::StorageInconsistentState("Failed to decode node".to_string())
+        })
+    }
+
     pub fn get_memory_usage_from_serialized(bytes: &Vec<u8>) -> Result<u64, StorageError> {
         match RawTrieNodeWithSize::decode(&bytes) {
             Ok(value) => Ok(TrieNodeWithSize::from_raw(value).memory_usage),
@@ -225,9 +381,10 @@ mod tests {
 
     use near_primitives::hash::{hash, CryptoHash};
 
-    use crate::test_utils::{create_tries, gen_changes, test_populate_trie};
+    use crate::test_utils::{create_tries, gen_changes, simplify_changes, test_populate_trie};
     use crate::trie::iterator::CrumbStatus;
     use crate::trie::ValueHandle;
+    use crate::{ColState, StoreUpdate};
 
     use super::*;
 
@@ -657,4 +814,61 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn test_apply_state_part_chunks() {
+        let mut rng = rand::thread_rng();
+        for _ in 0..20 {
+            let tries = create_tries();
+            let trie = tries.get_trie_for_shard(0);
+            let trie_changes = gen_changes(&mut rng, 20);
+            let state_root =
+                test_populate_trie(&tries, &Trie::empty_root(), 0, trie_changes.clone());
+
+            let new_tries = create_tries();
+            let new_trie = new_tries.get_trie_for_shard(0);
+            let num_parts = rng.gen_range(1, 10);
+            let mut progresses = vec![];
+            for part_id in 0..num_parts {
+                let part = trie.get_trie_nodes_for_part(part_id, num_parts, &state_root).unwrap();
+                let max_chunk_size = rng.gen_range(1, 1000);
+                let chunks = Trie::split_state_part(&state_root, part, max_chunk_size).unwrap();
+                let mut progress =
+                    StatePartSyncProgress::new(part_id, chunks.len() as u64, &state_root);
+                for chunk in chunks {
+                    let trie_changes =
+                        Trie::apply_state_part_chunk(&state_root, &mut progress, chunk).unwrap();
+                    let (store_update, _) = new_tries.apply_all(&trie_changes, 0).unwrap();
+                    store_update.commit().unwrap();
+                }
+                assert!(progress.is_complete());
+                new_trie.validate_applied_state_part(&state_root, part_id, num_parts).unwrap();
+                progresses.push(progress);
+            }
+            for (key, value) in simplify_changes(&trie_changes) {
+                assert_eq!(new_trie.get(&state_root, &key).unwrap(), value);
+            }
+            // Releasing the nodes of all parts leaves nothing behind.
+            let mut store_update = StoreUpdate::new_with_tries(new_tries.clone());
+            for progress in progresses.iter() {
+                let changes = Trie::applied_state_part_changes(progress);
+                new_tries.revert_insertions(&changes, 0, &mut store_update).unwrap();
+            }
+            store_update.commit().unwrap();
+            assert_eq!(new_tries.get_store().iter(ColState).count(), 0);
+        }
+    }
+
+    #[test]
+    fn test_apply_state_part_chunk_rejects_foreign_nodes() {
+        let tries = create_tries();
+        let trie = tries.get_trie_for_shard(0);
+        let changes = |value: &[u8]| vec![(b"foo".to_vec(), Some(value.to_vec()))];
+        let state_root = test_populate_trie(&tries, &Trie::empty_root(), 0, changes(b"bar"));
+        let other_root = test_populate_trie(&tries, &Trie::empty_root(), 0, changes(b"baz"));
+        let part = trie.get_trie_nodes_for_part(0, 1, &other_root).unwrap();
+        let mut chunks = Trie::split_state_part(&other_root, part, usize::max_value()).unwrap();
+        let mut progress = StatePartSyncProgress::new(0, 1, &state_root);
+        assert!(Trie::apply_state_part_chunk(&state_root, &mut progress, chunks.remove(0)).is_err());
+    }
 }
@@ -33,6 +33,8 @@ pub struct TrieUpdate {
 pub enum TrieUpdateValuePtr<'a> {
     HashAndSize(&'a Trie, u32, CryptoHash),
     MemoryRef(&'a Vec<u8>),
+    /// Length and raw trie key of a value which is read from the flat state of the trie.
+    FlatState(&'a Trie, u32, Vec<u8>),
 }
 
 impl<'a> TrieUpdateValuePtr<'a> {
@@ -40,6 +42,7 @@ impl<'a> TrieUpdateValuePtr<'a> {
         match self {
             TrieUpdateValuePtr::MemoryRef(value) => value.len() as u32,
             TrieUpdateValuePtr::HashAndSize(_, length, _) => *length,
+            TrieUpdateValuePtr::FlatState(_, length, _) => *length,
         }
     }
 
@@ -47,6 +50,7 @@ impl<'a> TrieUpdateValuePtr<'a> {
         match self {
             TrieUpdateValuePtr::MemoryRef(value) => Ok((*value).clone()),
             TrieUpdateValuePtr::HashAndSize(trie, _, hash) => trie.retrieve_raw_bytes(hash),
+            TrieUpdateValuePtr::FlatState(trie, _, key) => trie.retrieve_flat_state_value(key),
         }
     }
 }
@@ -70,6 +74,11 @@ impl TrieUpdate {
             }
         }
 
+        if let Some(flat_state) = self.trie.flat_state.as_ref() {
+            if flat_state.state_root() == &self.root {
+                return flat_state.get(&key);
+            }
+        }
         self.trie.get(&self.root, &key)
     }
 
@@ -82,8 +91,18 @@ impl TrieUpdate {
                 return Ok(data.as_ref().map(TrieUpdateValuePtr::MemoryRef));
             }
         }
+        // Contract storage access is charged per touched trie node, so the nodes on the path to
+        // the key are walked even with flat state, only the value itself is read from flat state.
+        let use_flat_state =
+            self.trie.flat_state.as_ref().map_or(false, |flat| flat.state_root() == &self.root);
         self.trie.get_ref(&self.root, &key).map(|option| {
-            option.map(|(length, hash)| TrieUpdateValuePtr::HashAndSize(&self.trie, length, hash))
+            option.map(|(length, hash)| {
+                if use_flat_state {
+                    TrieUpdateValuePtr::FlatState(&self.trie, length, key)
+                } else {
+                    TrieUpdateValuePtr::HashAndSize(&self.trie, length, hash)
+                }
+            })
         })
     }
 
@@ -343,7 +362,8 @@ impl<'a> Iterator for TrieUpdateIterator<'a> {
 
 #[cfg(test)]
 mod tests {
-    use crate::test_utils::create_tries;
+    use crate::test_utils::with_test_stores;
+    use crate::ShardTries;
 
     use super::*;
 
@@ -353,154 +373,164 @@ mod tests {
 
     #[test]
     fn trie() {
-        let tries = create_tries();
-        let root = CryptoHash::default();
-        let mut trie_update = tries.new_trie_update(0, root);
-        trie_update.set(test_key(b"dog".to_vec()), b"puppy".to_vec());
-        trie_update.set(test_key(b"dog2".to_vec()), b"puppy".to_vec());
-        trie_update.set(test_key(b"xxx".to_vec()), b"puppy".to_vec());
-        trie_update
-            .commit(StateChangeCause::TransactionProcessing { tx_hash: CryptoHash::default() });
-        let trie_changes = trie_update.finalize().unwrap().0;
-        let (store_update, new_root) = tries.apply_all(&trie_changes, 0).unwrap();
-        store_update.commit().unwrap();
-        let trie_update2 = tries.new_trie_update(0, new_root);
-        assert_eq!(trie_update2.get(&test_key(b"dog".to_vec())), Ok(Some(b"puppy".to_vec())));
-        let values = trie_update2
-            .iter(&test_key(b"dog".to_vec()).to_vec())
-            .unwrap()
-            .collect::<Result<Vec<_>, _>>()
-            .unwrap();
-        assert_eq!(
-            values,
-            vec![test_key(b"dog".to_vec()).to_vec(), test_key(b"dog2".to_vec()).to_vec()]
-        );
+        with_test_stores(|store| {
+            let tries = ShardTries::new(store, 1);
+            let root = CryptoHash::default();
+            let mut trie_update = tries.new_trie_update(0, root);
+            trie_update.set(test_key(b"dog".to_vec()), b"puppy".to_vec());
+            trie_update.set(test_key(b"dog2".to_vec()), b"puppy".to_vec());
+            trie_update.set(test_key(b"xxx".to_vec()), b"puppy".to_vec());
+            trie_update
+                .commit(StateChangeCause::TransactionProcessing { tx_hash: CryptoHash::default() });
+            let trie_changes = trie_update.finalize().unwrap().0;
+            let (store_update, new_root) = tries.apply_all(&trie_changes, 0).unwrap();
+            store_update.commit().unwrap();
+            let trie_update2 = tries.new_trie_update(0, new_root);
+            assert_eq!(trie_update2.get(&test_key(b"dog".to_vec())), Ok(Some(b"puppy".to_vec())));
+            let values = trie_update2
+                .iter(&test_key(b"dog".to_vec()).to_vec())
+                .unwrap()
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap();
+            assert_eq!(
+                values,
+                vec![test_key(b"dog".to_vec()).to_vec(), test_key(b"dog2".to_vec()).to_vec()]
+            );
+        });
     }
 
     #[test]
     fn trie_remove() {
-        let tries = create_tries();
-
-        // Delete non-existing element.
-        let mut trie_update = tries.new_trie_update(0, CryptoHash::default());
-        trie_update.remove(test_key(b"dog".to_vec()));
-        trie_update
-            .commit(StateChangeCause::TransactionProcessing { tx_hash: CryptoHash::default() });
-        let trie_changes = trie_update.finalize().unwrap().0;
-        let (store_update, new_root) = tries.apply_all(&trie_changes, 0).unwrap();
-        store_update.commit().unwrap();
-        assert_eq!(new_root, CryptoHash::default());
-
-        // Add and right away delete element.
-        let mut trie_update = tries.new_trie_update(0, CryptoHash::default());
-        trie_update.set(test_key(b"dog".to_vec()), b"puppy".to_vec());
-        trie_update.remove(test_key(b"dog".to_vec()));
-        trie_update
-            .commit(StateChangeCause::TransactionProcessing { tx_hash: CryptoHash::default() });
-        let trie_changes = trie_update.finalize().unwrap().0;
-        let (store_update, new_root) = tries.apply_all(&trie_changes, 0).unwrap();
-        store_update.commit().unwrap();
-        assert_eq!(new_root, CryptoHash::default());
-
-        // Add, apply changes and then delete element.
-        let mut trie_update = tries.new_trie_update(0, CryptoHash::default());
-        trie_update.set(test_key(b"dog".to_vec()), b"puppy".to_vec());
-        trie_update
-            .commit(StateChangeCause::TransactionProcessing { tx_hash: CryptoHash::default() });
-        let trie_changes = trie_update.finalize().unwrap().0;
-        let (store_update, new_root) = tries.apply_all(&trie_changes, 0).unwrap();
-        store_update.commit().unwrap();
-        assert_ne!(new_root, CryptoHash::default());
-        let mut trie_update = tries.new_trie_update(0, new_root);
-        trie_update.remove(test_key(b"dog".to_vec()));
-        trie_update
-            .commit(StateChangeCause::TransactionProcessing { tx_hash: CryptoHash::default() });
-        let trie_changes = trie_update.finalize().unwrap().0;
-        let (store_update, new_root) = tries.apply_all(&trie_changes, 0).unwrap();
-        store_update.commit().unwrap();
-        assert_eq!(new_root, CryptoHash::default());
+        with_test_stores(|store| {
+            let tries = ShardTries::new(store, 1);
+
+            // Delete non-existing element.
+            let mut trie_update = tries.new_trie_update(0, CryptoHash::default());
+            trie_update.remove(test_key(b"dog".to_vec()));
+            trie_update
+                .commit(StateChangeCause::TransactionProcessing { tx_hash: CryptoHash::default() });
+            let trie_changes = trie_update.finalize().unwrap().0;
+            let (store_update, new_root) = tries.apply_all(&trie_changes, 0).unwrap();
+            store_update.commit().unwrap();
+            assert_eq!(new_root, CryptoHash::default());
+
+            // Add and right away delete element.
+            let mut trie_update = tries.new_trie_update(0, CryptoHash::default());
+            trie_update.set(test_key(b"dog".to_vec()), b"puppy".to_vec());
+            trie_update.remove(test_key(b"dog".to_vec()));
+            trie_update
+                .commit(StateChangeCause::TransactionProcessing { tx_hash: CryptoHash::default() });
+            let trie_changes = trie_update.finalize().unwrap().0;
+            let (store_update, new_root) = tries.apply_all(&trie_changes, 0).unwrap();
+            store_update.commit().unwrap();
+            assert_eq!(new_root, CryptoHash::default());
+
+            // Add, apply changes and then delete element.
+            let mut trie_update = tries.new_trie_update(0, CryptoHash::default());
+            trie_update.set(test_key(b"dog".to_vec()), b"puppy".to_vec());
+            trie_update
+                .commit(StateChangeCause::TransactionProcessing { tx_hash: CryptoHash::default() });
+            let trie_changes = trie_update.finalize().unwrap().0;
+            let (store_update, new_root) = tries.apply_all(&trie_changes, 0).unwrap();
+            store_update.commit().unwrap();
+            assert_ne!(new_root, CryptoHash::default());
+            let mut trie_update = tries.new_trie_update(0, new_root);
+            trie_update.remove(test_key(b"dog".to_vec()));
+            trie_update
+                .commit(StateChangeCause::TransactionProcessing { tx_hash: CryptoHash::default() });
+            let trie_changes = trie_update.finalize().unwrap().0;
+            let (store_update, new_root) = tries.apply_all(&trie_changes, 0).unwrap();
+            store_update.commit().unwrap();
+            assert_eq!(new_root, CryptoHash::default());
+        });
     }
 
     #[test]
     fn trie_iter() {
-        let tries = create_tries();
-        let mut trie_update = tries.new_trie_update(0, CryptoHash::default());
-        trie_update.set(test_key(b"dog".to_vec()), b"puppy".to_vec());
-        trie_update.set(test_key(b"aaa".to_vec()), b"puppy".to_vec());
-        trie_update
-            .commit(StateChangeCause::TransactionProcessing { tx_hash: CryptoHash::default() });
-        let trie_changes = trie_update.finalize().unwrap().0;
-        let (store_update, new_root) = tries.apply_all(&trie_changes, 0).unwrap();
-        store_update.commit().unwrap();
-
-        let mut trie_update = tries.new_trie_update(0, new_root);
-        trie_update.set(test_key(b"dog2".to_vec()), b"puppy".to_vec());
-        trie_update.set(test_key(b"xxx".to_vec()), b"puppy".to_vec());
-
-        let values: Result<Vec<Vec<u8>>, _> =
-            trie_update.iter(&test_key(b"dog".to_vec()).to_vec()).unwrap().collect();
-        assert_eq!(
-            values.unwrap(),
-            vec![test_key(b"dog".to_vec()).to_vec(), test_key(b"dog2".to_vec()).to_vec()]
-        );
-
-        trie_update.rollback();
-
-        let values: Result<Vec<Vec<u8>>, _> =
-            trie_update.iter(&test_key(b"dog".to_vec()).to_vec()).unwrap().collect();
-        assert_eq!(values.unwrap(), vec![test_key(b"dog".to_vec()).to_vec()]);
-
-        let mut trie_update = tries.new_trie_update(0, new_root);
-        trie_update.remove(test_key(b"dog".to_vec()));
-
-        let values: Result<Vec<Vec<u8>>, _> =
-            trie_update.iter(&test_key(b"dog".to_vec()).to_vec()).unwrap().collect();
-        assert_eq!(values.unwrap().len(), 0);
-
-        let mut trie_update = tries.new_trie_update(0, new_root);
-        trie_update.set(test_key(b"dog2".to_vec()), b"puppy".to_vec());
-        trie_update
-            .commit(StateChangeCause::TransactionProcessing { tx_hash: CryptoHash::default() });
-        trie_update.remove(test_key(b"dog2".to_vec()));
-
-        let values: Result<Vec<Vec<u8>>, _> =
-            trie_update.iter(&test_key(b"dog".to_vec()).to_vec()).unwrap().collect();
-        assert_eq!(values.unwrap(), vec![test_key(b"dog".to_vec()).to_vec()]);
-
-        let mut trie_update = tries.new_trie_update(0, new_root);
-        trie_update.set(test_key(b"dog2".to_vec()), b"puppy".to_vec());
-        trie_update
-            .commit(StateChangeCause::TransactionProcessing { tx_hash: CryptoHash::default() });
-        trie_update.set(test_key(b"dog3".to_vec()), b"puppy".to_vec());
-
-        let values: Result<Vec<Vec<u8>>, _> =
-            trie_update.iter(&test_key(b"dog".to_vec()).to_vec()).unwrap().collect();
-        assert_eq!(
-            values.unwrap(),
-            vec![
-                test_key(b"dog".to_vec()).to_vec(),
-                test_key(b"dog2".to_vec()).to_vec(),
-                test_key(b"dog3".to_vec()).to_vec()
-            ]
-        );
-
-        let values: Result<Vec<Vec<u8>>, _> =
-            trie_update.range(&test_key(b"do".to_vec()).to_vec(), b"g", b"g21").unwrap().collect();
-        assert_eq!(
-            values.unwrap(),
-            vec![test_key(b"dog".to_vec()).to_vec(), test_key(b"dog2".to_vec()).to_vec(),]
-        );
-
-        let values: Result<Vec<Vec<u8>>, _> =
-            trie_update.range(&test_key(b"do".to_vec()).to_vec(), b"", b"xyz").unwrap().collect();
-
-        assert_eq!(
-            values.unwrap(),
-            vec![
-                test_key(b"dog".to_vec()).to_vec(),
-                test_key(b"dog2".to_vec()).to_vec(),
-                test_key(b"dog3".to_vec()).to_vec()
-            ]
-        );
+        with_test_stores(|store| {
+            let tries = ShardTries::new(store, 1);
+            let mut trie_update = tries.new_trie_update(0, CryptoHash::default());
+            trie_update.set(test_key(b"dog".to_vec()), b"puppy".to_vec());
+            trie_update.set(test_key(b"aaa".to_vec()), b"puppy".to_vec());
+            trie_update
+                .commit(StateChangeCause::TransactionProcessing { tx_hash: CryptoHash::default() });
+            let trie_changes = trie_update.finalize().unwrap().0;
+            let (store_update, new_root) = tries.apply_all(&trie_changes, 0).unwrap();
+            store_update.commit().unwrap();
+
+            let mut trie_update = tries.new_trie_update(0, new_root);
+            trie_update.set(test_key(b"dog2".to_vec()), b"puppy".to_vec());
+            trie_update.set(test_key(b"xxx".to_vec()), b"puppy".to_vec());
+
+            let values: Result<Vec<Vec<u8>>, _> =
+                trie_update.iter(&test_key(b"dog".to_vec()).to_vec()).unwrap().collect();
+            assert_eq!(
+                values.unwrap(),
+                vec![test_key(b"dog".to_vec()).to_vec(), test_key(b"dog2".to_vec()).to_vec()]
+            );
+
+            trie_update.rollback();
+
+            let values: Result<Vec<Vec<u8>>, _> =
+                trie_update.iter(&test_key(b"dog".to_vec()).to_vec()).unwrap().collect();
+            assert_eq!(values.unwrap(), vec![test_key(b"dog".to_vec()).to_vec()]);
+
+            let mut trie_update = tries.new_trie_update(0, new_root);
+            trie_update.remove(test_key(b"dog".to_vec()));
+
+            let values: Result<Vec<Vec<u8>>, _> =
+                trie_update.iter(&test_key(b"dog".to_vec()).to_vec()).unwrap().collect();
+            assert_eq!(values.unwrap().len(), 0);
+
+            let mut trie_update = tries.new_trie_update(0, new_root);
+            trie_update.set(test_key(b"dog2".to_vec()), b"puppy".to_vec());
+            trie_update
+                .commit(StateChangeCause::TransactionProcessing { tx_hash: CryptoHash::default() });
+            trie_update.remove(test_key(b"dog2".to_vec()));
+
+            let values: Result<Vec<Vec<u8>>, _> =
+                trie_update.iter(&test_key(b"dog".to_vec()).to_vec()).unwrap().collect();
+            assert_eq!(values.unwrap(), vec![test_key(b"dog".to_vec()).to_vec()]);
+
+            let mut trie_update = tries.new_trie_update(0, new_root);
+            trie_update.set(test_key(b"dog2".to_vec()), b"puppy".to_vec());
+            trie_update
+                .commit(StateChangeCause::TransactionProcessing { tx_hash: CryptoHash::default() });
+            trie_update.set(test_key(b"dog3".to_vec()), b"puppy".to_vec());
+
+            let values: Result<Vec<Vec<u8>>, _> =
+                trie_update.iter(&test_key(b"dog".to_vec()).to_vec()).unwrap().collect();
+            assert_eq!(
+                values.unwrap(),
+                vec![
+                    test_key(b"dog".to_vec()).to_vec(),
+                    test_key(b"dog2".to_vec()).to_vec(),
+                    test_key(b"dog3".to_vec()).to_vec()
+                ]
+            );
+
+            let values: Result<Vec<Vec<u8>>, _> = trie_update
+                .range(&test_key(b"do".to_vec()).to_vec(), b"g", b"g21")
+                .unwrap()
+                .collect();
+            assert_eq!(
+                values.unwrap(),
+                vec![test_key(b"dog".to_vec()).to_vec(), test_key(b"dog2".to_vec()).to_vec(),]
+            );
+
+            let values: Result<Vec<Vec<u8>>, _> = trie_update
+                .range(&test_key(b"do".to_vec()).to_vec(), b"", b"xyz")
+                .unwrap()
+                .collect();
+
+            assert_eq!(
+                values.unwrap(),
+                vec![
+                    test_key(b"dog".to_vec()).to_vec(),
+                    test_key(b"dog2".to_vec()).to_vec(),
+                    test_key(b"dog3".to_vec()).to_vec()
+                ]
+            );
+        });
     }
 }
@@ -22,7 +22,8 @@ use near_primitives::types::{
     AccountId, Balance, ChunkExtra, EpochId, ShardId, StateChangeCause, StateRoot,
 };
 use near_store::{
-    create_store, get_account, set_access_key, set_account, set_code, ColState, Store, TrieUpdate,
+    create_store, get_account, set_access_key, set_account, set_code, ColState, Store, StoreConfig,
+    TrieUpdate,
 };
 use neard::{get_store_path, NightshadeRuntime};
 
@@ -85,7 +86,7 @@ impl GenesisBuilder {
     }
 
     pub fn from_config(home_dir: &Path, genesis: Arc<Genesis>) -> Self {
-        let store = create_store(&get_store_path(home_dir));
+        let store = create_store(&get_store_path(home_dir), &StoreConfig::default());
         Self::from_config_and_store(home_dir, genesis, store)
     }
 
@@ -2,6 +2,7 @@ use std::convert::TryInto;
 use std::fs;
 use std::fs::File;
 use std::io::{Read, Write};
+use std::net::SocketAddr;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::time::Duration;
@@ -17,12 +18,14 @@ use lazy_static::lazy_static;
 use near_chain_configs::{ClientConfig, Genesis, GenesisConfig, LogSummaryStyle};
 use near_crypto::{InMemorySigner, KeyFile, KeyType, PublicKey, Signer};
 use near_jsonrpc::RpcConfig;
+use near_network::rate_limiter::RateLimitsConfig;
 use near_network::test_utils::open_port;
 use near_network::types::ROUTED_MESSAGE_TTL;
 use near_network::utils::blacklist_from_iter;
 use near_network::NetworkConfig;
 use near_primitives::account::{AccessKey, Account};
 use near_primitives::hash::CryptoHash;
+use near_primitives::network::PeerId;
 use near_primitives::runtime::config::RuntimeConfig;
 use near_primitives::state_record::StateRecord;
 use near_primitives::types::{
@@ -34,6 +37,7 @@ use near_primitives::validator_signer::{InMemoryValidatorSigner, ValidatorSigner
 use near_primitives::version::PROTOCOL_VERSION;
 #[cfg(feature = "rosetta_rpc")]
 use near_rosetta_rpc::RosettaRpcConfig;
+use near_store::StoreConfig;
 use near_telemetry::TelemetryConfig;
 
 /// Initial balance used in tests.
@@ -194,13 +198,22 @@ fn default_ttl_account_id_router() -> Duration {
 fn default_peer_stats_period() -> Duration {
     Duration::from_secs(5)
 }
+/// Maximum size of the traffic capture file, 1GB
+fn default_traffic_capture_max_size() -> u64 {
+    1024 * 1024 * 1024
+}
+/// Messages larger than this are compressed on connections which support it.
+fn default_compression_threshold() -> Option<usize> {
+    Some(4096)
+}
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Network {
     /// Address to listen for incoming connections.
     pub addr: String,
-    /// Address to advertise to peers for them to connect.
-    /// If empty, will use the same port as the addr, and will introspect on the listener.
+    /// Comma separated addresses to advertise to peers for them to connect, e.g. both the IPv4
+    /// and IPv6 ones of a dual-stack node, or the public one of a node behind NAT.
+    /// If empty, peers use the same port as the addr, at the IP they see the connection from.
     pub external_address: String,
     /// Comma separated list of nodes to connect to.
     pub boot_nodes: String,
@@ -245,6 +258,27 @@ pub struct Network {
     /// Period to check on peer status
     #[serde(default = "default_peer_stats_period")]
     pub peer_stats_period: Duration,
+    /// File to capture all messages exchanged with peers to. Only used by nodes built with
+    /// `traffic_capture` feature.
+    #[serde(default)]
+    pub traffic_capture_path: Option<PathBuf>,
+    /// Maximum size of the traffic capture file in bytes.
+    #[serde(default = "default_traffic_capture_max_size")]
+    pub traffic_capture_max_size: u64,
+    /// Limits on the messages received from each peer.
+    #[serde(default)]
+    pub rate_limits: RateLimitsConfig,
+    /// Messages larger than this many bytes are compressed on connections to peers which also
+    /// advertised compression. Compression is neither advertised nor accepted if not set.
+    #[serde(default = "default_compression_threshold")]
+    pub compression_threshold: Option<usize>,
+    /// Peer ids of the sentry nodes of this validator. If set, the validator only connects to
+    /// its sentries, which relay its messages and keep its address private.
+    #[serde(default)]
+    pub sentries: Vec<PeerId>,
+    /// Peer ids of the validators this node is a sentry for.
+    #[serde(default)]
+    pub private_peers: Vec<PeerId>,
 }
 
 impl Default for Network {
@@ -267,10 +301,61 @@ impl Default for Network {
             blacklist: vec![],
             ttl_account_id_router: default_ttl_account_id_router(),
             peer_stats_period: default_peer_stats_period(),
+            traffic_capture_path: None,
+            traffic_capture_max_size: default_traffic_capture_max_size(),
+            rate_limits: RateLimitsConfig::default(),
+            compression_threshold: default_compression_threshold(),
+            sentries: vec![],
+            private_peers: vec![],
         }
     }
 }
 
+impl Network {
+    /// A node is either behind sentries or a sentry for private peers.
+    pub fn validate_sentries(&self) -> Result<(), ConfigError> {
+        if let Some(peer_id) =
+            self.sentries.iter().find(|peer_id| self.private_peers.contains(peer_id))
+        {
+            return Err(ConfigError::SentryAndPrivatePeer(peer_id.clone()));
+        }
+        if !self.sentries.is_empty() && !self.private_peers.is_empty() {
+            return Err(ConfigError::PrivatePeersBehindSentries);
+        }
+        Ok(())
+    }
+
+    /// Parses the comma separated `external_address`.
+    pub fn external_addrs(&self) -> Result<Vec<SocketAddr>, ConfigError> {
+        if self.external_address.is_empty() {
+            return Ok(vec![]);
+        }
+        self.external_address
+            .split(',')
+            .map(|addr| {
+                addr.trim()
+                    .parse()
+                    .map_err(|_| ConfigError::InvalidExternalAddress(addr.to_string()))
+            })
+            .collect()
+    }
+}
+
+/// Invalid values in the config file, found when it's loaded.
+#[derive(thiserror::Error, Debug, PartialEq)]
+pub enum ConfigError {
+    #[error("Invalid external address {0:?} in network.external_address")]
+    InvalidExternalAddress(String),
+    #[error("Invalid rpc.admin: {0}")]
+    InvalidAdminRpc(String),
+    #[error("Invalid network.rate_limits: {0}")]
+    InvalidRateLimits(String),
+    #[error("Peer {0} is listed in both network.sentries and network.private_peers")]
+    SentryAndPrivatePeer(PeerId),
+    #[error("network.private_peers requires the node not to be behind sentries itself, as it only connects to its sentries")]
+    PrivatePeersBehindSentries,
+}
+
 /// Serde default only supports functions without parameters.
 fn default_reduce_wait_for_missing_block() -> Duration {
     Duration::from_millis(REDUCE_DELAY_FOR_MISSING_BLOCKS)
@@ -320,6 +405,10 @@ fn default_view_client_throttle_period() -> Duration {
     Duration::from_secs(30)
 }
 
+fn default_state_request_peer_quota_bytes() -> u64 {
+    100 * 1024 * 1024
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Consensus {
     /// Minimum number of peers to start syncing.
@@ -418,6 +507,9 @@ pub struct Config {
     pub tracked_accounts: Vec<AccountId>,
     pub tracked_shards: Vec<ShardId>,
     pub archive: bool,
+    /// Index transactions and receipts by account to serve `EXPERIMENTAL_account_history`.
+    /// Only takes effect on archival nodes.
+    pub account_history_index: bool,
     pub log_summary_style: LogSummaryStyle,
     #[serde(default = "default_gc_blocks_limit")]
     pub gc_blocks_limit: NumBlocks,
@@ -426,6 +518,13 @@ pub struct Config {
     pub epoch_sync_enabled: bool,
     #[serde(default = "default_view_client_throttle_period")]
     pub view_client_throttle_period: Duration,
+    #[serde(default = "default_state_request_peer_quota_bytes")]
+    pub state_request_peer_quota_bytes: u64,
+    /// Limits on the transaction pool of each shard. Transactions of the largest groups are
+    /// evicted to make space for new ones. Unbounded if not set.
+    pub transaction_pool_size_limit: Option<u64>,
+    pub transaction_pool_signer_limit: Option<usize>,
+    pub store: StoreConfig,
 }
 
 impl Default for Config {
@@ -444,11 +543,16 @@ impl Default for Config {
             tracked_accounts: vec![],
             tracked_shards: vec![],
             archive: false,
+            account_history_index: false,
             log_summary_style: LogSummaryStyle::Colored,
             gc_blocks_limit: default_gc_blocks_limit(),
             epoch_sync_enabled: true,
             view_client_threads: default_view_client_threads(),
             view_client_throttle_period: default_view_client_throttle_period(),
+            state_request_peer_quota_bytes: default_state_request_peer_quota_bytes(),
+            transaction_pool_size_limit: None,
+            transaction_pool_signer_limit: None,
+            store: StoreConfig::default(),
         }
     }
 }
@@ -562,6 +666,11 @@ pub struct NearConfig {
     #[cfg(feature = "rosetta_rpc")]
     pub rosetta_rpc_config: Option<RosettaRpcConfig>,
     pub telemetry_config: TelemetryConfig,
+    pub store_config: StoreConfig,
+    /// Whether to record the gas profiles of executed receipts into `ColReceiptGasProfiles`.
+    /// Not part of `config.json`, the indexer turns it on. The costs are only counted if the
+    /// node is built with `costs_counting`.
+    pub record_gas_profiles: bool,
     pub genesis: Genesis,
     pub validator_signer: Option<Arc<dyn ValidatorSigner>>,
 }
@@ -572,8 +681,14 @@ impl NearConfig {
         genesis: Genesis,
         network_key_pair: KeyFile,
         validator_signer: Option<Arc<dyn ValidatorSigner>>,
-    ) -> Self {
-        NearConfig {
+    ) -> Result<Self, ConfigError> {
+        let external_addrs = config.network.external_addrs()?;
+        config.network.validate_sentries()?;
+        config.network.rate_limits.validate().map_err(ConfigError::InvalidRateLimits)?;
+        if let Some(admin) = &config.rpc.admin {
+            admin.validate().map_err(ConfigError::InvalidAdminRpc)?;
+        }
+        Ok(NearConfig {
             config: config.clone(),
             client_config: ClientConfig {
                 version: Default::default(),
@@ -612,11 +727,15 @@ impl NearConfig {
                 tracked_accounts: config.tracked_accounts,
                 tracked_shards: config.tracked_shards,
                 archive: config.archive,
+                account_history_index: config.account_history_index,
                 log_summary_style: config.log_summary_style,
                 gc_blocks_limit: config.gc_blocks_limit,
                 view_client_threads: config.view_client_threads,
                 epoch_sync_enabled: config.epoch_sync_enabled,
                 view_client_throttle_period: config.view_client_throttle_period,
+                state_request_peer_quota_bytes: config.state_request_peer_quota_bytes,
+                transaction_pool_size_limit: config.transaction_pool_size_limit,
+                transaction_pool_signer_limit: config.transaction_pool_signer_limit,
             },
             network_config: NetworkConfig {
                 public_key: network_key_pair.public_key,
@@ -661,14 +780,23 @@ impl NearConfig {
                 blacklist: blacklist_from_iter(config.network.blacklist),
                 outbound_disabled: false,
                 archive: config.archive,
+                traffic_capture_path: config.network.traffic_capture_path,
+                traffic_capture_max_size: config.network.traffic_capture_max_size,
+                rate_limits: config.network.rate_limits,
+                compression_threshold: config.network.compression_threshold,
+                external_addrs,
+                sentries: config.network.sentries,
+                private_peers: config.network.private_peers,
             },
             telemetry_config: config.telemetry,
             rpc_config: config.rpc,
             #[cfg(feature = "rosetta_rpc")]
             rosetta_rpc_config: config.rosetta_rpc,
+            store_config: config.store,
+            record_gas_profiles: false,
             genesis,
             validator_signer,
-        }
+        })
     }
 }
 
@@ -1043,7 +1171,14 @@ pub fn download_genesis(url: &String, path: &PathBuf) {
 }
 
 pub fn load_config(dir: &Path) -> NearConfig {
-    let config = Config::from_file(&dir.join(CONFIG_FILENAME));
+    try_load_config(dir).unwrap_or_else(|err| panic!("Failed to load config: {}", err))
+}
+
+/// Like `load_config`, but returns the invalid values in the config as an error.
+pub fn try_load_config(dir: &Path) -> Result<NearConfig, ConfigError> {
+    let mut config = Config::from_file(&dir.join(CONFIG_FILENAME));
+    config.network.traffic_capture_path =
+        config.network.traffic_capture_path.map(|path| dir.join(path));
     let genesis = if let Some(ref genesis_records_file) = config.genesis_records_file {
         Genesis::from_files(&dir.join(&config.genesis_file), &dir.join(genesis_records_file))
     } else {
@@ -1080,4 +1215,5 @@ pub fn load_test_config(seed: &str, port: u16, genesis: Genesis) -> NearConfig {
         (signer, Some(validator_signer))
     };
     NearConfig::new(config, genesis, signer.into(), validator_signer)
+        .expect("Test config must be valid")
 }
@@ -1,4 +1,5 @@
 use std::fs;
+use std::io;
 use std::path::Path;
 use std::sync::Arc;
 
@@ -9,21 +10,27 @@ use tracing::{error, info, trace};
 use near_chain::ChainGenesis;
 #[cfg(feature = "adversarial")]
 use near_client::AdversarialControls;
-use near_client::{start_client, start_view_client, ClientActor, ViewClientActor};
-use near_jsonrpc::start_http;
+use near_client::{
+    start_client, start_view_client, ClientActor, PendingTransactionsSnapshot, ViewClientActor,
+};
+use near_jsonrpc::{start_admin_http, start_http};
 use near_network::{NetworkRecipient, PeerManagerActor};
+use near_primitives::types::NumShards;
 #[cfg(feature = "rosetta_rpc")]
 use near_rosetta_rpc::start_rosetta_rpc;
-use near_store::{create_store, Store};
+use near_store::{create_store, create_store_with_config, Store};
 use near_telemetry::TelemetryActor;
 
-pub use crate::config::{init_configs, load_config, load_test_config, NearConfig, NEAR_BASE};
+pub use crate::config::{
+    init_configs, load_config, load_test_config, try_load_config, NearConfig, NEAR_BASE,
+};
 use crate::migrations::migrate_12_to_13;
 pub use crate::runtime::NightshadeRuntime;
 use near_store::migrations::{
     fill_col_outcomes_by_hash, fill_col_transaction_refcount, get_store_version, migrate_10_to_11,
-    migrate_11_to_12, migrate_13_to_14, migrate_14_to_15, migrate_17_to_18, migrate_6_to_7,
-    migrate_7_to_8, migrate_8_to_9, migrate_9_to_10, set_store_version,
+    migrate_11_to_12, migrate_13_to_14, migrate_14_to_15, migrate_17_to_18, migrate_18_to_19,
+    migrate_19_to_20, migrate_6_to_7, migrate_7_to_8, migrate_8_to_9, migrate_9_to_10,
+    migrate_flat_state, set_store_version,
 };
 
 #[cfg(feature = "protocol_feature_rectify_inflation")]
@@ -82,21 +89,21 @@ pub fn apply_store_migrations(path: &String, near_config: &NearConfig) {
         // Does not need to do anything since open db with option `create_missing_column_families`
         // Nevertheless need to bump db version, because db_version 1 binary can't open db_version 2 db
         info!(target: "near", "Migrate DB from version 1 to 2");
-        let store = create_store(&path);
+        let store = create_store(&path, &near_config.store_config);
         set_store_version(&store, 2);
     }
     if db_version <= 2 {
         // version 2 => 3: add ColOutcomesByBlockHash + rename LastComponentNonce -> ColLastComponentNonce
         // The column number is the same, so we don't need additional updates
         info!(target: "near", "Migrate DB from version 2 to 3");
-        let store = create_store(&path);
+        let store = create_store(&path, &near_config.store_config);
         fill_col_outcomes_by_hash(&store);
         set_store_version(&store, 3);
     }
     if db_version <= 3 {
         // version 3 => 4: add ColTransactionRefCount
         info!(target: "near", "Migrate DB from version 3 to 4");
-        let store = create_store(&path);
+        let store = create_store(&path, &near_config.store_config);
         fill_col_transaction_refcount(&store);
         set_store_version(&store, 4);
     }
@@ -105,14 +112,14 @@ pub fn apply_store_migrations(path: &String, near_config: &NearConfig) {
         // version 4 => 5: add ColProcessedBlockHeights
         // we don't need to backfill the old heights since at worst we will just process some heights
         // again.
-        let store = create_store(&path);
+        let store = create_store(&path, &near_config.store_config);
         set_store_version(&store, 5);
     }
     if db_version <= 5 {
         info!(target: "near", "Migrate DB from version 5 to 6");
         // version 5 => 6: add merge operator to ColState
         // we don't have merge records before so old storage works
-        let store = create_store(&path);
+        let store = create_store(&path, &near_config.store_config);
         set_store_version(&store, 6);
     }
     if db_version <= 6 {
@@ -127,31 +134,31 @@ pub fn apply_store_migrations(path: &String, near_config: &NearConfig) {
         info!(target: "near", "Migrate DB from version 7 to 8");
         // version 7 => 8:
         // delete values in column `StateColParts`
-        migrate_7_to_8(path);
+        migrate_7_to_8(path, &near_config.store_config);
     }
     if db_version <= 8 {
         info!(target: "near", "Migrate DB from version 8 to 9");
         // version 8 => 9:
         // Repair `ColTransactions`, `ColReceiptIdToShardId`
-        migrate_8_to_9(path);
+        migrate_8_to_9(path, &near_config.store_config);
     }
     if db_version <= 9 {
         info!(target: "near", "Migrate DB from version 9 to 10");
         // version 9 => 10;
         // populate partial encoded chunks for chunks that exist in storage
-        migrate_9_to_10(path, near_config.client_config.archive);
+        migrate_9_to_10(path, &near_config.store_config, near_config.client_config.archive);
     }
     if db_version <= 10 {
         info!(target: "near", "Migrate DB from version 10 to 11");
         // version 10 => 11
         // Add final head
-        migrate_10_to_11(path);
+        migrate_10_to_11(path, &near_config.store_config);
     }
     if db_version <= 11 {
         info!(target: "near", "Migrate DB from version 11 to 12");
         // version 11 => 12;
         // populate ColReceipts with existing receipts
-        migrate_11_to_12(path);
+        migrate_11_to_12(path, &near_config.store_config);
     }
     if db_version <= 12 {
         info!(target: "near", "Migrate DB from version 12 to 13");
@@ -163,39 +170,49 @@ pub fn apply_store_migrations(path: &String, near_config: &NearConfig) {
         info!(target: "near", "Migrate DB from version 13 to 14");
         // version 13 => 14;
         // store versioned enums for shard chunks
-        migrate_13_to_14(path);
+        migrate_13_to_14(path, &near_config.store_config);
     }
     if db_version <= 14 {
         info!(target: "near", "Migrate DB from version 14 to 15");
         // version 14 => 15;
         // Change ColOutcomesByBlockHash to be ordered within each shard
-        migrate_14_to_15(path);
+        migrate_14_to_15(path, &near_config.store_config);
     }
     if db_version <= 15 {
         info!(target: "near", "Migrate DB from version 15 to 16");
         // version 15 => 16: add column for compiled contracts
-        let store = create_store(&path);
+        let store = create_store(&path, &near_config.store_config);
         set_store_version(&store, 16);
     }
     if db_version <= 16 {
         info!(target: "near", "Migrate DB from version 16 to 17");
         // version 16 => 17: add column for storing epoch validator info
-        let store = create_store(&path);
+        let store = create_store(&path, &near_config.store_config);
         set_store_version(&store, 17);
     }
     if db_version <= 17 {
         info!(target: "near", "Migrate DB from version 17 to 18");
         // version 17 => 18: add `hash` to `BlockInfo` and ColHeaderHashesByHeight
-        migrate_17_to_18(&path);
+        migrate_17_to_18(&path, &near_config.store_config);
+    }
+    if db_version <= 18 {
+        info!(target: "near", "Migrate DB from version 18 to 19");
+        // version 18 => 19: add `score` to `KnownPeerState`
+        migrate_18_to_19(&path, &near_config.store_config);
+    }
+    if db_version <= 19 {
+        info!(target: "near", "Migrate DB from version 19 to 20");
+        // version 19 => 20: store `KnownPeerState` as a versioned enum
+        migrate_19_to_20(&path, &near_config.store_config);
     }
     #[cfg(feature = "protocol_feature_rectify_inflation")]
     if db_version <= 18 {
         // version 18 => rectify inflation: add `timestamp` to `BlockInfo`
-        migrate_18_to_rectify_inflation(&path);
+        migrate_18_to_rectify_inflation(&path, &near_config.store_config);
     }
     #[cfg(feature = "nightly_protocol")]
     {
-        let store = create_store(&path);
+        let store = create_store(&path, &near_config.store_config);
         // set some dummy value to avoid conflict with other migrations from nightly features
         set_store_version(&store, 10000);
     }
@@ -212,27 +229,68 @@ pub fn init_and_migrate_store(home_dir: &Path, near_config: &NearConfig) -> Arc<
     let store_exists = store_path_exists(&path);
     if store_exists {
         apply_store_migrations(&path, near_config);
+        if near_config.store_config.flat_state {
+            let num_shards =
+                near_config.genesis.config.num_block_producer_seats_per_shard.len() as NumShards;
+            migrate_flat_state(&path, &near_config.store_config, num_shards);
+        }
     }
-    let store = create_store(&path);
+    let store = create_store_with_config(&path, &near_config.store_config);
     if !store_exists {
         set_store_version(&store, near_primitives::version::DB_VERSION);
     }
     store
 }
 
+/// Copies a store snapshot created with `Store::checkpoint` into the store directory of `home_dir`.
+/// The home directory must not have a store yet.
+pub fn restore_store_snapshot(snapshot_path: &Path, home_dir: &Path) -> io::Result<()> {
+    let path = get_store_path(home_dir);
+    if store_path_exists(&path) {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("Store already exists at {}, remove it before restoring a snapshot", path),
+        ));
+    }
+    copy_dir_all(snapshot_path, Path::new(&path))?;
+    info!(target: "near", "Restored store snapshot from {:?} to {}, DB version {}", snapshot_path, path, get_store_version(&path));
+    Ok(())
+}
+
+fn copy_dir_all(src: &Path, dst: &Path) -> io::Result<()> {
+    fs::create_dir_all(dst)?;
+    for entry in fs::read_dir(src)? {
+        let entry = entry?;
+        let dst_path = dst.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir_all(&entry.path(), &dst_path)?;
+        } else {
+            fs::copy(entry.path(), dst_path)?;
+        }
+    }
+    Ok(())
+}
+
 pub fn start_with_config(
     home_dir: &Path,
     config: NearConfig,
 ) -> (Addr<ClientActor>, Addr<ViewClientActor>, Vec<ArbiterHandle>) {
     let store = init_and_migrate_store(home_dir, &config);
 
-    let runtime = Arc::new(NightshadeRuntime::new(
+    let mut runtime = NightshadeRuntime::new(
         home_dir,
         Arc::clone(&store),
         &config.genesis,
         config.client_config.tracked_accounts.clone(),
         config.client_config.tracked_shards.clone(),
-    ));
+    );
+    if config.store_config.flat_state {
+        runtime.enable_flat_state();
+    }
+    if config.record_gas_profiles {
+        runtime.enable_gas_profiles();
+    }
+    let runtime = Arc::new(runtime);
 
     let telemetry = TelemetryActor::new(config.telemetry_config.clone()).start();
     let chain_genesis = ChainGenesis::from(&config.genesis);
@@ -241,6 +299,8 @@ pub fn start_with_config(
     let network_adapter = Arc::new(NetworkRecipient::new());
     #[cfg(feature = "adversarial")]
     let adv = Arc::new(std::sync::RwLock::new(AdversarialControls::default()));
+    let pending_transactions =
+        Arc::new(std::sync::RwLock::new(PendingTransactionsSnapshot::default()));
 
     let view_client = start_view_client(
         config.validator_signer.as_ref().map(|signer| signer.validator_id().clone()),
@@ -248,6 +308,7 @@ pub fn start_with_config(
         runtime.clone(),
         network_adapter.clone(),
         config.client_config.clone(),
+        pending_transactions.clone(),
         #[cfg(feature = "adversarial")]
         adv.clone(),
     );
@@ -259,9 +320,18 @@ pub fn start_with_config(
         network_adapter.clone(),
         config.validator_signer,
         telemetry,
+        pending_transactions,
         #[cfg(feature = "adversarial")]
         adv.clone(),
     );
+    if let Some(admin_config) = config.rpc_config.admin.clone() {
+        start_admin_http(
+            admin_config,
+            client_actor.clone(),
+            view_client.clone(),
+            network_adapter.clone(),
+        );
+    }
     start_http(
         config.rpc_config,
         config.genesis.config.clone(),
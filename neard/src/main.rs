@@ -18,7 +18,10 @@ use near_primitives::version::{Version, PROTOCOL_VERSION};
 use near_rust_allocator_proxy::allocator::MyAllocator;
 use neard::config::init_testnet_configs;
 use neard::genesis_validate::validate_genesis;
-use neard::{get_default_home, get_store_path, init_configs, load_config, start_with_config};
+use neard::{
+    get_default_home, get_store_path, init_configs, restore_store_snapshot, start_with_config,
+    try_load_config,
+};
 
 #[cfg(feature = "memory_stats")]
 #[global_allocator]
@@ -63,11 +66,19 @@ fn init_logging(verbose: Option<&str>) {
             }
         }
     }
-    tracing_subscriber::fmt::Subscriber::builder()
+    let builder = tracing_subscriber::fmt::Subscriber::builder()
         .with_span_events(tracing_subscriber::fmt::format::FmtSpan::CLOSE)
         .with_env_filter(env_filter)
         .with_writer(io::stderr)
-        .init();
+        .with_filter_reloading();
+    let reload_handle = builder.reload_handle();
+    builder.init();
+
+    // Lets operators change the log levels of the running node with `admin_set_log_filter`.
+    near_jsonrpc::set_log_filter_reloader(Box::new(move |filter| {
+        let env_filter = EnvFilter::try_new(filter).map_err(|err| err.to_string())?;
+        reload_handle.reload(env_filter).map_err(|err| err.to_string())
+    }));
 }
 
 fn main() {
@@ -117,6 +128,9 @@ fn main() {
             .arg(Arg::with_name("telemetry-url").long("telemetry-url").help("Customize telemetry url").takes_value(true))
             .arg(Arg::with_name("archive").long("archive").help("Keep old blocks in the storage (default false)").takes_value(false))
         )
+        .subcommand(SubCommand::with_name("restore_snapshot").about("Restores a store snapshot created by the admin_create_store_snapshot RPC method into the home directory")
+            .arg(Arg::with_name("snapshot").long("snapshot").required(true).takes_value(true).help("Path to the snapshot directory"))
+        )
         .subcommand(SubCommand::with_name("unsafe_reset_data").about("(unsafe) Remove all the data, effectively resetting node to genesis state (keeps genesis and config)"))
         .subcommand(SubCommand::with_name("unsafe_reset_all").about("(unsafe) Remove all the config, keys, data and effectively removing all information about the network"))
         .get_matches();
@@ -198,7 +212,10 @@ fn main() {
         }
         ("run", Some(args)) => {
             // Load configs from home.
-            let mut near_config = load_config(home_dir);
+            let mut near_config = try_load_config(home_dir).unwrap_or_else(|err| {
+                error!("{}", err);
+                std::process::exit(1);
+            });
             validate_genesis(&near_config.genesis);
             // Set current version in client config.
             near_config.client_config.version = version;
@@ -248,6 +265,10 @@ fn main() {
             });
             sys.run().unwrap();
         }
+        ("restore_snapshot", Some(args)) => {
+            let snapshot_path = Path::new(args.value_of("snapshot").unwrap());
+            restore_store_snapshot(snapshot_path, home_dir).expect("Failed to restore snapshot");
+        }
         ("unsafe_reset_data", Some(_args)) => {
             let store_path = get_store_path(home_dir);
             info!(target: "near", "Removing all data from {}", store_path);
@@ -74,7 +74,7 @@ fn apply_block_at_height(
 }
 
 pub fn migrate_12_to_13(path: &String, near_config: &NearConfig) {
-    let store = create_store(path);
+    let store = create_store(path, &near_config.store_config);
     if !near_config.client_config.archive {
         // Non archival node. Perform a simply migration without necessarily fixing the inconsistencies
         // since the old data will be garbage collected in five epochs
@@ -24,14 +24,16 @@ use near_epoch_manager::{EpochManager, RewardCalculator};
 use near_pool::types::PoolIterator;
 use near_primitives::account::{AccessKey, Account};
 use near_primitives::block::{Approval, ApprovalInner};
-use near_primitives::challenge::ChallengesResult;
+use near_primitives::challenge::{ChallengesResult, PartialState};
 use near_primitives::contract::ContractCode;
 use near_primitives::epoch_manager::{BlockInfo, EpochConfig, EpochInfo};
 use near_primitives::errors::{EpochError, InvalidTxError, RuntimeError};
 use near_primitives::hash::{hash, CryptoHash};
+use near_primitives::profile::ProfileData;
 use near_primitives::receipt::Receipt;
 use near_primitives::sharding::ChunkHash;
 use near_primitives::state_record::StateRecord;
+use near_primitives::syncing::{StatePartSyncProgress, STATE_PART_CHUNK_SIZE};
 use near_primitives::transaction::SignedTransaction;
 use near_primitives::types::{
     AccountId, ApprovalStake, Balance, BlockHeight, EpochHeight, EpochId, EpochInfoProvider, Gas,
@@ -40,7 +42,7 @@ use near_primitives::types::{
 use near_primitives::version::ProtocolVersion;
 use near_primitives::views::{
     AccessKeyInfoView, CallResult, EpochValidatorInfo, QueryRequest, QueryResponse,
-    QueryResponseKind, ViewApplyState, ViewStateResult,
+    QueryResponseKind, ViewApplyState, ViewStatePageResult, ViewStateResult,
 };
 use near_store::{
     get_genesis_hash, get_genesis_state_roots, set_genesis_hash, set_genesis_state_roots, ColState,
@@ -135,8 +137,9 @@ pub struct NightshadeRuntime {
     trie_viewer: TrieViewer,
     pub runtime: Runtime,
     epoch_manager: SafeEpochManager,
-    shard_tracker: ShardTracker,
+    shard_tracker: RwLock<ShardTracker>,
     genesis_state_roots: Vec<StateRoot>,
+    record_gas_profiles: bool,
 }
 
 impl NightshadeRuntime {
@@ -227,11 +230,23 @@ impl NightshadeRuntime {
             runtime,
             trie_viewer,
             epoch_manager: SafeEpochManager(epoch_manager),
-            shard_tracker,
+            shard_tracker: RwLock::new(shard_tracker),
             genesis_state_roots: state_roots,
+            record_gas_profiles: false,
         }
     }
 
+    /// Makes chunks be applied on top of flat state, see `StoreConfig::flat_state`.
+    pub fn enable_flat_state(&mut self) {
+        self.tries.enable_flat_state();
+    }
+
+    /// Makes applying chunks record the gas profiles of the receipts into `ColReceiptGasProfiles`,
+    /// see `NearConfig::record_gas_profiles`.
+    pub fn enable_gas_profiles(&mut self) {
+        self.record_gas_profiles = true;
+    }
+
     fn get_epoch_height_from_prev_block(
         &self,
         prev_block_hash: &CryptoHash,
@@ -464,7 +479,12 @@ impl NightshadeRuntime {
             cache: Some(Arc::new(StoreCompiledContractCache { store: self.store.clone() })),
             #[cfg(feature = "protocol_feature_evm")]
             evm_chain_id: self.evm_chain_id(),
-            profile: Default::default(),
+            // Only counts the costs if `costs_counting` is enabled as well
+            profile: if self.record_gas_profiles {
+                ProfileData::new_enabled()
+            } else {
+                ProfileData::new_disabled()
+            },
         };
 
         let apply_result = self
@@ -518,6 +538,7 @@ impl NightshadeRuntime {
                 shard_id,
                 apply_result.trie_changes,
                 apply_result.state_changes,
+                *prev_block_hash,
                 block_hash.clone(),
             ),
             new_root: apply_result.state_root,
@@ -527,6 +548,7 @@ impl NightshadeRuntime {
             total_gas_burnt,
             total_balance_burnt,
             proof: apply_result.proof,
+            gas_profiles: apply_result.gas_profiles,
         };
 
         Ok(result)
@@ -978,7 +1000,12 @@ impl RuntimeAdapter for NightshadeRuntime {
         shard_id: ShardId,
         is_me: bool,
     ) -> bool {
-        self.shard_tracker.care_about_shard(account_id, parent_hash, shard_id, is_me)
+        self.shard_tracker.read().expect(POISONED_LOCK_ERR).care_about_shard(
+            account_id,
+            parent_hash,
+            shard_id,
+            is_me,
+        )
     }
 
     fn will_care_about_shard(
@@ -988,7 +1015,31 @@ impl RuntimeAdapter for NightshadeRuntime {
         shard_id: ShardId,
         is_me: bool,
     ) -> bool {
-        self.shard_tracker.will_care_about_shard(account_id, parent_hash, shard_id, is_me)
+        self.shard_tracker.read().expect(POISONED_LOCK_ERR).will_care_about_shard(
+            account_id,
+            parent_hash,
+            shard_id,
+            is_me,
+        )
+    }
+
+    fn track_shards(&self, account_ids: &[AccountId], shard_ids: &[ShardId]) -> Result<(), Error> {
+        let mut shard_tracker = self.shard_tracker.write().expect(POISONED_LOCK_ERR);
+        shard_tracker.track_accounts(account_ids);
+        shard_tracker.track_shards(shard_ids);
+        Ok(())
+    }
+
+    fn untrack_shards(
+        &self,
+        block_hash: &CryptoHash,
+        account_ids: Vec<AccountId>,
+        shard_ids: Vec<ShardId>,
+    ) -> Result<(), Error> {
+        let mut shard_tracker = self.shard_tracker.write().expect(POISONED_LOCK_ERR);
+        shard_tracker.untrack_accounts(block_hash, account_ids)?;
+        shard_tracker.untrack_shards(block_hash, shard_ids)?;
+        Ok(())
     }
 
     fn is_next_block_epoch_start(&self, parent_hash: &CryptoHash) -> Result<bool, Error> {
@@ -1182,8 +1233,13 @@ impl RuntimeAdapter for NightshadeRuntime {
         random_seed: CryptoHash,
         generate_storage_proof: bool,
     ) -> Result<ApplyTransactionResult, Error> {
-        let trie = self.get_trie_for_shard(shard_id);
-        let trie = if generate_storage_proof { trie.recording_reads() } else { trie };
+        let trie = if generate_storage_proof {
+            self.get_trie_for_shard(shard_id).recording_reads()
+        } else {
+            self.tries
+                .get_trie_with_flat_state_for_shard(shard_id, prev_block_hash, state_root)
+                .map_err(|e| Error::from(ErrorKind::StorageError(e)))?
+        };
         match self.process_state_update(
             trie,
             *state_root,
@@ -1338,6 +1394,30 @@ impl RuntimeAdapter for NightshadeRuntime {
                     block_hash: *block_hash,
                 })
             }
+            QueryRequest::ViewStatePage { account_id, prefix, start_key, limit, include_proof } => {
+                let view_state_page_result = self
+                    .view_state_page(
+                        shard_id,
+                        *state_root,
+                        account_id,
+                        prefix.as_ref(),
+                        start_key.as_deref(),
+                        *limit,
+                        *include_proof,
+                    )
+                    .map_err(|err| {
+                        near_chain::near_chain_primitives::error::QueryError::from_view_state_error(
+                            err,
+                            block_height,
+                            *block_hash,
+                        )
+                    })?;
+                Ok(QueryResponse {
+                    kind: QueryResponseKind::ViewStatePage(view_state_page_result),
+                    block_height,
+                    block_hash: *block_hash,
+                })
+            }
             QueryRequest::ViewAccessKeyList { account_id } => {
                 let access_key_list =
                     self.view_access_keys(shard_id, *state_root, account_id).map_err(|err| {
@@ -1454,6 +1534,64 @@ impl RuntimeAdapter for NightshadeRuntime {
         Ok(store_update.commit()?)
     }
 
+    fn split_state_part(&self, state_root: &StateRoot, part: &[u8]) -> Result<Vec<Vec<u8>>, Error> {
+        let part = PartialState::try_from_slice(part)?;
+        let chunks = Trie::split_state_part(state_root, part, STATE_PART_CHUNK_SIZE)
+            .map_err(|e| Error::from(e.to_string()))?;
+        Ok(chunks
+            .into_iter()
+            .map(|chunk| chunk.try_to_vec().expect("serializer should not fail"))
+            .collect())
+    }
+
+    fn apply_state_part_chunk(
+        &self,
+        shard_id: ShardId,
+        state_root: &StateRoot,
+        progress: &mut StatePartSyncProgress,
+        chunk: &[u8],
+    ) -> Result<StoreUpdate, Error> {
+        // Deserialization error means we've got the data from malicious peer
+        let chunk = PartialState::try_from_slice(chunk)?;
+        let trie_changes = Trie::apply_state_part_chunk(state_root, progress, chunk)
+            .map_err(|e| Error::from(e.to_string()))?;
+        let (store_update, _) = self
+            .get_tries()
+            .apply_all(&trie_changes, shard_id)
+            .expect("TrieChanges::into never fails");
+        Ok(store_update)
+    }
+
+    fn release_applied_state_part(
+        &self,
+        shard_id: ShardId,
+        progress: &StatePartSyncProgress,
+    ) -> Result<StoreUpdate, Error> {
+        let tries = self.get_tries();
+        let mut store_update = StoreUpdate::new_with_tries(tries.clone());
+        tries
+            .revert_insertions(
+                &Trie::applied_state_part_changes(progress),
+                shard_id,
+                &mut store_update,
+            )
+            .map_err(|e| Error::from(e.to_string()))?;
+        Ok(store_update)
+    }
+
+    fn validate_applied_state_part(
+        &self,
+        shard_id: ShardId,
+        state_root: &StateRoot,
+        part_id: u64,
+        num_parts: u64,
+    ) -> bool {
+        self.get_tries()
+            .get_trie_for_shard(shard_id)
+            .validate_applied_state_part(state_root, part_id, num_parts)
+            .is_ok()
+    }
+
     fn get_state_root_node(
         &self,
         shard_id: ShardId,
@@ -1638,6 +1776,27 @@ impl node_runtime::adapter::ViewRuntimeAdapter for NightshadeRuntime {
         let state_update = self.get_tries().new_trie_update_view(shard_id, state_root);
         self.trie_viewer.view_state(&state_update, account_id, prefix)
     }
+
+    fn view_state_page(
+        &self,
+        shard_id: ShardId,
+        state_root: MerkleHash,
+        account_id: &AccountId,
+        prefix: &[u8],
+        start_key: Option<&[u8]>,
+        limit: Option<u64>,
+        include_proof: bool,
+    ) -> Result<ViewStatePageResult, node_runtime::state_viewer::errors::ViewStateError> {
+        let state_update = self.get_tries().new_trie_update_view(shard_id, state_root);
+        self.trie_viewer.view_state_page(
+            &state_update,
+            account_id,
+            prefix,
+            start_key,
+            limit,
+            include_proof,
+        )
+    }
 }
 
 #[cfg(test)]
@@ -1658,7 +1817,7 @@ mod test {
     use near_primitives::views::{
         AccountView, CurrentEpochValidatorInfo, NextEpochValidatorInfo, ValidatorKickoutView,
     };
-    use near_store::create_store;
+    use near_store::{create_store, StoreConfig};
 
     use crate::config::{GenesisExt, TESTING_INIT_BALANCE, TESTING_INIT_STAKE};
     use crate::get_store_path;
@@ -1747,7 +1906,7 @@ mod test {
             has_reward: bool,
         ) -> Self {
             let dir = tempfile::Builder::new().prefix(prefix).tempdir().unwrap();
-            let store = create_store(&get_store_path(dir.path()));
+            let store = create_store(&get_store_path(dir.path()), &StoreConfig::default());
             let all_validators = validators.iter().fold(BTreeSet::new(), |acc, x| {
                 acc.union(&x.iter().map(|x| x.as_str()).collect()).cloned().collect()
             });
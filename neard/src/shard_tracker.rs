@@ -84,7 +84,6 @@ impl ShardTracker {
     /// Track a list of accounts. The tracking will take effect immediately because
     /// even if we want to start tracking the accounts in the next epoch, it cannot harm
     /// us to start tracking them earlier.
-    #[allow(unused)]
     pub fn track_accounts(&mut self, account_ids: &[AccountId]) {
         for account_id in account_ids.iter() {
             self.track_account(account_id);
@@ -97,7 +96,6 @@ impl ShardTracker {
     }
 
     /// Track a list of shards. Similar to tracking accounts, the tracking starts immediately.
-    #[allow(unused)]
     pub fn track_shards(&mut self, shard_ids: &[ShardId]) {
         for shard_id in shard_ids.iter() {
             self.track_shard(*shard_id);
@@ -149,7 +147,6 @@ impl ShardTracker {
     }
 
     /// Stop tracking a list of accounts in the next epoch.
-    #[allow(unused)]
     pub fn untrack_accounts(
         &mut self,
         block_hash: &CryptoHash,
@@ -163,7 +160,6 @@ impl ShardTracker {
     }
 
     /// Stop tracking a list of shards in the next epoch.
-    #[allow(unused)]
     pub fn untrack_shards(
         &mut self,
         block_hash: &CryptoHash,
@@ -0,0 +1,62 @@
+/// Test that gas profiles are only recorded by nodes which enable them.
+use std::path::Path;
+use std::sync::Arc;
+
+use near_chain::{ChainGenesis, RuntimeAdapter};
+use near_chain_configs::Genesis;
+use near_client::test_utils::TestEnv;
+use near_crypto::{InMemorySigner, KeyType};
+use near_logger_utils::init_integration_logger;
+use near_primitives::transaction::SignedTransaction;
+use near_store::test_utils::create_test_store;
+use near_store::{DBCol, Store};
+use neard::config::{GenesisExt, TESTING_INIT_BALANCE};
+
+/// Sends money from `test0` to `test1` and produces enough blocks for the receipt to be executed.
+/// Returns the store of the node.
+fn send_money(record_gas_profiles: bool) -> Arc<Store> {
+    init_integration_logger();
+    let store = create_test_store();
+    let genesis = Genesis::test(vec!["test0", "test1"], 1);
+    let mut runtime =
+        neard::NightshadeRuntime::new(Path::new("."), store.clone(), &genesis, vec![], vec![]);
+    if record_gas_profiles {
+        runtime.enable_gas_profiles();
+    }
+    let runtimes: Vec<Arc<dyn RuntimeAdapter>> = vec![Arc::new(runtime)];
+    let mut env = TestEnv::new_with_runtime(ChainGenesis::from(&genesis), 1, 1, runtimes);
+    let signer = InMemorySigner::from_seed("test0", KeyType::ED25519, "test0");
+    let genesis_hash = *env.clients[0].chain.genesis().hash();
+    env.clients[0].process_tx(
+        SignedTransaction::send_money(
+            1,
+            "test0".to_string(),
+            "test1".to_string(),
+            &signer,
+            1000,
+            genesis_hash,
+        ),
+        false,
+        false,
+    );
+    for i in 1..5 {
+        env.produce_block(0, i);
+    }
+    assert_eq!(env.query_balance("test1".to_string()), TESTING_INIT_BALANCE + 1000);
+    store
+}
+
+/// The column stays empty unless the runtime is told to record gas profiles, whether or not the
+/// node counts the costs.
+#[test]
+fn test_gas_profiles_not_recorded_when_disabled() {
+    let store = send_money(false);
+    assert!(store.iter(DBCol::ColReceiptGasProfiles).next().is_none());
+}
+
+#[cfg(feature = "costs_counting")]
+#[test]
+fn test_gas_profiles_recorded_when_enabled() {
+    let store = send_money(true);
+    assert!(store.iter(DBCol::ColReceiptGasProfiles).next().is_some());
+}
@@ -7,11 +7,13 @@ use futures::future::join_all;
 use futures::{future, FutureExt, TryFutureExt};
 
 use near_actix_test_utils::run_actix_until_stop;
+use near_chain_configs::Genesis;
 use near_client::{GetBlock, GetExecutionOutcome, TxStatus};
 use near_crypto::{InMemorySigner, KeyType};
 use near_jsonrpc::client::new_client;
+use near_jsonrpc_primitives::rpc::{RpcAccountHistoryCursor, RpcAccountHistoryRequest};
 use near_logger_utils::init_integration_logger;
-use near_network::test_utils::WaitOrTimeout;
+use near_network::test_utils::{open_port, WaitOrTimeout};
 use near_primitives::hash::{hash, CryptoHash};
 use near_primitives::merkle::{compute_root_from_path_and_item, verify_path};
 use near_primitives::serialize::{from_base64, to_base64};
@@ -20,7 +22,8 @@ use near_primitives::types::{BlockId, BlockReference, Finality, TransactionOrRec
 use near_primitives::views::{
     ExecutionOutcomeView, ExecutionStatusView, FinalExecutionOutcomeViewEnum, FinalExecutionStatus,
 };
-use neard::config::TESTING_INIT_BALANCE;
+use neard::config::{GenesisExt, TESTING_INIT_BALANCE};
+use neard::{load_test_config, start_with_config};
 use std::sync::atomic::AtomicBool;
 use std::sync::atomic::Ordering::SeqCst;
 use std::sync::Arc;
@@ -847,3 +850,77 @@ fn test_query_rpc_account_view_account_doesnt_exist_must_return_error() {
         });
     });
 }
+
+/// Starts an archival node which maintains the account history index, sends a transfer to
+/// the signer itself and checks that the transaction and its local receipt are returned.
+#[test]
+fn test_account_history_rpc() {
+    init_integration_logger();
+    heavy_test(|| {
+        run_actix_until_stop(async move {
+            let dir = tempfile::Builder::new().prefix("account_history").tempdir().unwrap();
+            let genesis = Genesis::test(vec!["near.0"], 1);
+            let mut near_config = load_test_config("near.0", open_port(), genesis.clone());
+            near_config.client_config.archive = true;
+            near_config.client_config.account_history_index = true;
+            let rpc_addr = near_config.rpc_config.addr.clone();
+            start_with_config(dir.path(), near_config);
+
+            let genesis_hash = *genesis_block(&genesis).hash();
+            let signer = InMemorySigner::from_seed("near.0", KeyType::ED25519, "near.0");
+            let transaction = SignedTransaction::send_money(
+                1,
+                "near.0".to_string(),
+                "near.0".to_string(),
+                &signer,
+                10000,
+                genesis_hash,
+            );
+            let bytes = transaction.try_to_vec().unwrap();
+
+            actix::spawn(async move {
+                let client = new_client(&format!("http://{}", rpc_addr));
+                let outcome = loop {
+                    match client.broadcast_tx_commit(to_base64(&bytes)).await {
+                        Ok(outcome) => break outcome,
+                        Err(_) => sleep(Duration::from_millis(100)).await,
+                    }
+                };
+                let tx_hash = outcome.transaction_outcome.id;
+                let local_receipt_id = outcome.transaction_outcome.outcome.receipt_ids[0];
+                // Blocks are indexed once they are final.
+                for _ in 0..100 {
+                    let response = client
+                        .EXPERIMENTAL_account_history(RpcAccountHistoryRequest {
+                            account_id: "near.0".to_string(),
+                            after: None,
+                            limit: None,
+                        })
+                        .await
+                        .unwrap();
+                    let ids: Vec<_> = response.items.iter().map(|item| item.id).collect();
+                    if ids.contains(&tx_hash) {
+                        assert!(ids.contains(&local_receipt_id));
+                        let first = &response.items[0];
+                        let next_page = client
+                            .EXPERIMENTAL_account_history(RpcAccountHistoryRequest {
+                                account_id: "near.0".to_string(),
+                                after: Some(RpcAccountHistoryCursor {
+                                    block_height: first.block_height,
+                                    id: first.id,
+                                }),
+                                limit: None,
+                            })
+                            .await
+                            .unwrap();
+                        assert_eq!(next_page.items[..], response.items[1..]);
+                        System::current().stop();
+                        return;
+                    }
+                    sleep(Duration::from_millis(100)).await;
+                }
+                panic!("Transaction {} was not indexed", tx_hash);
+            });
+        });
+    });
+}
@@ -11,7 +11,7 @@ use near_primitives::test_utils::MockEpochInfoProvider;
 use near_primitives::transaction::{ExecutionStatus, SignedTransaction};
 use near_primitives::types::{Gas, MerkleHash, StateRoot};
 use near_primitives::version::PROTOCOL_VERSION;
-use near_store::{create_store, ColState, ShardTries, StoreCompiledContractCache};
+use near_store::{create_store, ColState, ShardTries, StoreCompiledContractCache, StoreConfig};
 use near_vm_logic::VMLimitConfig;
 use neard::get_store_path;
 use node_runtime::{ApplyState, Runtime};
@@ -38,7 +38,7 @@ impl RuntimeTestbed {
     pub fn from_state_dump(dump_dir: &Path) -> Self {
         let workdir = tempfile::Builder::new().prefix("runtime_testbed").tempdir().unwrap();
         println!("workdir {}", workdir.path().display());
-        let store = create_store(&get_store_path(workdir.path()));
+        let store = create_store(&get_store_path(workdir.path()), &StoreConfig::default());
         let tries = ShardTries::new(store.clone(), 1);
 
         let genesis = Genesis::from_file(dump_dir.join("genesis.json"));
@@ -13,6 +13,7 @@ use near_primitives::{
     contract::ContractCode,
     errors::{ActionError, ActionErrorKind, RuntimeError, TxExecutionError},
     hash::CryptoHash,
+    profile::ActionGasProfile,
     receipt::{
         ActionReceipt, DataReceipt, DelayedReceiptIndices, Receipt, ReceiptEnum, ReceivedData,
     },
@@ -113,6 +114,9 @@ pub struct ApplyResult {
     pub state_changes: Vec<RawStateChangesWithTrieKey>,
     pub stats: ApplyStats,
     pub proof: Option<PartialStorage>,
+    /// Gas profiles of the actions of every executed action receipt, keyed by the receipt id.
+    /// Only recorded if `ApplyState::profile` is enabled.
+    pub gas_profiles: Vec<(CryptoHash, Vec<ActionGasProfile>)>,
 }
 
 #[derive(Debug)]
@@ -441,6 +445,7 @@ impl Runtime {
         outgoing_receipts: &mut Vec<Receipt>,
         validator_proposals: &mut Vec<ValidatorStake>,
         stats: &mut ApplyStats,
+        gas_profiles: &mut Vec<(CryptoHash, Vec<ActionGasProfile>)>,
         epoch_info_provider: &dyn EpochInfoProvider,
     ) -> Result<ExecutionOutcomeWithId, RuntimeError> {
         let action_receipt = match receipt.receipt {
@@ -483,6 +488,7 @@ impl Runtime {
             apply_state.config.transaction_costs.action_receipt_creation_config.exec_fee();
         result.gas_used = exec_fee;
         result.gas_burnt = exec_fee;
+        let mut action_gas_profiles = vec![];
         // Executing actions one by one
         for (action_index, action) in action_receipt.actions.iter().enumerate() {
             let action_hash = create_action_hash(
@@ -492,6 +498,11 @@ impl Runtime {
                 &apply_state.block_hash,
                 action_index,
             );
+            let profile_before = if apply_state.profile.is_enabled() {
+                Some(apply_state.profile.gas_profile())
+            } else {
+                None
+            };
             let mut new_result = self.apply_action(
                 action,
                 state_update,
@@ -513,6 +524,12 @@ impl Runtime {
                     new_result.result = Err(ActionErrorKind::NewReceiptValidationError(e).into());
                 }
             }
+            if let Some(profile_before) = profile_before {
+                action_gas_profiles.push(ActionGasProfile {
+                    gas_burnt: new_result.gas_burnt,
+                    profile: apply_state.profile.gas_profile().since(&profile_before),
+                });
+            }
             result.merge(new_result)?;
             // TODO storage error
             if let Err(ref mut res) = result.result {
@@ -700,6 +717,10 @@ impl Runtime {
 
         Self::print_log(&result.logs);
 
+        if apply_state.profile.is_enabled() {
+            gas_profiles.push((receipt.receipt_id, action_gas_profiles));
+        }
+
         Ok(ExecutionOutcomeWithId {
             id: receipt.receipt_id,
             outcome: ExecutionOutcome {
@@ -793,6 +814,7 @@ impl Runtime {
         outgoing_receipts: &mut Vec<Receipt>,
         validator_proposals: &mut Vec<ValidatorStake>,
         stats: &mut ApplyStats,
+        gas_profiles: &mut Vec<(CryptoHash, Vec<ActionGasProfile>)>,
         epoch_info_provider: &dyn EpochInfoProvider,
     ) -> Result<Option<ExecutionOutcomeWithId>, RuntimeError> {
         let account_id = &receipt.receiver_id;
@@ -861,6 +883,7 @@ impl Runtime {
                                 outgoing_receipts,
                                 validator_proposals,
                                 stats,
+                                gas_profiles,
                                 epoch_info_provider,
                             )
                             .map(Some);
@@ -915,6 +938,7 @@ impl Runtime {
                             outgoing_receipts,
                             validator_proposals,
                             stats,
+                            gas_profiles,
                             epoch_info_provider,
                         )
                         .map(Some);
@@ -1092,6 +1116,7 @@ impl Runtime {
         let mut validator_proposals = vec![];
         let mut local_receipts = vec![];
         let mut outcomes = vec![];
+        let mut gas_profiles = vec![];
         let mut total_gas_burnt = 0;
 
         for signed_transaction in transactions {
@@ -1127,6 +1152,7 @@ impl Runtime {
                 &mut outgoing_receipts,
                 &mut validator_proposals,
                 &mut stats,
+                &mut gas_profiles,
                 epoch_info_provider,
             )?
             .into_iter()
@@ -1238,6 +1264,7 @@ impl Runtime {
             state_changes,
             stats,
             proof,
+            gas_profiles,
         })
     }
 
@@ -1425,12 +1452,14 @@ mod tests {
     use super::*;
 
     use near_crypto::{InMemorySigner, KeyType, Signer};
+    use near_primitives::config::{ActionCosts, ExtCosts};
     use near_primitives::errors::ReceiptValidationError;
     use near_primitives::hash::hash;
+    use near_primitives::profile::GasProfile;
     use near_primitives::profile::ProfileData;
     use near_primitives::test_utils::{account_new, MockEpochInfoProvider};
     use near_primitives::transaction::{
-        AddKeyAction, DeleteKeyAction, FunctionCallAction, TransferAction,
+        AddKeyAction, DeleteKeyAction, DeployContractAction, FunctionCallAction, TransferAction,
     };
     use near_primitives::types::MerkleHash;
     use near_primitives::version::PROTOCOL_VERSION;
@@ -2317,4 +2346,77 @@ mod tests {
 
         assert_eq!(final_account_state.storage_usage, 0);
     }
+
+    #[test]
+    fn test_apply_gas_profiles() {
+        let initial_balance = to_yocto(1_000_000);
+        let initial_locked = to_yocto(500_000);
+        let gas_limit = 10u64.pow(15);
+        let (runtime, tries, root, apply_state, _, epoch_info_provider) =
+            setup_runtime(initial_balance, initial_locked, gas_limit);
+
+        let code = include_bytes!("../../near-vm-runner/tests/res/test_contract_rs.wasm").to_vec();
+        let actions = vec![
+            Action::DeployContract(DeployContractAction { code }),
+            Action::FunctionCall(FunctionCallAction {
+                method_name: "log_something".to_string(),
+                args: vec![],
+                gas: 10u64.pow(14),
+                deposit: 0,
+            }),
+        ];
+        let deploy_exec_fee = exec_fee(
+            &apply_state.config.transaction_costs,
+            &actions[0],
+            &alice_account(),
+            PROTOCOL_VERSION,
+        );
+        let receipts = vec![Receipt {
+            predecessor_id: alice_account(),
+            receiver_id: alice_account(),
+            receipt_id: hash(b"receipt"),
+            receipt: ReceiptEnum::Action(ActionReceipt {
+                signer_id: alice_account(),
+                signer_public_key: PublicKey::empty(KeyType::ED25519),
+                gas_price: GAS_PRICE,
+                output_data_receivers: vec![],
+                input_data_ids: vec![],
+                actions,
+            }),
+        }];
+
+        let result = runtime
+            .apply(
+                tries.get_trie_for_shard(0),
+                root,
+                &None,
+                &apply_state,
+                &receipts,
+                &[],
+                &epoch_info_provider,
+            )
+            .unwrap();
+        if !apply_state.profile.is_enabled() {
+            // The profiles are only recorded if enabled and with the `costs_counting` feature
+            assert!(result.gas_profiles.is_empty());
+            return;
+        }
+        assert_eq!(result.gas_profiles.len(), 1);
+        let (receipt_id, action_gas_profiles) = &result.gas_profiles[0];
+        assert_eq!(receipt_id, &receipts[0].receipt_id);
+        assert_eq!(action_gas_profiles.len(), 2);
+        // Only the execution of the contract is broken down by costs, the other actions
+        // just burn their exec fee
+        assert_eq!(action_gas_profiles[0].gas_burnt, deploy_exec_fee);
+        assert_eq!(
+            action_gas_profiles[0].profile,
+            GasProfile {
+                ext_costs: vec![0; ExtCosts::count()],
+                action_costs: vec![0; ActionCosts::count()],
+            }
+        );
+        let log_base_gas = action_gas_profiles[1].profile.ext_costs[ExtCosts::log_base as usize];
+        assert!(log_base_gas > 0);
+        assert!(action_gas_profiles[1].gas_burnt > log_base_gas);
+    }
 }
@@ -11,17 +11,20 @@ use near_primitives::{
     transaction::FunctionCallAction,
     trie_key::trie_key_parsers,
     types::{AccountId, EpochInfoProvider},
-    views::{StateItem, ViewApplyState, ViewStateResult},
+    views::{StateItem, ViewApplyState, ViewStatePageResult, ViewStateResult},
 };
 use near_runtime_utils::is_valid_account_id;
 use near_store::{get_access_key, get_account, get_code, TrieUpdate};
 use near_vm_logic::ReturnData;
-use std::{str, sync::Arc, time::Instant};
+use std::{rc::Rc, str, sync::Arc, time::Instant};
 
 use crate::{actions::execute_function_call, ext::RuntimeExt};
 
 pub mod errors;
 
+/// Maximum number of contract data items returned by `TrieViewer::view_state_page`.
+pub const MAX_VIEW_STATE_PAGE_SIZE: u64 = 1000;
+
 pub struct TrieViewer {}
 
 impl TrieViewer {
@@ -144,6 +147,67 @@ impl TrieViewer {
         Ok(ViewStateResult { values, proof: vec![] })
     }
 
+    /// Returns up to `limit` contract data items with the given prefix, starting from `start_key`.
+    /// If `include_proof` is set, all trie nodes visited while reading the page are returned, so
+    /// the page can be verified against the state root by replaying the same iteration.
+    pub fn view_state_page(
+        &self,
+        state_update: &TrieUpdate,
+        account_id: &AccountId,
+        prefix: &[u8],
+        start_key: Option<&[u8]>,
+        limit: Option<u64>,
+        include_proof: bool,
+    ) -> Result<ViewStatePageResult, errors::ViewStateError> {
+        if !is_valid_account_id(account_id) {
+            return Err(errors::ViewStateError::InvalidAccountId {
+                requested_account_id: account_id.clone(),
+            });
+        }
+        let limit = limit.unwrap_or(MAX_VIEW_STATE_PAGE_SIZE).min(MAX_VIEW_STATE_PAGE_SIZE);
+        let query = trie_key_parsers::get_raw_prefix_for_contract_data(account_id, prefix);
+        let acc_sep_len = query.len() - prefix.len();
+        let seek_key = match start_key {
+            Some(start_key) if start_key > prefix => {
+                trie_key_parsers::get_raw_prefix_for_contract_data(account_id, start_key)
+            }
+            _ => query.clone(),
+        };
+        let trie = if include_proof {
+            Rc::new(state_update.trie.try_recording_reads().ok_or_else(|| {
+                errors::ViewStateError::InternalError {
+                    error_message: "state proofs are not available for this trie".to_string(),
+                }
+            })?)
+        } else {
+            state_update.trie.clone()
+        };
+        let mut values = vec![];
+        let mut next_key = None;
+        let mut iter = trie.iter(&state_update.get_root())?;
+        iter.seek(&seek_key)?;
+        for item in iter {
+            let (key, value) = item?;
+            if !key.starts_with(&query) {
+                break;
+            }
+            if values.len() as u64 == limit {
+                next_key = Some(to_base64(&key[acc_sep_len..]));
+                break;
+            }
+            values.push(StateItem {
+                key: to_base64(&key[acc_sep_len..]),
+                value: to_base64(&value),
+                proof: vec![],
+            });
+        }
+        let proof_nodes = match trie.recorded_storage() {
+            Some(storage) => storage.nodes.0.iter().map(to_base64).collect(),
+            None => vec![],
+        };
+        Ok(ViewStatePageResult { values, next_key, proof_nodes })
+    }
+
     pub fn call_function(
         &self,
         mut state_update: TrieUpdate,
@@ -259,11 +323,14 @@ mod tests {
     #[cfg(feature = "protocol_feature_evm")]
     use near_chain_configs::TESTNET_EVM_CHAIN_ID;
     use near_primitives::{
+        challenge::PartialState,
+        serialize::from_base64,
         test_utils::MockEpochInfoProvider,
         trie_key::TrieKey,
         types::{EpochId, StateChangeCause},
         version::PROTOCOL_VERSION,
     };
+    use near_store::{PartialStorage, Trie};
     use testlib::runtime_utils::{
         alice_account, encode_int, get_runtime_and_trie, get_test_trie_viewer,
     };
@@ -453,6 +520,86 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_view_state_page() {
+        let (_, tries, root) = get_runtime_and_trie();
+        let mut state_update = tries.new_trie_update(0, root);
+        for key in [b"test123", b"test321", b"test555"].iter() {
+            state_update.set(
+                TrieKey::ContractData { account_id: alice_account(), key: key.to_vec() },
+                b"123".to_vec(),
+            );
+        }
+        state_update.set(
+            TrieKey::ContractData { account_id: "alina".to_string(), key: b"test999".to_vec() },
+            b"321".to_vec(),
+        );
+        state_update.commit(StateChangeCause::InitialState);
+        let trie_changes = state_update.finalize().unwrap().0;
+        let (db_changes, new_root) = tries.apply_all(&trie_changes, 0).unwrap();
+        db_changes.commit().unwrap();
+
+        let state_update = tries.new_trie_update(0, new_root);
+        let trie_viewer = TrieViewer::new();
+        let page = trie_viewer
+            .view_state_page(&state_update, &alice_account(), b"test", None, Some(2), false)
+            .unwrap();
+        assert_eq!(
+            page.values.iter().map(|item| item.key.clone()).collect::<Vec<_>>(),
+            vec![to_base64(b"test123"), to_base64(b"test321")]
+        );
+        assert_eq!(page.next_key, Some(to_base64(b"test555")));
+        assert!(page.proof_nodes.is_empty());
+
+        let page = trie_viewer
+            .view_state_page(
+                &state_update,
+                &alice_account(),
+                b"test",
+                Some(&b"test555"[..]),
+                Some(2),
+                true,
+            )
+            .unwrap();
+        assert_eq!(page.values.len(), 1);
+        assert_eq!(page.values[0].key, to_base64(b"test555"));
+        assert_eq!(page.next_key, None);
+
+        // Replaying the same iteration over the proof nodes yields the same page.
+        let nodes = page.proof_nodes.iter().map(|node| from_base64(node).unwrap()).collect();
+        let partial_trie =
+            Trie::from_recorded_storage(PartialStorage { nodes: PartialState(nodes) });
+        let mut iter = partial_trie.iter(&new_root).unwrap();
+        iter.seek(&trie_key_parsers::get_raw_prefix_for_contract_data(
+            &alice_account(),
+            b"test555",
+        ))
+        .unwrap();
+        let (key, value) = iter.next().unwrap().unwrap();
+        assert_eq!(to_base64(&value), page.values[0].value);
+        assert!(key.ends_with(b"test555"));
+        let (key, _) = iter.next().unwrap().unwrap();
+        assert!(!key.starts_with(&trie_key_parsers::get_raw_prefix_for_contract_data(
+            &alice_account(),
+            b"test"
+        )));
+
+        // Proofs can't be recorded over a trie which is itself built from a proof.
+        let nodes = page.proof_nodes.iter().map(|node| from_base64(node).unwrap()).collect();
+        let partial_trie =
+            Trie::from_recorded_storage(PartialStorage { nodes: PartialState(nodes) });
+        let partial_state_update = TrieUpdate::new(Rc::new(partial_trie), new_root);
+        let result = trie_viewer.view_state_page(
+            &partial_state_update,
+            &alice_account(),
+            b"test",
+            Some(&b"test555"[..]),
+            Some(2),
+            true,
+        );
+        assert!(matches!(result, Err(errors::ViewStateError::InternalError { .. })));
+    }
+
     #[test]
     fn test_log_when_panic() {
         let (viewer, root) = get_test_trie_viewer();
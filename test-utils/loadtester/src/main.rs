@@ -16,7 +16,7 @@ use near_crypto::Signer;
 use near_primitives::types::{NumSeats, NumShards};
 use near_primitives::validator_signer::ValidatorSigner;
 use near_primitives::version::Version;
-use near_store::{create_store, ColState};
+use near_store::{create_store, ColState, StoreConfig};
 use neard::config::create_testnet_configs;
 use neard::{get_default_home, get_store_path};
 use remote_node::RemoteNode;
@@ -197,7 +197,7 @@ fn load_state_dump(matches: &clap::ArgMatches<'_>) {
     let state_dump_path = value_t_or_exit!(matches, "state_dump", PathBuf);
     let dir = dir_buf.as_path();
     let state_dump = state_dump_path.as_path();
-    let store = create_store(&get_store_path(dir));
+    let store = create_store(&get_store_path(dir), &StoreConfig::default());
     store.load_from_file(ColState, state_dump).expect("Failed to read state dump");
 }
 
@@ -14,12 +14,14 @@ use near_chain::types::{ApplyTransactionResult, BlockHeaderInfo};
 use near_chain::{ChainStore, ChainStoreAccess, ChainStoreUpdate, RuntimeAdapter};
 use near_logger_utils::init_integration_logger;
 use near_network::peer_store::PeerStore;
+use near_network::routing::{components_to_dot, load_stored_components};
 use near_primitives::block::BlockHeader;
 use near_primitives::contract::ContractCode;
 use near_primitives::hash::CryptoHash;
-use near_primitives::serialize::to_base;
+use near_primitives::serialize::{to_base, to_base64};
 use near_primitives::state_record::StateRecord;
 use near_primitives::types::{BlockHeight, ChunkExtra, ShardId, StateRoot};
+use near_primitives::views::TrieKeyView;
 use near_store::test_utils::create_test_store;
 use near_store::{create_store, Store, TrieIterator};
 use neard::{get_default_home, get_store_path, load_config, NearConfig, NightshadeRuntime};
@@ -369,6 +371,26 @@ fn dump_code(account: &str, contract_code: ContractCode, output: &str) {
     println!("Dump contract of account {} into file {}", account, output);
 }
 
+fn diff_state(
+    runtime: &NightshadeRuntime,
+    shard_id: ShardId,
+    old_state_root: &StateRoot,
+    new_state_root: &StateRoot,
+) {
+    let trie = runtime.get_trie_for_shard(shard_id);
+    let changes = trie.diff(old_state_root, new_state_root, None, None).unwrap();
+    let format_value = |value: Option<Vec<u8>>| value.map_or("<none>".to_string(), to_base64);
+    for item in changes.iter() {
+        println!(
+            "{:?}: {} -> {}",
+            TrieKeyView::from_raw_key(&item.key),
+            format_value(item.old_value.clone()),
+            format_value(item.new_value.clone()),
+        );
+    }
+    println!("{} records differ between {} and {}", changes.len(), old_state_root, new_state_root);
+}
+
 fn main() {
     init_integration_logger();
 
@@ -382,6 +404,7 @@ fn main() {
                 .takes_value(true),
         )
         .subcommand(SubCommand::with_name("peers"))
+        .subcommand(SubCommand::with_name("routing_graph"))
         .subcommand(SubCommand::with_name("state"))
         .subcommand(
             SubCommand::with_name("dump_state").arg(
@@ -487,12 +510,34 @@ fn main() {
                 )
                 .help("dump deployed contract code of given account to wasm file"),
         )
+        .subcommand(
+            SubCommand::with_name("diff_state")
+                .arg(
+                    Arg::with_name("root_a")
+                        .required(true)
+                        .help("Old state root")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("root_b")
+                        .required(true)
+                        .help("New state root")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("shard_id")
+                        .long("shard_id")
+                        .help("Id of the shard both state roots belong to")
+                        .takes_value(true),
+                )
+                .help("print records which differ between two state roots"),
+        )
         .get_matches();
 
     let home_dir = matches.value_of("home").map(|dir| Path::new(dir)).unwrap();
     let near_config = load_config(home_dir);
 
-    let store = create_store(&get_store_path(&home_dir));
+    let store = create_store(&get_store_path(&home_dir), &near_config.store_config);
 
     match matches.subcommand() {
         ("peers", Some(_args)) => {
@@ -501,6 +546,10 @@ fn main() {
                 println!("{} {:?}", peer_id, peer_info);
             }
         }
+        ("routing_graph", Some(_args)) => {
+            let components = load_stored_components(&store).unwrap();
+            print!("{}", components_to_dot(&components));
+        }
         ("state", Some(_args)) => {
             let (runtime, state_roots, header) = load_trie(store, &home_dir, &near_config);
             println!("Storage roots are {:?}, block height is {}", state_roots, header.height());
@@ -585,6 +634,18 @@ fn main() {
                 account_id
             );
         }
+        ("diff_state", Some(args)) => {
+            let parse_root = |name: &str| {
+                StateRoot::try_from(args.value_of(name).unwrap())
+                    .unwrap_or_else(|_| panic!("{} is not a valid state root", name))
+            };
+            let old_state_root = parse_root("root_a");
+            let new_state_root = parse_root("root_b");
+            let shard_id =
+                args.value_of("shard_id").map(|s| s.parse::<u64>().unwrap()).unwrap_or_default();
+            let (runtime, _state_roots, _header) = load_trie(store, &home_dir, &near_config);
+            diff_state(&runtime, shard_id, &old_state_root, &new_state_root);
+        }
         (_, _) => unreachable!(),
     }
 }
@@ -17,11 +17,24 @@ pub(crate) struct Opts {
 #[derive(Clap, Debug)]
 pub(crate) enum SubCommand {
     /// Run NEAR Indexer Example. Start observe the network
-    Run,
+    Run(RunArgs),
     /// Initialize necessary configs
     Init(InitConfigArgs),
 }
 
+#[derive(Clap, Debug)]
+pub(crate) struct RunArgs {
+    /// Write the blocks to newline-delimited JSON files in this directory instead of logging them
+    #[clap(long)]
+    pub ndjson_dir: Option<std::path::PathBuf>,
+    /// Start a new file once the current one is larger than this many bytes
+    #[clap(long)]
+    pub ndjson_max_file_size: Option<u64>,
+    /// Compress the finished files with zstd
+    #[clap(long)]
+    pub ndjson_compress: bool,
+}
+
 #[derive(Clap, Debug)]
 pub(crate) struct InitConfigArgs {
     /// chain/network id (localnet, testnet, devnet, betanet)
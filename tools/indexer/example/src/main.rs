@@ -6,9 +6,12 @@ use tracing::info;
 
 use configs::{init_logging, Opts, SubCommand};
 use near_indexer;
+use near_indexer::sinks::{run_sink, NdjsonSink, NdjsonSinkConfig};
 
 mod configs;
 
+const NDJSON_IN_FLIGHT_WINDOW: usize = 16;
+
 async fn listen_blocks(mut stream: mpsc::Receiver<near_indexer::StreamerMessage>) {
     while let Some(streamer_message) = stream.recv().await {
         // TODO: handle data as you need
@@ -145,6 +148,24 @@ async fn listen_blocks(mut stream: mpsc::Receiver<near_indexer::StreamerMessage>
         //                     },
         //                 },
         //                 receipt: None,
+        //                 state_changes: [
+        //                     StateChangeWithCauseView {
+        //                         cause: TransactionProcessing {
+        //                             tx_hash: `8dNv9S8rAFwso9fLwfDQXmw5yv5zscDjQpta96pMF6Bi`,
+        //                         },
+        //                         value: AccountUpdate {
+        //                             account_id: "test.near",
+        //                             account: AccountView {
+        //                                 amount: 999959999575444937500000000000000,
+        //                                 locked: 50000000000000000000000000000000,
+        //                                 code_hash: `11111111111111111111111111111111`,
+        //                                 storage_usage: 182,
+        //                                 storage_paid_at: 0,
+        //                             },
+        //                         },
+        //                     },
+        //                 ],
+        //                 gas_profile: None,
         //             },
         //         },
         //         ],
@@ -219,6 +240,39 @@ async fn listen_blocks(mut stream: mpsc::Receiver<near_indexer::StreamerMessage>
         //                 },
         //             },
         //             ),
+        //             state_changes: [
+        //                 StateChangeWithCauseView {
+        //                     cause: ReceiptProcessing {
+        //                         receipt_hash: `CbWu7WYYbYbn3kThs5gcxANrxy7AKLcMcBLxLw8Zq1Fz`,
+        //                     },
+        //                     value: AccountUpdate {
+        //                         account_id: "some.test.near",
+        //                         account: AccountView {
+        //                             amount: 40000000000000000000000000,
+        //                             locked: 0,
+        //                             code_hash: `11111111111111111111111111111111`,
+        //                             storage_usage: 182,
+        //                             storage_paid_at: 0,
+        //                         },
+        //                     },
+        //                 },
+        //             ],
+        //             gas_profile: Some(
+        //                 [
+        //                     ActionGasProfileView {
+        //                         gas_burnt: 99607375000,
+        //                         costs: [],
+        //                     },
+        //                     ActionGasProfileView {
+        //                         gas_burnt: 115123062500,
+        //                         costs: [],
+        //                     },
+        //                     ActionGasProfileView {
+        //                         gas_burnt: 101765125000,
+        //                         costs: [],
+        //                     },
+        //                 ],
+        //             ),
         //         },
         //         ],
         //     ],
@@ -263,16 +317,39 @@ fn main() {
         opts.home_dir.unwrap_or(std::path::PathBuf::from(near_indexer::get_default_home()));
 
     match opts.subcmd {
-        SubCommand::Run => {
+        SubCommand::Run(args) => {
             let indexer_config = near_indexer::IndexerConfig {
                 home_dir,
                 sync_mode: near_indexer::SyncModeEnum::FromInterruption,
                 await_for_node_synced: near_indexer::AwaitForNodeSyncedEnum::WaitForFullSync,
+                filter: near_indexer::IndexerFilter::default(),
+                // Blocks written to files are acknowledged, so none of them is lost on restart
+                in_flight_window: args.ndjson_dir.as_ref().map(|_| NDJSON_IN_FLIGHT_WINDOW),
             };
             actix::System::new().block_on(async move {
                 let indexer = near_indexer::Indexer::new(indexer_config);
                 let stream = indexer.streamer();
-                actix::spawn(listen_blocks(stream));
+                match args.ndjson_dir {
+                    Some(dir) => {
+                        let sink = NdjsonSink::open(NdjsonSinkConfig {
+                            dir,
+                            max_file_size: args.ndjson_max_file_size,
+                            max_file_height_span: None,
+                            compress: args.ndjson_compress,
+                        })
+                        .expect("Failed to open NDJSON files");
+                        actix::spawn(async move {
+                            // The error is logged by `run_sink`, the blocks after the failed
+                            // one are not written, so there is no point to keep running
+                            if run_sink(stream, sink, Some(indexer.acknowledger())).await.is_err() {
+                                actix::System::current().stop_with_code(1);
+                            }
+                        });
+                    }
+                    None => {
+                        actix::spawn(listen_blocks(stream));
+                    }
+                }
             });
         }
         SubCommand::Init(config) => near_indexer::indexer_init_configs(&home_dir, config.into()),